@@ -0,0 +1,315 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+const FI_ARG_LATENCY: &str = "inject-latency";
+const FI_ARG_LATENCY_PROBABILITY: &str = "inject-latency-probability";
+const FI_ARG_LOSS: &str = "inject-loss";
+
+pub(crate) trait AppendFaultInjectArgs {
+    fn append_fault_inject_args(self) -> Self;
+}
+
+impl AppendFaultInjectArgs for Command {
+    fn append_fault_inject_args(self) -> Self {
+        self.arg(
+            Arg::new(FI_ARG_LATENCY)
+                .help("Add latency before each read and write on the connections")
+                .value_name("DURATION")
+                .long(FI_ARG_LATENCY)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(FI_ARG_LATENCY_PROBABILITY)
+                .help("The probability to add latency to each read and write")
+                .value_name("PROBABILITY")
+                .long(FI_ARG_LATENCY_PROBABILITY)
+                .num_args(1)
+                .value_parser(value_parser!(f64))
+                .default_value("1.0")
+                .requires(FI_ARG_LATENCY),
+        )
+        .arg(
+            Arg::new(FI_ARG_LOSS)
+                .help(
+                    "The probability to silently drop each write on the connections, \
+                    the received data will never be dropped",
+                )
+                .value_name("PROBABILITY")
+                .long(FI_ARG_LOSS)
+                .num_args(1)
+                .value_parser(value_parser!(f64)),
+        )
+    }
+}
+
+pub(crate) trait FaultInjectStats {
+    fn add_delayed(&self);
+    fn add_dropped(&self);
+}
+
+pub(crate) type ArcFaultInjectStats = Arc<dyn FaultInjectStats + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct FaultInjectArgs {
+    latency: Option<Duration>,
+    latency_probability: f64,
+    loss: f64,
+}
+
+impl FaultInjectArgs {
+    fn parse_probability(args: &ArgMatches, id: &str) -> anyhow::Result<Option<f64>> {
+        let Some(v) = args.get_one::<f64>(id) else {
+            return Ok(None);
+        };
+        if !(0.0..=1.0).contains(v) {
+            return Err(anyhow!("{id} value should be in range 0.0 to 1.0"));
+        }
+        Ok(Some(*v))
+    }
+
+    pub(crate) fn parse_args(&mut self, args: &ArgMatches) -> anyhow::Result<()> {
+        if let Some(latency) = g3_clap::humanize::get_duration(args, FI_ARG_LATENCY)? {
+            if !latency.is_zero() {
+                self.latency = Some(latency);
+            }
+        }
+        if let Some(p) = Self::parse_probability(args, FI_ARG_LATENCY_PROBABILITY)? {
+            self.latency_probability = p;
+        }
+        if let Some(p) = Self::parse_probability(args, FI_ARG_LOSS)? {
+            self.loss = p;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        (self.latency.is_some() && self.latency_probability > 0.0) || self.loss > 0.0
+    }
+
+    fn new_delay(&self, stats: &ArcFaultInjectStats) -> Option<Pin<Box<Sleep>>> {
+        let latency = self.latency?;
+        if fastrand::f64() < self.latency_probability {
+            stats.add_delayed();
+            Some(Box::pin(tokio::time::sleep(latency)))
+        } else {
+            None
+        }
+    }
+
+    fn should_drop(&self, stats: &ArcFaultInjectStats) -> bool {
+        if self.loss > 0.0 && fastrand::f64() < self.loss {
+            stats.add_dropped();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Inject faults into each read or write operation.
+///
+/// The injection decision is made once for each operation, and is kept until the operation
+/// on the inner stream completes, so a pending operation won't be delayed again.
+struct FaultInjectState {
+    args: FaultInjectArgs,
+    stats: ArcFaultInjectStats,
+    delay: Option<Pin<Box<Sleep>>>,
+    checked: bool,
+}
+
+impl FaultInjectState {
+    fn new(args: FaultInjectArgs, stats: ArcFaultInjectStats) -> Self {
+        FaultInjectState {
+            args,
+            stats,
+            delay: None,
+            checked: false,
+        }
+    }
+
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.checked {
+            self.delay = self.args.new_delay(&self.stats);
+            self.checked = true;
+        }
+        if let Some(delay) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        Poll::Ready(())
+    }
+
+    fn reset(&mut self) {
+        self.checked = false;
+    }
+}
+
+pub(crate) struct FaultInjectReader<R> {
+    inner: R,
+    state: FaultInjectState,
+}
+
+impl<R> FaultInjectReader<R> {
+    pub(crate) fn new(inner: R, args: FaultInjectArgs, stats: ArcFaultInjectStats) -> Self {
+        FaultInjectReader {
+            inner,
+            state: FaultInjectState::new(args, stats),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FaultInjectReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.state.poll_delay(cx));
+        let r = ready!(Pin::new(&mut self.inner).poll_read(cx, buf));
+        self.state.reset();
+        Poll::Ready(r)
+    }
+}
+
+pub(crate) struct FaultInjectWriter<W> {
+    inner: W,
+    state: FaultInjectState,
+}
+
+impl<W> FaultInjectWriter<W> {
+    pub(crate) fn new(inner: W, args: FaultInjectArgs, stats: ArcFaultInjectStats) -> Self {
+        FaultInjectWriter {
+            inner,
+            state: FaultInjectState::new(args, stats),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for FaultInjectWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if !self.state.checked && self.state.args.should_drop(&self.state.stats) {
+            // pretend the data is sent, so the peer will never receive it
+            return Poll::Ready(Ok(buf.len()));
+        }
+        ready!(self.state.poll_delay(cx));
+        let r = ready!(Pin::new(&mut self.inner).poll_write(cx, buf));
+        self.state.reset();
+        Poll::Ready(r)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::Instant;
+
+    #[derive(Default)]
+    struct TestStats {
+        delayed: AtomicU64,
+        dropped: AtomicU64,
+    }
+
+    impl FaultInjectStats for TestStats {
+        fn add_delayed(&self) {
+            self.delayed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn add_dropped(&self) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_write() {
+        let stats = Arc::new(TestStats::default());
+        let args = FaultInjectArgs {
+            loss: 1.0,
+            ..Default::default()
+        };
+        assert!(args.is_enabled());
+
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut writer = FaultInjectWriter::new(client, args, stats.clone());
+        writer.write_all(b"lost").await.unwrap();
+        drop(writer);
+
+        let mut buf = Vec::new();
+        server.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.delayed.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn delay() {
+        let stats = Arc::new(TestStats::default());
+        let args = FaultInjectArgs {
+            latency: Some(Duration::from_millis(20)),
+            latency_probability: 1.0,
+            loss: 0.0,
+        };
+
+        let (client, server) = tokio::io::duplex(64);
+        let mut writer = FaultInjectWriter::new(client, args, stats.clone());
+        let mut reader = FaultInjectReader::new(server, args, stats.clone());
+
+        let time_start = Instant::now();
+        writer.write_all(b"data").await.unwrap();
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert!(time_start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(&buf, b"data");
+        assert_eq!(stats.delayed.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn disabled() {
+        let args = FaultInjectArgs {
+            latency: Some(Duration::from_millis(20)),
+            latency_probability: 0.0,
+            loss: 0.0,
+        };
+        assert!(!args.is_enabled());
+        assert!(!FaultInjectArgs::default().is_enabled());
+    }
+}
@@ -15,4 +15,7 @@
  */
 
 mod stats;
-pub(crate) use stats::{HttpHistogram, HttpHistogramRecorder, HttpRuntimeStats};
+pub(crate) use stats::{
+    spawn_openmetrics_server, HttpHistogram, HttpHistogramRecorder, HttpLiveQuantiles,
+    HttpRuntimeStats,
+};
@@ -14,41 +14,131 @@
  * limitations under the License.
  */
 
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-use g3_histogram::{HistogramRecorder, KeepingHistogram};
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+use g3_histogram::{
+    DurationHistogram, DurationHistogramRecorder, HistogramRecorder, KeepingHistogram,
+};
+use g3_socket::TcpInfo;
 use g3_statsd_client::StatsdClient;
-use g3_types::ext::DurationExt;
 
+use super::HttpLiveQuantiles;
 use crate::target::BenchHistogram;
 
 pub(crate) struct HttpHistogram {
-    send_hdr_time: KeepingHistogram<u64>,
-    recv_hdr_time: KeepingHistogram<u64>,
-    total_time: KeepingHistogram<u64>,
+    send_hdr_time: DurationHistogram,
+    recv_hdr_time: DurationHistogram,
+    total_time: DurationHistogram,
     conn_reuse_count: KeepingHistogram<u64>,
+    dns_time: DurationHistogram,
+    tcp_connect_time: DurationHistogram,
+    tls_handshake_time: DurationHistogram,
+    proxy_negotiation_time: DurationHistogram,
+    pool_wait_time: DurationHistogram,
+    tunnel_setup_time: DurationHistogram,
+    tunnel_payload_time: DurationHistogram,
+    tcp_rtt: DurationHistogram,
+    tcp_retrans: KeepingHistogram<u64>,
+    hdr_output: Option<PathBuf>,
+    total_time_recorded: Option<Arc<AtomicU64>>,
+    live_quantiles: Option<Arc<HttpLiveQuantiles>>,
 }
 
 impl HttpHistogram {
     pub(crate) fn new() -> (Self, HttpHistogramRecorder) {
-        let (send_hdr_time_h, send_hdr_time_r) = KeepingHistogram::new();
-        let (recv_hdr_time_h, recv_hdr_time_r) = KeepingHistogram::new();
-        let (total_time_h, total_time_r) = KeepingHistogram::new();
+        let (send_hdr_time_h, send_hdr_time_r) = DurationHistogram::new();
+        let (recv_hdr_time_h, recv_hdr_time_r) = DurationHistogram::new();
+        let (total_time_h, total_time_r) = DurationHistogram::new();
         let (conn_reuse_count_h, conn_reuse_count_r) = KeepingHistogram::new();
+        let (dns_time_h, dns_time_r) = DurationHistogram::new();
+        let (tcp_connect_time_h, tcp_connect_time_r) = DurationHistogram::new();
+        let (tls_handshake_time_h, tls_handshake_time_r) = DurationHistogram::new();
+        let (proxy_negotiation_time_h, proxy_negotiation_time_r) = DurationHistogram::new();
+        let (pool_wait_time_h, pool_wait_time_r) = DurationHistogram::new();
+        let (tunnel_setup_time_h, tunnel_setup_time_r) = DurationHistogram::new();
+        let (tunnel_payload_time_h, tunnel_payload_time_r) = DurationHistogram::new();
+        let (tcp_rtt_h, tcp_rtt_r) = DurationHistogram::new();
+        let (tcp_retrans_h, tcp_retrans_r) = KeepingHistogram::new();
         let h = HttpHistogram {
             send_hdr_time: send_hdr_time_h,
             recv_hdr_time: recv_hdr_time_h,
             total_time: total_time_h,
             conn_reuse_count: conn_reuse_count_h,
+            dns_time: dns_time_h,
+            tcp_connect_time: tcp_connect_time_h,
+            tls_handshake_time: tls_handshake_time_h,
+            proxy_negotiation_time: proxy_negotiation_time_h,
+            pool_wait_time: pool_wait_time_h,
+            tunnel_setup_time: tunnel_setup_time_h,
+            tunnel_payload_time: tunnel_payload_time_h,
+            tcp_rtt: tcp_rtt_h,
+            tcp_retrans: tcp_retrans_h,
+            hdr_output: None,
+            total_time_recorded: None,
+            live_quantiles: None,
         };
         let r = HttpHistogramRecorder {
             send_hdr_time: send_hdr_time_r,
             recv_hdr_time: recv_hdr_time_r,
             total_time: total_time_r,
             conn_reuse_count: conn_reuse_count_r,
+            dns_time: dns_time_r,
+            tcp_connect_time: tcp_connect_time_r,
+            tls_handshake_time: tls_handshake_time_r,
+            proxy_negotiation_time: proxy_negotiation_time_r,
+            pool_wait_time: pool_wait_time_r,
+            tunnel_setup_time: tunnel_setup_time_r,
+            tunnel_payload_time: tunnel_payload_time_r,
+            tcp_rtt: tcp_rtt_r,
+            tcp_retrans: tcp_retrans_r,
         };
         (h, r)
     }
+
+    /// write the total time histogram to this file in HdrHistogram percentile distribution format
+    pub(crate) fn set_hdr_output(&mut self, path: PathBuf) {
+        self.hdr_output = Some(path);
+    }
+
+    /// update the counter with the count of recorded total time values on each refresh
+    pub(crate) fn set_total_time_recorded(&mut self, counter: Arc<AtomicU64>) {
+        self.total_time_recorded = Some(counter);
+    }
+
+    /// update the shared quantiles of the duration histograms on each refresh
+    pub(crate) fn set_live_quantiles(&mut self, quantiles: Arc<HttpLiveQuantiles>) {
+        self.live_quantiles = Some(quantiles);
+    }
+
+    /// get the total time at the given quantile, or None if nothing recorded
+    pub(crate) fn total_time_at_quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.total_time.is_empty() {
+            None
+        } else {
+            Some(self.total_time.value_at_quantile(quantile))
+        }
+    }
+
+    fn has_conn_setup_time(&self) -> bool {
+        !self.tcp_connect_time.inner().is_empty()
+    }
+
+    fn has_tcp_info(&self) -> bool {
+        !self.tcp_rtt.inner().is_empty()
+    }
+
+    /// no http request will be sent in tunnel only mode
+    fn has_request_time(&self) -> bool {
+        !self.send_hdr_time.inner().is_empty()
+    }
 }
 
 impl BenchHistogram for HttpHistogram {
@@ -57,48 +147,266 @@ impl BenchHistogram for HttpHistogram {
         self.recv_hdr_time.refresh().unwrap();
         self.total_time.refresh().unwrap();
         self.conn_reuse_count.refresh().unwrap();
+        self.dns_time.refresh().unwrap();
+        self.tcp_connect_time.refresh().unwrap();
+        self.tls_handshake_time.refresh().unwrap();
+        self.proxy_negotiation_time.refresh().unwrap();
+        self.pool_wait_time.refresh().unwrap();
+        self.tunnel_setup_time.refresh().unwrap();
+        self.tunnel_payload_time.refresh().unwrap();
+        self.tcp_rtt.refresh().unwrap();
+        self.tcp_retrans.refresh().unwrap();
+        if let Some(counter) = &self.total_time_recorded {
+            counter.store(self.total_time.len(), Ordering::Relaxed);
+        }
+        if let Some(quantiles) = &self.live_quantiles {
+            quantiles.update("http_time_send_hdr", self.send_hdr_time.inner());
+            quantiles.update("http_time_recv_hdr", self.recv_hdr_time.inner());
+            quantiles.update("http_time_total", self.total_time.inner());
+        }
     }
 
     fn emit(&self, client: &mut StatsdClient) {
         self.emit_histogram(client, self.send_hdr_time.inner(), "http.time.send_hdr");
         self.emit_histogram(client, self.recv_hdr_time.inner(), "http.time.recv_hdr");
         self.emit_histogram(client, self.total_time.inner(), "http.time.total");
+        if !self.pool_wait_time.inner().is_empty() {
+            self.emit_histogram(client, self.pool_wait_time.inner(), "http.time.pool_wait");
+        }
+        if !self.tunnel_setup_time.inner().is_empty() {
+            self.emit_histogram(
+                client,
+                self.tunnel_setup_time.inner(),
+                "http.time.tunnel_setup",
+            );
+        }
+        if !self.tunnel_payload_time.inner().is_empty() {
+            self.emit_histogram(
+                client,
+                self.tunnel_payload_time.inner(),
+                "http.time.tunnel_payload",
+            );
+        }
+        if self.has_conn_setup_time() {
+            self.emit_histogram(
+                client,
+                self.tcp_connect_time.inner(),
+                "http.time.tcp_connect",
+            );
+            self.emit_histogram(
+                client,
+                self.tls_handshake_time.inner(),
+                "http.time.tls_handshake",
+            );
+            if !self.proxy_negotiation_time.inner().is_empty() {
+                self.emit_histogram(
+                    client,
+                    self.proxy_negotiation_time.inner(),
+                    "http.time.proxy_negotiation",
+                );
+            }
+        }
+        if self.has_tcp_info() {
+            self.emit_histogram(client, self.tcp_rtt.inner(), "http.tcp.rtt");
+            self.emit_histogram(client, self.tcp_retrans.inner(), "http.tcp.retrans");
+        }
     }
 
     fn summary(&self) {
-        Self::summary_histogram_title("# Connection Re-Usage:");
-        Self::summary_data_line("Req/Conn:", self.conn_reuse_count.inner());
+        if self.has_request_time() {
+            Self::summary_histogram_title("# Connection Re-Usage:");
+            Self::summary_data_line("Req/Conn:", self.conn_reuse_count.inner());
+        }
         Self::summary_histogram_title("# Duration Times");
-        Self::summary_duration_line("SendHdr:", self.send_hdr_time.inner());
-        Self::summary_duration_line("RecvHdr:", self.recv_hdr_time.inner());
+        if self.has_request_time() {
+            Self::summary_duration_line("SendHdr:", self.send_hdr_time.inner());
+            Self::summary_duration_line("RecvHdr:", self.recv_hdr_time.inner());
+        }
+        if !self.tunnel_setup_time.inner().is_empty() {
+            Self::summary_duration_line("TunSetup:", self.tunnel_setup_time.inner());
+        }
+        if !self.tunnel_payload_time.inner().is_empty() {
+            Self::summary_duration_line("TunEcho:", self.tunnel_payload_time.inner());
+        }
         Self::summary_duration_line("Total:", self.total_time.inner());
+        if !self.pool_wait_time.inner().is_empty() {
+            Self::summary_duration_line("PoolWait:", self.pool_wait_time.inner());
+        }
+        if self.has_conn_setup_time() {
+            Self::summary_histogram_title("# Connection Setup Times");
+            if !self.dns_time.inner().is_empty() {
+                Self::summary_duration_line("Dns:", self.dns_time.inner());
+            }
+            Self::summary_duration_line("TcpConn:", self.tcp_connect_time.inner());
+            Self::summary_duration_line("TlsHs:", self.tls_handshake_time.inner());
+            if !self.proxy_negotiation_time.inner().is_empty() {
+                Self::summary_duration_line("ProxyNego:", self.proxy_negotiation_time.inner());
+            }
+        }
+        if self.has_tcp_info() {
+            Self::summary_histogram_title("# TCP Info at Close");
+            Self::summary_duration_line("SmoothRtt:", self.tcp_rtt.inner());
+            Self::summary_data_line("Retrans:", self.tcp_retrans.inner());
+        }
         Self::summary_newline();
         Self::summary_total_percentage(self.total_time.inner());
     }
+
+    fn summary_json(&self) -> Option<Value> {
+        let mut map = Map::new();
+        map.insert(
+            "conn_reuse_count".to_string(),
+            Self::json_histogram(self.conn_reuse_count.inner()),
+        );
+        map.insert(
+            "send_hdr_time_ns".to_string(),
+            Self::json_histogram(self.send_hdr_time.inner()),
+        );
+        map.insert(
+            "recv_hdr_time_ns".to_string(),
+            Self::json_histogram(self.recv_hdr_time.inner()),
+        );
+        map.insert(
+            "total_time_ns".to_string(),
+            Self::json_histogram(self.total_time.inner()),
+        );
+        if !self.pool_wait_time.inner().is_empty() {
+            map.insert(
+                "pool_wait_time_ns".to_string(),
+                Self::json_histogram(self.pool_wait_time.inner()),
+            );
+        }
+        if !self.tunnel_setup_time.inner().is_empty() {
+            map.insert(
+                "tunnel_setup_time_ns".to_string(),
+                Self::json_histogram(self.tunnel_setup_time.inner()),
+            );
+        }
+        if !self.tunnel_payload_time.inner().is_empty() {
+            map.insert(
+                "tunnel_payload_time_ns".to_string(),
+                Self::json_histogram(self.tunnel_payload_time.inner()),
+            );
+        }
+        if self.has_conn_setup_time() {
+            if !self.dns_time.inner().is_empty() {
+                map.insert(
+                    "dns_time_ns".to_string(),
+                    Self::json_histogram(self.dns_time.inner()),
+                );
+            }
+            map.insert(
+                "tcp_connect_time_ns".to_string(),
+                Self::json_histogram(self.tcp_connect_time.inner()),
+            );
+            map.insert(
+                "tls_handshake_time_ns".to_string(),
+                Self::json_histogram(self.tls_handshake_time.inner()),
+            );
+            if !self.proxy_negotiation_time.inner().is_empty() {
+                map.insert(
+                    "proxy_negotiation_time_ns".to_string(),
+                    Self::json_histogram(self.proxy_negotiation_time.inner()),
+                );
+            }
+        }
+        if self.has_tcp_info() {
+            map.insert(
+                "tcp_rtt_ns".to_string(),
+                Self::json_histogram(self.tcp_rtt.inner()),
+            );
+            map.insert(
+                "tcp_retrans".to_string(),
+                Self::json_histogram(self.tcp_retrans.inner()),
+            );
+        }
+        Some(Value::Object(map))
+    }
+
+    fn write_output_files(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.hdr_output else {
+            return Ok(());
+        };
+
+        let file = File::create(path)
+            .map_err(|e| anyhow!("failed to create file {}: {e}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        // the values are in nanoseconds, output in milliseconds
+        g3_histogram::write_percentile_distribution(
+            self.total_time.inner(),
+            &mut writer,
+            5,
+            1_000_000.0,
+        )
+        .and_then(|_| writer.flush())
+        .map_err(|e| anyhow!("failed to write hdr output to {}: {e}", path.display()))
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct HttpHistogramRecorder {
-    send_hdr_time: HistogramRecorder<u64>,
-    recv_hdr_time: HistogramRecorder<u64>,
-    total_time: HistogramRecorder<u64>,
+    send_hdr_time: DurationHistogramRecorder,
+    recv_hdr_time: DurationHistogramRecorder,
+    total_time: DurationHistogramRecorder,
     conn_reuse_count: HistogramRecorder<u64>,
+    dns_time: DurationHistogramRecorder,
+    tcp_connect_time: DurationHistogramRecorder,
+    tls_handshake_time: DurationHistogramRecorder,
+    proxy_negotiation_time: DurationHistogramRecorder,
+    pool_wait_time: DurationHistogramRecorder,
+    tunnel_setup_time: DurationHistogramRecorder,
+    tunnel_payload_time: DurationHistogramRecorder,
+    tcp_rtt: DurationHistogramRecorder,
+    tcp_retrans: HistogramRecorder<u64>,
 }
 
 impl HttpHistogramRecorder {
     pub(crate) fn record_send_hdr_time(&mut self, dur: Duration) {
-        let _ = self.send_hdr_time.record(dur.as_nanos_u64());
+        let _ = self.send_hdr_time.record(dur);
     }
 
     pub(crate) fn record_recv_hdr_time(&mut self, dur: Duration) {
-        let _ = self.recv_hdr_time.record(dur.as_nanos_u64());
+        let _ = self.recv_hdr_time.record(dur);
     }
 
     pub(crate) fn record_total_time(&mut self, dur: Duration) {
-        let _ = self.total_time.record(dur.as_nanos_u64());
+        let _ = self.total_time.record(dur);
     }
 
     pub(crate) fn record_conn_reuse_count(&mut self, count: u64) {
         let _ = self.conn_reuse_count.record(count);
     }
+
+    pub(crate) fn record_dns_time(&mut self, dur: Duration) {
+        let _ = self.dns_time.record(dur);
+    }
+
+    pub(crate) fn record_tcp_connect_time(&mut self, dur: Duration) {
+        let _ = self.tcp_connect_time.record(dur);
+    }
+
+    pub(crate) fn record_tls_handshake_time(&mut self, dur: Duration) {
+        let _ = self.tls_handshake_time.record(dur);
+    }
+
+    pub(crate) fn record_proxy_negotiation_time(&mut self, dur: Duration) {
+        let _ = self.proxy_negotiation_time.record(dur);
+    }
+
+    pub(crate) fn record_pool_wait_time(&mut self, dur: Duration) {
+        let _ = self.pool_wait_time.record(dur);
+    }
+
+    pub(crate) fn record_tunnel_setup_time(&mut self, dur: Duration) {
+        let _ = self.tunnel_setup_time.record(dur);
+    }
+
+    pub(crate) fn record_tunnel_payload_time(&mut self, dur: Duration) {
+        let _ = self.tunnel_payload_time.record(dur);
+    }
+
+    pub(crate) fn record_tcp_info(&mut self, info: &TcpInfo) {
+        let _ = self.tcp_rtt.record(info.rtt);
+        let _ = self.tcp_retrans.record(info.total_retrans as u64);
+    }
 }
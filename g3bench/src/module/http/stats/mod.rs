@@ -15,7 +15,10 @@
  */
 
 mod histogram;
+mod openmetrics;
 mod runtime;
+mod soak;
 
 pub(crate) use histogram::{HttpHistogram, HttpHistogramRecorder};
+pub(crate) use openmetrics::{spawn_openmetrics_server, HttpLiveQuantiles};
 pub(crate) use runtime::HttpRuntimeStats;
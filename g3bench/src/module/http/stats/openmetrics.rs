@@ -0,0 +1,287 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::{Display, Write};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use hdrhistogram::Histogram;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::HttpRuntimeStats;
+
+const METRIC_NAME_PREFIX: &str = "g3bench_";
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const QUANTILES: [f64; 5] = [0.5, 0.9, 0.95, 0.99, 0.999];
+const REQUEST_MAX_SIZE: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The OpenMetrics text exposition of a single scrape
+pub(super) struct OpenMetricsText {
+    buf: String,
+    target: &'static str,
+}
+
+impl OpenMetricsText {
+    fn new(target: &'static str) -> Self {
+        OpenMetricsText {
+            buf: String::with_capacity(4096),
+            target,
+        }
+    }
+
+    fn metadata(&mut self, name: &str, kind: &str) {
+        let _ = writeln!(self.buf, "# TYPE {METRIC_NAME_PREFIX}{name} {kind}");
+    }
+
+    fn sample<T: Display>(&mut self, name: &str, label: Option<(&str, &str)>, value: T) {
+        let _ = write!(
+            self.buf,
+            "{METRIC_NAME_PREFIX}{name}{{target=\"{}\"",
+            self.target
+        );
+        if let Some((k, v)) = label {
+            let _ = write!(self.buf, ",{k}=\"{v}\"");
+        }
+        let _ = writeln!(self.buf, "}} {value}");
+    }
+
+    pub(super) fn counter(&mut self, name: &str, value: u64) {
+        self.metadata(name, "counter");
+        self.sample(&format!("{name}_total"), None, value);
+    }
+
+    /// a counter with one extra label, only the non-zero values will be added
+    pub(super) fn labeled_counter<'a, I>(&mut self, name: &str, label: &str, values: I)
+    where
+        I: IntoIterator<Item = (&'a str, u64)>,
+    {
+        self.metadata(name, "counter");
+        let sample_name = format!("{name}_total");
+        for (v, count) in values {
+            self.sample(&sample_name, Some((label, v)), count);
+        }
+    }
+
+    pub(super) fn gauge<T: Display>(&mut self, name: &str, value: T) {
+        self.metadata(name, "gauge");
+        self.sample(name, None, value);
+    }
+
+    fn finish(mut self) -> String {
+        self.buf.push_str("# EOF\n");
+        self.buf
+    }
+}
+
+#[derive(Clone, Default)]
+struct QuantilesSnapshot {
+    count: u64,
+    sum_secs: f64,
+    values: Vec<(f64, f64)>,
+}
+
+/// The latest duration quantiles of the histograms, updated on each histogram refresh
+#[derive(Default)]
+pub(crate) struct HttpLiveQuantiles {
+    inner: Mutex<Vec<(&'static str, QuantilesSnapshot)>>,
+}
+
+impl HttpLiveQuantiles {
+    /// update the quantiles of the duration histogram `name`, in which the values are in nanoseconds
+    pub(super) fn update(&self, name: &'static str, histogram: &Histogram<u64>) {
+        let snapshot = QuantilesSnapshot {
+            count: histogram.len(),
+            sum_secs: histogram.mean() * histogram.len() as f64 / 1e9,
+            values: QUANTILES
+                .iter()
+                .map(|q| (*q, histogram.value_at_quantile(*q) as f64 / 1e9))
+                .collect(),
+        };
+        let mut inner = self.inner.lock().unwrap();
+        match inner.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, s)) => *s = snapshot,
+            None => inner.push((name, snapshot)),
+        }
+    }
+
+    fn encode(&self, text: &mut OpenMetricsText) {
+        let inner = self.inner.lock().unwrap().clone();
+        for (name, snapshot) in inner {
+            let name = format!("{name}_seconds");
+            text.metadata(&name, "summary");
+            let _ = writeln!(text.buf, "# UNIT {METRIC_NAME_PREFIX}{name} seconds");
+            for (q, v) in &snapshot.values {
+                text.sample(&name, Some(("quantile", &q.to_string())), v);
+            }
+            text.sample(&format!("{name}_count"), None, snapshot.count);
+            text.sample(&format!("{name}_sum"), None, snapshot.sum_secs);
+        }
+    }
+}
+
+fn encode(stats: &HttpRuntimeStats, quantiles: &HttpLiveQuantiles) -> String {
+    let mut text = OpenMetricsText::new(stats.target());
+    stats.encode_openmetrics(&mut text);
+    quantiles.encode(&mut text);
+    text.finish()
+}
+
+/// serve the OpenMetrics text at `/metrics` on `port` of all local addresses until the process exits
+pub(crate) async fn spawn_openmetrics_server(
+    port: u16,
+    stats: Arc<HttpRuntimeStats>,
+    quantiles: Arc<HttpLiveQuantiles>,
+) -> anyhow::Result<()> {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("failed to bind metrics port {port}: {e}"))?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                // avoid busy looping if out of fds
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            };
+            let stats = stats.clone();
+            let quantiles = quantiles.clone();
+            tokio::spawn(async move {
+                let _ = tokio::time::timeout(
+                    REQUEST_TIMEOUT,
+                    serve_one(stream, || encode(&stats, &quantiles)),
+                )
+                .await;
+            });
+        }
+    });
+    Ok(())
+}
+
+/// serve one request and close the connection
+async fn serve_one<S, F>(mut stream: S, encode: F) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnOnce() -> String,
+{
+    let mut buf = Vec::with_capacity(1024);
+    loop {
+        if buf.len() >= REQUEST_MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "too large request header",
+            ));
+        }
+        let mut chunk = [0u8; 1024];
+        let nr = stream.read(&mut chunk).await?;
+        if nr == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        buf.extend_from_slice(&chunk[..nr]);
+        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let request_line = buf.split(|c| *c == b'\n').next().unwrap_or_default();
+    let mut parts = std::str::from_utf8(request_line)
+        .unwrap_or_default()
+        .split_ascii_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .and_then(|v| v.split('?').next())
+        .unwrap_or_default();
+
+    let rsp = match (method, path) {
+        ("GET", "/metrics") => {
+            let body = encode();
+            format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: {CONTENT_TYPE}\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n\
+                 {body}",
+                body.len()
+            )
+        }
+        (_, "/metrics") => "HTTP/1.1 405 Method Not Allowed\r\n\
+                            Allow: GET\r\n\
+                            Content-Length: 0\r\n\
+                            Connection: close\r\n\r\n"
+            .to_string(),
+        _ => "HTTP/1.1 404 Not Found\r\n\
+              Content-Length: 0\r\n\
+              Connection: close\r\n\r\n"
+            .to_string(),
+    };
+    stream.write_all(rsp.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        let stats = HttpRuntimeStats::new_tcp("h1");
+        stats.add_task_total();
+        stats.add_task_passed();
+        stats.add_rsp_status(200);
+
+        let quantiles = HttpLiveQuantiles::default();
+        let mut histogram = Histogram::<u64>::new(3).unwrap();
+        histogram.record(1_000_000).unwrap();
+        quantiles.update("http_time_total", &histogram);
+
+        let text = encode(&stats, &quantiles);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"# TYPE g3bench_http_task_passed counter"));
+        assert!(lines.contains(&"g3bench_http_task_passed_total{target=\"h1\"} 1"));
+        assert!(lines.contains(&"g3bench_http_response_status_total{target=\"h1\",code=\"200\"} 1"));
+        assert!(lines.contains(&"# TYPE g3bench_http_time_total_seconds summary"));
+        assert!(lines.contains(&"g3bench_http_time_total_seconds_count{target=\"h1\"} 1"));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+
+    #[tokio::test]
+    async fn serve() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let task = tokio::spawn(serve_one(server, || "# EOF\n".to_string()));
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut rsp = String::new();
+        client.read_to_string(&mut rsp).await.unwrap();
+        task.await.unwrap().unwrap();
+        assert!(rsp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(rsp.ends_with("\r\n\r\n# EOF\n"));
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        let task = tokio::spawn(serve_one(server, String::new));
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut rsp = String::new();
+        client.read_to_string(&mut rsp).await.unwrap();
+        task.await.unwrap().unwrap();
+        assert!(rsp.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -14,12 +14,21 @@
  * limitations under the License.
  */
 
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use serde_json::{Map, Value};
+use tokio::time::MissedTickBehavior;
+
+use g3_http::HttpBodyType;
 use g3_io_ext::{LimitedReaderStats, LimitedRecvStats, LimitedSendStats, LimitedWriterStats};
 use g3_statsd_client::StatsdClient;
 
+use super::openmetrics::OpenMetricsText;
+use super::soak::HttpSoakStats;
+use crate::module::fault_inject::FaultInjectStats;
 use crate::target::BenchRuntimeStats;
 
 #[derive(Default)]
@@ -48,18 +57,387 @@ enum HttpIoStats {
     Udp(HttpUdpIoStats),
 }
 
+#[derive(Default)]
+struct HttpRspBodyFramingStats {
+    short_read: AtomicU64,
+    over_read: AtomicU64,
+}
+
+impl HttpRspBodyFramingStats {
+    fn summary(&self, name: &str) {
+        let short_read = self.short_read.load(Ordering::Relaxed);
+        let over_read = self.over_read.load(Ordering::Relaxed);
+        if short_read > 0 || over_read > 0 {
+            println!("{name:<15}short read {short_read}, over read {over_read}");
+        }
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            "short_read".to_string(),
+            self.short_read.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "over_read".to_string(),
+            self.over_read.load(Ordering::Relaxed).into(),
+        );
+        Value::Object(map)
+    }
+}
+
+/// latency stats for each of the replayed requests
+struct HttpEntryStats {
+    name: String,
+    count: AtomicU64,
+    time_total_ns: AtomicU64,
+    time_max_ns: AtomicU64,
+}
+
+impl HttpEntryStats {
+    fn new(name: String) -> Self {
+        HttpEntryStats {
+            name,
+            count: AtomicU64::new(0),
+            time_total_ns: AtomicU64::new(0),
+            time_max_ns: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, time: Duration) {
+        let ns = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.time_total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.time_max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    fn time_avg(&self) -> Duration {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.time_total_ns.load(Ordering::Relaxed) / count)
+    }
+
+    fn summary(&self, index: usize) {
+        println!(
+            "#{index:<4}{}: count {}, avg {:?}, max {:?}",
+            self.name,
+            self.count.load(Ordering::Relaxed),
+            self.time_avg(),
+            Duration::from_nanos(self.time_max_ns.load(Ordering::Relaxed)),
+        );
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("name".to_string(), self.name.clone().into());
+        map.insert(
+            "count".to_string(),
+            self.count.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "time_avg_ns".to_string(),
+            u64::try_from(self.time_avg().as_nanos())
+                .unwrap_or(u64::MAX)
+                .into(),
+        );
+        map.insert(
+            "time_max_ns".to_string(),
+            self.time_max_ns.load(Ordering::Relaxed).into(),
+        );
+        Value::Object(map)
+    }
+}
+
+/// task result and latency stats for each of the target hosts
+struct HttpHostStats {
+    passed: AtomicU64,
+    failed: AtomicU64,
+    time: HttpEntryStats,
+}
+
+impl HttpHostStats {
+    fn new(host: String) -> Self {
+        HttpHostStats {
+            passed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            time: HttpEntryStats::new(host),
+        }
+    }
+
+    fn host(&self) -> &str {
+        &self.time.name
+    }
+
+    fn summary(&self) {
+        println!(
+            "{}: passed {}, failed {}, avg {:?}, max {:?}",
+            self.host(),
+            self.passed.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.time.time_avg(),
+            Duration::from_nanos(self.time.time_max_ns.load(Ordering::Relaxed)),
+        );
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("host".to_string(), self.host().into());
+        map.insert(
+            "passed".to_string(),
+            self.passed.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "failed".to_string(),
+            self.failed.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "time_avg_ns".to_string(),
+            u64::try_from(self.time.time_avg().as_nanos())
+                .unwrap_or(u64::MAX)
+                .into(),
+        );
+        map.insert(
+            "time_max_ns".to_string(),
+            self.time.time_max_ns.load(Ordering::Relaxed).into(),
+        );
+        Value::Object(map)
+    }
+}
+
+/// the observed idle timeout of keep-alive connections to the origin
+struct HttpKeepAliveProbeStats {
+    origin: String,
+    closed: AtomicU64,
+    closed_min_ns: AtomicU64,
+    closed_max_ns: AtomicU64,
+    survived_max_ns: AtomicU64,
+}
+
+impl HttpKeepAliveProbeStats {
+    fn new(origin: String) -> Self {
+        HttpKeepAliveProbeStats {
+            origin,
+            closed: AtomicU64::new(0),
+            closed_min_ns: AtomicU64::new(u64::MAX),
+            closed_max_ns: AtomicU64::new(0),
+            survived_max_ns: AtomicU64::new(0),
+        }
+    }
+
+    fn record_closed(&self, idle: Duration) {
+        let ns = u64::try_from(idle.as_nanos()).unwrap_or(u64::MAX);
+        self.closed.fetch_add(1, Ordering::Relaxed);
+        self.closed_min_ns.fetch_min(ns, Ordering::Relaxed);
+        self.closed_max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    fn record_survived(&self, idle: Duration) {
+        let ns = u64::try_from(idle.as_nanos()).unwrap_or(u64::MAX);
+        self.survived_max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    fn summary(&self) {
+        println!("Origin: {}", self.origin);
+        let survived = Duration::from_nanos(self.survived_max_ns.load(Ordering::Relaxed));
+        println!("Longest idle survived: {survived:?}");
+        let closed = self.closed.load(Ordering::Relaxed);
+        if closed > 0 {
+            println!(
+                "Observed idle timeout: min {:?}, max {:?}, count {closed}",
+                Duration::from_nanos(self.closed_min_ns.load(Ordering::Relaxed)),
+                Duration::from_nanos(self.closed_max_ns.load(Ordering::Relaxed)),
+            );
+        } else {
+            println!("Observed idle timeout: none");
+        }
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("origin".to_string(), self.origin.clone().into());
+        map.insert(
+            "survived_max_ns".to_string(),
+            self.survived_max_ns.load(Ordering::Relaxed).into(),
+        );
+        let closed = self.closed.load(Ordering::Relaxed);
+        map.insert("closed".to_string(), closed.into());
+        if closed > 0 {
+            map.insert(
+                "closed_min_ns".to_string(),
+                self.closed_min_ns.load(Ordering::Relaxed).into(),
+            );
+            map.insert(
+                "closed_max_ns".to_string(),
+                self.closed_max_ns.load(Ordering::Relaxed).into(),
+            );
+        }
+        Value::Object(map)
+    }
+}
+
+/// certificate verification results of the target, which won't fail the handshake
+#[derive(Default)]
+struct HttpTlsVerifyStats {
+    passed: AtomicU64,
+    failed: AtomicU64,
+    failed_total: AtomicU64,
+    /// the first verification error of each host
+    first_errors: Mutex<BTreeMap<String, String>>,
+}
+
+impl HttpTlsVerifyStats {
+    fn record(&self, host: &str, error: Option<String>) {
+        let Some(error) = error else {
+            self.passed.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        let mut first_errors = self.first_errors.lock().unwrap();
+        if !first_errors.contains_key(host) {
+            first_errors.insert(host.to_string(), error);
+        }
+    }
+
+    fn total_failed(&self) -> u64 {
+        self.failed_total.load(Ordering::Relaxed) + self.failed.load(Ordering::Relaxed)
+    }
+
+    fn summary(&self) {
+        println!("# TLS Verify");
+        println!("Passed: {}", self.passed.load(Ordering::Relaxed));
+        println!("Failed: {}", self.total_failed());
+        for (host, error) in self.first_errors.lock().unwrap().iter() {
+            println!("{host}: {error}");
+        }
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            "passed".to_string(),
+            self.passed.load(Ordering::Relaxed).into(),
+        );
+        map.insert("failed".to_string(), self.total_failed().into());
+        let errors = self
+            .first_errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, error)| (host.clone(), Value::String(error.clone())))
+            .collect();
+        map.insert("first_errors".to_string(), Value::Object(errors));
+        Value::Object(map)
+    }
+}
+
+/// count of the faults injected into the connections
+#[derive(Default)]
+struct HttpFaultInjectStats {
+    delayed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl HttpFaultInjectStats {
+    fn summary(&self) {
+        println!("# Fault Injection");
+        println!("Delayed: {}", self.delayed.load(Ordering::Relaxed));
+        println!("Dropped: {}", self.dropped.load(Ordering::Relaxed));
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            "delayed".to_string(),
+            self.delayed.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "dropped".to_string(),
+            self.dropped.load(Ordering::Relaxed).into(),
+        );
+        Value::Object(map)
+    }
+}
+
+/// setup and payload exchange results of the tunnels in tunnel only mode
+#[derive(Default)]
+struct HttpTunnelStats {
+    setup_attempt: AtomicU64,
+    setup_success: AtomicU64,
+    payload_attempt: AtomicU64,
+    payload_success: AtomicU64,
+}
+
+impl HttpTunnelStats {
+    fn summary(&self) {
+        println!("# Tunnel");
+        let setup_attempt = self.setup_attempt.load(Ordering::Relaxed);
+        let setup_success = self.setup_success.load(Ordering::Relaxed);
+        println!("Setup attempt: {setup_attempt}");
+        println!("Setup success: {setup_success}");
+        println!(
+            "Setup ratio:   {:.2}%",
+            (setup_success as f64 / setup_attempt as f64) * 100.0
+        );
+        let payload_attempt = self.payload_attempt.load(Ordering::Relaxed);
+        if payload_attempt > 0 {
+            let payload_success = self.payload_success.load(Ordering::Relaxed);
+            println!("Payload attempt: {payload_attempt}");
+            println!("Payload success: {payload_success}");
+        }
+    }
+
+    fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            "setup_attempt".to_string(),
+            self.setup_attempt.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "setup_success".to_string(),
+            self.setup_success.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "payload_attempt".to_string(),
+            self.payload_attempt.load(Ordering::Relaxed).into(),
+        );
+        map.insert(
+            "payload_success".to_string(),
+            self.payload_success.load(Ordering::Relaxed).into(),
+        );
+        Value::Object(map)
+    }
+}
+
+const RSP_STATUS_MIN: u16 = 100;
+const RSP_STATUS_SLOTS: usize = 500;
+
 pub(crate) struct HttpRuntimeStats {
     target: &'static str,
     task_total: AtomicU64,
+    task_total_total: AtomicU64,
     task_alive: AtomicI64,
     task_passed: AtomicU64,
+    task_passed_total: AtomicU64,
     task_failed: AtomicU64,
+    task_failed_total: AtomicU64,
     conn_attempt: AtomicU64,
     conn_attempt_total: AtomicU64,
     conn_success: AtomicU64,
     conn_success_total: AtomicU64,
     conn_close_error: AtomicU64,
     conn_close_timeout: AtomicU64,
+    rsp_status: [AtomicU64; RSP_STATUS_SLOTS],
+    rsp_body_content_length: HttpRspBodyFramingStats,
+    rsp_body_chunked: HttpRspBodyFramingStats,
+    entries: Vec<HttpEntryStats>,
+    hosts: Vec<HttpHostStats>,
+    keepalive_probe: Option<HttpKeepAliveProbeStats>,
+    soak: Option<HttpSoakStats>,
+    tls_verify: Option<HttpTlsVerifyStats>,
+    fault_inject: Option<HttpFaultInjectStats>,
+    tunnel: Option<HttpTunnelStats>,
 
     io: HttpIoStats,
 }
@@ -78,19 +456,177 @@ impl HttpRuntimeStats {
         HttpRuntimeStats {
             target,
             task_total: AtomicU64::new(0),
+            task_total_total: AtomicU64::new(0),
             task_alive: AtomicI64::new(0),
             task_passed: AtomicU64::new(0),
+            task_passed_total: AtomicU64::new(0),
             task_failed: AtomicU64::new(0),
+            task_failed_total: AtomicU64::new(0),
             conn_attempt: AtomicU64::new(0),
             conn_attempt_total: AtomicU64::new(0),
             conn_success: AtomicU64::new(0),
             conn_success_total: AtomicU64::new(0),
             conn_close_error: AtomicU64::new(0),
             conn_close_timeout: AtomicU64::new(0),
+            rsp_status: [const { AtomicU64::new(0) }; RSP_STATUS_SLOTS],
+            rsp_body_content_length: HttpRspBodyFramingStats::default(),
+            rsp_body_chunked: HttpRspBodyFramingStats::default(),
+            entries: Vec::new(),
+            hosts: Vec::new(),
+            keepalive_probe: None,
+            soak: None,
+            tls_verify: None,
+            fault_inject: None,
+            tunnel: None,
             io,
         }
     }
 
+    pub(super) fn target(&self) -> &'static str {
+        self.target
+    }
+
+    /// enable per request latency stats, with names of the requests to replay
+    pub(crate) fn with_entries(mut self, names: Vec<String>) -> Self {
+        self.entries = names.into_iter().map(HttpEntryStats::new).collect();
+        self
+    }
+
+    pub(crate) fn record_entry_time(&self, index: usize, time: Duration) {
+        if let Some(entry) = self.entries.get(index) {
+            entry.record(time);
+        }
+    }
+
+    /// enable per target host stats, which is only needed if there are multiple hosts
+    pub(crate) fn with_hosts(mut self, hosts: Vec<String>) -> Self {
+        if hosts.len() > 1 {
+            self.hosts = hosts.into_iter().map(HttpHostStats::new).collect();
+        }
+        self
+    }
+
+    /// get the index of the target host, which should be used when recording host stats
+    pub(crate) fn host_index(&self, host: &str) -> Option<usize> {
+        // the count should be small, so just do a linear search
+        self.hosts.iter().position(|h| h.host() == host)
+    }
+
+    pub(crate) fn record_host_passed(&self, index: Option<usize>, time: Duration) {
+        if let Some(host) = index.and_then(|i| self.hosts.get(i)) {
+            host.passed.fetch_add(1, Ordering::Relaxed);
+            host.time.record(time);
+        }
+    }
+
+    pub(crate) fn record_host_failed(&self, index: Option<usize>) {
+        if let Some(host) = index.and_then(|i| self.hosts.get(i)) {
+            host.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// enable keep-alive idle timeout stats for the origin
+    pub(crate) fn with_keepalive_probe(mut self, origin: String) -> Self {
+        self.keepalive_probe = Some(HttpKeepAliveProbeStats::new(origin));
+        self
+    }
+
+    /// the idle keep-alive connection was closed by the server after `idle`
+    pub(crate) fn record_keepalive_closed(&self, idle: Duration) {
+        if let Some(probe) = &self.keepalive_probe {
+            probe.record_closed(idle);
+        }
+    }
+
+    /// the keep-alive connection was still open after being idle for `idle`
+    pub(crate) fn record_keepalive_survived(&self, idle: Duration) {
+        if let Some(probe) = &self.keepalive_probe {
+            probe.record_survived(idle);
+        }
+    }
+
+    /// enable soak stats, which should be sampled at `interval`
+    pub(crate) fn with_soak(mut self, interval: Duration) -> Self {
+        self.soak = Some(HttpSoakStats::new(interval));
+        self
+    }
+
+    /// the counter that should be updated with the count of total time values in the histogram
+    pub(crate) fn soak_histogram_recorded(&self) -> Option<Arc<AtomicU64>> {
+        self.soak.as_ref().map(|s| s.histogram_recorded())
+    }
+
+    pub(crate) fn record_soak_time(&self, time: Duration) {
+        if let Some(soak) = &self.soak {
+            soak.record(time);
+        }
+    }
+
+    /// spawn a task to sample the soak stats if enabled
+    pub(crate) fn spawn_soak_sampler(self: &Arc<Self>) {
+        let Some(soak) = &self.soak else {
+            return;
+        };
+        let mut interval = tokio::time::interval(soak.interval());
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let stats = Arc::downgrade(self);
+        tokio::spawn(async move {
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(stats) = stats.upgrade() else {
+                    break;
+                };
+                if let Some(soak) = &stats.soak {
+                    soak.sample();
+                }
+            }
+        });
+    }
+
+    /// enable certificate verification stats
+    pub(crate) fn with_tls_verify(mut self) -> Self {
+        self.tls_verify = Some(HttpTlsVerifyStats::default());
+        self
+    }
+
+    /// record the certificate verification result of a new connection to `host`
+    pub(crate) fn record_tls_verify(&self, host: &str, error: Option<String>) {
+        if let Some(tls_verify) = &self.tls_verify {
+            tls_verify.record(host, error);
+        }
+    }
+
+    /// enable fault injection stats
+    pub(crate) fn with_fault_inject(mut self) -> Self {
+        self.fault_inject = Some(HttpFaultInjectStats::default());
+        self
+    }
+
+    /// enable tunnel stats for the tunnel only mode
+    pub(crate) fn with_tunnel(mut self) -> Self {
+        self.tunnel = Some(HttpTunnelStats::default());
+        self
+    }
+
+    pub(crate) fn record_tunnel_setup(&self, success: bool) {
+        if let Some(tunnel) = &self.tunnel {
+            tunnel.setup_attempt.fetch_add(1, Ordering::Relaxed);
+            if success {
+                tunnel.setup_success.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn record_tunnel_payload(&self, success: bool) {
+        if let Some(tunnel) = &self.tunnel {
+            tunnel.payload_attempt.fetch_add(1, Ordering::Relaxed);
+            if success {
+                tunnel.payload_success.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
     pub(crate) fn add_task_total(&self) {
         self.task_total.fetch_add(1, Ordering::Relaxed);
     }
@@ -126,6 +662,106 @@ impl HttpRuntimeStats {
     pub(crate) fn add_conn_close_timeout(&self) {
         self.conn_close_timeout.fetch_add(1, Ordering::Relaxed);
     }
+
+    fn rsp_body_framing_stats(&self, body_type: HttpBodyType) -> Option<&HttpRspBodyFramingStats> {
+        match body_type {
+            HttpBodyType::ContentLength(_) => Some(&self.rsp_body_content_length),
+            HttpBodyType::Chunked => Some(&self.rsp_body_chunked),
+            HttpBodyType::ReadUntilEnd => None,
+        }
+    }
+
+    /// the connection ended before all the body declared in the header was received
+    pub(crate) fn add_rsp_body_short_read(&self, body_type: HttpBodyType) {
+        if let Some(stats) = self.rsp_body_framing_stats(body_type) {
+            stats.short_read.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// extra data was received after the end of the body declared in the header
+    pub(crate) fn add_rsp_body_over_read(&self, body_type: HttpBodyType) {
+        if let Some(stats) = self.rsp_body_framing_stats(body_type) {
+            stats.over_read.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn add_rsp_status(&self, code: u16) {
+        if let Some(c) = code
+            .checked_sub(RSP_STATUS_MIN)
+            .and_then(|i| self.rsp_status.get(i as usize))
+        {
+            c.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl HttpRuntimeStats {
+    /// add the current values of the counters, which are not reset by the statsd emit
+    pub(super) fn encode_openmetrics(&self, text: &mut OpenMetricsText) {
+        macro_rules! total {
+            ($obj:expr, $field:ident, $total:ident) => {
+                $obj.$total.load(Ordering::Relaxed) + $obj.$field.load(Ordering::Relaxed)
+            };
+        }
+
+        text.gauge("http_task_alive", self.task_alive.load(Ordering::Relaxed));
+        text.counter("http_task", total!(self, task_total, task_total_total));
+        text.counter(
+            "http_task_passed",
+            total!(self, task_passed, task_passed_total),
+        );
+        text.counter(
+            "http_task_failed",
+            total!(self, task_failed, task_failed_total),
+        );
+        text.counter(
+            "http_connection_attempt",
+            total!(self, conn_attempt, conn_attempt_total),
+        );
+        text.counter(
+            "http_connection_success",
+            total!(self, conn_success, conn_success_total),
+        );
+
+        match &self.io {
+            HttpIoStats::Tcp(tcp) => {
+                text.counter("http_io_tcp_write_bytes", total!(tcp, write, write_total));
+                text.counter("http_io_tcp_read_bytes", total!(tcp, read, read_total));
+            }
+            HttpIoStats::Udp(udp) => {
+                text.counter(
+                    "http_io_udp_send_bytes",
+                    total!(udp, send_bytes, send_bytes_total),
+                );
+                text.counter(
+                    "http_io_udp_send_packets",
+                    total!(udp, send_packets, send_packets_total),
+                );
+                text.counter(
+                    "http_io_udp_recv_bytes",
+                    total!(udp, recv_bytes, recv_bytes_total),
+                );
+                text.counter(
+                    "http_io_udp_recv_packets",
+                    total!(udp, recv_packets, recv_packets_total),
+                );
+            }
+        }
+
+        let status = self
+            .rsp_status
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (RSP_STATUS_MIN as usize + i, c.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .map(|(code, count)| (code.to_string(), count))
+            .collect::<Vec<_>>();
+        text.labeled_counter(
+            "http_response_status",
+            "code",
+            status.iter().map(|(code, count)| (code.as_str(), *count)),
+        );
+    }
 }
 
 impl LimitedReaderStats for HttpRuntimeStats {
@@ -172,6 +808,20 @@ impl LimitedRecvStats for HttpRuntimeStats {
     }
 }
 
+impl FaultInjectStats for HttpRuntimeStats {
+    fn add_delayed(&self) {
+        if let Some(fault_inject) = &self.fault_inject {
+            fault_inject.delayed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn add_dropped(&self) {
+        if let Some(fault_inject) = &self.fault_inject {
+            fault_inject.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
 impl BenchRuntimeStats for HttpRuntimeStats {
     fn emit(&self, client: &mut StatsdClient) {
         const TAG_NAME_TARGET: &str = "target";
@@ -193,8 +843,14 @@ impl BenchRuntimeStats for HttpRuntimeStats {
             .send();
 
         emit_count!(task_total, "task.total");
+        self.task_total_total
+            .fetch_add(task_total, Ordering::Relaxed);
         emit_count!(task_passed, "task.passed");
+        self.task_passed_total
+            .fetch_add(task_passed, Ordering::Relaxed);
         emit_count!(task_failed, "task.failed");
+        self.task_failed_total
+            .fetch_add(task_failed, Ordering::Relaxed);
         emit_count!(conn_attempt, "connection.attempt");
         self.conn_attempt_total
             .fetch_add(conn_attempt, Ordering::Relaxed);
@@ -234,6 +890,28 @@ impl BenchRuntimeStats for HttpRuntimeStats {
                     .fetch_add(recv_packets, Ordering::Relaxed);
             }
         }
+
+        if let Some(tls_verify) = &self.tls_verify {
+            let failed = tls_verify.failed.swap(0, Ordering::Relaxed);
+            client
+                .count("http.tls.verify_failed", failed)
+                .with_tag(TAG_NAME_TARGET, self.target)
+                .send();
+            tls_verify.failed_total.fetch_add(failed, Ordering::Relaxed);
+        }
+
+        if let Some(soak) = &self.soak {
+            if let Some(rss) = soak.current_rss() {
+                client
+                    .gauge("http.soak.rss", rss)
+                    .with_tag(TAG_NAME_TARGET, self.target)
+                    .send();
+            }
+            client
+                .gauge("http.soak.histogram_lag", soak.current_histogram_lag())
+                .with_tag(TAG_NAME_TARGET, self.target)
+                .send();
+        }
     }
 
     fn summary(&self, total_time: Duration) {
@@ -259,6 +937,8 @@ impl BenchRuntimeStats for HttpRuntimeStats {
         if close_timeout > 0 {
             println!("Close timeout: {close_timeout}");
         }
+        self.rsp_body_content_length.summary("Body(Length):");
+        self.rsp_body_chunked.summary("Body(Chunked):");
 
         println!("# Traffic");
         match &self.io {
@@ -289,5 +969,223 @@ impl BenchRuntimeStats for HttpRuntimeStats {
                 );
             }
         }
+
+        if !self.entries.is_empty() {
+            println!("# Entries");
+            for (i, entry) in self.entries.iter().enumerate() {
+                entry.summary(i);
+            }
+        }
+
+        if !self.hosts.is_empty() {
+            println!("# Targets");
+            for host in &self.hosts {
+                host.summary();
+            }
+        }
+
+        if let Some(probe) = &self.keepalive_probe {
+            println!("# Keep-Alive Probe");
+            probe.summary();
+        }
+
+        if let Some(tls_verify) = &self.tls_verify {
+            tls_verify.summary();
+        }
+
+        if let Some(fault_inject) = &self.fault_inject {
+            fault_inject.summary();
+        }
+
+        if let Some(tunnel) = &self.tunnel {
+            tunnel.summary();
+        }
+
+        if let Some(soak) = &self.soak {
+            soak.summary();
+        }
+    }
+
+    fn summary_json(&self, total_time: Duration) -> Option<Value> {
+        let total_secs = total_time.as_secs_f64();
+        let mut map = Map::new();
+
+        let mut conn = Map::new();
+        let total_attempt = self.conn_attempt_total.load(Ordering::Relaxed)
+            + self.conn_attempt.load(Ordering::Relaxed);
+        conn.insert("attempt".to_string(), total_attempt.into());
+        let total_success = self.conn_success_total.load(Ordering::Relaxed)
+            + self.conn_success.load(Ordering::Relaxed);
+        conn.insert("success".to_string(), total_success.into());
+        conn.insert(
+            "success_rate".to_string(),
+            (total_success as f64 / total_secs).into(),
+        );
+        conn.insert(
+            "close_error".to_string(),
+            self.conn_close_error.load(Ordering::Relaxed).into(),
+        );
+        conn.insert(
+            "close_timeout".to_string(),
+            self.conn_close_timeout.load(Ordering::Relaxed).into(),
+        );
+        map.insert("connection".to_string(), Value::Object(conn));
+
+        let mut traffic = Map::new();
+        match &self.io {
+            HttpIoStats::Tcp(tcp) => {
+                let total_send =
+                    tcp.write_total.load(Ordering::Relaxed) + tcp.write.load(Ordering::Relaxed);
+                traffic.insert("send_bytes".to_string(), total_send.into());
+                let total_recv =
+                    tcp.read_total.load(Ordering::Relaxed) + tcp.read.load(Ordering::Relaxed);
+                traffic.insert("recv_bytes".to_string(), total_recv.into());
+            }
+            HttpIoStats::Udp(udp) => {
+                let total_send_bytes = udp.send_bytes_total.load(Ordering::Relaxed)
+                    + udp.send_bytes.load(Ordering::Relaxed);
+                traffic.insert("send_bytes".to_string(), total_send_bytes.into());
+                let total_recv_bytes = udp.recv_bytes_total.load(Ordering::Relaxed)
+                    + udp.recv_bytes.load(Ordering::Relaxed);
+                traffic.insert("recv_bytes".to_string(), total_recv_bytes.into());
+            }
+        }
+        map.insert("traffic".to_string(), Value::Object(traffic));
+
+        let mut status = Map::new();
+        for (i, c) in self.rsp_status.iter().enumerate() {
+            let count = c.load(Ordering::Relaxed);
+            if count > 0 {
+                let code = RSP_STATUS_MIN as usize + i;
+                status.insert(code.to_string(), count.into());
+            }
+        }
+        map.insert("response_status".to_string(), Value::Object(status));
+
+        let mut body = Map::new();
+        body.insert(
+            "content_length".to_string(),
+            self.rsp_body_content_length.summary_json(),
+        );
+        body.insert("chunked".to_string(), self.rsp_body_chunked.summary_json());
+        map.insert("response_body".to_string(), Value::Object(body));
+
+        if !self.entries.is_empty() {
+            let entries = self.entries.iter().map(|e| e.summary_json()).collect();
+            map.insert("entries".to_string(), Value::Array(entries));
+        }
+
+        if !self.hosts.is_empty() {
+            let hosts = self.hosts.iter().map(|h| h.summary_json()).collect();
+            map.insert("targets".to_string(), Value::Array(hosts));
+        }
+
+        if let Some(probe) = &self.keepalive_probe {
+            map.insert("keepalive_probe".to_string(), probe.summary_json());
+        }
+
+        if let Some(tls_verify) = &self.tls_verify {
+            map.insert("tls_verify".to_string(), tls_verify.summary_json());
+        }
+
+        if let Some(fault_inject) = &self.fault_inject {
+            map.insert("fault_inject".to_string(), fault_inject.summary_json());
+        }
+
+        if let Some(tunnel) = &self.tunnel {
+            map.insert("tunnel".to_string(), tunnel.summary_json());
+        }
+
+        if let Some(soak) = &self.soak {
+            map.insert("soak".to_string(), soak.summary_json());
+        }
+
+        Some(Value::Object(map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_stats() {
+        let stats = HttpRuntimeStats::new_tcp("test").with_hosts(vec!["a:80".to_string()]);
+        assert!(stats.hosts.is_empty());
+        assert_eq!(stats.host_index("a:80"), None);
+
+        let stats = HttpRuntimeStats::new_tcp("test")
+            .with_hosts(vec!["a:80".to_string(), "b:443".to_string()]);
+        let b = stats.host_index("b:443");
+        assert_eq!(b, Some(1));
+        stats.record_host_passed(b, Duration::from_millis(10));
+        stats.record_host_passed(b, Duration::from_millis(30));
+        stats.record_host_failed(b);
+        stats.record_host_failed(None);
+
+        let v = stats.summary_json(Duration::from_secs(1)).unwrap();
+        let targets = v["targets"].as_array().unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0]["passed"], 0);
+        assert_eq!(targets[1]["host"], "b:443");
+        assert_eq!(targets[1]["passed"], 2);
+        assert_eq!(targets[1]["failed"], 1);
+        assert_eq!(targets[1]["time_avg_ns"], 20_000_000);
+    }
+
+    #[test]
+    fn tls_verify() {
+        let stats = HttpRuntimeStats::new_tcp("test");
+        stats.record_tls_verify("a:443", Some("expired".to_string()));
+        assert!(stats.summary_json(Duration::from_secs(1)).unwrap()["tls_verify"].is_null());
+
+        let stats = HttpRuntimeStats::new_tcp("test").with_tls_verify();
+        stats.record_tls_verify("a:443", None);
+        stats.record_tls_verify("a:443", Some("expired".to_string()));
+        stats.record_tls_verify("a:443", Some("self signed".to_string()));
+        stats.record_tls_verify("b:443", Some("self signed".to_string()));
+
+        let v = stats.summary_json(Duration::from_secs(1)).unwrap();
+        let tls_verify = &v["tls_verify"];
+        assert_eq!(tls_verify["passed"], 1);
+        assert_eq!(tls_verify["failed"], 3);
+        assert_eq!(tls_verify["first_errors"]["a:443"], "expired");
+        assert_eq!(tls_verify["first_errors"]["b:443"], "self signed");
+    }
+
+    #[test]
+    fn fault_inject() {
+        let stats = HttpRuntimeStats::new_tcp("test");
+        stats.add_dropped();
+        assert!(stats.summary_json(Duration::from_secs(1)).unwrap()["fault_inject"].is_null());
+
+        let stats = HttpRuntimeStats::new_tcp("test").with_fault_inject();
+        stats.add_delayed();
+        stats.add_delayed();
+        stats.add_dropped();
+
+        let v = stats.summary_json(Duration::from_secs(1)).unwrap();
+        assert_eq!(v["fault_inject"]["delayed"], 2);
+        assert_eq!(v["fault_inject"]["dropped"], 1);
+    }
+
+    #[test]
+    fn tunnel() {
+        let stats = HttpRuntimeStats::new_tcp("test");
+        stats.record_tunnel_setup(true);
+        assert!(stats.summary_json(Duration::from_secs(1)).unwrap()["tunnel"].is_null());
+
+        let stats = HttpRuntimeStats::new_tcp("test").with_tunnel();
+        stats.record_tunnel_setup(true);
+        stats.record_tunnel_setup(true);
+        stats.record_tunnel_setup(false);
+        stats.record_tunnel_payload(true);
+        stats.record_tunnel_payload(false);
+
+        let v = stats.summary_json(Duration::from_secs(1)).unwrap();
+        assert_eq!(v["tunnel"]["setup_attempt"], 3);
+        assert_eq!(v["tunnel"]["setup_success"], 2);
+        assert_eq!(v["tunnel"]["payload_attempt"], 2);
+        assert_eq!(v["tunnel"]["payload_success"], 1);
     }
 }
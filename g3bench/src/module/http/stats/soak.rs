@@ -0,0 +1,258 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{Map, Value};
+
+/// the relative growth between the start and the end of the run that will be flagged
+const TREND_FLAG_THRESHOLD: f64 = 0.1;
+
+struct HttpSoakSample {
+    elapsed: Duration,
+    rss: Option<u64>,
+    passed: u64,
+    time_avg: Duration,
+    histogram_lag: u64,
+}
+
+#[derive(Default)]
+struct HttpSoakLast {
+    count: u64,
+    time_total_ns: u64,
+}
+
+/// periodically sampled memory and latency stats for long running tests
+pub(super) struct HttpSoakStats {
+    interval: Duration,
+    started: Instant,
+    count: AtomicU64,
+    time_total_ns: AtomicU64,
+    histogram_recorded: Arc<AtomicU64>,
+    last: Mutex<HttpSoakLast>,
+    samples: Mutex<Vec<HttpSoakSample>>,
+}
+
+impl HttpSoakStats {
+    pub(super) fn new(interval: Duration) -> Self {
+        HttpSoakStats {
+            interval,
+            started: Instant::now(),
+            count: AtomicU64::new(0),
+            time_total_ns: AtomicU64::new(0),
+            histogram_recorded: Arc::new(AtomicU64::new(0)),
+            last: Mutex::new(HttpSoakLast::default()),
+            samples: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(super) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub(super) fn histogram_recorded(&self) -> Arc<AtomicU64> {
+        self.histogram_recorded.clone()
+    }
+
+    pub(super) fn record(&self, time: Duration) {
+        let ns = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+        self.time_total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn histogram_lag(&self) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        count.saturating_sub(self.histogram_recorded.load(Ordering::Relaxed))
+    }
+
+    pub(super) fn sample(&self) {
+        let count = self.count.load(Ordering::Relaxed);
+        let time_total_ns = self.time_total_ns.load(Ordering::Relaxed);
+
+        let mut last = self.last.lock().unwrap();
+        let passed = count - last.count;
+        let time_avg = (time_total_ns - last.time_total_ns)
+            .checked_div(passed)
+            .map(Duration::from_nanos)
+            .unwrap_or_default();
+        last.count = count;
+        last.time_total_ns = time_total_ns;
+        drop(last);
+
+        let sample = HttpSoakSample {
+            elapsed: self.started.elapsed(),
+            rss: process_rss(),
+            passed,
+            time_avg,
+            histogram_lag: self.histogram_lag(),
+        };
+        self.samples.lock().unwrap().push(sample);
+    }
+
+    pub(super) fn current_rss(&self) -> Option<u64> {
+        process_rss()
+    }
+
+    pub(super) fn current_histogram_lag(&self) -> u64 {
+        self.histogram_lag()
+    }
+
+    fn rss_trend(samples: &[HttpSoakSample]) -> Option<f64> {
+        let values = samples
+            .iter()
+            .map(|s| s.rss.map(|v| v as f64))
+            .collect::<Option<Vec<f64>>>()?;
+        trend(&values)
+    }
+
+    fn time_trend(samples: &[HttpSoakSample]) -> Option<f64> {
+        // skip the intervals that have no request finished
+        let values = samples
+            .iter()
+            .filter(|s| s.passed > 0)
+            .map(|s| s.time_avg.as_secs_f64())
+            .collect::<Vec<f64>>();
+        trend(&values)
+    }
+
+    pub(super) fn summary(&self) {
+        let samples = self.samples.lock().unwrap();
+        println!("# Soak");
+        println!("  elapsed        rss      passed    time_avg    histogram_lag");
+        for s in samples.iter() {
+            let rss = s
+                .rss
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "n/a".to_string());
+            println!(
+                "{:>9.1?} {rss:>10} {:>11} {:>11.3?} {:>16}",
+                s.elapsed, s.passed, s.time_avg, s.histogram_lag
+            );
+        }
+        summary_trend("RSS trend:", Self::rss_trend(&samples));
+        summary_trend("Time trend:", Self::time_trend(&samples));
+    }
+
+    pub(super) fn summary_json(&self) -> Value {
+        let samples = self.samples.lock().unwrap();
+        let mut map = Map::new();
+        let list = samples
+            .iter()
+            .map(|s| {
+                let mut m = Map::new();
+                m.insert(
+                    "elapsed_ms".to_string(),
+                    u64::try_from(s.elapsed.as_millis())
+                        .unwrap_or(u64::MAX)
+                        .into(),
+                );
+                m.insert("rss".to_string(), s.rss.into());
+                m.insert("passed".to_string(), s.passed.into());
+                m.insert(
+                    "time_avg_ns".to_string(),
+                    u64::try_from(s.time_avg.as_nanos())
+                        .unwrap_or(u64::MAX)
+                        .into(),
+                );
+                m.insert("histogram_lag".to_string(), s.histogram_lag.into());
+                Value::Object(m)
+            })
+            .collect();
+        map.insert("samples".to_string(), Value::Array(list));
+        map.insert("rss_trend".to_string(), Self::rss_trend(&samples).into());
+        map.insert("time_trend".to_string(), Self::time_trend(&samples).into());
+        Value::Object(map)
+    }
+}
+
+fn summary_trend(name: &str, trend: Option<f64>) {
+    match trend {
+        Some(v) if v > TREND_FLAG_THRESHOLD => {
+            println!("{name:<12} {:+.2}% (UPWARD)", v * 100.0)
+        }
+        Some(v) => println!("{name:<12} {:+.2}%", v * 100.0),
+        None => println!("{name:<12} n/a"),
+    }
+}
+
+/// get the relative change between the mean of the first quarter and the last quarter
+fn trend(values: &[f64]) -> Option<f64> {
+    if values.len() < 4 {
+        return None;
+    }
+    let n = values.len() / 4;
+    let head = values[..n].iter().sum::<f64>() / n as f64;
+    let tail = values[values.len() - n..].iter().sum::<f64>() / n as f64;
+    if head <= 0.0 {
+        return None;
+    }
+    Some((tail - head) / head)
+}
+
+#[cfg(target_os = "linux")]
+fn process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trend_value() {
+        assert_eq!(trend(&[1.0, 2.0, 3.0]), None);
+        assert_eq!(trend(&[1.0, 1.0, 1.0, 1.0]), Some(0.0));
+        assert_eq!(trend(&[1.0, 1.0, 1.5, 1.5]), Some(0.5));
+        assert_eq!(trend(&[0.0, 1.0, 1.0, 1.0]), None);
+        let v = trend(&[2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 1.0, 1.0]).unwrap();
+        assert!(v < 0.0);
+    }
+
+    #[test]
+    fn sample() {
+        let stats = HttpSoakStats::new(Duration::from_secs(1));
+        stats.record(Duration::from_millis(10));
+        stats.record(Duration::from_millis(30));
+        stats.sample();
+        stats.sample();
+
+        let v = stats.summary_json();
+        let samples = v["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0]["passed"], 2);
+        assert_eq!(samples[0]["time_avg_ns"], 20_000_000);
+        assert_eq!(samples[0]["histogram_lag"], 2);
+        assert_eq!(samples[1]["passed"], 0);
+        #[cfg(target_os = "linux")]
+        assert!(samples[1]["rss"].as_u64().unwrap() > 0);
+    }
+}
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+pub(crate) mod fault_inject;
 pub(crate) mod http;
 pub(crate) mod openssl;
 pub(crate) mod proxy_protocol;
@@ -23,7 +23,7 @@ use anyhow::{anyhow, Context};
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
 use openssl::pkey::{PKey, Private};
 use openssl::ssl::SslVerifyMode;
-use openssl::x509::X509;
+use openssl::x509::{X509VerifyResult, X509};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use g3_openssl::{SslConnector, SslStream};
@@ -33,7 +33,7 @@ use g3_types::net::{
 };
 
 const TLS_ARG_CA_CERT: &str = "tls-ca-cert";
-const TLS_ARG_CERT: &str = "tls-cert";
+pub(crate) const TLS_ARG_CERT: &str = "tls-cert";
 const TLS_ARG_KEY: &str = "tls-key";
 const TLS_ARG_NAME: &str = "tls-name";
 const TLS_ARG_SESSION_CACHE: &str = "tls-session-cache";
@@ -79,7 +79,11 @@ pub(crate) struct OpensslTlsClientArgs {
     pub(crate) client: Option<OpensslClientConfig>,
     pub(crate) tls_name: Option<Host>,
     pub(crate) cert_pair: OpensslCertificatePair,
+    pub(crate) cert_file: Option<PathBuf>,
+    pub(crate) key_file: Option<PathBuf>,
     pub(crate) no_verify: bool,
+    /// do not fail the handshake on certificate verification errors, get them by `verify_error()`
+    pub(crate) report_verify_error: bool,
     pub(crate) alpn_protocol: Option<AlpnProtocol>,
 }
 
@@ -97,7 +101,8 @@ impl OpensslTlsClientArgs {
         let mut ssl = tls_client
             .build_ssl(tls_name, target.port())
             .context("failed to build ssl context")?;
-        if self.no_verify {
+        if self.no_verify || self.report_verify_error {
+            // the chain will still be verified, with the result saved in the ssl object
             ssl.set_verify(SslVerifyMode::NONE);
         }
         let tls_connector = SslConnector::new(ssl, stream)
@@ -109,6 +114,20 @@ impl OpensslTlsClientArgs {
         Ok(tls_stream)
     }
 
+    /// get the certificate verification error of the completed handshake,
+    /// which is only available if `report_verify_error` is set
+    pub(crate) fn verify_error<S>(&self, tls_stream: &SslStream<S>) -> Option<String> {
+        if !self.report_verify_error {
+            return None;
+        }
+        let result = tls_stream.ssl().verify_result();
+        if result == X509VerifyResult::OK {
+            None
+        } else {
+            Some(result.error_string().to_string())
+        }
+    }
+
     fn parse_tls_name(&mut self, args: &ArgMatches, id: &str) -> anyhow::Result<()> {
         if let Some(name) = args.get_one::<String>(id) {
             let host = Host::from_str(name).context(format!("invalid host name {name}"))?;
@@ -148,6 +167,7 @@ impl OpensslTlsClientArgs {
             self.cert_pair
                 .set_certificates(cert)
                 .context("failed to set client certificate")?;
+            self.cert_file = Some(file.clone());
         }
         if let Some(file) = args.get_one::<PathBuf>(key_id) {
             let key = load_key(file).context(format!(
@@ -157,6 +177,7 @@ impl OpensslTlsClientArgs {
             self.cert_pair
                 .set_private_key(key)
                 .context("failed to set client private key")?;
+            self.key_file = Some(file.clone());
         }
         Ok(())
     }
@@ -284,17 +305,25 @@ impl OpensslTlsClientArgs {
         }
 
         tls_config.check().context("invalid tls config")?;
-        let tls_client = if let Some(p) = self.alpn_protocol {
-            tls_config
-                .build_with_alpn_protocols(Some(vec![p]))
-                .context(format!("failed to build tls client with alpn protocol {p}"))?
-        } else {
-            tls_config.build().context("failed to build tls client")?
-        };
+        let tls_client = build_client_config(tls_config, self.alpn_protocol)?;
         self.client = Some(tls_client);
         Ok(())
     }
 
+    /// build a new tls client with the same config but another client certificate pair
+    pub(crate) fn build_client_with_cert_pair(
+        &self,
+        cert_pair: OpensslCertificatePair,
+    ) -> anyhow::Result<OpensslClientConfig> {
+        let mut tls_config = self
+            .config
+            .clone()
+            .ok_or_else(|| anyhow!("no tls config found"))?;
+        tls_config.set_cert_pair(cert_pair);
+        tls_config.check().context("invalid tls config")?;
+        build_client_config(&tls_config, self.alpn_protocol)
+    }
+
     pub(crate) fn parse_tls_args(&mut self, args: &ArgMatches) -> anyhow::Result<()> {
         if self.config.is_none() {
             return Ok(());
@@ -336,6 +365,42 @@ impl OpensslTlsClientArgs {
     }
 }
 
+fn build_client_config(
+    tls_config: &OpensslClientConfigBuilder,
+    alpn_protocol: Option<AlpnProtocol>,
+) -> anyhow::Result<OpensslClientConfig> {
+    if let Some(p) = alpn_protocol {
+        tls_config
+            .build_with_alpn_protocols(Some(vec![p]))
+            .context(format!("failed to build tls client with alpn protocol {p}"))
+    } else {
+        tls_config.build().context("failed to build tls client")
+    }
+}
+
+/// load the client certificate pair from the certificate file and the private key file
+pub(crate) fn load_cert_pair(
+    cert_file: &Path,
+    key_file: &Path,
+) -> anyhow::Result<OpensslCertificatePair> {
+    let mut cert_pair = OpensslCertificatePair::default();
+    let cert = load_certs(cert_file).context(format!(
+        "failed to load client certificate from file {}",
+        cert_file.display()
+    ))?;
+    cert_pair
+        .set_certificates(cert)
+        .context("failed to set client certificate")?;
+    let key = load_key(key_file).context(format!(
+        "failed to load client private key from file {}",
+        key_file.display()
+    ))?;
+    cert_pair
+        .set_private_key(key)
+        .context("failed to set client private key")?;
+    Ok(cert_pair)
+}
+
 pub(crate) fn load_certs(path: &Path) -> anyhow::Result<Vec<X509>> {
     const MAX_FILE_SIZE: usize = 4_000_000; // 4MB
     let mut contents = String::with_capacity(MAX_FILE_SIZE);
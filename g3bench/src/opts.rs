@@ -52,6 +52,8 @@ const GLOBAL_ARG_EMIT_METRICS: &str = "emit-metrics";
 const GLOBAL_ARG_STATSD_TARGET_UDP: &str = "statsd-target-udp";
 const GLOBAL_ARG_STATSD_TARGET_UNIX: &str = "statsd-target-unix";
 const GLOBAL_ARG_NO_PROGRESS_BAR: &str = "no-progress-bar";
+const GLOBAL_ARG_OUTPUT: &str = "output";
+const GLOBAL_ARG_OUTPUT_FILE: &str = "output-file";
 
 const GLOBAL_ARG_PEER_PICK_POLICY: &str = "peer-pick-policy";
 const GLOBAL_ARG_TCP_LIMIT_SHIFT: &str = "tcp-limit-shift";
@@ -60,6 +62,12 @@ const GLOBAL_ARG_UDP_LIMIT_SHIFT: &str = "udp-limit-shift";
 const GLOBAL_ARG_UDP_LIMIT_BYTES: &str = "udp-limit-bytes";
 const GLOBAL_ARG_UDP_LIMIT_PACKETS: &str = "udp-limit-packets";
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum OutputFormat {
+    Text,
+    Json,
+}
+
 pub struct ProcArgs {
     pub(super) concurrency: usize,
     pub(super) latency: Option<Duration>,
@@ -78,6 +86,8 @@ pub struct ProcArgs {
 
     statsd_client_config: Option<StatsdClientConfig>,
     no_progress_bar: bool,
+    pub(super) output_format: OutputFormat,
+    pub(super) output_file: Option<PathBuf>,
 
     peer_pick_policy: SelectivePickPolicy,
     pub(super) tcp_sock_speed_limit: TcpSockSpeedLimitConfig,
@@ -103,6 +113,8 @@ impl Default for ProcArgs {
             openssl_async_job_size: 0,
             statsd_client_config: None,
             no_progress_bar: false,
+            output_format: OutputFormat::Text,
+            output_file: None,
             peer_pick_policy: SelectivePickPolicy::RoundRobin,
             tcp_sock_speed_limit: TcpSockSpeedLimitConfig::default(),
             udp_sock_speed_limit: UdpSockSpeedLimitConfig::default(),
@@ -112,6 +124,9 @@ impl Default for ProcArgs {
 
 impl ProcArgs {
     pub fn summary(&self) {
+        if self.output_format != OutputFormat::Text {
+            return;
+        }
         println!("Concurrency Level: {}", self.concurrency);
         println!();
     }
@@ -376,6 +391,25 @@ pub fn add_global_args(app: Command) -> Command {
             .long(GLOBAL_ARG_NO_PROGRESS_BAR)
             .global(true),
     )
+    .arg(
+        Arg::new(GLOBAL_ARG_OUTPUT)
+            .help("Set the output format of the final report")
+            .long(GLOBAL_ARG_OUTPUT)
+            .global(true)
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .num_args(1),
+    )
+    .arg(
+        Arg::new(GLOBAL_ARG_OUTPUT_FILE)
+            .help("Write the json report to this file instead of stdout")
+            .value_name("FILE PATH")
+            .long(GLOBAL_ARG_OUTPUT_FILE)
+            .global(true)
+            .num_args(1)
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf)),
+    )
     .arg(
         Arg::new(GLOBAL_ARG_PEER_PICK_POLICY)
             .help("Set the pick policy for selecting peers")
@@ -514,6 +548,15 @@ pub fn parse_global_args(args: &ArgMatches) -> anyhow::Result<ProcArgs> {
         proc_args.no_progress_bar = true;
     }
 
+    if let Some(s) = args.get_one::<String>(GLOBAL_ARG_OUTPUT) {
+        if s == "json" {
+            proc_args.output_format = OutputFormat::Json;
+        }
+    }
+    if let Some(path) = args.get_one::<PathBuf>(GLOBAL_ARG_OUTPUT_FILE) {
+        proc_args.output_file = Some(path.clone());
+    }
+
     if let Some(s) = args.get_one::<String>(GLOBAL_ARG_PEER_PICK_POLICY) {
         proc_args.peer_pick_policy = SelectivePickPolicy::from_str(s).unwrap();
     }
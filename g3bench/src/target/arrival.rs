@@ -0,0 +1,221 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use hdrhistogram::Histogram;
+use serde_json::{Map, Value};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::stats;
+
+/// The open-loop arrival process of the requests.
+///
+/// The requests arrive at the target rate regardless of the count of in-flight requests,
+/// and will wait in a queue if all the task contexts are busy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ArrivalProcess {
+    Poisson { rate: f64 },
+}
+
+impl ArrivalProcess {
+    pub(crate) fn new(name: &str, rate: f64) -> anyhow::Result<Self> {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(anyhow!("invalid arrival rate {rate}"));
+        }
+        match name.to_lowercase().as_str() {
+            "poisson" => Ok(ArrivalProcess::Poisson { rate }),
+            _ => Err(anyhow!("unsupported arrival process {name}")),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ArrivalProcess::Poisson { .. } => "poisson",
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        match self {
+            ArrivalProcess::Poisson { rate } => *rate,
+        }
+    }
+
+    /// sample the interval to the next arrival
+    fn next_interval(&self) -> Duration {
+        match self {
+            ArrivalProcess::Poisson { rate } => {
+                // the inter-arrival times of a poisson process are exponentially distributed
+                let u = 1.0 - fastrand::f64(); // in range (0, 1]
+                Duration::from_secs_f64(-u.ln() / rate)
+            }
+        }
+    }
+}
+
+/// The queue of the arrived requests, shared by all task contexts.
+pub(super) struct ArrivalQueue {
+    receiver: Mutex<mpsc::UnboundedReceiver<(usize, Instant)>>,
+}
+
+impl ArrivalQueue {
+    /// get the next arrived request and its arrival time
+    pub(super) async fn recv(&self) -> Option<(usize, Instant)> {
+        if stats::global_state().is_force_quit() {
+            return None;
+        }
+        self.receiver.lock().await.recv().await
+    }
+}
+
+pub(super) struct ArrivalDispatcher {
+    process: ArrivalProcess,
+    sender: mpsc::UnboundedSender<(usize, Instant)>,
+}
+
+impl ArrivalDispatcher {
+    pub(super) fn new(process: ArrivalProcess) -> (Self, ArrivalQueue) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let dispatcher = ArrivalDispatcher { process, sender };
+        let queue = ArrivalQueue {
+            receiver: Mutex::new(receiver),
+        };
+        (dispatcher, queue)
+    }
+
+    /// dispatch the requests until no more request is left,
+    /// the count of dispatched requests and the time spent will be returned
+    pub(super) fn spawn(self) -> JoinHandle<(usize, Duration)> {
+        tokio::spawn(async move {
+            let global_state = stats::global_state();
+            let time_start = Instant::now();
+            let mut next_arrival = time_start;
+            let mut dispatched = 0usize;
+            loop {
+                next_arrival += self.process.next_interval();
+                // no sleep if we are behind the schedule, so the target rate is kept
+                tokio::time::sleep_until(next_arrival).await;
+                let Some(task_id) = global_state.fetch_request() else {
+                    break;
+                };
+                if self.sender.send((task_id, next_arrival)).is_err() {
+                    break;
+                }
+                dispatched += 1;
+            }
+            (dispatched, time_start.elapsed())
+        })
+    }
+}
+
+pub(super) struct ArrivalReport {
+    process: ArrivalProcess,
+    dispatched: usize,
+    time_spent: Duration,
+    queue_delay: Histogram<u64>,
+}
+
+impl ArrivalReport {
+    pub(super) fn new(
+        process: ArrivalProcess,
+        dispatched: usize,
+        time_spent: Duration,
+        queue_delay: Histogram<u64>,
+    ) -> Self {
+        ArrivalReport {
+            process,
+            dispatched,
+            time_spent,
+            queue_delay,
+        }
+    }
+
+    fn achieved_rate(&self) -> f64 {
+        let secs = self.time_spent.as_secs_f64();
+        if secs > 0.0 {
+            self.dispatched as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub(super) fn summary(&self) {
+        println!("Arrival process:      {}", self.process.name());
+        println!(
+            "Arrival rate:         {:.3} [#/sec] (target), {:.3} [#/sec] (achieved)",
+            self.process.rate(),
+            self.achieved_rate()
+        );
+        if self.queue_delay.is_empty() {
+            return;
+        }
+        println!("Queueing delay:");
+        println!("  min   {:?}", Duration::from_nanos(self.queue_delay.min()));
+        println!(
+            "  mean  {:?}",
+            Duration::from_nanos(self.queue_delay.mean() as u64)
+        );
+        println!(
+            "  pct99 {:?}",
+            Duration::from_nanos(self.queue_delay.value_at_quantile(0.99))
+        );
+        println!("  max   {:?}", Duration::from_nanos(self.queue_delay.max()));
+    }
+
+    pub(super) fn summary_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("process".to_string(), self.process.name().into());
+        map.insert("target_rate".to_string(), self.process.rate().into());
+        map.insert("achieved_rate".to_string(), self.achieved_rate().into());
+        map.insert("dispatched".to_string(), self.dispatched.into());
+
+        let mut delay = Map::new();
+        delay.insert("min".to_string(), self.queue_delay.min().into());
+        delay.insert("mean".to_string(), self.queue_delay.mean().into());
+        delay.insert(
+            "pct99".to_string(),
+            self.queue_delay.value_at_quantile(0.99).into(),
+        );
+        delay.insert("max".to_string(), self.queue_delay.max().into());
+        map.insert("queue_delay".to_string(), Value::Object(delay));
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        let process = ArrivalProcess::new("Poisson", 100.0).unwrap();
+        assert_eq!(process, ArrivalProcess::Poisson { rate: 100.0 });
+        assert!(ArrivalProcess::new("poisson", 0.0).is_err());
+        assert!(ArrivalProcess::new("poisson", f64::INFINITY).is_err());
+        assert!(ArrivalProcess::new("uniform", 10.0).is_err());
+    }
+
+    #[test]
+    fn poisson_mean_interval() {
+        let process = ArrivalProcess::Poisson { rate: 1000.0 };
+        let total: Duration = (0..10000).map(|_| process.next_interval()).sum();
+        let mean = total.as_secs_f64() / 10000.0;
+        assert!((mean - 0.001).abs() < 0.0001);
+    }
+}
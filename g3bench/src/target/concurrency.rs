@@ -0,0 +1,200 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+
+/// A runtime change to the count of the running task contexts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ConcurrencyChange {
+    Increase(usize),
+    Decrease(usize),
+}
+
+/// Shared between the main loop and the task contexts, to retire task contexts gracefully.
+///
+/// A task context will only check for retirement before fetching the next request,
+/// so the in-flight request will always be finished.
+#[derive(Default)]
+pub(super) struct ConcurrencyControl {
+    pending_retire: AtomicUsize,
+}
+
+impl ConcurrencyControl {
+    pub(super) fn retire(&self, count: usize) {
+        self.pending_retire.fetch_add(count, Ordering::AcqRel);
+    }
+
+    /// cancel the pending retirement, and return the count that has been cancelled
+    pub(super) fn cancel_retire(&self, count: usize) -> usize {
+        let mut cur = self.pending_retire.load(Ordering::Acquire);
+        loop {
+            let cancel = cur.min(count);
+            match self.pending_retire.compare_exchange(
+                cur,
+                cur - cancel,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return cancel,
+                Err(v) => cur = v,
+            }
+        }
+    }
+
+    /// called by the task contexts, return true if the caller should quit
+    pub(super) fn try_retire(&self) -> bool {
+        let mut cur = self.pending_retire.load(Ordering::Acquire);
+        while cur > 0 {
+            match self.pending_retire.compare_exchange(
+                cur,
+                cur - 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(v) => cur = v,
+            }
+        }
+        false
+    }
+}
+
+/// Track the target concurrency and record all the changes during the run.
+pub(super) struct ConcurrencyTimeline {
+    target: usize,
+    points: Vec<(Duration, usize)>,
+}
+
+impl ConcurrencyTimeline {
+    pub(super) fn new(concurrency: usize) -> Self {
+        ConcurrencyTimeline {
+            target: concurrency,
+            points: vec![(Duration::ZERO, concurrency)],
+        }
+    }
+
+    /// apply the change and return the new target concurrency,
+    /// the concurrency will never be decreased below 1
+    pub(super) fn apply(&mut self, elapsed: Duration, change: ConcurrencyChange) -> usize {
+        let target = match change {
+            ConcurrencyChange::Increase(n) => self.target.saturating_add(n),
+            ConcurrencyChange::Decrease(n) => self.target.saturating_sub(n).max(1),
+        };
+        if target != self.target {
+            self.target = target;
+            self.points.push((elapsed, target));
+        }
+        target
+    }
+
+    pub(super) fn target(&self) -> usize {
+        self.target
+    }
+
+    pub(super) fn summary(&self) {
+        println!("Concurrency timeline:");
+        for (elapsed, concurrency) in &self.points {
+            println!("  {:>10.3}s {concurrency}", elapsed.as_secs_f64());
+        }
+    }
+
+    pub(super) fn summary_json(&self) -> Value {
+        let points = self
+            .points
+            .iter()
+            .map(|(elapsed, concurrency)| {
+                let mut map = Map::new();
+                map.insert("time".to_string(), elapsed.as_secs_f64().into());
+                map.insert("concurrency".to_string(), (*concurrency).into());
+                Value::Object(map)
+            })
+            .collect();
+        Value::Array(points)
+    }
+}
+
+/// Listen for SIGUSR1 to increase and SIGUSR2 to decrease the concurrency by `step`.
+#[cfg(unix)]
+pub(super) fn spawn_signal_listener(
+    step: usize,
+) -> anyhow::Result<mpsc::UnboundedReceiver<ConcurrencyChange>> {
+    use anyhow::anyhow;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut increase = signal(SignalKind::user_defined1())
+        .map_err(|e| anyhow!("failed to listen for SIGUSR1: {e}"))?;
+    let mut decrease = signal(SignalKind::user_defined2())
+        .map_err(|e| anyhow!("failed to listen for SIGUSR2: {e}"))?;
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let change = tokio::select! {
+                r = increase.recv() => r.map(|_| ConcurrencyChange::Increase(step)),
+                r = decrease.recv() => r.map(|_| ConcurrencyChange::Decrease(step)),
+            };
+            let Some(change) = change else {
+                break;
+            };
+            if sender.send(change).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+#[cfg(not(unix))]
+pub(super) fn spawn_signal_listener(
+    _step: usize,
+) -> anyhow::Result<mpsc::UnboundedReceiver<ConcurrencyChange>> {
+    Err(anyhow::anyhow!(
+        "runtime concurrency change is not supported on this platform"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retire() {
+        let control = ConcurrencyControl::default();
+        assert!(!control.try_retire());
+        control.retire(2);
+        assert_eq!(control.cancel_retire(1), 1);
+        assert!(control.try_retire());
+        assert!(!control.try_retire());
+        control.retire(1);
+        assert_eq!(control.cancel_retire(3), 1);
+        assert!(!control.try_retire());
+    }
+
+    #[test]
+    fn timeline() {
+        let mut timeline = ConcurrencyTimeline::new(4);
+        let t = Duration::from_secs(1);
+        assert_eq!(timeline.apply(t, ConcurrencyChange::Increase(2)), 6);
+        assert_eq!(timeline.apply(t * 2, ConcurrencyChange::Decrease(10)), 1);
+        assert_eq!(timeline.apply(t * 3, ConcurrencyChange::Decrease(1)), 1);
+        assert_eq!(timeline.target(), 1);
+        assert_eq!(timeline.points.len(), 3);
+        assert_eq!(timeline.summary_json().as_array().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use g3_io_ext::{LimitedReader, LimitedWriter};
+use g3_socket::RawSocket;
+
+use super::HttpHistogramRecorder;
+
+pub(super) type BoxHttpForwardWriter = Box<dyn AsyncWrite + Send + Unpin>;
+pub(super) type BoxHttpForwardReader = Box<dyn AsyncRead + Send + Unpin>;
+pub(super) type BoxHttpForwardConnection = (BoxHttpForwardReader, BoxHttpForwardWriter);
+
+/// Sample TCP_INFO of the underlying tcp connection when dropped
+struct TcpInfoSampler {
+    socket: RawSocket,
+    histogram_recorder: HttpHistogramRecorder,
+}
+
+impl Drop for TcpInfoSampler {
+    fn drop(&mut self) {
+        // ignore the error, as it's not supported on all platforms
+        if let Ok(info) = self.socket.tcp_info() {
+            self.histogram_recorder.record_tcp_info(&info);
+        }
+    }
+}
+
+pub(super) struct SavedHttpForwardConnection {
+    pub(super) reader: BufReader<LimitedReader<BoxHttpForwardReader>>,
+    pub(super) writer: LimitedWriter<BoxHttpForwardWriter>,
+    tcp_info_sampler: Option<TcpInfoSampler>,
+}
+
+impl SavedHttpForwardConnection {
+    pub(super) fn new(
+        reader: BufReader<LimitedReader<BoxHttpForwardReader>>,
+        writer: LimitedWriter<BoxHttpForwardWriter>,
+    ) -> Self {
+        SavedHttpForwardConnection {
+            reader,
+            writer,
+            tcp_info_sampler: None,
+        }
+    }
+
+    /// sample TCP_INFO of the socket when this connection is closed
+    pub(super) fn sample_tcp_info(
+        &mut self,
+        socket: RawSocket,
+        histogram_recorder: HttpHistogramRecorder,
+    ) {
+        self.tcp_info_sampler = Some(TcpInfoSampler {
+            socket,
+            histogram_recorder,
+        });
+    }
+}
+
+/// Time spent in each phase while setting up a new connection.
+/// The peer address is resolved only once before the bench starts, so DNS is not included.
+#[derive(Default)]
+pub(super) struct HttpConnectionSetupTimes {
+    pub(super) tcp_connect: Duration,
+    pub(super) tls_handshake: Duration,
+    pub(super) proxy_negotiation: Duration,
+    /// the certificate verification error of the target, only set if verify-cert is enabled
+    pub(super) tls_verify_error: Option<String>,
+    /// a dup of the tcp socket, only set if tcp-info is enabled
+    pub(super) tcp_socket: Option<RawSocket>,
+}
+
+/// A bounded pool of connections shared by all task contexts.
+/// At most `size` connections exist at the same time, tasks will queue if none is free.
+pub(super) struct HttpConnectionPool {
+    semaphore: Arc<Semaphore>,
+    idle: Mutex<Vec<SavedHttpForwardConnection>>,
+}
+
+impl HttpConnectionPool {
+    pub(super) fn new(size: usize) -> Self {
+        HttpConnectionPool {
+            semaphore: Arc::new(Semaphore::new(size)),
+            idle: Mutex::new(Vec::with_capacity(size)),
+        }
+    }
+
+    /// wait for a free slot, and take an idle connection if there is one.
+    /// The slot will be released when the returned permit is dropped
+    pub(super) async fn acquire(
+        &self,
+    ) -> anyhow::Result<(OwnedSemaphorePermit, Option<SavedHttpForwardConnection>)> {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| anyhow!("connection pool closed"))?;
+        let connection = self.idle.lock().unwrap().pop();
+        Ok((permit, connection))
+    }
+
+    /// put back a connection, this should be called before the permit is dropped
+    pub(super) fn release(&self, connection: SavedHttpForwardConnection) {
+        self.idle.lock().unwrap().push(connection);
+    }
+}
@@ -0,0 +1,214 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use http::{HeaderName, HeaderValue, Method};
+use serde_json::Value;
+use url::Url;
+
+/// A request recorded in the HAR file.
+///
+/// The host part of the recorded url is not used, as all requests will be sent to the target.
+pub(super) struct HarRequest {
+    pub(super) method: Method,
+    path: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Vec<u8>,
+}
+
+impl HarRequest {
+    pub(super) fn name(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+
+    #[inline]
+    pub(super) fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub(super) fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub(super) fn write_headers<W: io::Write>(&self, buf: &mut W) -> io::Result<()> {
+        for (name, value) in &self.headers {
+            buf.write_all(name.as_str().as_bytes())?;
+            buf.write_all(b": ")?;
+            buf.write_all(value.as_bytes())?;
+            buf.write_all(b"\r\n")?;
+        }
+        if !self.body.is_empty() || self.method == Method::POST || self.method == Method::PUT {
+            write!(buf, "Content-Length: {}\r\n", self.body.len())?;
+        }
+        Ok(())
+    }
+
+    fn parse_json(v: &Value) -> anyhow::Result<Self> {
+        let Value::Object(map) = v else {
+            return Err(anyhow!("the request should be a json map"));
+        };
+
+        let method = match map.get("method") {
+            Some(Value::String(s)) => {
+                Method::from_str(s).map_err(|e| anyhow!("invalid method {s}: {e}"))?
+            }
+            _ => return Err(anyhow!("no valid method found")),
+        };
+
+        let url = match map.get("url") {
+            Some(Value::String(s)) => Url::parse(s).map_err(|e| anyhow!("invalid url {s}: {e}"))?,
+            _ => return Err(anyhow!("no valid url found")),
+        };
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let mut headers = Vec::new();
+        if let Some(Value::Array(seq)) = map.get("headers") {
+            for (i, v) in seq.iter().enumerate() {
+                let (Some(Value::String(name)), Some(Value::String(value))) =
+                    (v.get("name"), v.get("value"))
+                else {
+                    return Err(anyhow!("invalid header #{i}"));
+                };
+                // http/2 pseudo headers
+                if name.starts_with(':') {
+                    continue;
+                }
+                let name = HeaderName::from_str(name)
+                    .map_err(|e| anyhow!("invalid header name {name}: {e}"))?;
+                if is_skipped_header(&name) {
+                    continue;
+                }
+                let value = HeaderValue::from_str(value)
+                    .map_err(|e| anyhow!("invalid value for header {name}: {e}"))?;
+                headers.push((name, value));
+            }
+        }
+
+        let body = match map.get("postData").and_then(|v| v.get("text")) {
+            Some(Value::String(s)) => s.as_bytes().to_vec(),
+            Some(_) => return Err(anyhow!("invalid postData text value")),
+            None => Vec::new(),
+        };
+
+        Ok(HarRequest {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+}
+
+/// the headers that will be set by us
+fn is_skipped_header(name: &HeaderName) -> bool {
+    matches!(
+        name,
+        &http::header::HOST
+            | &http::header::CONNECTION
+            | &http::header::CONTENT_LENGTH
+            | &http::header::TRANSFER_ENCODING
+            | &http::header::PROXY_AUTHORIZATION
+            | &http::header::TE
+            | &http::header::UPGRADE
+    ) || name.as_str() == "keep-alive"
+        || name.as_str() == "proxy-connection"
+}
+
+fn parse_har(v: &Value) -> anyhow::Result<Vec<HarRequest>> {
+    let Some(Value::Array(entries)) = v.get("log").and_then(|v| v.get("entries")) else {
+        return Err(anyhow!("no log.entries array found"));
+    };
+
+    let mut requests = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let request = entry
+            .get("request")
+            .ok_or_else(|| anyhow!("no request found in entry #{i}"))?;
+        let request =
+            HarRequest::parse_json(request).context(format!("invalid request in entry #{i}"))?;
+        requests.push(request);
+    }
+    if requests.is_empty() {
+        return Err(anyhow!("no entries found"));
+    }
+    Ok(requests)
+}
+
+pub(super) fn load_har_file(path: &Path) -> anyhow::Result<Vec<HarRequest>> {
+    let content =
+        std::fs::read(path).map_err(|e| anyhow!("failed to read file {}: {e}", path.display()))?;
+    let v: Value = serde_json::from_slice(&content)
+        .map_err(|e| anyhow!("invalid json content in file {}: {e}", path.display()))?;
+    parse_har(&v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let v = serde_json::json!({
+            "log": {
+                "version": "1.2",
+                "entries": [
+                    {
+                        "request": {
+                            "method": "GET",
+                            "url": "https://example.com/index.html?a=1",
+                            "headers": [
+                                {"name": ":authority", "value": "example.com"},
+                                {"name": "Host", "value": "example.com"},
+                                {"name": "Accept", "value": "text/html"}
+                            ]
+                        }
+                    },
+                    {
+                        "request": {
+                            "method": "POST",
+                            "url": "https://example.com/api",
+                            "headers": [],
+                            "postData": {"mimeType": "application/json", "text": "{}"}
+                        }
+                    }
+                ]
+            }
+        });
+
+        let requests = parse_har(&v).unwrap();
+        assert_eq!(requests.len(), 2);
+
+        assert_eq!(requests[0].name(), "GET /index.html?a=1");
+        let mut buf = Vec::new();
+        requests[0].write_headers(&mut buf).unwrap();
+        assert_eq!(buf, b"accept: text/html\r\n");
+
+        assert_eq!(requests[1].method, Method::POST);
+        assert_eq!(requests[1].body(), b"{}");
+        let mut buf = Vec::new();
+        requests[1].write_headers(&mut buf).unwrap();
+        assert_eq!(buf, b"Content-Length: 2\r\n");
+    }
+}
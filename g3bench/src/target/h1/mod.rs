@@ -0,0 +1,161 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+
+use clap::{ArgMatches, Command};
+use tokio::time::Instant;
+
+use super::{BenchTarget, BenchTaskContext, ProcArgs};
+use crate::module::http::{
+    HttpHistogram, HttpHistogramRecorder, HttpLiveQuantiles, HttpRuntimeStats,
+};
+use crate::target::ArrivalProcess;
+
+mod connection;
+use connection::{
+    BoxHttpForwardConnection, BoxHttpForwardReader, BoxHttpForwardWriter, HttpConnectionPool,
+    HttpConnectionSetupTimes, SavedHttpForwardConnection,
+};
+
+mod har;
+use har::HarRequest;
+
+mod opts;
+use opts::BenchHttpArgs;
+
+mod slo;
+use slo::HttpSlo;
+
+mod task;
+use task::HttpTaskContext;
+
+pub const COMMAND: &str = "h1";
+
+struct HttpTarget {
+    args: Arc<BenchHttpArgs>,
+    proc_args: Arc<ProcArgs>,
+    stats: Arc<HttpRuntimeStats>,
+    histogram: Option<HttpHistogram>,
+    histogram_recorder: HttpHistogramRecorder,
+    pool: Option<Arc<HttpConnectionPool>>,
+}
+
+impl BenchTarget<HttpRuntimeStats, HttpHistogram, HttpTaskContext> for HttpTarget {
+    fn new_context(&self) -> anyhow::Result<HttpTaskContext> {
+        HttpTaskContext::new(
+            &self.args,
+            &self.proc_args,
+            &self.stats,
+            self.histogram_recorder.clone(),
+            self.pool.clone(),
+        )
+    }
+
+    fn fetch_runtime_stats(&self) -> Arc<HttpRuntimeStats> {
+        self.stats.clone()
+    }
+
+    fn take_histogram(&mut self) -> Option<HttpHistogram> {
+        self.histogram.take()
+    }
+
+    fn check_result(
+        &self,
+        histogram: Option<&HttpHistogram>,
+        passed: usize,
+        failed: usize,
+    ) -> anyhow::Result<()> {
+        let p99 = histogram.and_then(|h| h.total_time_at_quantile(0.99));
+        self.args.slo.check(p99, passed, failed)
+    }
+
+    fn arrival(&self) -> Option<ArrivalProcess> {
+        self.args.arrival
+    }
+
+    fn concurrency_step(&self) -> Option<usize> {
+        self.args.concurrency_step
+    }
+}
+
+pub fn command() -> Command {
+    opts::add_http_args(Command::new(COMMAND))
+}
+
+pub async fn run(proc_args: &Arc<ProcArgs>, cmd_args: &ArgMatches) -> anyhow::Result<()> {
+    let mut http_args = opts::parse_http_args(cmd_args)?;
+    let resolve_started = Instant::now();
+    http_args.resolve_target_address(proc_args).await?;
+    let resolve_time = resolve_started.elapsed();
+
+    let mut stats = HttpRuntimeStats::new_tcp(COMMAND)
+        .with_entries(
+            http_args
+                .har_requests
+                .iter()
+                .map(|req| req.name())
+                .collect(),
+        )
+        .with_hosts(vec![http_args.target_host()]);
+    if http_args.probe_keepalive_step.is_some() {
+        stats = stats.with_keepalive_probe(http_args.origin());
+    }
+    if let Some(interval) = http_args.soak_interval {
+        stats = stats.with_soak(interval);
+    }
+    if http_args.verify_cert {
+        stats = stats.with_tls_verify();
+    }
+    if http_args.fault_inject.is_enabled() {
+        stats = stats.with_fault_inject();
+    }
+    if http_args.tunnel_only {
+        stats = stats.with_tunnel();
+    }
+
+    let (mut histogram, mut histogram_recorder) = HttpHistogram::new();
+    histogram_recorder.record_dns_time(resolve_time);
+    if let Some(path) = http_args.hdr_output.take() {
+        histogram.set_hdr_output(path);
+    }
+    if let Some(counter) = stats.soak_histogram_recorded() {
+        histogram.set_total_time_recorded(counter);
+    }
+    let live_quantiles = http_args.metrics_port.map(|port| {
+        let quantiles = Arc::new(HttpLiveQuantiles::default());
+        histogram.set_live_quantiles(quantiles.clone());
+        (port, quantiles)
+    });
+    let pool = http_args
+        .pool_size
+        .map(|size| Arc::new(HttpConnectionPool::new(size)));
+    let stats = Arc::new(stats);
+    stats.spawn_soak_sampler();
+    if let Some((port, quantiles)) = live_quantiles {
+        crate::module::http::spawn_openmetrics_server(port, stats.clone(), quantiles).await?;
+    }
+    let target = HttpTarget {
+        args: Arc::new(http_args),
+        proc_args: Arc::clone(proc_args),
+        stats,
+        histogram: Some(histogram),
+        histogram_recorder,
+        pool,
+    };
+
+    super::run(target, proc_args).await
+}
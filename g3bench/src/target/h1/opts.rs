@@ -0,0 +1,962 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use http::{HeaderValue, Method, StatusCode};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use url::Url;
+
+use g3_io_ext::AggregatedIo;
+use g3_openssl::SslStream;
+use g3_socket::RawSocket;
+use g3_types::collection::{SelectiveVec, WeightedValue};
+use g3_types::net::{
+    HttpAuth, HttpProxy, OpensslClientConfig, OpensslClientConfigBuilder, Proxy, UpstreamAddr,
+};
+
+use super::{
+    ArrivalProcess, BoxHttpForwardConnection, HarRequest, HttpConnectionSetupTimes, HttpSlo,
+    ProcArgs,
+};
+use crate::module::fault_inject::{AppendFaultInjectArgs, FaultInjectArgs};
+use crate::module::openssl::{AppendOpensslArgs, OpensslTlsClientArgs};
+use crate::module::proxy_protocol::{AppendProxyProtocolArgs, ProxyProtocolArgs};
+
+const HTTP_ARG_URL: &str = "url";
+const HTTP_ARG_METHOD: &str = "method";
+const HTTP_ARG_PROXY: &str = "proxy";
+const HTTP_ARG_PROXY_TUNNEL: &str = "proxy-tunnel";
+const HTTP_ARG_LOCAL_ADDRESS: &str = "local-address";
+const HTTP_ARG_NO_KEEPALIVE: &str = "no-keepalive";
+const HTTP_ARG_OK_STATUS: &str = "ok-status";
+const HTTP_ARG_TIMEOUT: &str = "timeout";
+const HTTP_ARG_HEADER_SIZE: &str = "header-size";
+const HTTP_ARG_CONNECT_TIMEOUT: &str = "connect-timeout";
+const HTTP_ARG_HAR: &str = "har";
+const HTTP_ARG_KEEPALIVE_HEADER: &str = "keepalive-header";
+const HTTP_ARG_PROBE_KEEPALIVE: &str = "probe-keepalive";
+const HTTP_ARG_PROBE_KEEPALIVE_MAX: &str = "probe-keepalive-max";
+const HTTP_ARG_HDR_OUTPUT: &str = "hdr-output";
+const HTTP_ARG_POOL_SIZE: &str = "pool-size";
+const HTTP_ARG_SOAK_INTERVAL: &str = "soak-interval";
+const HTTP_ARG_VERIFY_CERT: &str = "verify-cert";
+const HTTP_ARG_SLO_P99: &str = "slo-p99";
+const HTTP_ARG_SLO_ERROR_RATE: &str = "slo-error-rate";
+const HTTP_ARG_ARRIVAL: &str = "arrival";
+const HTTP_ARG_RATE: &str = "rate";
+const HTTP_ARG_METRICS_PORT: &str = "metrics-port";
+const HTTP_ARG_TCP_INFO: &str = "tcp-info";
+const HTTP_ARG_CONCURRENCY_STEP: &str = "concurrency-step";
+const HTTP_ARG_TUNNEL_ONLY: &str = "tunnel-only";
+const HTTP_ARG_TUNNEL_PAYLOAD: &str = "tunnel-payload";
+
+pub(super) struct BenchHttpArgs {
+    pub(super) method: Method,
+    target_url: Url,
+    forward_proxy: Option<HttpProxy>,
+    connect_proxy: Option<Proxy>,
+    bind: Option<IpAddr>,
+    pub(super) no_keepalive: bool,
+    pub(super) ok_status: Option<StatusCode>,
+    pub(super) timeout: Duration,
+    pub(super) max_header_size: usize,
+    pub(super) connect_timeout: Duration,
+    pub(super) har_requests: Vec<HarRequest>,
+    keepalive_header: Option<HeaderValue>,
+    pub(super) probe_keepalive_step: Option<Duration>,
+    pub(super) probe_keepalive_max: Duration,
+    pub(super) hdr_output: Option<PathBuf>,
+    pub(super) pool_size: Option<usize>,
+    pub(super) soak_interval: Option<Duration>,
+    pub(super) verify_cert: bool,
+    pub(super) slo: HttpSlo,
+    pub(super) arrival: Option<ArrivalProcess>,
+    pub(super) metrics_port: Option<u16>,
+    tcp_info: bool,
+    pub(super) concurrency_step: Option<usize>,
+    pub(super) tunnel_only: bool,
+    pub(super) tunnel_payload: usize,
+    pub(super) fault_inject: FaultInjectArgs,
+
+    target_tls: OpensslTlsClientArgs,
+    proxy_tls: OpensslTlsClientArgs,
+    proxy_protocol: ProxyProtocolArgs,
+
+    target: UpstreamAddr,
+    auth: HttpAuth,
+    peer_addrs: Option<SelectiveVec<WeightedValue<SocketAddr>>>,
+}
+
+impl BenchHttpArgs {
+    fn new(url: Url) -> anyhow::Result<Self> {
+        let upstream = UpstreamAddr::try_from(&url)?;
+        let auth = HttpAuth::try_from(&url)
+            .map_err(|e| anyhow!("failed to detect upstream auth method: {e}"))?;
+
+        let mut target_tls = OpensslTlsClientArgs::default();
+        if url.scheme() == "https" {
+            target_tls.config = Some(OpensslClientConfigBuilder::with_cache_for_one_site());
+        }
+
+        Ok(BenchHttpArgs {
+            method: Method::GET,
+            target_url: url,
+            forward_proxy: None,
+            connect_proxy: None,
+            bind: None,
+            no_keepalive: false,
+            ok_status: None,
+            timeout: Duration::from_secs(30),
+            max_header_size: 4096,
+            connect_timeout: Duration::from_secs(15),
+            har_requests: Vec::new(),
+            keepalive_header: None,
+            probe_keepalive_step: None,
+            probe_keepalive_max: Duration::from_secs(300),
+            hdr_output: None,
+            pool_size: None,
+            soak_interval: None,
+            verify_cert: false,
+            slo: HttpSlo::default(),
+            arrival: None,
+            metrics_port: None,
+            tcp_info: false,
+            concurrency_step: None,
+            tunnel_only: false,
+            tunnel_payload: 0,
+            fault_inject: FaultInjectArgs::default(),
+            target_tls,
+            proxy_tls: OpensslTlsClientArgs::default(),
+            proxy_protocol: ProxyProtocolArgs::default(),
+            target: upstream,
+            auth,
+            peer_addrs: None,
+        })
+    }
+
+    pub(super) fn origin(&self) -> String {
+        format!("{}://{}", self.target_url.scheme(), self.target)
+    }
+
+    /// the host of the target, which is used as the key of per host stats
+    pub(super) fn target_host(&self) -> String {
+        self.target.to_string()
+    }
+
+    pub(super) fn use_tunnel_proxy(&self) -> bool {
+        self.connect_proxy.is_some()
+    }
+
+    pub(super) async fn resolve_target_address(
+        &mut self,
+        proc_args: &ProcArgs,
+    ) -> anyhow::Result<()> {
+        let host = if let Some(proxy) = &self.connect_proxy {
+            proxy.peer()
+        } else if let Some(proxy) = &self.forward_proxy {
+            proxy.peer()
+        } else {
+            &self.target
+        };
+        let addrs = proc_args.resolve(host).await?;
+        self.peer_addrs = Some(addrs);
+        Ok(())
+    }
+
+    async fn new_tcp_connection(
+        &self,
+        proc_args: &ProcArgs,
+        times: &mut HttpConnectionSetupTimes,
+    ) -> anyhow::Result<TcpStream> {
+        let addrs = self
+            .peer_addrs
+            .as_ref()
+            .ok_or_else(|| anyhow!("no peer address set"))?;
+        let peer = *proc_args.select_peer(addrs);
+
+        let socket = g3_socket::tcp::new_socket_to(
+            peer.ip(),
+            self.bind,
+            &Default::default(),
+            &Default::default(),
+            !self.no_keepalive,
+        )
+        .map_err(|e| anyhow!("failed to setup socket to {peer}: {e:?}"))?;
+        let connect_started = Instant::now();
+        let mut stream = socket
+            .connect(peer)
+            .await
+            .map_err(|e| anyhow!("connect to {peer} error: {e:?}"))?;
+        times.tcp_connect = connect_started.elapsed();
+        if self.tcp_info {
+            // keep a dup of the socket, so TCP_INFO can still be read after the stream is split
+            times.tcp_socket = RawSocket::from(&stream).try_dup().ok();
+        }
+
+        if let Some(data) = self.proxy_protocol.data() {
+            stream
+                .write_all(data)
+                .await
+                .map_err(|e| anyhow!("failed to send proxy protocol data: {e:?}"))?;
+        }
+
+        Ok(stream)
+    }
+
+    pub(super) async fn new_http_connection(
+        &self,
+        proc_args: &ProcArgs,
+        times: &mut HttpConnectionSetupTimes,
+    ) -> anyhow::Result<BoxHttpForwardConnection> {
+        if let Some(proxy) = &self.connect_proxy {
+            match proxy {
+                Proxy::Http(http_proxy) => {
+                    let stream =
+                        self.new_tcp_connection(proc_args, times)
+                            .await
+                            .context(format!(
+                                "failed to connect to http proxy {}",
+                                http_proxy.peer()
+                            ))?;
+
+                    if let Some(tls_config) = &self.proxy_tls.client {
+                        let tls_stream = self
+                            .tls_connect_to_proxy(tls_config, http_proxy.peer(), stream, times)
+                            .await?;
+
+                        let (r, mut w) = tokio::io::split(tls_stream);
+                        let mut buf_r = BufReader::new(r);
+
+                        let negotiation_started = Instant::now();
+                        g3_http::connect::client::http_connect_to(
+                            &mut buf_r,
+                            &mut w,
+                            &http_proxy.auth,
+                            &self.target,
+                        )
+                        .await
+                        .map_err(|e| {
+                            anyhow!("http connect to {} failed: {e}", http_proxy.peer())
+                        })?;
+                        times.proxy_negotiation = negotiation_started.elapsed();
+
+                        if let Some(tls_client) = &self.target_tls.client {
+                            self.tls_connect_to_peer(
+                                tls_client,
+                                AggregatedIo::new(buf_r.into_inner(), w),
+                                times,
+                            )
+                            .await
+                        } else {
+                            Ok((Box::new(buf_r.into_inner()), Box::new(w)))
+                        }
+                    } else {
+                        let (r, mut w) = stream.into_split();
+                        let mut buf_r = BufReader::new(r);
+
+                        let negotiation_started = Instant::now();
+                        g3_http::connect::client::http_connect_to(
+                            &mut buf_r,
+                            &mut w,
+                            &http_proxy.auth,
+                            &self.target,
+                        )
+                        .await
+                        .map_err(|e| {
+                            anyhow!("http connect to {} failed: {e}", http_proxy.peer())
+                        })?;
+                        times.proxy_negotiation = negotiation_started.elapsed();
+
+                        if let Some(tls_client) = &self.target_tls.client {
+                            self.tls_connect_to_peer(
+                                tls_client,
+                                AggregatedIo::new(buf_r.into_inner(), w),
+                                times,
+                            )
+                            .await
+                        } else {
+                            Ok((Box::new(buf_r.into_inner()), Box::new(w)))
+                        }
+                    }
+                }
+                Proxy::Socks4(socks4_proxy) => {
+                    let stream =
+                        self.new_tcp_connection(proc_args, times)
+                            .await
+                            .context(format!(
+                                "failed to connect to socks4 proxy {}",
+                                socks4_proxy.peer()
+                            ))?;
+                    let (mut r, mut w) = stream.into_split();
+
+                    let negotiation_started = Instant::now();
+                    g3_socks::v4a::client::socks4a_connect_to(&mut r, &mut w, &self.target)
+                        .await
+                        .map_err(|e| {
+                            anyhow!("socks4a connect to {} failed: {e}", socks4_proxy.peer())
+                        })?;
+                    times.proxy_negotiation = negotiation_started.elapsed();
+
+                    if let Some(tls_client) = &self.target_tls.client {
+                        self.tls_connect_to_peer(tls_client, AggregatedIo::new(r, w), times)
+                            .await
+                    } else {
+                        Ok((Box::new(r), Box::new(w)))
+                    }
+                }
+                Proxy::Socks5(socks5_proxy) => {
+                    let stream =
+                        self.new_tcp_connection(proc_args, times)
+                            .await
+                            .context(format!(
+                                "failed to connect to socks5 proxy {}",
+                                socks5_proxy.peer()
+                            ))?;
+                    let (mut r, mut w) = stream.into_split();
+
+                    let negotiation_started = Instant::now();
+                    g3_socks::v5::client::socks5_connect_to(
+                        &mut r,
+                        &mut w,
+                        &socks5_proxy.auth,
+                        &self.target,
+                    )
+                    .await
+                    .map_err(|e| {
+                        anyhow!("socks5 connect to {} failed: {e}", socks5_proxy.peer())
+                    })?;
+                    times.proxy_negotiation = negotiation_started.elapsed();
+
+                    if let Some(tls_client) = &self.target_tls.client {
+                        self.tls_connect_to_peer(tls_client, AggregatedIo::new(r, w), times)
+                            .await
+                    } else {
+                        Ok((Box::new(r), Box::new(w)))
+                    }
+                }
+            }
+        } else if let Some(proxy) = &self.forward_proxy {
+            let stream = self
+                .new_tcp_connection(proc_args, times)
+                .await
+                .context(format!("failed to connect to http proxy {}", proxy.peer()))?;
+
+            if let Some(tls_client) = &self.proxy_tls.client {
+                let tls_stream = self
+                    .tls_connect_to_proxy(tls_client, proxy.peer(), stream, times)
+                    .await?;
+
+                let (r, w) = tokio::io::split(tls_stream);
+                Ok((Box::new(r), Box::new(w)))
+            } else {
+                let (r, w) = stream.into_split();
+                Ok((Box::new(r), Box::new(w)))
+            }
+        } else {
+            let stream = self
+                .new_tcp_connection(proc_args, times)
+                .await
+                .context(format!("failed to connect to target host {}", self.target))?;
+
+            if let Some(tls_client) = &self.target_tls.client {
+                self.tls_connect_to_peer(tls_client, stream, times).await
+            } else {
+                let (r, w) = stream.into_split();
+                Ok((Box::new(r), Box::new(w)))
+            }
+        }
+    }
+
+    async fn tls_connect_to_peer<S>(
+        &self,
+        tls_client: &OpensslClientConfig,
+        stream: S,
+        times: &mut HttpConnectionSetupTimes,
+    ) -> anyhow::Result<BoxHttpForwardConnection>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let handshake_started = Instant::now();
+        let tls_stream = self
+            .target_tls
+            .connect_target(tls_client, stream, &self.target)
+            .await?;
+        times.tls_handshake += handshake_started.elapsed();
+        times.tls_verify_error = self.target_tls.verify_error(&tls_stream);
+        let (r, w) = tokio::io::split(tls_stream);
+        Ok((Box::new(r), Box::new(w)))
+    }
+
+    async fn tls_connect_to_proxy(
+        &self,
+        tls_client: &OpensslClientConfig,
+        peer: &UpstreamAddr,
+        stream: TcpStream,
+        times: &mut HttpConnectionSetupTimes,
+    ) -> anyhow::Result<SslStream<TcpStream>> {
+        let handshake_started = Instant::now();
+        let tls_stream = self
+            .proxy_tls
+            .connect_target(tls_client, stream, peer)
+            .await?;
+        times.tls_handshake += handshake_started.elapsed();
+        Ok(tls_stream)
+    }
+
+    fn write_request_line<W: io::Write>(&self, buf: &mut W) -> io::Result<()> {
+        write!(buf, "{} ", self.method)?;
+        if self.forward_proxy.is_some() {
+            write!(buf, "{}://{}", self.target_url.scheme(), self.target)?;
+        }
+        buf.write_all(self.target_url.path().as_bytes())?;
+        if let Some(s) = self.target_url.query() {
+            write!(buf, "?{s}")?;
+        }
+        buf.write_all(b" HTTP/1.1\r\n")?; // TODO allow to use http1.0 ?
+
+        Ok(())
+    }
+
+    pub(super) fn write_fixed_request_header<W: io::Write>(&self, buf: &mut W) -> io::Result<()> {
+        self.write_request_line(buf)?;
+        self.write_common_headers(buf)
+    }
+
+    pub(super) fn write_har_request_header<W: io::Write>(
+        &self,
+        req: &HarRequest,
+        buf: &mut W,
+    ) -> io::Result<()> {
+        write!(buf, "{} ", req.method)?;
+        if self.forward_proxy.is_some() {
+            write!(buf, "{}://{}", self.target_url.scheme(), self.target)?;
+        }
+        buf.write_all(req.path().as_bytes())?;
+        buf.write_all(b" HTTP/1.1\r\n")?;
+
+        self.write_common_headers(buf)?;
+        req.write_headers(buf)
+    }
+
+    fn write_common_headers<W: io::Write>(&self, buf: &mut W) -> io::Result<()> {
+        write!(buf, "Host: {}\r\n", self.target)?;
+
+        if let Some(p) = &self.forward_proxy {
+            match &p.auth {
+                HttpAuth::None => {}
+                HttpAuth::Basic(basic) => {
+                    buf.write_all(b"Proxy-Authorization: Basic ")?;
+                    buf.write_all(basic.encoded_value().as_bytes())?;
+                    buf.write_all(b"\r\n")?;
+                }
+            }
+        }
+
+        match &self.auth {
+            HttpAuth::None => {}
+            HttpAuth::Basic(basic) => {
+                buf.write_all(b"Authorization: Basic ")?;
+                buf.write_all(basic.encoded_value().as_bytes())?;
+                buf.write_all(b"\r\n")?;
+            }
+        }
+
+        if self.no_keepalive {
+            buf.write_all(b"Connection: close\r\n")?;
+        } else {
+            buf.write_all(b"Connection: keep-alive\r\n")?;
+            if let Some(v) = &self.keepalive_header {
+                buf.write_all(b"Keep-Alive: ")?;
+                buf.write_all(v.as_bytes())?;
+                buf.write_all(b"\r\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn add_http_args(app: Command) -> Command {
+    app.arg(Arg::new(HTTP_ARG_URL).required(true).num_args(1))
+        .arg(
+            Arg::new(HTTP_ARG_METHOD)
+                .value_name("METHOD")
+                .short('m')
+                .long(HTTP_ARG_METHOD)
+                .num_args(1)
+                .value_parser(["GET", "HEAD"])
+                .default_value("GET"),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_PROXY)
+                .value_name("PROXY URL")
+                .short('x')
+                .help("use a proxy")
+                .long(HTTP_ARG_PROXY)
+                .num_args(1)
+                .value_name("PROXY URL"),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_PROXY_TUNNEL)
+                .short('p')
+                .long(HTTP_ARG_PROXY_TUNNEL)
+                .action(ArgAction::SetTrue)
+                .help("Use tunnel if the proxy is an HTTP proxy"),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_LOCAL_ADDRESS)
+                .value_name("LOCAL IP ADDRESS")
+                .short('B')
+                .long(HTTP_ARG_LOCAL_ADDRESS)
+                .num_args(1)
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_NO_KEEPALIVE)
+                .help("Disable http keepalive")
+                .action(ArgAction::SetTrue)
+                .long(HTTP_ARG_NO_KEEPALIVE),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_OK_STATUS)
+                .help("Only treat this status code as success")
+                .value_name("STATUS CODE")
+                .long(HTTP_ARG_OK_STATUS)
+                .num_args(1)
+                .value_parser(value_parser!(StatusCode)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_TIMEOUT)
+                .value_name("TIMEOUT DURATION")
+                .help("Http response timeout")
+                .default_value("30s")
+                .long(HTTP_ARG_TIMEOUT)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_HEADER_SIZE)
+                .value_name("SIZE")
+                .help("Set max response header size")
+                .long(HTTP_ARG_HEADER_SIZE)
+                .num_args(1)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_CONNECT_TIMEOUT)
+                .value_name("TIMEOUT DURATION")
+                .help("Timeout for connection to next peer")
+                .default_value("15s")
+                .long(HTTP_ARG_CONNECT_TIMEOUT)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_HAR)
+                .value_name("HAR FILE")
+                .help("Replay the requests recorded in the HAR file, the target host in url will be used")
+                .long(HTTP_ARG_HAR)
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_KEEPALIVE_HEADER)
+                .value_name("HEADER VALUE")
+                .help("Set the value of the Keep-Alive header, e.g. 'timeout=30'")
+                .long(HTTP_ARG_KEEPALIVE_HEADER)
+                .num_args(1)
+                .conflicts_with(HTTP_ARG_NO_KEEPALIVE),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_PROBE_KEEPALIVE)
+                .value_name("STEP DURATION")
+                .help(
+                    "Probe the keep-alive idle timeout of the origin.\n\
+                    The connection will be held idle after each request, \
+                    for a duration increased by this step each time, until closed by the server",
+                )
+                .long(HTTP_ARG_PROBE_KEEPALIVE)
+                .num_args(1)
+                .conflicts_with(HTTP_ARG_NO_KEEPALIVE),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_PROBE_KEEPALIVE_MAX)
+                .value_name("MAX DURATION")
+                .help("Set the max idle duration when probing keep-alive idle timeout [default: 5m]")
+                .long(HTTP_ARG_PROBE_KEEPALIVE_MAX)
+                .num_args(1)
+                .requires(HTTP_ARG_PROBE_KEEPALIVE),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_HDR_OUTPUT)
+                .value_name("FILE")
+                .help(
+                    "Write the total time histogram to this file \
+                    in HdrHistogram percentile distribution format, with values in milliseconds",
+                )
+                .long(HTTP_ARG_HDR_OUTPUT)
+                .num_args(1)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_POOL_SIZE)
+                .value_name("COUNT")
+                .help(
+                    "Share a pool of at most this many connections among all concurrent tasks, \
+                    tasks will wait if no connection is free",
+                )
+                .long(HTTP_ARG_POOL_SIZE)
+                .num_args(1)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_SOAK_INTERVAL)
+                .value_name("INTERVAL DURATION")
+                .help(
+                    "Enable soak mode, sample the process RSS, the average latency and the histogram \
+                    recording lag at this interval, and report the trend at the end.\n\
+                    Use it along with the global time limit option for long running tests",
+                )
+                .long(HTTP_ARG_SOAK_INTERVAL)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_VERIFY_CERT)
+                .help(
+                    "Verify the certificate chain of the target without failing the handshake, \
+                    the count of verification failures and the first error of each host will be reported",
+                )
+                .action(ArgAction::SetTrue)
+                .long(HTTP_ARG_VERIFY_CERT),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_SLO_P99)
+                .value_name("MILLISECONDS")
+                .help(
+                    "Fail the run if the p99 of the total time is larger than this value \
+                    at the end of the test",
+                )
+                .long(HTTP_ARG_SLO_P99)
+                .num_args(1)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_SLO_ERROR_RATE)
+                .value_name("PERCENT")
+                .help(
+                    "Fail the run if the percentage of failed requests is larger than this value \
+                    at the end of the test",
+                )
+                .long(HTTP_ARG_SLO_ERROR_RATE)
+                .num_args(1)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_ARRIVAL)
+                .value_name("PROCESS")
+                .help(
+                    "Run in open-loop mode, the requests will arrive by this process at the rate \
+                    set by --rate, regardless of the count of in-flight requests.\n\
+                    The arrived requests will be queued if all the task contexts are busy, \
+                    and the queueing delay will be reported separately",
+                )
+                .long(HTTP_ARG_ARRIVAL)
+                .num_args(1)
+                .value_parser(["poisson"])
+                .requires(HTTP_ARG_RATE),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_RATE)
+                .value_name("REQUESTS PER SECOND")
+                .help("Set the target arrival rate for the open-loop mode")
+                .long(HTTP_ARG_RATE)
+                .num_args(1)
+                .value_parser(value_parser!(f64))
+                .requires(HTTP_ARG_ARRIVAL),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_METRICS_PORT)
+                .value_name("PORT")
+                .help(
+                    "Serve the live stats in OpenMetrics text format at /metrics on this port \
+                    during the run, so it can be scraped by Prometheus directly",
+                )
+                .long(HTTP_ARG_METRICS_PORT)
+                .num_args(1)
+                .value_parser(value_parser!(u16)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_TCP_INFO)
+                .help(
+                    "Sample TCP_INFO when each connection is closed, and report the smoothed RTT \
+                    and the retransmission count of the connections at the end of the test.\n\
+                    The connection to the proxy will be sampled if a proxy is used. \
+                    This is only supported on Linux, and will be ignored on other platforms",
+                )
+                .long(HTTP_ARG_TCP_INFO)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_CONCURRENCY_STEP)
+                .value_name("COUNT")
+                .help(
+                    "Allow to change the concurrency during the run, send SIGUSR1 to spawn this \
+                    count of new task contexts, and SIGUSR2 to retire this count of task contexts \
+                    after their in-flight requests. The concurrency will be kept at least 1, \
+                    and the concurrency timeline will be reported at the end.\n\
+                    This is only supported on Unix",
+                )
+                .long(HTTP_ARG_CONCURRENCY_STEP)
+                .num_args(1)
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_TUNNEL_ONLY)
+                .help(
+                    "Only benchmark the tunnel setup through the proxy, each task will open a new \
+                    tunnel to the host and port in the target url, and no http request will be sent.\n\
+                    The tunnel setup time and success ratio will be reported separately. \
+                    A socks proxy or an http proxy with --proxy-tunnel is required",
+                )
+                .long(HTTP_ARG_TUNNEL_ONLY)
+                .action(ArgAction::SetTrue)
+                .requires(HTTP_ARG_PROXY)
+                .conflicts_with_all([
+                    HTTP_ARG_HAR,
+                    HTTP_ARG_POOL_SIZE,
+                    HTTP_ARG_PROBE_KEEPALIVE,
+                    HTTP_ARG_VERIFY_CERT,
+                ]),
+        )
+        .arg(
+            Arg::new(HTTP_ARG_TUNNEL_PAYLOAD)
+                .value_name("SIZE")
+                .help(
+                    "Send this size of payload through each new tunnel, \
+                    and wait for it to be echoed back by the target",
+                )
+                .long(HTTP_ARG_TUNNEL_PAYLOAD)
+                .num_args(1)
+                .requires(HTTP_ARG_TUNNEL_ONLY),
+        )
+        .append_openssl_args()
+        .append_proxy_openssl_args()
+        .append_proxy_protocol_args()
+        .append_fault_inject_args()
+}
+
+pub(super) fn parse_http_args(args: &ArgMatches) -> anyhow::Result<BenchHttpArgs> {
+    let url = if let Some(v) = args.get_one::<String>(HTTP_ARG_URL) {
+        Url::parse(v).context(format!("invalid {HTTP_ARG_URL} value"))?
+    } else {
+        return Err(anyhow!("no target url set"));
+    };
+
+    let mut h1_args = BenchHttpArgs::new(url)?;
+
+    if let Some(v) = args.get_one::<String>(HTTP_ARG_METHOD) {
+        let method = Method::from_str(v).context(format!("invalid {HTTP_ARG_METHOD} value"))?;
+        h1_args.method = method;
+    }
+
+    if let Some(v) = args.get_one::<String>(HTTP_ARG_PROXY) {
+        let url = Url::parse(v).context(format!("invalid {HTTP_ARG_PROXY} value"))?;
+        let proxy = Proxy::try_from(&url).map_err(|e| anyhow!("invalid proxy: {e}"))?;
+        if let Proxy::Http(mut http_proxy) = proxy {
+            h1_args.proxy_tls.config = http_proxy.tls_config.take();
+            if args.get_flag(HTTP_ARG_PROXY_TUNNEL) {
+                h1_args.connect_proxy = Some(Proxy::Http(http_proxy));
+            } else {
+                h1_args.forward_proxy = Some(http_proxy);
+            }
+        } else {
+            h1_args.connect_proxy = Some(proxy);
+        }
+    }
+
+    if let Some(ip) = args.get_one::<IpAddr>(HTTP_ARG_LOCAL_ADDRESS) {
+        h1_args.bind = Some(*ip);
+    }
+
+    if args.get_flag(HTTP_ARG_NO_KEEPALIVE) {
+        h1_args.no_keepalive = true;
+    }
+
+    if let Some(code) = args.get_one::<StatusCode>(HTTP_ARG_OK_STATUS) {
+        h1_args.ok_status = Some(*code);
+    }
+
+    if let Some(timeout) = g3_clap::humanize::get_duration(args, HTTP_ARG_TIMEOUT)? {
+        h1_args.timeout = timeout;
+    }
+    if let Some(header_size) = g3_clap::humanize::get_usize(args, HTTP_ARG_HEADER_SIZE)? {
+        h1_args.max_header_size = header_size;
+    }
+
+    if let Some(timeout) = g3_clap::humanize::get_duration(args, HTTP_ARG_CONNECT_TIMEOUT)? {
+        h1_args.connect_timeout = timeout;
+    }
+
+    if let Some(path) = args.get_one::<PathBuf>(HTTP_ARG_HAR) {
+        h1_args.har_requests =
+            super::har::load_har_file(path).context(format!("invalid {HTTP_ARG_HAR} value"))?;
+    }
+
+    if let Some(v) = args.get_one::<String>(HTTP_ARG_KEEPALIVE_HEADER) {
+        let value = HeaderValue::from_str(v)
+            .map_err(|e| anyhow!("invalid {HTTP_ARG_KEEPALIVE_HEADER} value: {e}"))?;
+        h1_args.keepalive_header = Some(value);
+    }
+
+    if let Some(step) = g3_clap::humanize::get_duration(args, HTTP_ARG_PROBE_KEEPALIVE)? {
+        if step.is_zero() {
+            return Err(anyhow!(
+                "{HTTP_ARG_PROBE_KEEPALIVE} value should not be zero"
+            ));
+        }
+        h1_args.probe_keepalive_step = Some(step);
+    }
+    if let Some(max) = g3_clap::humanize::get_duration(args, HTTP_ARG_PROBE_KEEPALIVE_MAX)? {
+        h1_args.probe_keepalive_max = max;
+    }
+
+    if let Some(path) = args.get_one::<PathBuf>(HTTP_ARG_HDR_OUTPUT) {
+        h1_args.hdr_output = Some(path.clone());
+    }
+
+    if let Some(size) = args.get_one::<usize>(HTTP_ARG_POOL_SIZE) {
+        if *size == 0 {
+            return Err(anyhow!("{HTTP_ARG_POOL_SIZE} value should not be zero"));
+        }
+        h1_args.pool_size = Some(*size);
+    }
+    if let Some(interval) = g3_clap::humanize::get_duration(args, HTTP_ARG_SOAK_INTERVAL)? {
+        if interval.is_zero() {
+            return Err(anyhow!("{HTTP_ARG_SOAK_INTERVAL} value should not be zero"));
+        }
+        h1_args.soak_interval = Some(interval);
+    }
+
+    if let Some(ms) = args.get_one::<u64>(HTTP_ARG_SLO_P99) {
+        if *ms == 0 {
+            return Err(anyhow!("{HTTP_ARG_SLO_P99} value should not be zero"));
+        }
+        h1_args.slo.p99 = Some(Duration::from_millis(*ms));
+    }
+    if let Some(rate) = args.get_one::<f64>(HTTP_ARG_SLO_ERROR_RATE) {
+        if !(0.0..=100.0).contains(rate) {
+            return Err(anyhow!(
+                "{HTTP_ARG_SLO_ERROR_RATE} value should be in range 0 to 100"
+            ));
+        }
+        h1_args.slo.error_rate = Some(*rate);
+    }
+    if let Some(process) = args.get_one::<String>(HTTP_ARG_ARRIVAL) {
+        let rate = args
+            .get_one::<f64>(HTTP_ARG_RATE)
+            .copied()
+            .unwrap_or_default();
+        let arrival = ArrivalProcess::new(process, rate).context(format!(
+            "invalid {HTTP_ARG_ARRIVAL} and {HTTP_ARG_RATE} value"
+        ))?;
+        h1_args.arrival = Some(arrival);
+    }
+    if let Some(port) = args.get_one::<u16>(HTTP_ARG_METRICS_PORT) {
+        h1_args.metrics_port = Some(*port);
+    }
+    if args.get_flag(HTTP_ARG_TCP_INFO) {
+        h1_args.tcp_info = true;
+    }
+    if let Some(step) = args.get_one::<usize>(HTTP_ARG_CONCURRENCY_STEP) {
+        if *step == 0 {
+            return Err(anyhow!(
+                "invalid {HTTP_ARG_CONCURRENCY_STEP} value: should not be 0"
+            ));
+        }
+        h1_args.concurrency_step = Some(*step);
+    }
+    if args.get_flag(HTTP_ARG_TUNNEL_ONLY) {
+        if !h1_args.use_tunnel_proxy() {
+            return Err(anyhow!(
+                "{HTTP_ARG_TUNNEL_ONLY} requires a socks proxy or an http proxy with {HTTP_ARG_PROXY_TUNNEL}"
+            ));
+        }
+        // only the host and port of the target url will be used
+        h1_args.target_tls.config = None;
+        h1_args.tunnel_only = true;
+    }
+    if let Some(size) = g3_clap::humanize::get_usize(args, HTTP_ARG_TUNNEL_PAYLOAD)? {
+        if size == 0 {
+            return Err(anyhow!(
+                "{HTTP_ARG_TUNNEL_PAYLOAD} value should not be zero"
+            ));
+        }
+        h1_args.tunnel_payload = size;
+    }
+    h1_args
+        .fault_inject
+        .parse_args(args)
+        .context("invalid fault inject args")?;
+
+    h1_args
+        .target_tls
+        .parse_tls_args(args)
+        .context("invalid target tls config")?;
+    if args.get_flag(HTTP_ARG_VERIFY_CERT) {
+        if h1_args.target_tls.client.is_none() {
+            return Err(anyhow!(
+                "{HTTP_ARG_VERIFY_CERT} requires a https target url"
+            ));
+        }
+        if h1_args.target_tls.no_verify {
+            return Err(anyhow!(
+                "{HTTP_ARG_VERIFY_CERT} conflicts with the target tls no verify option"
+            ));
+        }
+        h1_args.target_tls.report_verify_error = true;
+        h1_args.verify_cert = true;
+    }
+    h1_args
+        .proxy_tls
+        .parse_proxy_tls_args(args)
+        .context("invalid proxy tls config")?;
+    h1_args
+        .proxy_protocol
+        .parse_args(args)
+        .context("invalid proxy protocol config")?;
+
+    match h1_args.target_url.scheme() {
+        "http" | "https" => {}
+        "ftp" => {
+            if h1_args.forward_proxy.is_none() {
+                return Err(anyhow!(
+                    "forward proxy is required for target url {}",
+                    h1_args.target_url
+                ));
+            }
+        }
+        _ => return Err(anyhow!("unsupported target url {}", h1_args.target_url)),
+    }
+
+    Ok(h1_args)
+}
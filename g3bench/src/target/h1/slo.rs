@@ -0,0 +1,96 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+#[derive(Default)]
+pub(super) struct HttpSlo {
+    pub(super) p99: Option<Duration>,
+    pub(super) error_rate: Option<f64>,
+}
+
+impl HttpSlo {
+    pub(super) fn check(
+        &self,
+        p99: Option<Duration>,
+        passed: usize,
+        failed: usize,
+    ) -> anyhow::Result<()> {
+        let mut violations = Vec::new();
+
+        if let Some(max_p99) = self.p99 {
+            match p99 {
+                Some(p99) if p99 <= max_p99 => {}
+                Some(p99) => violations.push(format!("p99 latency {p99:?} > {max_p99:?}")),
+                None => violations.push(format!("p99 latency unknown > {max_p99:?}")),
+            }
+        }
+
+        if let Some(max_rate) = self.error_rate {
+            let total = passed + failed;
+            if total == 0 {
+                violations.push(format!("error rate unknown > {max_rate}%"));
+            } else {
+                let rate = failed as f64 * 100.0 / total as f64;
+                if rate > max_rate {
+                    violations.push(format!("error rate {rate:.3}% > {max_rate}%"));
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("SLO violated: {}", violations.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let slo = HttpSlo {
+            p99: Some(Duration::from_millis(100)),
+            error_rate: Some(1.0),
+        };
+        assert!(slo.check(Some(Duration::from_millis(100)), 99, 1).is_ok());
+
+        let e = slo
+            .check(Some(Duration::from_millis(101)), 99, 1)
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("p99 latency"));
+        assert!(!e.contains("error rate"));
+
+        let e = slo
+            .check(Some(Duration::from_millis(50)), 98, 2)
+            .unwrap_err()
+            .to_string();
+        assert!(!e.contains("p99 latency"));
+        assert!(e.contains("error rate 2.000%"));
+
+        let e = slo.check(None, 0, 0).unwrap_err().to_string();
+        assert!(e.contains("p99 latency unknown"));
+        assert!(e.contains("error rate unknown"));
+
+        assert!(HttpSlo::default().check(None, 0, 0).is_ok());
+    }
+}
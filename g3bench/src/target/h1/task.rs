@@ -0,0 +1,534 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use futures_util::FutureExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::Instant;
+
+use g3_http::client::HttpForwardRemoteResponse;
+use g3_http::{HttpBodyReader, HttpBodyType};
+use g3_io_ext::{LimitedReader, LimitedWriter};
+
+use super::{
+    BenchHttpArgs, BenchTaskContext, BoxHttpForwardReader, BoxHttpForwardWriter,
+    HttpConnectionPool, HttpConnectionSetupTimes, HttpHistogramRecorder, HttpRuntimeStats,
+    ProcArgs, SavedHttpForwardConnection,
+};
+use crate::module::fault_inject::{FaultInjectReader, FaultInjectWriter};
+use crate::target::BenchError;
+
+pub(super) struct HttpTaskContext {
+    args: Arc<BenchHttpArgs>,
+    proc_args: Arc<ProcArgs>,
+    saved_connection: Option<SavedHttpForwardConnection>,
+    reuse_conn_count: u64,
+    pool: Option<Arc<HttpConnectionPool>>,
+    host_index: Option<usize>,
+
+    runtime_stats: Arc<HttpRuntimeStats>,
+    histogram_recorder: HttpHistogramRecorder,
+
+    req_header: Vec<u8>,
+    req_header_fixed_len: usize,
+    har_index: usize,
+    probe_idle: Duration,
+    tunnel_payload: Vec<u8>,
+}
+
+impl HttpTaskContext {
+    pub(super) fn new(
+        args: &Arc<BenchHttpArgs>,
+        proc_args: &Arc<ProcArgs>,
+        runtime_stats: &Arc<HttpRuntimeStats>,
+        histogram_recorder: HttpHistogramRecorder,
+        pool: Option<Arc<HttpConnectionPool>>,
+    ) -> anyhow::Result<Self> {
+        let mut hdr_buf = Vec::with_capacity(1024);
+        args.write_fixed_request_header(&mut hdr_buf)
+            .map_err(|e| anyhow!("failed to generate request header: {}", e))?;
+
+        let req_header_fixed_len = hdr_buf.len();
+
+        Ok(HttpTaskContext {
+            args: Arc::clone(args),
+            proc_args: Arc::clone(proc_args),
+            saved_connection: None,
+            reuse_conn_count: 0,
+            pool,
+            host_index: runtime_stats.host_index(&args.target_host()),
+            runtime_stats: Arc::clone(runtime_stats),
+            histogram_recorder,
+            req_header: hdr_buf,
+            req_header_fixed_len,
+            har_index: 0,
+            probe_idle: Duration::ZERO,
+            tunnel_payload: (0..args.tunnel_payload).map(|i| i as u8).collect(),
+        })
+    }
+
+    async fn fetch_connection(&mut self) -> anyhow::Result<SavedHttpForwardConnection> {
+        if let Some(mut c) = self.saved_connection.take() {
+            let mut buf = [0u8; 4];
+            if c.reader.read(&mut buf).now_or_never().is_none() {
+                // no eof, reuse the old connection
+                self.reuse_conn_count += 1;
+                self.record_conn_setup_times(&HttpConnectionSetupTimes::default());
+                return Ok(c);
+            }
+        }
+
+        self.histogram_recorder
+            .record_conn_reuse_count(self.reuse_conn_count);
+        self.reuse_conn_count = 0;
+
+        self.runtime_stats.add_conn_attempt();
+        let mut setup_times = HttpConnectionSetupTimes::default();
+        let (r, w) = match tokio::time::timeout(
+            self.args.connect_timeout,
+            self.args
+                .new_http_connection(&self.proc_args, &mut setup_times),
+        )
+        .await
+        {
+            Ok(Ok(c)) => c,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(anyhow!("timeout to get new connection")),
+        };
+        self.runtime_stats.add_conn_success();
+        self.record_conn_setup_times(&setup_times);
+        if self.args.verify_cert {
+            self.runtime_stats
+                .record_tls_verify(&self.args.target_host(), setup_times.tls_verify_error);
+        }
+
+        let (r, w) = if self.args.fault_inject.is_enabled() {
+            let fault_inject = self.args.fault_inject;
+            let r: BoxHttpForwardReader = Box::new(FaultInjectReader::new(
+                r,
+                fault_inject,
+                self.runtime_stats.clone(),
+            ));
+            let w: BoxHttpForwardWriter = Box::new(FaultInjectWriter::new(
+                w,
+                fault_inject,
+                self.runtime_stats.clone(),
+            ));
+            (r, w)
+        } else {
+            (r, w)
+        };
+
+        let r = LimitedReader::new(
+            r,
+            self.proc_args.tcp_sock_speed_limit.shift_millis,
+            self.proc_args.tcp_sock_speed_limit.max_south,
+            self.runtime_stats.clone() as _,
+        );
+        let w = LimitedWriter::new(
+            w,
+            self.proc_args.tcp_sock_speed_limit.shift_millis,
+            self.proc_args.tcp_sock_speed_limit.max_north,
+            self.runtime_stats.clone() as _,
+        );
+        let mut connection = SavedHttpForwardConnection::new(BufReader::new(r), w);
+        if let Some(socket) = setup_times.tcp_socket.take() {
+            connection.sample_tcp_info(socket, self.histogram_recorder.clone());
+        }
+        Ok(connection)
+    }
+
+    fn record_conn_setup_times(&mut self, times: &HttpConnectionSetupTimes) {
+        self.histogram_recorder
+            .record_tcp_connect_time(times.tcp_connect);
+        self.histogram_recorder
+            .record_tls_handshake_time(times.tls_handshake);
+        if self.args.use_tunnel_proxy() {
+            self.histogram_recorder
+                .record_proxy_negotiation_time(times.proxy_negotiation);
+        }
+    }
+
+    fn save_connection(&mut self, c: SavedHttpForwardConnection) {
+        self.saved_connection = Some(c);
+    }
+
+    fn reset_request_header(&mut self) -> anyhow::Result<()> {
+        if let Some(req) = self.args.har_requests.get(self.har_index) {
+            self.req_header.clear();
+            self.args
+                .write_har_request_header(req, &mut self.req_header)
+                .map_err(|e| anyhow!("failed to generate request header: {e}"))?;
+        } else {
+            // reset request header
+            self.req_header.truncate(self.req_header_fixed_len);
+            // TODO generate dynamic header
+        }
+        self.req_header.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+
+    /// move to the next request in the HAR file, and return the index of the current one
+    fn advance_har_index(&mut self) -> Option<usize> {
+        let len = self.args.har_requests.len();
+        if len == 0 {
+            return None;
+        }
+        let index = self.har_index;
+        self.har_index = (index + 1) % len;
+        Some(index)
+    }
+
+    /// hold the connection idle for a longer duration than the last time,
+    /// and return whether it's still usable
+    async fn probe_keepalive(
+        &mut self,
+        step: Duration,
+        connection: &mut SavedHttpForwardConnection,
+    ) -> bool {
+        let idle = (self.probe_idle + step).min(self.args.probe_keepalive_max);
+        let idle_started = Instant::now();
+        let mut buf = [0u8; 4];
+        match tokio::time::timeout(idle, connection.reader.read(&mut buf)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => {
+                self.runtime_stats
+                    .record_keepalive_closed(idle_started.elapsed());
+                self.probe_idle = Duration::ZERO;
+                false
+            }
+            Ok(Ok(_)) => false, // unexpected data
+            Err(_) => {
+                self.runtime_stats.record_keepalive_survived(idle);
+                self.probe_idle = idle;
+                true
+            }
+        }
+    }
+
+    async fn run_with_connection(
+        &mut self,
+        time_started: Instant,
+        connection: &mut SavedHttpForwardConnection,
+    ) -> anyhow::Result<bool> {
+        let keep_alive = !self.args.no_keepalive;
+        let ups_r = &mut connection.reader;
+        let ups_w = &mut connection.writer;
+        let har_request = self.args.har_requests.get(self.har_index);
+        let method = har_request
+            .map(|req| &req.method)
+            .unwrap_or(&self.args.method);
+
+        // send hdr
+        ups_w
+            .write_all(self.req_header.as_slice())
+            .await
+            .map_err(|e| anyhow!("failed to send request header: {e:?}"))?;
+        let send_hdr_time = time_started.elapsed();
+        self.histogram_recorder.record_send_hdr_time(send_hdr_time);
+
+        // send body
+        if let Some(req) = har_request {
+            if !req.body().is_empty() {
+                ups_w
+                    .write_all(req.body())
+                    .await
+                    .map_err(|e| anyhow!("failed to send request body: {e:?}"))?;
+            }
+        }
+
+        // recv hdr
+        let rsp = match tokio::time::timeout(
+            self.args.timeout,
+            HttpForwardRemoteResponse::parse(ups_r, method, keep_alive, self.args.max_header_size),
+        )
+        .await
+        {
+            Ok(Ok(r)) => r,
+            Ok(Err(e)) => return Err(anyhow!("failed to read response: {e}")),
+            Err(_) => return Err(anyhow!("timeout to read response")),
+        };
+
+        let recv_hdr_time = time_started.elapsed();
+        self.histogram_recorder.record_recv_hdr_time(recv_hdr_time);
+        self.runtime_stats.add_rsp_status(rsp.code);
+        if let Some(ok_status) = self.args.ok_status {
+            if rsp.code != ok_status.as_u16() {
+                return Err(anyhow!(
+                    "Got rsp code {} while {} is expected",
+                    rsp.code,
+                    ok_status.as_u16()
+                ));
+            }
+        }
+
+        // recv body
+        if let Some(body_type) = rsp.body_type(method) {
+            let mut body_reader = HttpBodyReader::new(&mut *ups_r, body_type, 2048);
+            let mut sink = tokio::io::sink();
+            match tokio::io::copy(&mut body_reader, &mut sink).await {
+                Ok(n) => {
+                    if let HttpBodyType::ContentLength(expected) = body_type {
+                        if n < expected {
+                            self.runtime_stats.add_rsp_body_short_read(body_type);
+                            return Err(anyhow!(
+                                "response body too short: {n} of {expected} bytes received"
+                            ));
+                        }
+                    }
+                    // no pipelining, so there should be no more data after the body
+                    if !ups_r.buffer().is_empty() {
+                        self.runtime_stats.add_rsp_body_over_read(body_type);
+                        return Err(anyhow!(
+                            "{} bytes extra data received after response body",
+                            ups_r.buffer().len()
+                        ));
+                    }
+                }
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::UnexpectedEof {
+                        self.runtime_stats.add_rsp_body_short_read(body_type);
+                    }
+                    return Err(anyhow!("failed to read response body: {e:?}"));
+                }
+            }
+        }
+
+        Ok(keep_alive & rsp.keep_alive())
+    }
+
+    async fn run_task(&mut self, time_started: Instant) -> Result<(), BenchError> {
+        self.reset_request_header().map_err(BenchError::Fatal)?;
+
+        let mut connection = self
+            .fetch_connection()
+            .await
+            .context("connect to upstream failed")
+            .map_err(BenchError::Fatal)?;
+
+        let ret = self
+            .run_with_connection(time_started, &mut connection)
+            .await;
+        let har_index = self.advance_har_index();
+        match ret {
+            Ok(keep_alive) => {
+                let total_time = time_started.elapsed();
+                self.histogram_recorder.record_total_time(total_time);
+                self.runtime_stats.record_soak_time(total_time);
+                if let Some(index) = har_index {
+                    self.runtime_stats.record_entry_time(index, total_time);
+                }
+                self.runtime_stats
+                    .record_host_passed(self.host_index, total_time);
+
+                if keep_alive {
+                    if let Some(step) = self.args.probe_keepalive_step {
+                        if !self.probe_keepalive(step, &mut connection).await {
+                            return Ok(());
+                        }
+                    }
+                    self.save_connection(connection);
+                } else {
+                    let runtime_stats = self.runtime_stats.clone();
+                    tokio::spawn(async move {
+                        // make sure the tls ticket will be reused
+                        match tokio::time::timeout(
+                            Duration::from_secs(4),
+                            connection.writer.shutdown(),
+                        )
+                        .await
+                        {
+                            Ok(Ok(_)) => {}
+                            Ok(Err(_e)) => runtime_stats.add_conn_close_fail(),
+                            Err(_) => runtime_stats.add_conn_close_timeout(),
+                        }
+                    });
+                }
+                Ok(())
+            }
+            Err(e) => Err(BenchError::Task(e)),
+        }
+    }
+}
+
+impl HttpTaskContext {
+    /// open a new tunnel through the proxy, and exchange the payload if set
+    async fn run_tunnel_task(&mut self, time_started: Instant) -> Result<(), BenchError> {
+        self.runtime_stats.add_conn_attempt();
+        let mut setup_times = HttpConnectionSetupTimes::default();
+        let setup_started = Instant::now();
+        let r = tokio::time::timeout(
+            self.args.connect_timeout,
+            self.args
+                .new_http_connection(&self.proc_args, &mut setup_times),
+        )
+        .await;
+        let (r, w) = match r {
+            Ok(Ok(c)) => c,
+            Ok(Err(e)) => {
+                self.runtime_stats.record_tunnel_setup(false);
+                return Err(BenchError::Task(e.context("tunnel setup failed")));
+            }
+            Err(_) => {
+                self.runtime_stats.record_tunnel_setup(false);
+                return Err(BenchError::Task(anyhow!("timeout to setup tunnel")));
+            }
+        };
+        self.histogram_recorder
+            .record_tunnel_setup_time(setup_started.elapsed());
+        self.runtime_stats.add_conn_success();
+        self.runtime_stats.record_tunnel_setup(true);
+        self.record_conn_setup_times(&setup_times);
+
+        if !self.tunnel_payload.is_empty() {
+            let mut r = LimitedReader::new(
+                r,
+                self.proc_args.tcp_sock_speed_limit.shift_millis,
+                self.proc_args.tcp_sock_speed_limit.max_south,
+                self.runtime_stats.clone() as _,
+            );
+            let mut w = LimitedWriter::new(
+                w,
+                self.proc_args.tcp_sock_speed_limit.shift_millis,
+                self.proc_args.tcp_sock_speed_limit.max_north,
+                self.runtime_stats.clone() as _,
+            );
+            let payload_started = Instant::now();
+            let r = match tokio::time::timeout(
+                self.args.timeout,
+                exchange_payload(&mut r, &mut w, &self.tunnel_payload),
+            )
+            .await
+            {
+                Ok(r) => r,
+                Err(_) => Err(anyhow!("timeout to exchange payload")),
+            };
+            self.runtime_stats.record_tunnel_payload(r.is_ok());
+            r.map_err(BenchError::Task)?;
+            self.histogram_recorder
+                .record_tunnel_payload_time(payload_started.elapsed());
+        }
+
+        let total_time = time_started.elapsed();
+        self.histogram_recorder.record_total_time(total_time);
+        self.runtime_stats.record_soak_time(total_time);
+        self.runtime_stats
+            .record_host_passed(self.host_index, total_time);
+        Ok(())
+    }
+}
+
+/// send the payload and wait for the same data to be echoed back
+async fn exchange_payload<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    payload: &[u8],
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    writer
+        .write_all(payload)
+        .await
+        .map_err(|e| anyhow!("failed to send payload: {e:?}"))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| anyhow!("failed to send payload: {e:?}"))?;
+
+    let mut buf = vec![0u8; payload.len()];
+    reader
+        .read_exact(&mut buf)
+        .await
+        .map_err(|e| anyhow!("failed to recv echoed payload: {e:?}"))?;
+    if buf != payload {
+        return Err(anyhow!(
+            "the echoed payload is not the same as the sent one"
+        ));
+    }
+    Ok(())
+}
+
+impl BenchTaskContext for HttpTaskContext {
+    fn mark_task_start(&self) {
+        self.runtime_stats.add_task_total();
+        self.runtime_stats.inc_task_alive();
+    }
+
+    fn mark_task_passed(&self) {
+        self.runtime_stats.add_task_passed();
+        self.runtime_stats.dec_task_alive();
+    }
+
+    fn mark_task_failed(&self) {
+        self.runtime_stats.add_task_failed();
+        self.runtime_stats.record_host_failed(self.host_index);
+        self.runtime_stats.dec_task_alive();
+    }
+
+    async fn run(&mut self, _task_id: usize, time_started: Instant) -> Result<(), BenchError> {
+        if self.args.tunnel_only {
+            return self.run_tunnel_task(time_started).await;
+        }
+
+        let Some(pool) = self.pool.clone() else {
+            return self.run_task(time_started).await;
+        };
+
+        let wait_started = Instant::now();
+        let (_permit, connection) = pool.acquire().await.map_err(BenchError::Fatal)?;
+        self.histogram_recorder
+            .record_pool_wait_time(wait_started.elapsed());
+        self.saved_connection = connection;
+
+        let ret = self.run_task(time_started).await;
+        if let Some(c) = self.saved_connection.take() {
+            pool.release(c);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn echo_payload() {
+        let (client, server) = tokio::io::duplex(64);
+        let (mut server_r, mut server_w) = tokio::io::split(server);
+        tokio::spawn(async move {
+            tokio::io::copy(&mut server_r, &mut server_w).await.unwrap();
+        });
+        let (mut r, mut w) = tokio::io::split(client);
+        let payload = (0..100).map(|i| i as u8).collect::<Vec<_>>();
+        exchange_payload(&mut r, &mut w, &payload).await.unwrap();
+
+        let (client, server) = tokio::io::duplex(64);
+        let (_server_r, mut server_w) = tokio::io::split(server);
+        tokio::spawn(async move {
+            server_w.write_all(b"unexpected").await.unwrap();
+        });
+        let (mut r, mut w) = tokio::io::split(client);
+        assert!(exchange_payload(&mut r, &mut w, b"0123456789")
+            .await
+            .is_err());
+    }
+}
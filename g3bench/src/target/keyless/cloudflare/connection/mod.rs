@@ -0,0 +1,31 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{
+    KeylessCompression, KeylessLocalError, KeylessRequest, KeylessResponse, KeylessResponseError,
+    KeylessRuntimeStats,
+};
+
+mod multiplex;
+pub(super) use multiplex::{KeylessConnectionBuilder, KeylessRequestPriority, MultiplexTransfer};
+
+mod simplex;
+pub(super) use simplex::SimplexTransfer;
+
+#[allow(unused)]
+mod sink;
+#[allow(unused)]
+pub(super) use sink::SinkAdapter;
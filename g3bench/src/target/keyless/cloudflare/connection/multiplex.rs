@@ -0,0 +1,1247 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll, Waker};
+use std::time::Duration;
+
+use atomic_waker::AtomicWaker;
+use concurrent_queue::{ConcurrentQueue, PopError, PushError};
+use rustc_hash::FxHashMap;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, Sleep};
+
+use super::{
+    KeylessCompression, KeylessLocalError, KeylessRequest, KeylessResponse, KeylessResponseError,
+    KeylessRuntimeStats,
+};
+
+/// the max count of high priority requests that can be sent in a row when there are
+/// low priority requests waiting, so the low priority ones won't be starved
+const HIGH_PRIORITY_BURST: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum KeylessRequestPriority {
+    High,
+    Low,
+}
+
+type QueuedRequest = (KeylessRequest, Waker);
+
+struct RequestQueue {
+    high: ConcurrentQueue<QueuedRequest>,
+    low: ConcurrentQueue<QueuedRequest>,
+}
+
+impl RequestQueue {
+    fn bounded(cap: usize) -> Self {
+        RequestQueue {
+            high: ConcurrentQueue::bounded(cap),
+            low: ConcurrentQueue::bounded(cap),
+        }
+    }
+
+    fn push(
+        &self,
+        priority: KeylessRequestPriority,
+        v: QueuedRequest,
+    ) -> Result<(), PushError<QueuedRequest>> {
+        match priority {
+            KeylessRequestPriority::High => self.high.push(v),
+            KeylessRequestPriority::Low => self.low.push(v),
+        }
+    }
+
+    /// pop from the high priority queue first, unless `prefer_low` is set
+    fn pop(&self, prefer_low: bool) -> Result<(QueuedRequest, KeylessRequestPriority), PopError> {
+        let (first, first_priority, second, second_priority) = if prefer_low {
+            (
+                &self.low,
+                KeylessRequestPriority::Low,
+                &self.high,
+                KeylessRequestPriority::High,
+            )
+        } else {
+            (
+                &self.high,
+                KeylessRequestPriority::High,
+                &self.low,
+                KeylessRequestPriority::Low,
+            )
+        };
+        match first.pop() {
+            Ok(v) => Ok((v, first_priority)),
+            Err(_) => second.pop().map(|v| (v, second_priority)),
+        }
+    }
+
+    fn close(&self) {
+        self.high.close();
+        self.low.close();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.high.is_closed()
+    }
+
+    fn len(&self) -> usize {
+        self.high.len() + self.low.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.low.is_empty()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.high.capacity()
+    }
+}
+
+struct ResponseValue {
+    data: Option<KeylessResponse>,
+    waker: Option<Waker>,
+    created: Instant,
+    end: bool,
+}
+
+impl ResponseValue {
+    fn new(waker: Waker) -> Self {
+        ResponseValue {
+            data: None,
+            waker: Some(waker),
+            created: Instant::now(),
+            end: false,
+        }
+    }
+
+    fn empty() -> Self {
+        ResponseValue {
+            data: None,
+            waker: None,
+            created: Instant::now(),
+            end: true,
+        }
+    }
+}
+
+struct SharedState {
+    local_addr: SocketAddr,
+    peer_addr: Option<SocketAddr>,
+    write_waker: AtomicWaker,
+    next_req_id: AtomicU32,
+    req_queue: RequestQueue,
+    rsp_table: Mutex<FxHashMap<u32, ResponseValue>>,
+    error: Mutex<Option<Arc<KeylessResponseError>>>,
+    send_throttled: AtomicBool,
+    req_queue_depth: AtomicUsize,
+    runtime_stats: Arc<KeylessRuntimeStats>,
+    /// the time since when we are waiting for a response, always locked after rsp_table
+    waiting_since: Mutex<Option<Instant>>,
+}
+
+impl SharedState {
+    fn new(
+        local_addr: SocketAddr,
+        peer_addr: Option<SocketAddr>,
+        runtime_stats: Arc<KeylessRuntimeStats>,
+    ) -> Self {
+        SharedState {
+            local_addr,
+            peer_addr,
+            write_waker: AtomicWaker::new(),
+            next_req_id: AtomicU32::new(0),
+            req_queue: RequestQueue::bounded(1024),
+            rsp_table: Mutex::new(FxHashMap::default()),
+            error: Mutex::new(None),
+            send_throttled: AtomicBool::new(false),
+            req_queue_depth: AtomicUsize::new(0),
+            runtime_stats,
+            waiting_since: Mutex::new(None),
+        }
+    }
+
+    fn next_req_id(&self) -> u32 {
+        self.next_req_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn set_req_error(&self, e: io::Error) {
+        let mut req_err_guard = self.error.lock().unwrap();
+        *req_err_guard = Some(Arc::new(KeylessLocalError::WriteFailed(e).into()));
+    }
+
+    fn set_rsp_error(&self, e: KeylessResponseError) {
+        let mut rsp_err_guard = self.error.lock().unwrap();
+        *rsp_err_guard = Some(Arc::new(e));
+    }
+
+    fn clean_pending_req(&self) {
+        let mut rsp_table_guard = self.rsp_table.lock().unwrap();
+        while let Ok(((r, waker), _)) = self.req_queue.pop(false) {
+            rsp_table_guard.insert(r.id(), ResponseValue::empty());
+            waker.wake();
+        }
+        self.update_req_queue_depth();
+        for v in (*rsp_table_guard).values_mut() {
+            if let Some(waker) = v.waker.take() {
+                waker.wake();
+            }
+            v.end = true;
+        }
+    }
+
+    /// should be called with the rsp_table lock held, after a new request is sent
+    fn mark_waiting(&self) {
+        let mut waiting_since = self.waiting_since.lock().unwrap();
+        if waiting_since.is_none() {
+            *waiting_since = Some(Instant::now());
+        }
+    }
+
+    /// should be called with the rsp_table lock held, after a response is received
+    fn mark_received(&self, rsp_table: &FxHashMap<u32, ResponseValue>) {
+        let mut waiting_since = self.waiting_since.lock().unwrap();
+        if rsp_table.values().any(|v| v.waker.is_some()) {
+            *waiting_since = Some(Instant::now());
+        } else {
+            *waiting_since = None;
+        }
+    }
+
+    /// return None if no request is waiting for response
+    fn waiting_time(&self) -> Option<Duration> {
+        let waiting_since = self.waiting_since.lock().unwrap();
+        waiting_since.map(|t| t.elapsed())
+    }
+
+    fn close_with_error(&self, e: KeylessResponseError) {
+        self.req_queue.close();
+        self.set_rsp_error(e);
+        self.clean_pending_req();
+        if let Some(waker) = self.take_write_waker() {
+            waker.wake(); // tell the writer to quit
+        }
+    }
+
+    fn take_write_waker(&self) -> Option<Waker> {
+        self.write_waker.take()
+    }
+
+    /// sync the length of the request queue to the runtime stats
+    fn update_req_queue_depth(&self) {
+        let depth = self.req_queue.len();
+        let old = self.req_queue_depth.swap(depth, Ordering::Relaxed);
+        if depth > old {
+            self.runtime_stats.add_req_queue_depth(depth - old);
+        } else if depth < old {
+            self.runtime_stats.sub_req_queue_depth(old - depth);
+        }
+    }
+
+    /// should be called with the rsp_table lock held, after some entries were removed
+    fn wake_throttled_writer(&self) {
+        if self.send_throttled.swap(false, Ordering::Relaxed) {
+            self.write_waker.wake();
+        }
+    }
+}
+
+impl Drop for SharedState {
+    fn drop(&mut self) {
+        let depth = self.req_queue_depth.swap(0, Ordering::Relaxed);
+        self.runtime_stats.sub_req_queue_depth(depth);
+    }
+}
+
+struct SendSlowStart {
+    started: Instant,
+    warmup: Duration,
+    capacity: usize,
+    window: usize,
+    finished: bool,
+    runtime_stats: Arc<KeylessRuntimeStats>,
+}
+
+impl SendSlowStart {
+    fn new(warmup: Duration, capacity: usize, runtime_stats: Arc<KeylessRuntimeStats>) -> Self {
+        runtime_stats.add_send_window(1);
+        SendSlowStart {
+            started: Instant::now(),
+            warmup,
+            capacity,
+            window: 1,
+            finished: false,
+            runtime_stats,
+        }
+    }
+
+    /// get the current allowed in-flight request count, or None if the warmup has finished
+    fn window(&mut self) -> Option<usize> {
+        if self.finished {
+            return None;
+        }
+
+        let elapsed = self.started.elapsed();
+        let window = if elapsed >= self.warmup {
+            self.finished = true;
+            self.capacity
+        } else {
+            let ramp =
+                (self.capacity - 1) as f64 * elapsed.as_secs_f64() / self.warmup.as_secs_f64();
+            1 + ramp as usize
+        };
+        if window != self.window {
+            self.runtime_stats.add_send_window(window - self.window);
+            self.window = window;
+        }
+
+        if self.finished {
+            None
+        } else {
+            Some(window)
+        }
+    }
+}
+
+impl Drop for SendSlowStart {
+    fn drop(&mut self) {
+        self.runtime_stats.sub_send_window(self.window);
+    }
+}
+
+struct UnderlyingWriterState {
+    shared: Arc<SharedState>,
+    current_offset: usize,
+    current_request: Option<KeylessRequest>,
+    request_timeout: Duration,
+    shutdown_wait: Option<Pin<Box<Sleep>>>,
+    slow_start: Option<SendSlowStart>,
+    /// the count of high priority requests sent in a row
+    high_priority_streak: usize,
+}
+
+impl UnderlyingWriterState {
+    fn send_window_full(&mut self) -> bool {
+        let Some(slow_start) = &mut self.slow_start else {
+            return false;
+        };
+        let Some(window) = slow_start.window() else {
+            return false;
+        };
+        if self.shared.req_queue.is_closed() {
+            return false;
+        }
+
+        let rsp_table = self.shared.rsp_table.lock().unwrap();
+        if rsp_table.len() >= window {
+            // the writer will be woken up after some in-flight requests finished
+            self.shared.send_throttled.store(true, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn poll_write<W>(&mut self, cx: &mut Context<'_>, mut writer: Pin<&mut W>) -> Poll<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.shared.write_waker.register(cx.waker());
+
+        let mut do_flush = false;
+        loop {
+            if let Some(req) = self.current_request.take() {
+                let current_buffer = req.as_bytes();
+                while self.current_offset < current_buffer.len() {
+                    match writer
+                        .as_mut()
+                        .poll_write(cx, &current_buffer[self.current_offset..])
+                    {
+                        Poll::Ready(Ok(n)) => {
+                            self.current_offset += n;
+                            do_flush = true;
+                        }
+                        Poll::Ready(Err(e)) => {
+                            self.shared.req_queue.close();
+                            self.shared.set_req_error(e);
+                            self.shared.clean_pending_req();
+                            let _ = writer.as_mut().poll_shutdown(cx);
+                            return Poll::Ready(());
+                        }
+                        Poll::Pending => {
+                            self.current_request = Some(req);
+                            return Poll::Pending;
+                        }
+                    };
+                }
+            }
+
+            let next = if self.send_window_full() {
+                Err(PopError::Empty)
+            } else {
+                let prefer_low = self.high_priority_streak >= HIGH_PRIORITY_BURST;
+                self.shared.req_queue.pop(prefer_low)
+            };
+            match next {
+                Ok(((req, waker), priority)) => {
+                    match priority {
+                        KeylessRequestPriority::High => self.high_priority_streak += 1,
+                        KeylessRequestPriority::Low => self.high_priority_streak = 0,
+                    }
+                    self.shared.update_req_queue_depth();
+                    let mut rsp_table = self.shared.rsp_table.lock().unwrap();
+                    if rsp_table.remove(&req.id()).is_some() {
+                        // the request has been cancelled before we send it out
+                        continue;
+                    }
+                    rsp_table.insert(req.id(), ResponseValue::new(waker));
+                    self.shared.mark_waiting();
+                    drop(rsp_table);
+                    self.current_offset = 0;
+                    self.current_request = Some(req);
+                }
+                Err(PopError::Empty) => {
+                    if do_flush {
+                        if let Err(e) = ready!(writer.as_mut().poll_flush(cx)) {
+                            self.shared.req_queue.close();
+                            self.shared.set_req_error(e);
+                            self.shared.clean_pending_req();
+                            let _ = writer.as_mut().poll_shutdown(cx);
+                            return Poll::Ready(());
+                        }
+                    }
+                    return Poll::Pending;
+                }
+                Err(PopError::Closed) => {
+                    let _ = self.shared.take_write_waker(); // make sure no more wake by others
+                    let mut sleep = self
+                        .shutdown_wait
+                        .take()
+                        .unwrap_or_else(|| Box::pin(tokio::time::sleep(self.request_timeout)));
+                    return match sleep.as_mut().poll(cx) {
+                        Poll::Ready(_) => {
+                            let _ = writer.as_mut().poll_shutdown(cx);
+                            Poll::Ready(())
+                        }
+                        Poll::Pending => {
+                            self.shutdown_wait = Some(sleep);
+                            Poll::Pending
+                        }
+                    };
+                }
+            }
+        }
+    }
+}
+
+struct UnderlyingWriter<W> {
+    writer: W,
+    state: UnderlyingWriterState,
+}
+
+impl<W> Future for UnderlyingWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = &mut *self;
+
+        me.state.poll_write(cx, Pin::new(&mut me.writer))
+    }
+}
+
+/// send each request as a single datagram on a connected udp socket
+struct DatagramWriter {
+    socket: Arc<UdpSocket>,
+}
+
+impl AsyncWrite for DatagramWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let nw = ready!(self.socket.poll_send(cx, buf))?;
+        if nw < buf.len() {
+            return Poll::Ready(Err(io::Error::other(format!(
+                "datagram truncated: {nw} of {} bytes sent",
+                buf.len()
+            ))));
+        }
+        Poll::Ready(Ok(nw))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+trait ResponseReader: Send + 'static {
+    fn read_response(
+        &mut self,
+    ) -> impl Future<Output = Result<KeylessResponse, KeylessResponseError>> + Send;
+}
+
+struct StreamResponseReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R> ResponseReader for StreamResponseReader<R>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    fn read_response(
+        &mut self,
+    ) -> impl Future<Output = Result<KeylessResponse, KeylessResponseError>> + Send {
+        KeylessResponse::read(&mut self.reader, &mut self.buf)
+    }
+}
+
+struct DatagramResponseReader {
+    socket: Arc<UdpSocket>,
+    buf: Box<[u8]>,
+}
+
+impl ResponseReader for DatagramResponseReader {
+    async fn read_response(&mut self) -> Result<KeylessResponse, KeylessResponseError> {
+        let nr = self
+            .socket
+            .recv(&mut self.buf)
+            .await
+            .map_err(KeylessLocalError::ReadFailed)?;
+        KeylessResponse::parse_datagram(&self.buf[..nr])
+    }
+}
+
+pub(crate) struct SendRequest {
+    shared: Arc<SharedState>,
+    request: Option<KeylessRequest>,
+    priority: KeylessRequestPriority,
+    rsp_id: u32,
+    in_flight: bool,
+}
+
+impl Drop for SendRequest {
+    fn drop(&mut self) {
+        if !self.in_flight {
+            return;
+        }
+
+        let mut rsp_table_guard = self.shared.rsp_table.lock().unwrap();
+        if rsp_table_guard.remove(&self.rsp_id).is_some() {
+            self.shared.wake_throttled_writer();
+        } else if !self.shared.req_queue.is_closed() {
+            // the request is still in the queue, leave a mark for the writer to skip it
+            rsp_table_guard.insert(self.rsp_id, ResponseValue::empty());
+        }
+    }
+}
+
+impl SendRequest {
+    /// check if the request has been pushed to the send queue
+    pub(super) fn is_queued(&self) -> bool {
+        self.request.is_none()
+    }
+}
+
+impl Future for SendRequest {
+    type Output = Result<KeylessResponse, u32>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(mut req) = self.request.take() {
+            let rsp_waker = cx.waker().clone();
+            let id = self.shared.next_req_id();
+            req.set_id(id);
+            match self.shared.req_queue.push(self.priority, (req, rsp_waker)) {
+                Ok(_) => {
+                    self.shared.update_req_queue_depth();
+                    self.shared.write_waker.wake();
+                    self.rsp_id = id;
+                    self.in_flight = true;
+                    Poll::Pending
+                }
+                Err(PushError::Closed(_)) => Poll::Ready(Err(self.rsp_id)),
+                Err(PushError::Full((req, waker))) => {
+                    self.request = Some(req);
+                    waker.wake();
+                    Poll::Pending
+                }
+            }
+        } else {
+            let rsp_id = self.rsp_id;
+            let mut rsp_table_guard = self.shared.rsp_table.lock().unwrap();
+            match rsp_table_guard.get(&rsp_id) {
+                Some(v) if v.end => {
+                    let v = rsp_table_guard.remove(&rsp_id).unwrap();
+                    self.shared.wake_throttled_writer();
+                    drop(rsp_table_guard);
+                    self.in_flight = false;
+                    Poll::Ready(v.data.ok_or(rsp_id))
+                }
+                _ => Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A point-in-time view of a multiplex connection, for debug output
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct KeylessConnectionSnapshot {
+    pub(crate) local_addr: SocketAddr,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) queued: usize,
+    pub(crate) waiting: usize,
+}
+
+impl fmt::Display for KeylessConnectionSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peer_addr {
+            Some(peer_addr) => write!(f, "{}->{}", self.local_addr, peer_addr)?,
+            None => write!(f, "{}", self.local_addr)?,
+        }
+        write!(f, " [queued {}, waiting {}]", self.queued, self.waiting)
+    }
+}
+
+pub(crate) struct MultiplexTransfer {
+    shared: Arc<SharedState>,
+    compression: Option<KeylessCompression>,
+    tls_epoch: u64,
+}
+
+impl Drop for MultiplexTransfer {
+    fn drop(&mut self) {
+        self.shared.req_queue.close();
+        if let Some(waker) = self.shared.take_write_waker() {
+            waker.wake(); // let the writer handle the quit
+        }
+    }
+}
+
+impl MultiplexTransfer {
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.req_queue.is_closed()
+    }
+
+    /// check if there is no request queued or waiting for response
+    pub(crate) fn is_idle(&self) -> bool {
+        if !self.shared.req_queue.is_empty() {
+            return false;
+        }
+        let rsp_table_guard = self.shared.rsp_table.lock().unwrap();
+        rsp_table_guard.is_empty()
+    }
+
+    /// the peer address is the resolved one of the keyless server, which may be a backend behind a VIP
+    pub(crate) fn snapshot(&self) -> KeylessConnectionSnapshot {
+        let waiting = self.shared.rsp_table.lock().unwrap().len();
+        KeylessConnectionSnapshot {
+            local_addr: self.shared.local_addr,
+            peer_addr: self.shared.peer_addr,
+            queued: self.shared.req_queue.len(),
+            waiting,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn compression(&self) -> Option<KeylessCompression> {
+        self.compression
+    }
+
+    /// the epoch of the rotated tls client that this connection was created with
+    #[inline]
+    pub(crate) fn tls_epoch(&self) -> u64 {
+        self.tls_epoch
+    }
+
+    pub(crate) fn send_request(
+        &self,
+        req: KeylessRequest,
+        priority: KeylessRequestPriority,
+    ) -> SendRequest {
+        SendRequest {
+            shared: self.shared.clone(),
+            request: Some(req),
+            priority,
+            rsp_id: 0,
+            in_flight: false,
+        }
+    }
+
+    pub(crate) fn fetch_error(&self) -> Option<Arc<KeylessResponseError>> {
+        let guard = self.shared.error.lock().unwrap();
+        guard.clone()
+    }
+
+    /// start with the default options, see [`KeylessConnectionBuilder`] for more options
+    #[cfg(test)]
+    pub(crate) fn start<R, W>(
+        r: R,
+        w: W,
+        local_addr: SocketAddr,
+        request_timeout: Duration,
+        slow_start_warmup: Option<Duration>,
+        heartbeat_interval: Option<Duration>,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+    ) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        KeylessConnectionBuilder::new(local_addr, runtime_stats)
+            .request_timeout(request_timeout)
+            .slow_start_warmup(slow_start_warmup)
+            .heartbeat_interval(heartbeat_interval)
+            .start(r, w)
+    }
+
+    /// start on a udp socket with the default options, see [`KeylessConnectionBuilder`] for more options
+    #[cfg(test)]
+    pub(crate) fn start_datagram(
+        socket: UdpSocket,
+        local_addr: SocketAddr,
+        request_timeout: Duration,
+        slow_start_warmup: Option<Duration>,
+        heartbeat_interval: Option<Duration>,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+    ) -> Self {
+        KeylessConnectionBuilder::new(local_addr, runtime_stats)
+            .request_timeout(request_timeout)
+            .slow_start_warmup(slow_start_warmup)
+            .heartbeat_interval(heartbeat_interval)
+            .start_datagram(socket)
+    }
+}
+
+/// Builder for multiplex keyless connections.
+///
+/// The defaults are the same as the ones of the command line options.
+#[derive(Clone)]
+pub(crate) struct KeylessConnectionBuilder {
+    local_addr: SocketAddr,
+    peer_addr: Option<SocketAddr>,
+    request_timeout: Duration,
+    slow_start_warmup: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    compression: Option<KeylessCompression>,
+    tls_epoch: u64,
+    runtime_stats: Arc<KeylessRuntimeStats>,
+}
+
+impl KeylessConnectionBuilder {
+    pub(crate) fn new(local_addr: SocketAddr, runtime_stats: &Arc<KeylessRuntimeStats>) -> Self {
+        KeylessConnectionBuilder {
+            local_addr,
+            peer_addr: None,
+            request_timeout: Duration::from_secs(5),
+            slow_start_warmup: None,
+            heartbeat_interval: None,
+            compression: None,
+            tls_epoch: 0,
+            runtime_stats: runtime_stats.clone(),
+        }
+    }
+
+    /// set the resolved peer address of the underlying transport
+    pub(crate) fn peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    pub(crate) fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub(crate) fn slow_start_warmup(mut self, warmup: Option<Duration>) -> Self {
+        self.slow_start_warmup = warmup;
+        self
+    }
+
+    pub(crate) fn heartbeat_interval(mut self, interval: Option<Duration>) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// set the compression negotiated at connection start
+    pub(crate) fn compression(mut self, compression: Option<KeylessCompression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// set the epoch of the rotated tls client used for the connection
+    pub(crate) fn tls_epoch(mut self, epoch: u64) -> Self {
+        self.tls_epoch = epoch;
+        self
+    }
+
+    pub(crate) fn start<R, W>(self, r: R, w: W) -> MultiplexTransfer
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let reader = StreamResponseReader {
+            reader: r,
+            buf: Vec::with_capacity(1024),
+        };
+        self.start_with(reader, w)
+    }
+
+    /// Start the transfer on a connected udp socket, with each request and response framed
+    /// in a single datagram. A lost datagram will make the request fail when it times out.
+    pub(crate) fn start_datagram(self, socket: UdpSocket) -> MultiplexTransfer {
+        let socket = Arc::new(socket);
+        let reader = DatagramResponseReader {
+            socket: socket.clone(),
+            buf: vec![0u8; u16::MAX as usize].into_boxed_slice(),
+        };
+        self.start_with(reader, DatagramWriter { socket })
+    }
+
+    fn start_with<T, W>(self, mut reader: T, w: W) -> MultiplexTransfer
+    where
+        T: ResponseReader,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let request_timeout = self.request_timeout;
+        let heartbeat_interval = self.heartbeat_interval;
+        let runtime_stats = &self.runtime_stats;
+        let shared = Arc::new(SharedState::new(
+            self.local_addr,
+            self.peer_addr,
+            runtime_stats.clone(),
+        ));
+        let slow_start = self.slow_start_warmup.map(|warmup| {
+            let capacity = shared.req_queue.capacity().unwrap_or(usize::MAX);
+            SendSlowStart::new(warmup, capacity, runtime_stats.clone())
+        });
+        let handle = MultiplexTransfer {
+            shared: shared.clone(),
+            compression: self.compression,
+            tls_epoch: self.tls_epoch,
+        };
+
+        let underlying_w = UnderlyingWriter {
+            writer: w,
+            state: UnderlyingWriterState {
+                shared: Arc::clone(&shared),
+                current_offset: 0,
+                current_request: None,
+                request_timeout,
+                shutdown_wait: None,
+                slow_start,
+                high_priority_streak: 0,
+            },
+        };
+        tokio::spawn(underlying_w);
+
+        let clean_shared = shared.clone();
+        tokio::spawn(async move {
+            // use a timer to clean timeout cache and keep hashtable small
+            let mut interval = tokio::time::interval(request_timeout);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+
+                let mut rsp_table_guard = clean_shared.rsp_table.lock().unwrap();
+                rsp_table_guard.retain(|_, v| {
+                    if v.created.elapsed() > request_timeout {
+                        if let Some(waker) = v.waker.take() {
+                            v.end = true;
+                            waker.wake();
+                        }
+                        false
+                    } else {
+                        true
+                    }
+                });
+                clean_shared.wake_throttled_writer();
+            }
+        });
+
+        tokio::spawn(async move {
+            loop {
+                let read = reader.read_response();
+                let rsp = match heartbeat_interval {
+                    Some(interval) => {
+                        tokio::pin!(read);
+                        loop {
+                            match tokio::time::timeout(interval, &mut read).await {
+                                Ok(rsp) => break rsp,
+                                Err(_) => match shared.waiting_time() {
+                                    Some(waited) if waited >= interval => {
+                                        shared.runtime_stats.add_conn_dead();
+                                        break Err(KeylessLocalError::HeartbeatTimeout.into());
+                                    }
+                                    Some(_) => {}
+                                    None => send_heartbeat(&shared),
+                                },
+                            }
+                        }
+                    }
+                    None => read.await,
+                };
+                match rsp {
+                    Ok(r) => {
+                        let mut rsp_table_guard = shared.rsp_table.lock().unwrap();
+                        let waker = rsp_table_guard.get_mut(&r.id()).and_then(|entry| {
+                            let waker = entry.waker.take()?;
+                            entry.data = Some(r);
+                            entry.end = true;
+                            Some(waker)
+                        });
+                        shared.mark_received(&rsp_table_guard);
+                        drop(rsp_table_guard);
+                        if let Some(waker) = waker {
+                            waker.wake();
+                        }
+                    }
+                    Err(e) => {
+                        shared.close_with_error(e);
+                        break;
+                    }
+                };
+            }
+        });
+
+        handle
+    }
+}
+
+fn send_heartbeat(shared: &Arc<SharedState>) {
+    let ping = SendRequest {
+        shared: shared.clone(),
+        request: Some(KeylessRequest::new_ping()),
+        priority: KeylessRequestPriority::High,
+        rsp_id: 0,
+        in_flight: false,
+    };
+    let runtime_stats = shared.runtime_stats.clone();
+    tokio::spawn(async move {
+        let start = Instant::now();
+        // a lost pong will be detected by the reader, as we are waiting for it
+        if ping.await.is_ok() {
+            runtime_stats.record_heartbeat_rtt(start.elapsed());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    use crate::target::keyless::cloudflare::KeylessRequestBuilder;
+    use crate::target::keyless::opts::KeylessAction;
+
+    fn build_request() -> KeylessRequest {
+        KeylessRequestBuilder::new(&[0u8; 20], KeylessAction::Ed25519Sign)
+            .unwrap()
+            .build(b"test")
+            .unwrap()
+    }
+
+    fn start_transfer() -> (MultiplexTransfer, tokio::io::DuplexStream) {
+        start_transfer_with_heartbeat(None)
+    }
+
+    fn start_transfer_with_heartbeat(
+        heartbeat_interval: Option<Duration>,
+    ) -> (MultiplexTransfer, tokio::io::DuplexStream) {
+        let (client, server) = tokio::io::duplex(4096);
+        let (r, w) = tokio::io::split(client);
+        let runtime_stats = Arc::new(KeylessRuntimeStats::default());
+        let transfer = MultiplexTransfer::start(
+            r,
+            w,
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            Duration::from_secs(10),
+            None,
+            heartbeat_interval,
+            &runtime_stats,
+        );
+        (transfer, server)
+    }
+
+    #[tokio::test]
+    async fn drop_in_flight() {
+        let (transfer, _server) = start_transfer();
+
+        let mut send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        assert!((&mut send).now_or_never().is_none());
+        // let the writer send out the request
+        tokio::task::yield_now().await;
+        assert_eq!(transfer.shared.rsp_table.lock().unwrap().len(), 1);
+
+        drop(send);
+        assert!(transfer.shared.rsp_table.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn req_queue_depth() {
+        let (transfer, _server) = start_transfer();
+        let runtime_stats = transfer.shared.runtime_stats.clone();
+
+        let mut send1 = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        let mut send2 = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        assert!((&mut send1).now_or_never().is_none());
+        assert!((&mut send2).now_or_never().is_none());
+        assert_eq!(runtime_stats.req_queue_depth(), 2);
+        assert_eq!(runtime_stats.req_queue_peak(), 2);
+
+        // let the writer send out the requests
+        tokio::task::yield_now().await;
+        assert_eq!(runtime_stats.req_queue_depth(), 0);
+        assert_eq!(runtime_stats.req_queue_peak(), 2);
+    }
+
+    #[tokio::test]
+    async fn idle() {
+        let (transfer, _server) = start_transfer();
+        assert!(transfer.is_idle());
+
+        let mut send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        assert!((&mut send).now_or_never().is_none());
+        assert!(!transfer.is_idle());
+
+        // let the writer send out the request
+        tokio::task::yield_now().await;
+        assert!(!transfer.is_idle());
+
+        drop(send);
+        assert!(transfer.is_idle());
+    }
+
+    #[tokio::test]
+    async fn snapshot() {
+        let (client, _server) = tokio::io::duplex(4096);
+        let (r, w) = tokio::io::split(client);
+        let runtime_stats = Arc::new(KeylessRuntimeStats::default());
+        let transfer =
+            KeylessConnectionBuilder::new(SocketAddr::from(([127, 0, 0, 1], 1234)), &runtime_stats)
+                .peer_addr(SocketAddr::from(([10, 0, 0, 1], 1300)))
+                .start(r, w);
+
+        let mut send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        assert!((&mut send).now_or_never().is_none());
+        tokio::task::yield_now().await;
+
+        let snapshot = transfer.snapshot();
+        assert_eq!(
+            snapshot.peer_addr,
+            Some(SocketAddr::from(([10, 0, 0, 1], 1300)))
+        );
+        assert_eq!(snapshot.queued, 0);
+        assert_eq!(snapshot.waiting, 1);
+        assert_eq!(
+            snapshot.to_string(),
+            "127.0.0.1:1234->10.0.0.1:1300 [queued 0, waiting 1]"
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_in_queue() {
+        let (transfer, _server) = start_transfer();
+
+        let mut send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        assert!((&mut send).now_or_never().is_none());
+        drop(send);
+        // a cancel mark is left as the request is still in queue
+        assert_eq!(transfer.shared.rsp_table.lock().unwrap().len(), 1);
+
+        // the writer should skip the request and remove the mark
+        tokio::task::yield_now().await;
+        assert!(transfer.shared.req_queue.is_empty());
+        assert!(transfer.shared.rsp_table.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn heartbeat_timeout() {
+        let (transfer, _server) = start_transfer_with_heartbeat(Some(Duration::from_millis(50)));
+
+        // the server never responds
+        let send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        let r = tokio::time::timeout(Duration::from_secs(1), send)
+            .await
+            .unwrap();
+        assert!(r.is_err());
+        assert!(transfer.is_closed());
+        let e = transfer.fetch_error().unwrap();
+        assert!(matches!(
+            e.as_ref(),
+            KeylessResponseError::LocalError(KeylessLocalError::HeartbeatTimeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn heartbeat_ping() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (transfer, mut server) = start_transfer_with_heartbeat(Some(Duration::from_millis(50)));
+
+        let mut ping = [0u8; 15];
+        server.read_exact(&mut ping).await.unwrap();
+        assert_eq!(ping[11], 0xF1);
+        let pong = [
+            0x01, 0x00, 0x00, 0x07, ping[4], ping[5], ping[6], ping[7], 0x11, 0x00, 0x01, 0xF2,
+            0x12, 0x00, 0x00,
+        ];
+        server.write_all(&pong).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!transfer.is_closed());
+        assert!(transfer.shared.waiting_time().is_none());
+    }
+
+    async fn start_datagram_transfer(
+        request_timeout: Duration,
+    ) -> (MultiplexTransfer, tokio::net::UdpSocket) {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+        let local_addr = client.local_addr().unwrap();
+        let runtime_stats = Arc::new(KeylessRuntimeStats::default());
+        let transfer = MultiplexTransfer::start_datagram(
+            client,
+            local_addr,
+            request_timeout,
+            None,
+            None,
+            &runtime_stats,
+        );
+        (transfer, server)
+    }
+
+    #[tokio::test]
+    async fn datagram_response() {
+        let (transfer, server) = start_datagram_transfer(Duration::from_secs(10)).await;
+
+        let send1 = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        let send2 = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let mut reqs = Vec::new();
+            for _ in 0..2 {
+                let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+                let len_in_hdr = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+                assert_eq!(len, 8 + len_in_hdr);
+                reqs.push(([buf[4], buf[5], buf[6], buf[7]], peer));
+            }
+            // respond in reverse order, each in a single datagram
+            for (id, peer) in reqs.into_iter().rev() {
+                let rsp = [
+                    0x01, 0x00, 0x00, 0x0B, id[0], id[1], id[2], id[3], 0x11, 0x00, 0x01, 0xF0,
+                    0x12, 0x00, 0x04, id[0], id[1], id[2], id[3],
+                ];
+                server.send_to(&rsp, peer).await.unwrap();
+            }
+        });
+
+        let (r1, r2) = tokio::join!(send1, send2);
+        server_task.await.unwrap();
+        let r1 = r1.unwrap();
+        let r2 = r2.unwrap();
+        assert_eq!(r1.into_vec(), 0u32.to_be_bytes());
+        assert_eq!(r2.into_vec(), 1u32.to_be_bytes());
+        assert!(!transfer.is_closed());
+    }
+
+    #[tokio::test]
+    async fn datagram_lost() {
+        let (transfer, server) = start_datagram_transfer(Duration::from_secs(10)).await;
+
+        // the request is received but the response datagram is lost
+        let send = transfer.send_request(build_request(), KeylessRequestPriority::Low);
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            server.recv_from(&mut buf).await.unwrap();
+        });
+        // the same as the per request timeout in the task
+        let r = tokio::time::timeout(Duration::from_millis(100), send).await;
+        assert!(r.is_err());
+        server_task.await.unwrap();
+        // the in-flight entry should be removed, and the transfer should still be usable
+        assert!(transfer.shared.rsp_table.lock().unwrap().is_empty());
+        assert!(!transfer.is_closed());
+    }
+
+    #[tokio::test]
+    async fn priority_order() {
+        use tokio::io::AsyncReadExt;
+
+        // use a small buffer to make the writer slow
+        let (client, mut server) = tokio::io::duplex(64);
+        let (r, w) = tokio::io::split(client);
+        let runtime_stats = Arc::new(KeylessRuntimeStats::default());
+        let transfer = MultiplexTransfer::start(
+            r,
+            w,
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            Duration::from_secs(10),
+            None,
+            None,
+            &runtime_stats,
+        );
+
+        let mut priority_map = FxHashMap::default();
+        let mut sends = Vec::new();
+        for i in 0..40 {
+            let priority = if i % 2 == 0 {
+                KeylessRequestPriority::Low
+            } else {
+                KeylessRequestPriority::High
+            };
+            let mut send = transfer.send_request(build_request(), priority);
+            assert!((&mut send).now_or_never().is_none());
+            priority_map.insert(i as u32, priority);
+            sends.push(send);
+        }
+
+        let mut high_pos_sum = 0;
+        let mut low_pos_sum = 0;
+        let mut hdr = [0u8; 8];
+        let mut body = Vec::new();
+        for pos in 0..40 {
+            server.read_exact(&mut hdr).await.unwrap();
+            let len = u16::from_be_bytes([hdr[2], hdr[3]]) as usize;
+            body.resize(len, 0);
+            server.read_exact(&mut body).await.unwrap();
+            let id = u32::from_be_bytes([hdr[4], hdr[5], hdr[6], hdr[7]]);
+            match priority_map.get(&id).unwrap() {
+                KeylessRequestPriority::High => high_pos_sum += pos,
+                KeylessRequestPriority::Low => low_pos_sum += pos,
+            }
+            // make the writer slow
+            tokio::task::yield_now().await;
+        }
+        assert!(high_pos_sum < low_pos_sum);
+        // the low priority requests should not be starved
+        assert!(low_pos_sum < (20..40).sum());
+    }
+}
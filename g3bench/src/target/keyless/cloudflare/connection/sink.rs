@@ -0,0 +1,272 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use atomic_waker::AtomicWaker;
+use futures_util::{Sink, Stream};
+
+use super::multiplex::SendRequest;
+use super::{KeylessRequest, KeylessRequestPriority, KeylessResponse, MultiplexTransfer};
+
+/// Wake both the sink side and the stream side, as the requests are polled by either of them
+#[derive(Default)]
+struct AdapterWaker {
+    sink: AtomicWaker,
+    stream: AtomicWaker,
+}
+
+impl Wake for AdapterWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.sink.wake();
+        self.stream.wake();
+    }
+}
+
+struct PendingRequest {
+    send: SendRequest,
+    result: Option<Result<KeylessResponse, u32>>,
+}
+
+impl PendingRequest {
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<KeylessResponse, u32>> {
+        if let Some(r) = self.result.take() {
+            return Poll::Ready(r);
+        }
+        Pin::new(&mut self.send).poll(cx)
+    }
+
+    /// push the request to the send queue of the connection if not yet, return false if it's full
+    fn submit(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.result.is_some() || self.send.is_queued() {
+            return true;
+        }
+        match Pin::new(&mut self.send).poll(cx) {
+            Poll::Ready(r) => {
+                self.result = Some(r);
+                true
+            }
+            Poll::Pending => self.send.is_queued(),
+        }
+    }
+}
+
+/// A [`Sink`] of keyless requests over a multiplex connection,
+/// which is also the [`Stream`] of the responses.
+///
+/// The request ids are assigned by the connection, and the responses will be yielded in the
+/// submission order of the requests, no matter in which order they are received, so a slow
+/// response will hold back all the ones submitted after it.
+/// `Err` with the request id will be yielded if no response can be received for the request,
+/// the reason can be fetched by [`MultiplexTransfer::fetch_error`].
+///
+/// The requests will be sent out as soon as they are submitted, at most `max_pending` requests
+/// can be waiting for responses, and the sink won't be ready until some responses are taken
+/// from the stream. Use `StreamExt::split` to get separate sink and stream halves.
+pub(crate) struct SinkAdapter {
+    transfer: Arc<MultiplexTransfer>,
+    priority: KeylessRequestPriority,
+    max_pending: usize,
+    pending: VecDeque<PendingRequest>,
+    /// the count of the pending requests at the front that have been submitted
+    submitted: usize,
+    closed: bool,
+    waker: Arc<AdapterWaker>,
+}
+
+impl SinkAdapter {
+    pub(crate) fn new(
+        transfer: Arc<MultiplexTransfer>,
+        priority: KeylessRequestPriority,
+        max_pending: usize,
+    ) -> Self {
+        SinkAdapter {
+            transfer,
+            priority,
+            max_pending: max_pending.max(1),
+            pending: VecDeque::new(),
+            submitted: 0,
+            closed: false,
+            waker: Arc::new(AdapterWaker::default()),
+        }
+    }
+
+    /// submit the pending requests in order, return true if all of them have been submitted
+    fn submit_pending(&mut self) -> bool {
+        let waker = Waker::from(self.waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        while let Some(req) = self.pending.get_mut(self.submitted) {
+            if !req.submit(&mut cx) {
+                return false;
+            }
+            self.submitted += 1;
+        }
+        true
+    }
+}
+
+impl Sink<KeylessRequest> for SinkAdapter {
+    type Error = u32;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), u32>> {
+        self.waker.sink.register(cx.waker());
+        if self.pending.len() < self.max_pending {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: KeylessRequest) -> Result<(), u32> {
+        let send = self.transfer.send_request(item, self.priority);
+        self.pending
+            .push_back(PendingRequest { send, result: None });
+        self.submit_pending();
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), u32>> {
+        self.waker.sink.register(cx.waker());
+        if self.submit_pending() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), u32>> {
+        self.closed = true;
+        self.waker.stream.wake();
+        self.poll_flush(cx)
+    }
+}
+
+impl Stream for SinkAdapter {
+    type Item = Result<KeylessResponse, u32>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.waker.stream.register(cx.waker());
+        self.submit_pending();
+        if self.submitted == 0 {
+            // either nothing submitted, or the send queue is full
+            return if self.pending.is_empty() && self.closed {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        }
+
+        let waker = Waker::from(self.waker.clone());
+        let mut req_cx = Context::from_waker(&waker);
+        let me = &mut *self;
+        let r = match me.pending.front_mut() {
+            Some(req) => std::task::ready!(req.poll_send(&mut req_cx)),
+            None => return Poll::Pending,
+        };
+        me.pending.pop_front();
+        me.submitted -= 1;
+        me.waker.sink.wake();
+        Poll::Ready(Some(r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+
+    use crate::target::keyless::cloudflare::{KeylessRequestBuilder, KeylessRuntimeStats};
+    use crate::target::keyless::opts::KeylessAction;
+
+    fn build_request() -> KeylessRequest {
+        KeylessRequestBuilder::new(&[0u8; 20], KeylessAction::Ed25519Sign)
+            .unwrap()
+            .build(b"test")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn submission_order() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+        let local_addr: SocketAddr = client.local_addr().unwrap();
+        let runtime_stats = Arc::new(KeylessRuntimeStats::default());
+        let transfer = MultiplexTransfer::start_datagram(
+            client,
+            local_addr,
+            Duration::from_secs(10),
+            None,
+            None,
+            &runtime_stats,
+        );
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            // the third request won't be sent before a response is taken
+            for batch in [2, 1] {
+                let mut reqs = Vec::new();
+                for _ in 0..batch {
+                    let (_, peer) = server.recv_from(&mut buf).await.unwrap();
+                    reqs.push(([buf[4], buf[5], buf[6], buf[7]], peer));
+                }
+                // respond in reverse order
+                for (id, peer) in reqs.into_iter().rev() {
+                    let rsp = [
+                        0x01, 0x00, 0x00, 0x0B, id[0], id[1], id[2], id[3], 0x11, 0x00, 0x01, 0xF0,
+                        0x12, 0x00, 0x04, id[0], id[1], id[2], id[3],
+                    ];
+                    server.send_to(&rsp, peer).await.unwrap();
+                }
+            }
+        });
+
+        let adapter = SinkAdapter::new(Arc::new(transfer), KeylessRequestPriority::Low, 2);
+        let (mut sink, stream) = adapter.split();
+        let send_task = tokio::spawn(async move {
+            for _ in 0..3 {
+                sink.send(build_request()).await.unwrap();
+            }
+            sink.close().await.unwrap();
+        });
+
+        let ids = stream
+            .map(|r| r.unwrap().into_vec())
+            .collect::<Vec<_>>()
+            .await;
+        send_task.await.unwrap();
+        server_task.await.unwrap();
+        assert_eq!(
+            ids,
+            vec![
+                0u32.to_be_bytes().to_vec(),
+                1u32.to_be_bytes().to_vec(),
+                2u32.to_be_bytes().to_vec()
+            ]
+        );
+    }
+}
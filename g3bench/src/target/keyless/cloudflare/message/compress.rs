@@ -0,0 +1,177 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::{self, Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::{KeylessLocalError, KeylessRequest, KeylessResponse, KeylessResponseError};
+
+/// The item tag used for compression negotiation and for marking compressed payloads.
+///
+/// This is not part of the cloudflare keyless protocol, it's taken from the unassigned range.
+pub(super) const COMPRESSION_ITEM_TAG: u8 = 0xC0;
+
+const DECOMPRESSED_SIZE_MAX: usize = u16::MAX as usize;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum KeylessCompression {
+    Deflate = 0x01,
+}
+
+impl KeylessCompression {
+    pub(super) fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0x01 => Some(KeylessCompression::Deflate),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            KeylessCompression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    pub(crate) fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            KeylessCompression::Deflate => {
+                let mut buf = Vec::with_capacity(data.len() * 2);
+                DeflateDecoder::new(data)
+                    .take(DECOMPRESSED_SIZE_MAX as u64 + 1)
+                    .read_to_end(&mut buf)?;
+                if buf.len() > DECOMPRESSED_SIZE_MAX {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "too large decompressed payload",
+                    ));
+                }
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// Send a ping with the compression capability item at connection start.
+///
+/// The server should echo the item back in the pong if it supports the compression.
+/// `None` will be returned if the server ignores the item or replies with a server error,
+/// so the connection can fall back to uncompressed payloads.
+pub(crate) async fn negotiate_compression<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    compression: KeylessCompression,
+) -> Result<Option<KeylessCompression>, KeylessResponseError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let req = KeylessRequest::new_compression_probe(compression);
+    writer
+        .write_all(req.as_bytes())
+        .await
+        .map_err(KeylessLocalError::WriteFailed)?;
+    writer
+        .flush()
+        .await
+        .map_err(KeylessLocalError::WriteFailed)?;
+
+    let mut buf = Vec::with_capacity(64);
+    match KeylessResponse::read(reader, &mut buf).await {
+        Ok(rsp) => Ok(rsp.compression().filter(|c| *c == compression)),
+        Err(KeylessResponseError::ServerError(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn deflate() {
+        let data = [0x5au8; 256];
+        let compressed = KeylessCompression::Deflate.compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        let decompressed = KeylessCompression::Deflate.decompress(&compressed).unwrap();
+        assert_eq!(decompressed, data);
+
+        let large = vec![0u8; DECOMPRESSED_SIZE_MAX + 1];
+        let compressed = KeylessCompression::Deflate.compress(&large).unwrap();
+        assert!(KeylessCompression::Deflate.decompress(&compressed).is_err());
+    }
+
+    async fn negotiate_with_reply(reply: &[u8]) -> Option<KeylessCompression> {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (mut r, mut w) = tokio::io::split(client);
+
+        let mut probe = [0u8; 19];
+        let reply = reply.to_vec();
+        let server_task = tokio::spawn(async move {
+            server.read_exact(&mut probe).await.unwrap();
+            assert_eq!(probe[12], COMPRESSION_ITEM_TAG);
+            server.write_all(&reply).await.unwrap();
+        });
+
+        let r = negotiate_compression(&mut r, &mut w, KeylessCompression::Deflate)
+            .await
+            .unwrap();
+        server_task.await.unwrap();
+        r
+    }
+
+    #[tokio::test]
+    async fn negotiated() {
+        let pong = [
+            0x01, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x00, 0x00, // header
+            0x11, 0x00, 0x01, 0xF2, // opcode
+            0xC0, 0x00, 0x01, 0x01, // compression
+            0x12, 0x00, 0x00, // payload
+        ];
+        assert_eq!(
+            negotiate_with_reply(&pong).await,
+            Some(KeylessCompression::Deflate)
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        // the server ignores the unknown item
+        let pong = [
+            0x01, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00, // header
+            0x11, 0x00, 0x01, 0xF2, // opcode
+            0x12, 0x00, 0x00, // payload
+        ];
+        assert_eq!(negotiate_with_reply(&pong).await, None);
+
+        // the server rejects the unknown item
+        let error = [
+            0x01, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, // header
+            0x11, 0x00, 0x01, 0xFF, // opcode
+            0x12, 0x00, 0x01, 0x07, // format error
+        ];
+        assert_eq!(negotiate_with_reply(&error).await, None);
+    }
+}
@@ -0,0 +1,583 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+use g3_openssl::SslStream;
+use g3_types::collection::{SelectiveVec, WeightedValue};
+use g3_types::net::{OpensslClientConfig, OpensslClientConfigBuilder, UpstreamAddr};
+
+use super::{
+    ClientCertLoader, KeylessCompression, KeylessConnectionBuilder, KeylessRuntimeStats,
+    KeylessTlsRotation, MultiplexTransfer, SimplexTransfer,
+};
+use crate::module::openssl::{self, AppendOpensslArgs, OpensslTlsClientArgs};
+use crate::module::proxy_protocol::{AppendProxyProtocolArgs, ProxyProtocolArgs};
+use crate::opts::ProcArgs;
+use crate::target::keyless::opts::{ARG_PAYLOAD, ARG_VERIFY};
+use crate::target::keyless::{AppendKeylessArgs, KeylessGlobalArgs};
+
+const ARG_CONNECTION_POOL: &str = "connection-pool";
+const ARG_POOL_IDLE_TTL: &str = "pool-idle-ttl";
+const ARG_TARGET: &str = "target";
+const ARG_NO_TLS: &str = "no-tls";
+const ARG_TLS_CERT_ROTATE: &str = "tls-cert-rotate";
+const ARG_UDP: &str = "udp";
+const ARG_LOCAL_ADDRESS: &str = "local-address";
+const ARG_CONNECT_TIMEOUT: &str = "connect-timeout";
+const ARG_TIMEOUT: &str = "timeout";
+const ARG_NO_MULTIPLEX: &str = "no-multiplex";
+const ARG_SLOW_START: &str = "slow-start";
+const ARG_HEARTBEAT_INTERVAL: &str = "heartbeat-interval";
+const ARG_COMPRESS: &str = "compress";
+const ARG_PAYLOAD_DIR: &str = "payload-dir";
+
+pub(super) struct KeylessCloudflareArgs {
+    pub(super) global: KeylessGlobalArgs,
+    pub(super) pool_size: Option<usize>,
+    pub(super) pool_idle_ttl: Option<Duration>,
+    target: UpstreamAddr,
+    bind: Option<IpAddr>,
+    udp: bool,
+    pub(super) no_multiplex: bool,
+    slow_start: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    pub(super) compression: Option<KeylessCompression>,
+    /// the payloads to use in turn, at least one
+    pub(super) payloads: Vec<Vec<u8>>,
+    pub(super) timeout: Duration,
+    pub(super) connect_timeout: Duration,
+    pub(super) tls: OpensslTlsClientArgs,
+    pub(super) tls_rotation: Option<KeylessTlsRotation>,
+    proxy_protocol: ProxyProtocolArgs,
+
+    target_addrs: Option<SelectiveVec<WeightedValue<SocketAddr>>>,
+}
+
+impl KeylessCloudflareArgs {
+    fn new(global_args: KeylessGlobalArgs, target: UpstreamAddr, no_tls: bool) -> Self {
+        let tls = if no_tls {
+            OpensslTlsClientArgs::default()
+        } else {
+            OpensslTlsClientArgs {
+                config: Some(OpensslClientConfigBuilder::with_cache_for_one_site()),
+                ..Default::default()
+            }
+        };
+        KeylessCloudflareArgs {
+            global: global_args,
+            pool_size: None,
+            pool_idle_ttl: None,
+            target,
+            bind: None,
+            udp: false,
+            no_multiplex: false,
+            slow_start: None,
+            heartbeat_interval: None,
+            compression: None,
+            payloads: Vec::new(),
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(10),
+            tls,
+            tls_rotation: None,
+            proxy_protocol: ProxyProtocolArgs::default(),
+            target_addrs: None,
+        }
+    }
+
+    pub(super) async fn resolve_target_address(
+        &mut self,
+        proc_args: &ProcArgs,
+    ) -> anyhow::Result<()> {
+        let addrs = proc_args.resolve(&self.target).await?;
+        self.target_addrs = Some(addrs);
+        Ok(())
+    }
+
+    /// get the tls client for new connections, along with the epoch of it
+    fn tls_client(&self) -> Option<(u64, Arc<OpensslClientConfig>)> {
+        if let Some(rotation) = &self.tls_rotation {
+            return Some(rotation.current());
+        }
+        self.tls.client.as_ref().map(|c| (0, Arc::new(c.clone())))
+    }
+
+    /// check if the connection created with the tls client of `epoch` should be replaced
+    pub(super) fn is_tls_outdated(&self, epoch: u64) -> bool {
+        self.tls_rotation
+            .as_ref()
+            .map(|r| r.epoch() != epoch)
+            .unwrap_or(false)
+    }
+
+    /// load a fresh client certificate pair and use it for new connections
+    pub(super) fn rotate_tls_client(&self) -> anyhow::Result<u64> {
+        let rotation = self
+            .tls_rotation
+            .as_ref()
+            .ok_or_else(|| anyhow!("tls rotation is not enabled"))?;
+        let cert_pair = rotation.load_cert_pair()?;
+        let tls_client = self.tls.build_client_with_cert_pair(cert_pair)?;
+        Ok(rotation.update(tls_client))
+    }
+
+    fn multiplex_builder(
+        &self,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+    ) -> KeylessConnectionBuilder {
+        KeylessConnectionBuilder::new(local_addr, runtime_stats)
+            .peer_addr(peer_addr)
+            .request_timeout(self.timeout)
+            .slow_start_warmup(self.slow_start)
+            .heartbeat_interval(self.heartbeat_interval)
+    }
+
+    pub(super) async fn new_multiplex_keyless_connection(
+        &self,
+        proc_args: &ProcArgs,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+    ) -> anyhow::Result<MultiplexTransfer> {
+        if self.udp {
+            let socket = self.new_udp_connection(proc_args)?;
+            let local_addr = socket
+                .local_addr()
+                .map_err(|e| anyhow!("failed to get local address: {e:?}"))?;
+            let peer_addr = socket
+                .peer_addr()
+                .map_err(|e| anyhow!("failed to get peer address: {e:?}"))?;
+            return Ok(self
+                .multiplex_builder(local_addr, peer_addr, runtime_stats)
+                .start_datagram(socket));
+        }
+
+        let tcp_stream = self.new_tcp_connection(proc_args).await?;
+        let local_addr = tcp_stream
+            .local_addr()
+            .map_err(|e| anyhow!("failed to get local address: {e:?}"))?;
+        let peer_addr = tcp_stream
+            .peer_addr()
+            .map_err(|e| anyhow!("failed to get peer address: {e:?}"))?;
+        if let Some((tls_epoch, tls_client)) = self.tls_client() {
+            let ssl_stream = self.tls_connect_to_target(&tls_client, tcp_stream).await?;
+            let (mut r, mut w) = tokio::io::split(ssl_stream);
+            let compression = self
+                .negotiate_compression(&mut r, &mut w, runtime_stats)
+                .await?;
+            Ok(self
+                .multiplex_builder(local_addr, peer_addr, runtime_stats)
+                .compression(compression)
+                .tls_epoch(tls_epoch)
+                .start(r, w))
+        } else {
+            let (mut r, mut w) = tcp_stream.into_split();
+            let compression = self
+                .negotiate_compression(&mut r, &mut w, runtime_stats)
+                .await?;
+            Ok(self
+                .multiplex_builder(local_addr, peer_addr, runtime_stats)
+                .compression(compression)
+                .start(r, w))
+        }
+    }
+
+    pub(super) async fn new_simplex_keyless_connection(
+        &self,
+        proc_args: &ProcArgs,
+        runtime_stats: &KeylessRuntimeStats,
+    ) -> anyhow::Result<SimplexTransfer> {
+        let tcp_stream = self.new_tcp_connection(proc_args).await?;
+        let local_addr = tcp_stream
+            .local_addr()
+            .map_err(|e| anyhow!("failed to get local address: {e:?}"))?;
+        let peer_addr = tcp_stream
+            .peer_addr()
+            .map_err(|e| anyhow!("failed to get peer address: {e:?}"))?;
+        if let Some((tls_epoch, tls_client)) = self.tls_client() {
+            let ssl_stream = self.tls_connect_to_target(&tls_client, tcp_stream).await?;
+            let (mut r, mut w) = tokio::io::split(ssl_stream);
+            let compression = self
+                .negotiate_compression(&mut r, &mut w, runtime_stats)
+                .await?;
+            let mut connection = SimplexTransfer::new(r, w, local_addr, peer_addr);
+            connection.set_compression(compression);
+            connection.set_tls_epoch(tls_epoch);
+            Ok(connection)
+        } else {
+            let (mut r, mut w) = tcp_stream.into_split();
+            let compression = self
+                .negotiate_compression(&mut r, &mut w, runtime_stats)
+                .await?;
+            let mut connection = SimplexTransfer::new(r, w, local_addr, peer_addr);
+            connection.set_compression(compression);
+            Ok(connection)
+        }
+    }
+
+    async fn negotiate_compression<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        runtime_stats: &KeylessRuntimeStats,
+    ) -> anyhow::Result<Option<KeylessCompression>>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let Some(compression) = self.compression else {
+            return Ok(None);
+        };
+        let negotiated = super::message::negotiate_compression(reader, writer, compression)
+            .await
+            .map_err(|e| anyhow!("compression negotiation failed: {e}"))?;
+        runtime_stats.add_compress_negotiation(negotiated.is_some());
+        Ok(negotiated)
+    }
+
+    async fn new_tcp_connection(&self, proc_args: &ProcArgs) -> anyhow::Result<TcpStream> {
+        let addrs = self
+            .target_addrs
+            .as_ref()
+            .ok_or_else(|| anyhow!("no target addr set"))?;
+        let peer = *proc_args.select_peer(addrs);
+
+        let socket = g3_socket::tcp::new_socket_to(
+            peer.ip(),
+            self.bind,
+            &Default::default(),
+            &Default::default(),
+            true,
+        )
+        .map_err(|e| anyhow!("failed to setup socket to peer {peer}: {e:?}"))?;
+        let mut stream = socket
+            .connect(peer)
+            .await
+            .map_err(|e| anyhow!("connect to {peer} error: {e:?}"))?;
+
+        if let Some(data) = self.proxy_protocol.data() {
+            stream
+                .write_all(data)
+                .await
+                .map_err(|e| anyhow!("failed to write proxy protocol data: {e:?}"))?;
+        }
+
+        Ok(stream)
+    }
+
+    fn new_udp_connection(&self, proc_args: &ProcArgs) -> anyhow::Result<UdpSocket> {
+        let addrs = self
+            .target_addrs
+            .as_ref()
+            .ok_or_else(|| anyhow!("no target addr set"))?;
+        let peer = *proc_args.select_peer(addrs);
+
+        let socket = g3_socket::udp::new_std_socket_to(
+            peer,
+            self.bind,
+            Default::default(),
+            Default::default(),
+        )
+        .map_err(|e| anyhow!("failed to setup local udp socket: {e}"))?;
+        socket
+            .connect(peer)
+            .map_err(|e| anyhow!("failed to connect local udp socket to {peer}: {e}"))?;
+        UdpSocket::from_std(socket).map_err(|e| anyhow!("failed to setup tokio udp socket: {e}"))
+    }
+
+    async fn tls_connect_to_target<S>(
+        &self,
+        tls_client: &OpensslClientConfig,
+        stream: S,
+    ) -> anyhow::Result<SslStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.tls
+            .connect_target(tls_client, stream, &self.target)
+            .await
+    }
+}
+
+pub(super) fn add_cloudflare_args(app: Command) -> Command {
+    app.arg(
+        Arg::new(ARG_TARGET)
+            .help("Target service address")
+            .value_name("ADDRESS")
+            .long(ARG_TARGET)
+            .required(true)
+            .num_args(1)
+            .value_parser(value_parser!(UpstreamAddr)),
+    )
+    .arg(
+        Arg::new(ARG_NO_TLS)
+            .help("Use no tls")
+            .long(ARG_NO_TLS)
+            .action(ArgAction::SetTrue)
+            .num_args(0),
+    )
+    .arg(
+        Arg::new(ARG_TLS_CERT_ROTATE)
+            .value_name("INTERVAL DURATION")
+            .help(
+                "Reload the TLS client certificate and key files in this interval.\n\
+                        TLS renegotiation is not used, so connections will be re-established \
+                        with the new certificate, after the in-flight requests on the old ones are finished",
+            )
+            .long(ARG_TLS_CERT_ROTATE)
+            .num_args(1)
+            .requires(openssl::TLS_ARG_CERT)
+            .conflicts_with_all([ARG_NO_TLS, ARG_UDP]),
+    )
+    .arg(
+        Arg::new(ARG_UDP)
+            .help(
+                "Use udp instead of tcp, with each message sent in a single datagram.\n\
+                        DTLS is not supported, so this should be used along with --no-tls",
+            )
+            .long(ARG_UDP)
+            .action(ArgAction::SetTrue)
+            .num_args(0)
+            .requires(ARG_NO_TLS)
+            .conflicts_with(ARG_NO_MULTIPLEX),
+    )
+    .arg(
+        Arg::new(ARG_CONNECTION_POOL)
+            .help(
+                "Set the number of pooled underlying keyless connections.\n\
+                        If not set, each concurrency will use it's own keyless connection",
+            )
+            .value_name("POOL SIZE")
+            .long(ARG_CONNECTION_POOL)
+            .short('C')
+            .num_args(1)
+            .value_parser(value_parser!(usize))
+            .conflicts_with(ARG_NO_MULTIPLEX),
+    )
+    .arg(
+        Arg::new(ARG_POOL_IDLE_TTL)
+            .value_name("TTL DURATION")
+            .help(
+                "Close pooled connections that have been idle for longer than this duration.\n\
+                        The connection will be re-established on next use",
+            )
+            .long(ARG_POOL_IDLE_TTL)
+            .num_args(1)
+            .requires(ARG_CONNECTION_POOL),
+    )
+    .arg(
+        Arg::new(ARG_LOCAL_ADDRESS)
+            .value_name("LOCAL IP ADDRESS")
+            .short('B')
+            .long(ARG_LOCAL_ADDRESS)
+            .num_args(1)
+            .value_parser(value_parser!(IpAddr)),
+    )
+    .arg(
+        Arg::new(ARG_CONNECT_TIMEOUT)
+            .value_name("TIMEOUT DURATION")
+            .help("Timeout for connection to next peer")
+            .default_value("10s")
+            .long(ARG_CONNECT_TIMEOUT)
+            .num_args(1),
+    )
+    .arg(
+        Arg::new(ARG_TIMEOUT)
+            .value_name("TIMEOUT DURATION")
+            .help("Timeout for a single request")
+            .default_value("5s")
+            .long(ARG_TIMEOUT)
+            .num_args(1),
+    )
+    .arg(
+        Arg::new(ARG_NO_MULTIPLEX)
+            .help("Disable multiplex usage on the connection")
+            .long(ARG_NO_MULTIPLEX)
+            .action(ArgAction::SetTrue)
+            .num_args(0)
+            .conflicts_with_all([ARG_CONNECTION_POOL, ARG_UDP]),
+    )
+    .arg(
+        Arg::new(ARG_SLOW_START)
+            .value_name("WARMUP DURATION")
+            .help(
+                "Enable slow start on each new multiplexed connection.\n\
+                        The allowed in-flight request count will ramp up from 1 to the queue capacity in the warmup duration",
+            )
+            .long(ARG_SLOW_START)
+            .num_args(1)
+            .conflicts_with(ARG_NO_MULTIPLEX),
+    )
+    .arg(
+        Arg::new(ARG_HEARTBEAT_INTERVAL)
+            .value_name("INTERVAL DURATION")
+            .help(
+                "Enable heartbeat on each multiplexed connection.\n\
+                        A ping will be sent if the connection is idle for the interval, \
+                        and the connection will be closed if no response received in the interval while requests are pending",
+            )
+            .long(ARG_HEARTBEAT_INTERVAL)
+            .num_args(1)
+            .conflicts_with(ARG_NO_MULTIPLEX),
+    )
+    .arg(
+        Arg::new(ARG_COMPRESS)
+            .help(
+                "Negotiate deflate payload compression at the start of each connection.\n\
+                        Requests with compressed payload will not be padded. \
+                        Uncompressed payloads will be used if the server doesn't support it",
+            )
+            .long(ARG_COMPRESS)
+            .action(ArgAction::SetTrue)
+            .num_args(0)
+            .conflicts_with(ARG_UDP),
+    )
+    .arg(
+        Arg::new(ARG_PAYLOAD_DIR)
+            .help(
+                "Load the payload data from all files in this directory, in raw format.\n\
+                        The payloads will be used in turn for the requests",
+            )
+            .value_name("DIR")
+            .long(ARG_PAYLOAD_DIR)
+            .num_args(1)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::DirPath)
+            .conflicts_with_all([ARG_PAYLOAD, ARG_VERIFY]),
+    )
+    .append_keyless_args()
+    .mut_arg(ARG_PAYLOAD, |arg| {
+        arg.required(false).required_unless_present(ARG_PAYLOAD_DIR)
+    })
+    .append_openssl_args()
+    .append_proxy_protocol_args()
+}
+
+pub(super) fn parse_cloudflare_args(args: &ArgMatches) -> anyhow::Result<KeylessCloudflareArgs> {
+    let target = if let Some(v) = args.get_one::<UpstreamAddr>(ARG_TARGET) {
+        v.clone()
+    } else {
+        return Err(anyhow!("no target set"));
+    };
+    let no_tls = args.get_flag(ARG_NO_TLS);
+
+    let global_args =
+        KeylessGlobalArgs::parse_args(args).context("failed to parse global keyless args")?;
+
+    let mut cf_args = KeylessCloudflareArgs::new(global_args, target, no_tls);
+
+    if let Some(c) = args.get_one::<usize>(ARG_CONNECTION_POOL) {
+        if *c > 0 {
+            cf_args.pool_size = Some(*c);
+        }
+    }
+    cf_args.pool_idle_ttl = g3_clap::humanize::get_duration(args, ARG_POOL_IDLE_TTL)?;
+
+    if let Some(ip) = args.get_one::<IpAddr>(ARG_LOCAL_ADDRESS) {
+        cf_args.bind = Some(*ip);
+    }
+
+    if let Some(timeout) = g3_clap::humanize::get_duration(args, ARG_CONNECT_TIMEOUT)? {
+        cf_args.connect_timeout = timeout;
+    }
+    if let Some(timeout) = g3_clap::humanize::get_duration(args, ARG_TIMEOUT)? {
+        cf_args.timeout = timeout;
+    }
+
+    if args.get_flag(ARG_UDP) {
+        cf_args.udp = true;
+    }
+    if args.get_flag(ARG_NO_MULTIPLEX) {
+        cf_args.no_multiplex = true;
+    }
+    cf_args.slow_start = g3_clap::humanize::get_duration(args, ARG_SLOW_START)?;
+    cf_args.heartbeat_interval = g3_clap::humanize::get_duration(args, ARG_HEARTBEAT_INTERVAL)?;
+    if args.get_flag(ARG_COMPRESS) {
+        cf_args.compression = Some(KeylessCompression::Deflate);
+    }
+    if let Some(dir) = args.get_one::<PathBuf>(ARG_PAYLOAD_DIR) {
+        cf_args.payloads = load_payload_dir(&cf_args.global, dir)?;
+    } else {
+        cf_args.payloads = vec![cf_args.global.payload.clone()];
+    }
+
+    cf_args
+        .tls
+        .parse_tls_args(args)
+        .context("invalid tls config")?;
+    if let Some(interval) = g3_clap::humanize::get_duration(args, ARG_TLS_CERT_ROTATE)? {
+        if interval.is_zero() {
+            return Err(anyhow!("the tls cert rotate interval should not be zero"));
+        }
+        let (Some(cert_file), Some(key_file)) =
+            (cf_args.tls.cert_file.clone(), cf_args.tls.key_file.clone())
+        else {
+            return Err(anyhow!("no tls client certificate set for rotation"));
+        };
+        let Some(tls_client) = cf_args.tls.client.clone() else {
+            return Err(anyhow!("no tls client config found"));
+        };
+        let loader: ClientCertLoader =
+            Box::new(move || openssl::load_cert_pair(&cert_file, &key_file));
+        cf_args.tls_rotation = Some(KeylessTlsRotation::new(interval, loader, tls_client));
+    }
+    cf_args
+        .proxy_protocol
+        .parse_args(args)
+        .context("invalid proxy protocol config")?;
+    if cf_args.udp && cf_args.proxy_protocol.data().is_some() {
+        return Err(anyhow!("proxy protocol is not supported with udp"));
+    }
+
+    Ok(cf_args)
+}
+
+fn load_payload_dir(global_args: &KeylessGlobalArgs, dir: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow!("failed to read payload dir {}: {e}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| anyhow!("failed to read payload dir {}: {e}", dir.display()))?;
+        let path = entry.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!("no payload file found in dir {}", dir.display()));
+    }
+    files.sort();
+
+    let mut payloads = Vec::with_capacity(files.len());
+    for path in files {
+        let payload = std::fs::read(&path)
+            .map_err(|e| anyhow!("failed to read payload file {}: {e}", path.display()))?;
+        global_args
+            .check_payload(&payload)
+            .context(format!("invalid payload file {}", path.display()))?;
+        payloads.push(payload);
+    }
+    Ok(payloads)
+}
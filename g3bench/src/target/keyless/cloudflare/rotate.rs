@@ -0,0 +1,133 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use g3_types::net::{OpensslCertificatePair, OpensslClientConfig};
+
+use super::{KeylessCloudflareArgs, KeylessRuntimeStats};
+
+/// callback to supply a fresh client certificate pair
+pub(super) type ClientCertLoader =
+    Box<dyn Fn() -> anyhow::Result<OpensslCertificatePair> + Send + Sync>;
+
+/// Rotate the client certificate of the keyless connections.
+///
+/// The keyless transports (TLS 1.3 over a split stream) can't renegotiate, so the rotation is
+/// done by a graceful reconnect: each connection is stamped with the epoch of the tls client it
+/// was created with, and connections of an old epoch will be replaced on next fetch, with the
+/// in-flight requests left to complete on the old ones.
+pub(super) struct KeylessTlsRotation {
+    interval: Duration,
+    loader: ClientCertLoader,
+    epoch: AtomicU64,
+    client: Mutex<Arc<OpensslClientConfig>>,
+}
+
+impl KeylessTlsRotation {
+    pub(super) fn new(
+        interval: Duration,
+        loader: ClientCertLoader,
+        initial: OpensslClientConfig,
+    ) -> Self {
+        KeylessTlsRotation {
+            interval,
+            loader,
+            epoch: AtomicU64::new(0),
+            client: Mutex::new(Arc::new(initial)),
+        }
+    }
+
+    #[inline]
+    pub(super) fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// get the current tls client along with its epoch
+    pub(super) fn current(&self) -> (u64, Arc<OpensslClientConfig>) {
+        let client = self.client.lock().unwrap();
+        (self.epoch(), client.clone())
+    }
+
+    pub(super) fn load_cert_pair(&self) -> anyhow::Result<OpensslCertificatePair> {
+        (self.loader)()
+    }
+
+    pub(super) fn update(&self, new: OpensslClientConfig) -> u64 {
+        let mut client = self.client.lock().unwrap();
+        *client = Arc::new(new);
+        self.epoch.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// spawn a background task to rotate the tls client in every interval.
+    ///
+    /// The task will quit after the args is dropped.
+    pub(super) fn spawn(
+        &self,
+        args: &Arc<KeylessCloudflareArgs>,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+    ) {
+        let args: Weak<KeylessCloudflareArgs> = Arc::downgrade(args);
+        let runtime_stats = runtime_stats.clone();
+        let interval = self.interval;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+
+                let Some(args) = args.upgrade() else {
+                    break;
+                };
+                match args.rotate_tls_client() {
+                    Ok(_) => runtime_stats.add_tls_rotate(),
+                    Err(e) => {
+                        eprintln!("failed to rotate tls client certificate: {e:?}");
+                        runtime_stats.add_tls_rotate_failed();
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use g3_types::net::OpensslClientConfigBuilder;
+
+    #[test]
+    fn update_epoch() {
+        let builder = OpensslClientConfigBuilder::with_cache_for_one_site();
+        let rotation = KeylessTlsRotation::new(
+            Duration::from_secs(60),
+            Box::new(|| Err(anyhow!("no certificate"))),
+            builder.build().unwrap(),
+        );
+        let (epoch, old) = rotation.current();
+        assert_eq!(epoch, 0);
+        assert!(rotation.load_cert_pair().is_err());
+
+        assert_eq!(rotation.update(builder.build().unwrap()), 1);
+        assert_eq!(rotation.epoch(), 1);
+        let (epoch, new) = rotation.current();
+        assert_eq!(epoch, 1);
+        assert!(!Arc::ptr_eq(&old, &new));
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use g3_histogram::{HistogramRecorder, KeepingHistogram};
+use g3_statsd_client::StatsdClient;
+use g3_types::ext::DurationExt;
+
+use crate::target::BenchHistogram;
+
+pub(crate) struct KeylessHistogram {
+    total_time: KeepingHistogram<u64>,
+    conn_reuse_count: KeepingHistogram<u64>,
+    size_total_time: BTreeMap<usize, KeepingHistogram<u64>>,
+}
+
+/// the payload size bucket, which is the next power of two of the payload length
+fn size_bucket(payload_len: usize) -> usize {
+    payload_len.next_power_of_two()
+}
+
+impl KeylessHistogram {
+    /// per payload size total time will be recorded if there are more than one size buckets
+    pub(crate) fn new(payload_sizes: &[usize]) -> (Self, KeylessHistogramRecorder) {
+        let (total_time_h, total_time_r) = KeepingHistogram::new();
+        let (conn_reuse_count_h, conn_reuse_count_r) = KeepingHistogram::new();
+
+        let mut buckets: Vec<usize> = payload_sizes.iter().map(|l| size_bucket(*l)).collect();
+        buckets.sort_unstable();
+        buckets.dedup();
+        let mut size_total_time_h = BTreeMap::new();
+        let mut size_total_time_r = BTreeMap::new();
+        if buckets.len() > 1 {
+            for bucket in buckets {
+                let (h, r) = KeepingHistogram::new();
+                size_total_time_h.insert(bucket, h);
+                size_total_time_r.insert(bucket, r);
+            }
+        }
+
+        let h = KeylessHistogram {
+            total_time: total_time_h,
+            conn_reuse_count: conn_reuse_count_h,
+            size_total_time: size_total_time_h,
+        };
+        let r = KeylessHistogramRecorder {
+            total_time: total_time_r,
+            conn_reuse_count: conn_reuse_count_r,
+            size_total_time: size_total_time_r,
+        };
+        (h, r)
+    }
+}
+
+impl BenchHistogram for KeylessHistogram {
+    fn refresh(&mut self) {
+        self.total_time.refresh().unwrap();
+        self.conn_reuse_count.refresh().unwrap();
+        for h in self.size_total_time.values_mut() {
+            h.refresh().unwrap();
+        }
+    }
+
+    fn emit(&self, client: &mut StatsdClient) {
+        self.emit_histogram(client, self.total_time.inner(), "keyless.time.total");
+    }
+
+    fn summary(&self) {
+        Self::summary_histogram_title("# Connection Re-Usage:");
+        Self::summary_data_line("Req/Conn:", self.conn_reuse_count.inner());
+        Self::summary_histogram_title("# Duration Times");
+        Self::summary_duration_line("Total:", self.total_time.inner());
+        if !self.size_total_time.is_empty() {
+            Self::summary_histogram_title("# Duration Times by Payload Size");
+            for (bucket, h) in &self.size_total_time {
+                Self::summary_duration_line(&format!("<={bucket}B:"), h.inner());
+            }
+        }
+        Self::summary_newline();
+        Self::summary_total_percentage(self.total_time.inner());
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct KeylessHistogramRecorder {
+    total_time: HistogramRecorder<u64>,
+    conn_reuse_count: HistogramRecorder<u64>,
+    size_total_time: BTreeMap<usize, HistogramRecorder<u64>>,
+}
+
+impl KeylessHistogramRecorder {
+    pub(crate) fn record_total_time(&mut self, dur: Duration) {
+        let _ = self.total_time.record(dur.as_nanos_u64());
+    }
+
+    pub(crate) fn record_size_total_time(&mut self, payload_len: usize, dur: Duration) {
+        if let Some(r) = self.size_total_time.get_mut(&size_bucket(payload_len)) {
+            let _ = r.record(dur.as_nanos_u64());
+        }
+    }
+
+    pub(crate) fn record_conn_reuse_count(&mut self, count: u64) {
+        let _ = self.conn_reuse_count.record(count);
+    }
+}
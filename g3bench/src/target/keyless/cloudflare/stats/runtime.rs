@@ -0,0 +1,297 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use g3_statsd_client::StatsdClient;
+
+use crate::target::BenchRuntimeStats;
+
+#[derive(Default)]
+pub(crate) struct KeylessRuntimeStats {
+    task_total: AtomicU64,
+    task_alive: AtomicI64,
+    task_passed: AtomicU64,
+    task_failed: AtomicU64,
+    conn_attempt: AtomicU64,
+    conn_attempt_total: AtomicU64,
+    conn_success: AtomicU64,
+    conn_success_total: AtomicU64,
+    conn_dead: AtomicU64,
+    conn_dead_total: AtomicU64,
+    send_window: AtomicU64,
+    req_queue_depth: AtomicU64,
+    req_queue_peak: AtomicU64,
+    heartbeat_count: AtomicU64,
+    heartbeat_rtt_total: AtomicU64,
+    heartbeat_rtt_max: AtomicU64,
+    compress_negotiated: AtomicU64,
+    compress_fallback: AtomicU64,
+    compress_raw_bytes: AtomicU64,
+    compress_wire_bytes: AtomicU64,
+    pool_size: AtomicU64,
+    pool_alive: AtomicU64,
+    pool_evicted: AtomicU64,
+    pool_evicted_total: AtomicU64,
+    tls_rotate: AtomicU64,
+    tls_rotate_total: AtomicU64,
+    tls_rotate_failed: AtomicU64,
+    tls_rotate_failed_total: AtomicU64,
+    conn_rotated: AtomicU64,
+    conn_rotated_total: AtomicU64,
+}
+
+impl KeylessRuntimeStats {
+    pub(crate) fn add_task_total(&self) {
+        self.task_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inc_task_alive(&self) {
+        self.task_alive.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dec_task_alive(&self) {
+        self.task_alive.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_task_passed(&self) {
+        self.task_passed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_task_failed(&self) {
+        self.task_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_conn_attempt(&self) {
+        self.conn_attempt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_conn_success(&self) {
+        self.conn_success.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_conn_dead(&self) {
+        self.conn_dead.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_heartbeat_rtt(&self, rtt: Duration) {
+        let us = rtt.as_micros() as u64;
+        self.heartbeat_count.fetch_add(1, Ordering::Relaxed);
+        self.heartbeat_rtt_total.fetch_add(us, Ordering::Relaxed);
+        self.heartbeat_rtt_max.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_compress_negotiation(&self, negotiated: bool) {
+        if negotiated {
+            self.compress_negotiated.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.compress_fallback.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// record the payload size before and after compression, for both requests and responses
+    pub(crate) fn add_compress_bytes(&self, raw: usize, wire: usize) {
+        self.compress_raw_bytes
+            .fetch_add(raw as u64, Ordering::Relaxed);
+        self.compress_wire_bytes
+            .fetch_add(wire as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_pool_size(&self, size: usize) {
+        self.pool_size.store(size as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inc_pool_alive(&self) {
+        self.pool_alive.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dec_pool_alive(&self) {
+        self.pool_alive.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_pool_evicted(&self) {
+        self.pool_evicted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_tls_rotate(&self) {
+        self.tls_rotate.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_tls_rotate_failed(&self) {
+        self.tls_rotate_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// a connection is replaced as it's using an outdated client certificate
+    pub(crate) fn add_conn_rotated(&self) {
+        self.conn_rotated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_send_window(&self, n: usize) {
+        self.send_window.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sub_send_window(&self, n: usize) {
+        self.send_window.fetch_sub(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_req_queue_depth(&self, n: usize) {
+        let depth = self.req_queue_depth.fetch_add(n as u64, Ordering::Relaxed) + n as u64;
+        self.req_queue_peak.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sub_req_queue_depth(&self, n: usize) {
+        self.req_queue_depth.fetch_sub(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn req_queue_depth(&self) -> u64 {
+        self.req_queue_depth.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn req_queue_peak(&self) -> u64 {
+        self.req_queue_peak.load(Ordering::Relaxed)
+    }
+}
+
+impl BenchRuntimeStats for KeylessRuntimeStats {
+    fn emit(&self, client: &mut StatsdClient) {
+        macro_rules! emit_count {
+            ($field:ident, $name:literal) => {
+                let $field = self.$field.swap(0, Ordering::Relaxed);
+                client.count(concat!("keyless.", $name), $field).send();
+            };
+        }
+
+        let task_alive = self.task_alive.load(Ordering::Relaxed);
+        client.gauge("keyless.task.alive", task_alive).send();
+        let send_window = self.send_window.load(Ordering::Relaxed);
+        client
+            .gauge("keyless.connection.send_window", send_window)
+            .send();
+        client
+            .gauge("keyless.connection.req_queue_depth", self.req_queue_depth())
+            .send();
+        client
+            .gauge("keyless.connection.req_queue_peak", self.req_queue_peak())
+            .send();
+        if self.pool_size.load(Ordering::Relaxed) > 0 {
+            let pool_alive = self.pool_alive.load(Ordering::Relaxed);
+            client.gauge("keyless.pool.alive", pool_alive).send();
+        }
+
+        emit_count!(task_total, "task.total");
+        emit_count!(task_passed, "task.passed");
+        emit_count!(task_failed, "task.failed");
+        emit_count!(conn_attempt, "connection.attempt");
+        self.conn_attempt_total
+            .fetch_add(conn_attempt, Ordering::Relaxed);
+        emit_count!(conn_success, "connection.success");
+        self.conn_success_total
+            .fetch_add(conn_success, Ordering::Relaxed);
+        emit_count!(conn_dead, "connection.dead");
+        self.conn_dead_total.fetch_add(conn_dead, Ordering::Relaxed);
+        emit_count!(pool_evicted, "pool.evicted");
+        self.pool_evicted_total
+            .fetch_add(pool_evicted, Ordering::Relaxed);
+        emit_count!(tls_rotate, "tls.rotate");
+        self.tls_rotate_total
+            .fetch_add(tls_rotate, Ordering::Relaxed);
+        emit_count!(tls_rotate_failed, "tls.rotate_failed");
+        self.tls_rotate_failed_total
+            .fetch_add(tls_rotate_failed, Ordering::Relaxed);
+        emit_count!(conn_rotated, "connection.rotated");
+        self.conn_rotated_total
+            .fetch_add(conn_rotated, Ordering::Relaxed);
+    }
+
+    fn summary(&self, total_time: Duration) {
+        let total_secs = total_time.as_secs_f64();
+
+        println!("# Connections");
+        let total_attempt = self.conn_attempt_total.load(Ordering::Relaxed)
+            + self.conn_attempt.load(Ordering::Relaxed);
+        println!("Attempt count: {total_attempt}");
+        let total_success = self.conn_success_total.load(Ordering::Relaxed)
+            + self.conn_success.load(Ordering::Relaxed);
+        println!("Success count: {total_success}");
+        println!(
+            "Success ratio: {:.2}%",
+            (total_success as f64 / total_attempt as f64) * 100.0
+        );
+        println!("Success rate:  {:.3}/s", total_success as f64 / total_secs);
+        let total_dead =
+            self.conn_dead_total.load(Ordering::Relaxed) + self.conn_dead.load(Ordering::Relaxed);
+        if total_dead > 0 {
+            println!("Dead count: {total_dead}");
+        }
+        let heartbeat_count = self.heartbeat_count.load(Ordering::Relaxed);
+        if heartbeat_count > 0 {
+            let rtt_total = self.heartbeat_rtt_total.load(Ordering::Relaxed);
+            let rtt_max = self.heartbeat_rtt_max.load(Ordering::Relaxed);
+            println!("Heartbeat count: {heartbeat_count}");
+            println!(
+                "Heartbeat RTT: avg {}us, max {rtt_max}us",
+                rtt_total / heartbeat_count
+            );
+        }
+        let req_queue_peak = self.req_queue_peak();
+        if req_queue_peak > 0 {
+            println!("Request queue peak depth: {req_queue_peak}");
+        }
+
+        let pool_size = self.pool_size.load(Ordering::Relaxed);
+        if pool_size > 0 {
+            println!("# Connection Pool");
+            println!("Pool size: {pool_size}");
+            let total_evicted = self.pool_evicted_total.load(Ordering::Relaxed)
+                + self.pool_evicted.load(Ordering::Relaxed);
+            println!("Idle evicted count: {total_evicted}");
+        }
+
+        let total_rotate =
+            self.tls_rotate_total.load(Ordering::Relaxed) + self.tls_rotate.load(Ordering::Relaxed);
+        let total_rotate_failed = self.tls_rotate_failed_total.load(Ordering::Relaxed)
+            + self.tls_rotate_failed.load(Ordering::Relaxed);
+        if total_rotate + total_rotate_failed > 0 {
+            println!("# TLS Rotation");
+            println!("Rotate count: {total_rotate}");
+            if total_rotate_failed > 0 {
+                println!("Rotate failed count: {total_rotate_failed}");
+            }
+            let total_conn_rotated = self.conn_rotated_total.load(Ordering::Relaxed)
+                + self.conn_rotated.load(Ordering::Relaxed);
+            println!("Reconnect count: {total_conn_rotated}");
+        }
+
+        let compress_negotiated = self.compress_negotiated.load(Ordering::Relaxed);
+        let compress_fallback = self.compress_fallback.load(Ordering::Relaxed);
+        if compress_negotiated + compress_fallback > 0 {
+            println!("# Compression");
+            println!("Negotiated connections: {compress_negotiated}");
+            println!("Fallback connections: {compress_fallback}");
+            let raw_bytes = self.compress_raw_bytes.load(Ordering::Relaxed);
+            let wire_bytes = self.compress_wire_bytes.load(Ordering::Relaxed);
+            if raw_bytes > 0 {
+                println!("Raw payload bytes: {raw_bytes}");
+                println!("Compressed payload bytes: {wire_bytes}");
+                println!(
+                    "Compression ratio: {:.2}%",
+                    (wire_bytes as f64 / raw_bytes as f64) * 100.0
+                );
+            }
+        }
+    }
+}
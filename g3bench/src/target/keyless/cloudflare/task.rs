@@ -0,0 +1,351 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tokio::time::Instant;
+
+use super::{
+    BenchTaskContext, KeylessCloudflareArgs, KeylessConnectionPool, KeylessHistogramRecorder,
+    KeylessRequest, KeylessRequestBuilder, KeylessRequestPriority, KeylessResponse,
+    KeylessRuntimeStats, MultiplexTransfer, SimplexTransfer,
+};
+use crate::opts::ProcArgs;
+use crate::target::keyless::opts::KeylessAction;
+use crate::target::BenchError;
+
+struct CompressedRequest {
+    message: KeylessRequest,
+    raw_len: usize,
+    wire_len: usize,
+}
+
+struct PreparedRequest {
+    message: KeylessRequest,
+    compressed: Option<CompressedRequest>,
+    payload_len: usize,
+}
+
+pub(super) struct KeylessCloudflareTaskContext {
+    args: Arc<KeylessCloudflareArgs>,
+    proc_args: Arc<ProcArgs>,
+
+    pool: Option<Arc<KeylessConnectionPool>>,
+    multiplex: Option<Arc<MultiplexTransfer>>,
+    simplex: Option<SimplexTransfer>,
+
+    reuse_conn_count: u64,
+    requests: Vec<PreparedRequest>,
+    next_request: usize,
+    request_priority: KeylessRequestPriority,
+
+    runtime_stats: Arc<KeylessRuntimeStats>,
+    histogram_recorder: KeylessHistogramRecorder,
+}
+
+impl Drop for KeylessCloudflareTaskContext {
+    fn drop(&mut self) {
+        self.histogram_recorder
+            .record_conn_reuse_count(self.reuse_conn_count);
+    }
+}
+
+impl KeylessCloudflareTaskContext {
+    pub(super) fn new(
+        args: &Arc<KeylessCloudflareArgs>,
+        proc_args: &Arc<ProcArgs>,
+        runtime_stats: &Arc<KeylessRuntimeStats>,
+        histogram_recorder: KeylessHistogramRecorder,
+        pool: Option<Arc<KeylessConnectionPool>>,
+    ) -> anyhow::Result<Self> {
+        let request_builder =
+            KeylessRequestBuilder::new(args.global.subject_key_id(), args.global.action)?;
+        let mut requests = Vec::with_capacity(args.payloads.len());
+        for payload in &args.payloads {
+            let message = request_builder.build(payload)?;
+            let compressed = match args.compression {
+                Some(compression) => {
+                    let wire_len = compression
+                        .compress(payload)
+                        .map_err(|e| anyhow!("failed to compress payload: {e}"))?
+                        .len();
+                    let message = request_builder.build_compressed(payload, compression)?;
+                    Some(CompressedRequest {
+                        message,
+                        raw_len: payload.len(),
+                        wire_len,
+                    })
+                }
+                None => None,
+            };
+            requests.push(PreparedRequest {
+                message,
+                compressed,
+                payload_len: payload.len(),
+            });
+        }
+        // signing is used in TLS handshakes, which is latency critical
+        let request_priority = match args.global.action {
+            KeylessAction::RsaSign(_, _)
+            | KeylessAction::EcdsaSign(_)
+            | KeylessAction::Ed25519Sign => KeylessRequestPriority::High,
+            _ => KeylessRequestPriority::Low,
+        };
+        Ok(KeylessCloudflareTaskContext {
+            args: Arc::clone(args),
+            proc_args: Arc::clone(proc_args),
+            pool,
+            multiplex: None,
+            simplex: None,
+            reuse_conn_count: 0,
+            requests,
+            next_request: 0,
+            request_priority,
+            runtime_stats: Arc::clone(runtime_stats),
+            histogram_recorder,
+        })
+    }
+
+    async fn fetch_multiplex_handle(&mut self) -> anyhow::Result<Arc<MultiplexTransfer>> {
+        if let Some(pool) = &self.pool {
+            return pool.fetch_handle().await;
+        }
+
+        if let Some(handle) = &self.multiplex {
+            if handle.is_closed() {
+                self.multiplex = None;
+            } else if self.args.is_tls_outdated(handle.tls_epoch()) {
+                // the in-flight requests will be finished on the old connection
+                self.multiplex = None;
+                self.runtime_stats.add_conn_rotated();
+            } else {
+                self.reuse_conn_count += 1;
+                return Ok(handle.clone());
+            }
+        }
+
+        if self.reuse_conn_count > 0 {
+            self.histogram_recorder
+                .record_conn_reuse_count(self.reuse_conn_count);
+            self.reuse_conn_count = 0;
+        }
+
+        self.runtime_stats.add_conn_attempt();
+        let handle = match tokio::time::timeout(
+            self.args.connect_timeout,
+            self.args
+                .new_multiplex_keyless_connection(&self.proc_args, &self.runtime_stats),
+        )
+        .await
+        {
+            Ok(Ok(h)) => Arc::new(h),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(anyhow!("timeout to get new connection")),
+        };
+        self.runtime_stats.add_conn_success();
+
+        self.multiplex = Some(handle.clone());
+        Ok(handle)
+    }
+
+    async fn fetch_simplex_connection(&mut self) -> anyhow::Result<SimplexTransfer> {
+        if let Some(mut c) = self.simplex.take() {
+            if !c.is_closed() {
+                if !self.args.is_tls_outdated(c.tls_epoch()) {
+                    self.reuse_conn_count += 1;
+                    return Ok(c);
+                }
+                // no request is in-flight as the connection is taken
+                self.runtime_stats.add_conn_rotated();
+            }
+        }
+
+        if self.reuse_conn_count > 0 {
+            self.histogram_recorder
+                .record_conn_reuse_count(self.reuse_conn_count);
+            self.reuse_conn_count = 0;
+        }
+
+        self.runtime_stats.add_conn_attempt();
+        match tokio::time::timeout(
+            self.args.connect_timeout,
+            self.args
+                .new_simplex_keyless_connection(&self.proc_args, &self.runtime_stats),
+        )
+        .await
+        {
+            Ok(Ok(c)) => {
+                self.runtime_stats.add_conn_success();
+                Ok(c)
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(anyhow!("timeout to get new connection")),
+        }
+    }
+
+    /// use the prepared requests in turn, and return the index of the selected one
+    fn next_request(&mut self) -> usize {
+        let index = self.next_request;
+        self.next_request = (index + 1) % self.requests.len();
+        index
+    }
+
+    /// select the compressed request if compression is negotiated on the connection
+    fn select_request(&self, index: usize, compressed: bool) -> &KeylessRequest {
+        let prepared = &self.requests[index];
+        match &prepared.compressed {
+            Some(req) if compressed => {
+                self.runtime_stats
+                    .add_compress_bytes(req.raw_len, req.wire_len);
+                &req.message
+            }
+            _ => &prepared.message,
+        }
+    }
+
+    fn record_total_time(&mut self, index: usize, total_time: Duration) {
+        self.histogram_recorder.record_total_time(total_time);
+        let payload_len = self.requests[index].payload_len;
+        self.histogram_recorder
+            .record_size_total_time(payload_len, total_time);
+    }
+
+    fn record_response(&self, rsp: &KeylessResponse) {
+        if rsp.compression().is_some() {
+            // the decompressed data is not consumed yet
+            self.runtime_stats
+                .add_compress_bytes(rsp.raw_payload_len(), rsp.wire_payload_len());
+        }
+    }
+
+    async fn do_run_multiplex(
+        &self,
+        handle: &MultiplexTransfer,
+        index: usize,
+    ) -> anyhow::Result<KeylessResponse> {
+        let req = self
+            .select_request(index, handle.compression().is_some())
+            .clone();
+        match tokio::time::timeout(
+            self.args.timeout,
+            handle.send_request(req, self.request_priority),
+        )
+        .await
+        {
+            Ok(Ok(rsp)) => {
+                self.record_response(&rsp);
+                Ok(rsp)
+            }
+            Ok(Err(id)) => match handle.fetch_error() {
+                Some(e) => Err(anyhow!("{}/{id} error: {e}", handle.snapshot())),
+                None => Err(anyhow!(
+                    "{}/{id}: we get no response but no error reported",
+                    handle.snapshot()
+                )),
+            },
+            Err(_) => Err(anyhow!("{}: request timed out", handle.snapshot())),
+        }
+    }
+
+    async fn do_run_simplex(
+        &mut self,
+        connection: &mut SimplexTransfer,
+        index: usize,
+    ) -> anyhow::Result<KeylessResponse> {
+        let mut req = self
+            .select_request(index, connection.compression().is_some())
+            .clone();
+        match tokio::time::timeout(self.args.timeout, connection.send_request(&mut req)).await {
+            Ok(Ok(rsp)) => {
+                self.record_response(&rsp);
+                Ok(rsp)
+            }
+            Ok(Err(e)) => Err(anyhow!(
+                "{}->{} error: {e}",
+                connection.local_addr(),
+                connection.peer_addr()
+            )),
+            Err(_) => Err(anyhow!(
+                "{}->{}: request timed out",
+                connection.local_addr(),
+                connection.peer_addr()
+            )),
+        }
+    }
+}
+
+impl BenchTaskContext for KeylessCloudflareTaskContext {
+    fn mark_task_start(&self) {
+        self.runtime_stats.add_task_total();
+        self.runtime_stats.inc_task_alive();
+    }
+
+    fn mark_task_passed(&self) {
+        self.runtime_stats.add_task_passed();
+        self.runtime_stats.dec_task_alive();
+    }
+
+    fn mark_task_failed(&self) {
+        self.runtime_stats.add_task_failed();
+        self.runtime_stats.dec_task_alive();
+    }
+
+    async fn run(&mut self, task_id: usize, time_started: Instant) -> Result<(), BenchError> {
+        if self.args.no_multiplex {
+            let mut connection = self
+                .fetch_simplex_connection()
+                .await
+                .map_err(BenchError::Fatal)?;
+
+            let index = self.next_request();
+            match self.do_run_simplex(&mut connection, index).await {
+                Ok(rsp) => {
+                    let total_time = time_started.elapsed();
+                    self.simplex = Some(connection);
+                    self.record_total_time(index, total_time);
+                    self.args
+                        .global
+                        .check_result(task_id, rsp.into_vec())
+                        .map_err(BenchError::Task)
+                }
+                Err(e) => Err(BenchError::Task(e)),
+            }
+        } else {
+            let handle = self
+                .fetch_multiplex_handle()
+                .await
+                .map_err(BenchError::Fatal)?;
+
+            let index = self.next_request();
+            match self.do_run_multiplex(&handle, index).await {
+                Ok(rsp) => {
+                    let total_time = time_started.elapsed();
+                    self.record_total_time(index, total_time);
+                    self.args
+                        .global
+                        .check_result(task_id, rsp.into_vec())
+                        .map_err(BenchError::Task)
+                }
+                Err(e) => {
+                    self.multiplex = None;
+                    Err(BenchError::Task(e))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,649 @@
+/*
+ * Copyright 2023 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use governor::RateLimiter;
+use hdrhistogram::Histogram;
+use serde_json::{Map, Value};
+use tokio::sync::{mpsc, Barrier, Semaphore};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use g3_statsd_client::StatsdClient;
+
+use super::ProcArgs;
+use crate::opts::OutputFormat;
+
+mod stats;
+
+mod arrival;
+use arrival::{ArrivalDispatcher, ArrivalProcess, ArrivalReport};
+
+mod concurrency;
+use concurrency::{ConcurrencyControl, ConcurrencyTimeline};
+
+pub mod dns;
+pub mod h1;
+pub mod h2;
+pub mod keyless;
+pub mod openssl;
+pub mod rustls;
+
+#[cfg_attr(feature = "quic", path = "h3/mod.rs")]
+#[cfg_attr(not(feature = "quic"), path = "no_h3.rs")]
+pub mod h3;
+
+const QUANTILE: &str = "quantile";
+
+pub(crate) trait BenchHistogram {
+    fn refresh(&mut self);
+    fn emit(&self, client: &mut StatsdClient);
+
+    fn emit_histogram(&self, client: &mut StatsdClient, histogram: &Histogram<u64>, key: &str) {
+        let min = histogram.min();
+        client.gauge(key, min).with_tag(QUANTILE, "min").send();
+        let max = histogram.max();
+        client.gauge(key, max).with_tag(QUANTILE, "max").send();
+        let mean = histogram.mean();
+        client
+            .gauge_float(key, mean)
+            .with_tag(QUANTILE, "mean")
+            .send();
+        let pct50 = histogram.value_at_quantile(0.50);
+        client.gauge(key, pct50).with_tag(QUANTILE, "0.50").send();
+        let pct80 = histogram.value_at_quantile(0.80);
+        client.gauge(key, pct80).with_tag(QUANTILE, "0.80").send();
+        let pct90 = histogram.value_at_quantile(0.90);
+        client.gauge(key, pct90).with_tag(QUANTILE, "0.90").send();
+        let pct95 = histogram.value_at_quantile(0.95);
+        client.gauge(key, pct95).with_tag(QUANTILE, "0.95").send();
+        let pct98 = histogram.value_at_quantile(0.98);
+        client.gauge(key, pct98).with_tag(QUANTILE, "0.98").send();
+        let pct99 = histogram.value_at_quantile(0.99);
+        client.gauge(key, pct99).with_tag(QUANTILE, "0.99").send();
+    }
+
+    fn summary(&self);
+
+    fn summary_histogram_title(title: &str) {
+        println!("{title}");
+        println!("                 min      mean[+/-sd]        pct90       max");
+    }
+
+    fn summary_newline() {
+        println!();
+    }
+
+    fn summary_data_line(name: &str, h: &Histogram<u64>) {
+        let d_min = h.min();
+        let d_mean = h.mean();
+        let d_std_dev = h.stdev();
+        let d_pct90 = h.value_at_quantile(0.90);
+        let d_max = h.max();
+
+        println!(
+            "{name:<10} {d_min:>9.3?} {d_mean:>9.3?} {d_std_dev:<9.3?} {d_pct90:>9.3?} {d_max:>9.3?}"
+        );
+    }
+
+    fn summary_duration_line(name: &str, h: &Histogram<u64>) {
+        const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+        let t_min = Duration::from_nanos(h.min());
+        let t_mean = Duration::from_secs_f64(h.mean() / NANOS_PER_SEC);
+        let t_std_dev = Duration::from_secs_f64(h.stdev() / NANOS_PER_SEC);
+        let t_pct90 = Duration::from_nanos(h.value_at_quantile(0.90));
+        let t_max = Duration::from_nanos(h.max());
+
+        println!(
+            "{name:<10} {t_min:>9.3?} {t_mean:>9.3?} {t_std_dev:9.3?} {t_pct90:>9.3?} {t_max:>9.3?}"
+        );
+    }
+
+    fn summary_total_percentage(h: &Histogram<u64>) {
+        macro_rules! print_pct {
+            ($pct:literal) => {
+                let v = Duration::from_nanos(h.value_at_percentile($pct as f64));
+                println!("{:4}% {v:8.3?}", $pct);
+            };
+        }
+
+        println!("Percentage of the requests served within a certain time");
+
+        print_pct!(50);
+        print_pct!(66);
+        print_pct!(75);
+        print_pct!(80);
+        print_pct!(90);
+        print_pct!(95);
+        print_pct!(98);
+        print_pct!(99);
+        print_pct!(100);
+    }
+
+    fn summary_json(&self) -> Option<Value> {
+        None
+    }
+
+    /// write the histogram to the extra output files if configured
+    fn write_output_files(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn json_histogram(h: &Histogram<u64>) -> Value {
+        let mut map = Map::new();
+        map.insert("count".to_string(), h.len().into());
+        map.insert("min".to_string(), h.min().into());
+        map.insert("max".to_string(), h.max().into());
+        map.insert("mean".to_string(), h.mean().into());
+        map.insert("stdev".to_string(), h.stdev().into());
+
+        let mut percentiles = Map::new();
+        for pct in [
+            50.0, 66.0, 75.0, 80.0, 90.0, 95.0, 98.0, 99.0, 99.9, 99.99, 100.0,
+        ] {
+            percentiles.insert(pct.to_string(), h.value_at_percentile(pct).into());
+        }
+        map.insert("percentiles".to_string(), Value::Object(percentiles));
+        Value::Object(map)
+    }
+}
+
+pub(crate) trait BenchRuntimeStats {
+    fn emit(&self, client: &mut StatsdClient);
+    fn summary(&self, total_time: Duration);
+
+    fn summary_json(&self, _total_time: Duration) -> Option<Value> {
+        None
+    }
+}
+
+enum BenchError {
+    Fatal(anyhow::Error),
+    Task(anyhow::Error),
+}
+
+trait BenchTaskContext {
+    fn mark_task_start(&self);
+    fn mark_task_passed(&self);
+    fn mark_task_failed(&self);
+
+    // TODO use native async fn declaration
+    fn run(
+        &mut self,
+        task_id: usize,
+        time_started: Instant,
+    ) -> impl Future<Output = Result<(), BenchError>> + Send;
+}
+
+trait BenchTarget<RS, H, C>
+where
+    RS: BenchRuntimeStats,
+    H: BenchHistogram,
+    C: BenchTaskContext,
+{
+    fn new_context(&self) -> anyhow::Result<C>;
+    fn fetch_runtime_stats(&self) -> Arc<RS>;
+    fn take_histogram(&mut self) -> Option<H>;
+
+    fn notify_finish(&mut self) {}
+
+    /// the open-loop arrival process, the task contexts will run in closed-loop if not set
+    fn arrival(&self) -> Option<ArrivalProcess> {
+        None
+    }
+
+    /// the step to change the concurrency at runtime by signals, the concurrency is fixed if not set
+    fn concurrency_step(&self) -> Option<usize> {
+        None
+    }
+
+    /// check the final result after the report has been printed,
+    /// an error should be returned if the run should be treated as failed
+    fn check_result(
+        &self,
+        _histogram: Option<&H>,
+        _passed: usize,
+        _failed: usize,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn register_signal_handler() {
+    tokio::spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("error when waiting Ctrl-C: {e}");
+        }
+        stats::mark_force_quit();
+    });
+}
+
+async fn run<RS, H, C, T>(mut target: T, proc_args: &ProcArgs) -> anyhow::Result<()>
+where
+    RS: BenchRuntimeStats + Send + Sync + 'static,
+    H: BenchHistogram + Send + 'static,
+    C: BenchTaskContext + Send + 'static,
+    T: BenchTarget<RS, H, C> + Send + Sync + 'static,
+{
+    let sync_sem = Arc::new(Semaphore::new(0));
+    let sync_barrier = Arc::new(Barrier::new(proc_args.concurrency + 1));
+    let (sender, mut receiver) =
+        mpsc::channel::<(usize, Option<Histogram<u64>>)>(proc_args.concurrency);
+    let progress = proc_args.new_progress_bar();
+    let progress_counter = progress.as_ref().map(|p| p.counter());
+
+    stats::init_global_state(proc_args.requests, proc_args.log_error_count);
+    register_signal_handler();
+
+    let rate_limit = proc_args
+        .rate_limit
+        .as_ref()
+        .map(|c| Arc::new(RateLimiter::direct(c.get_inner())));
+    let arrival = target.arrival();
+    let (arrival_dispatcher, arrival_queue) = match arrival {
+        Some(process) => {
+            let (dispatcher, queue) = ArrivalDispatcher::new(process);
+            (Some(dispatcher), Some(Arc::new(queue)))
+        }
+        None => (None, None),
+    };
+    let concurrency_step = target.concurrency_step();
+    let concurrency_control = concurrency_step.map(|_| Arc::new(ConcurrencyControl::default()));
+    let task_unconstrained = proc_args.task_unconstrained;
+    let latency = proc_args.latency;
+    let ignore_fatal_error = proc_args.ignore_fatal_error;
+    let spawn_task = {
+        let concurrency_control = concurrency_control.clone();
+        move |i: usize, mut context: C, start_sync: Option<(Arc<Semaphore>, Arc<Barrier>)>| {
+            let quit_sender = sender.clone();
+            let progress_counter = progress_counter.clone();
+            let rate_limit = rate_limit.clone();
+            let arrival_queue = arrival_queue.clone();
+            let concurrency_control = concurrency_control.clone();
+            let rt =
+                super::worker::select_handle(i).unwrap_or_else(tokio::runtime::Handle::current);
+            rt.spawn(async move {
+                if let Some((sem, barrier)) = start_sync {
+                    sem.add_permits(1);
+                    barrier.wait().await;
+                }
+
+                let mut latency_interval = if let Some(latency) = latency {
+                    let mut interval = tokio::time::interval(latency);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    Some(interval)
+                } else {
+                    None
+                };
+
+                let global_state = stats::global_state();
+                let mut req_count = 0;
+                let mut queue_delay = arrival_queue
+                    .as_ref()
+                    .map(|_| Histogram::<u64>::new(3).unwrap());
+                loop {
+                    if let Some(control) = &concurrency_control {
+                        if control.try_retire() {
+                            break;
+                        }
+                    }
+
+                    let task_id = match &arrival_queue {
+                        Some(queue) => {
+                            let Some((task_id, arrived)) = queue.recv().await else {
+                                break;
+                            };
+                            if let Some(h) = &mut queue_delay {
+                                let _ = h.record(arrived.elapsed().as_nanos() as u64);
+                            }
+                            task_id
+                        }
+                        None => {
+                            let Some(task_id) = global_state.fetch_request() else {
+                                break;
+                            };
+                            task_id
+                        }
+                    };
+
+                    if let Some(latency) = &mut latency_interval {
+                        latency.tick().await;
+                    }
+
+                    if let Some(r) = &rate_limit {
+                        while let Err(t) = r.check() {
+                            tokio::time::sleep_until(t.earliest_possible().into()).await;
+                        }
+                    }
+
+                    let time_start = Instant::now();
+                    context.mark_task_start();
+                    let rt = if task_unconstrained {
+                        tokio::task::unconstrained(context.run(task_id, time_start)).await
+                    } else {
+                        context.run(task_id, time_start).await
+                    };
+                    match rt {
+                        Ok(_) => {
+                            context.mark_task_passed();
+                            if let Some(c) = progress_counter.as_ref() {
+                                c.inc();
+                            }
+                            global_state.add_passed();
+                        }
+                        Err(BenchError::Fatal(e)) => {
+                            context.mark_task_failed();
+                            global_state.add_failed();
+                            if ignore_fatal_error {
+                                if global_state.check_log_error() {
+                                    eprintln!("! request {task_id} failed: {e:?}\n");
+                                }
+                            } else {
+                                eprintln!("!! Fatal error with task context {i}: {e:?}");
+                                break;
+                            }
+                        }
+                        Err(BenchError::Task(e)) => {
+                            context.mark_task_failed();
+                            global_state.add_failed();
+                            if global_state.check_log_error() {
+                                eprintln!("! request {task_id} failed: {e:?}\n");
+                            }
+                        }
+                    }
+                    req_count += 1;
+                }
+
+                drop(context);
+                if let Err(e) = quit_sender.send((req_count, queue_delay)).await {
+                    eprintln!("failed to send quit signal: {e}");
+                }
+            });
+        }
+    };
+    for i in 0..proc_args.concurrency {
+        let context = target
+            .new_context()
+            .context(format!("failed to to create context #{i}"))?;
+        spawn_task(
+            i,
+            context,
+            Some((Arc::clone(&sync_sem), Arc::clone(&sync_barrier))),
+        );
+    }
+    // keep the spawner only if the concurrency can be changed at runtime,
+    // so the quit channel will be closed after all task contexts quit
+    let mut task_spawner = concurrency_step.map(|_| spawn_task);
+    let mut concurrency_changes = match concurrency_step {
+        Some(step) => Some(concurrency::spawn_signal_listener(step)?),
+        None => None,
+    };
+
+    let _run_permit = sync_sem
+        .acquire_many(proc_args.concurrency as u32)
+        .await
+        .context("failed to start all task contexts")?;
+
+    let quit_notifier = Arc::new(AtomicBool::new(false));
+    // progress bar
+    let progress_bar_handler = if let Some(progress) = progress {
+        let handler = progress.spawn(quit_notifier.clone())?;
+        Some(handler)
+    } else {
+        None
+    };
+    // simple runtime stats
+    let runtime_stats_handler =
+        if let Some((mut statsd_client, emit_duration)) = proc_args.new_statsd_client() {
+            let runtime_stats = target.fetch_runtime_stats();
+            let quit_notifier = quit_notifier.clone();
+            let handler = std::thread::Builder::new()
+                .name("runtime-stats".to_string())
+                .spawn(move || loop {
+                    runtime_stats.emit(&mut statsd_client);
+                    statsd_client.flush_sink();
+
+                    if quit_notifier.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    std::thread::sleep(emit_duration);
+                })
+                .map_err(|e| anyhow!("failed to create runtime stats thread: {e}"))?;
+            Some(handler)
+        } else {
+            None
+        };
+    // histogram runtime stats
+    let histogram_stats_handler = if let Some(mut histogram) = target.take_histogram() {
+        let quit_notifier = quit_notifier.clone();
+        let thread_builder = std::thread::Builder::new().name("histogram".to_string());
+        if let Some((mut statsd_client, emit_duration)) = proc_args.new_statsd_client() {
+            let handler = thread_builder
+                .spawn(move || {
+                    loop {
+                        histogram.refresh();
+                        histogram.emit(&mut statsd_client);
+
+                        if quit_notifier.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        std::thread::sleep(emit_duration);
+                    }
+                    histogram
+                })
+                .map_err(|e| anyhow!("failed to create histogram metrics thread: {e}"))?;
+            Some(handler)
+        } else {
+            let handler = thread_builder
+                .spawn(move || {
+                    loop {
+                        histogram.refresh();
+
+                        if quit_notifier.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        std::thread::sleep(Duration::from_millis(100));
+                    }
+                    histogram
+                })
+                .map_err(|e| anyhow!("failed to create histogram refresh thread: {e}"))?;
+            Some(handler)
+        }
+    } else {
+        None
+    };
+
+    let time_start = Instant::now();
+    sync_barrier.wait().await;
+    let arrival_handle = arrival_dispatcher.map(|dispatcher| dispatcher.spawn());
+
+    if let Some(time_limit) = proc_args.time_limit {
+        std::thread::Builder::new()
+            .name("quit-timer".to_string())
+            .spawn(move || {
+                std::thread::sleep(time_limit);
+                stats::mark_force_quit();
+            })
+            .map_err(|e| anyhow!("failed to create quit timer thread: {e}"))?;
+    }
+
+    let mut distribute_histogram = Histogram::<u64>::new(3).unwrap();
+    let mut queue_delay_histogram = Histogram::<u64>::new(3).unwrap();
+    let mut concurrency_timeline =
+        concurrency_step.map(|_| ConcurrencyTimeline::new(proc_args.concurrency));
+    let mut running = proc_args.concurrency;
+    let mut next_task_id = proc_args.concurrency;
+    loop {
+        let msg = match &mut concurrency_changes {
+            Some(changes) => tokio::select! {
+                msg = receiver.recv() => msg,
+                change = changes.recv() => {
+                    let (Some(change), Some(timeline), Some(control), Some(spawn_task)) = (
+                        change,
+                        &mut concurrency_timeline,
+                        &concurrency_control,
+                        &task_spawner,
+                    ) else {
+                        concurrency_changes = None;
+                        continue;
+                    };
+
+                    let current = timeline.target();
+                    let target_concurrency = timeline.apply(time_start.elapsed(), change);
+                    if target_concurrency > current {
+                        let add = target_concurrency - current;
+                        let add = add - control.cancel_retire(add);
+                        for _ in 0..add {
+                            let i = next_task_id;
+                            next_task_id += 1;
+                            match target.new_context() {
+                                Ok(context) => {
+                                    spawn_task(i, context, None);
+                                    running += 1;
+                                }
+                                Err(e) => {
+                                    eprintln!("failed to create context #{i}: {e:?}");
+                                    break;
+                                }
+                            }
+                        }
+                    } else if target_concurrency < current {
+                        control.retire(current - target_concurrency);
+                    }
+                    continue;
+                }
+            },
+            None => receiver.recv().await,
+        };
+        let Some((req_count, queue_delay)) = msg else {
+            break;
+        };
+
+        distribute_histogram.record(req_count as u64).unwrap();
+        if let Some(h) = queue_delay {
+            let _ = queue_delay_histogram.add(h);
+        }
+        running = running.saturating_sub(1);
+        if running == 0 {
+            task_spawner = None;
+            concurrency_changes = None;
+        }
+    }
+    let total_time = time_start.elapsed();
+
+    let arrival_report = match (arrival, arrival_handle) {
+        (Some(process), Some(handle)) => {
+            let (dispatched, time_spent) = handle
+                .await
+                .map_err(|e| anyhow!("failed to join arrival dispatcher: {e}"))?;
+            Some(ArrivalReport::new(
+                process,
+                dispatched,
+                time_spent,
+                queue_delay_histogram,
+            ))
+        }
+        _ => None,
+    };
+
+    quit_notifier.store(true, Ordering::Relaxed);
+
+    if let Some(handler) = progress_bar_handler {
+        match handler.join() {
+            Ok(bar) => bar.finish(),
+            Err(e) => eprintln!("error to join progress bar thread: {e:?}"),
+        }
+    }
+
+    if let Some(handler) = runtime_stats_handler {
+        let _ = handler.join();
+    }
+    target.notify_finish();
+    let histogram = match histogram_stats_handler {
+        Some(handler) => match handler.join() {
+            Ok(mut histogram) => {
+                histogram.refresh();
+                Some(histogram)
+            }
+            Err(e) => {
+                eprintln!("error to join histogram stats thread: {e:?}");
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(histogram) = &histogram {
+        histogram.write_output_files()?;
+    }
+
+    match proc_args.output_format {
+        OutputFormat::Text => {
+            stats::global_state().summary(total_time, &distribute_histogram);
+            if let Some(report) = &arrival_report {
+                report.summary();
+            }
+            if let Some(timeline) = &concurrency_timeline {
+                timeline.summary();
+            }
+            H::summary_newline();
+            target.fetch_runtime_stats().summary(total_time);
+            if let Some(histogram) = &histogram {
+                histogram.summary();
+            }
+        }
+        OutputFormat::Json => {
+            let mut map = Map::new();
+            map.insert(
+                "global".to_string(),
+                stats::global_state().summary_json(total_time, &distribute_histogram),
+            );
+            if let Some(report) = &arrival_report {
+                map.insert("arrival".to_string(), report.summary_json());
+            }
+            if let Some(timeline) = &concurrency_timeline {
+                map.insert("concurrency".to_string(), timeline.summary_json());
+            }
+            if let Some(v) = target.fetch_runtime_stats().summary_json(total_time) {
+                map.insert("runtime".to_string(), v);
+            }
+            if let Some(v) = histogram.as_ref().and_then(|h| h.summary_json()) {
+                map.insert("histogram".to_string(), v);
+            }
+            let report = Value::Object(map).to_string();
+            if let Some(path) = &proc_args.output_file {
+                std::fs::write(path, report)
+                    .map_err(|e| anyhow!("failed to write report to {}: {e}", path.display()))?;
+            } else {
+                println!("{report}");
+            }
+        }
+    }
+
+    let global_state = stats::global_state();
+    target.check_result(
+        histogram.as_ref(),
+        global_state.passed(),
+        global_state.failed(),
+    )
+}
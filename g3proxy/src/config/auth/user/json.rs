@@ -22,6 +22,7 @@ use g3_types::metrics::MetricsName;
 use serde_json::{Map, Value};
 
 use super::{PasswordToken, UserConfig, UserSiteConfig};
+use crate::config::escaper::proxy_float::PeerSelectionMode;
 use crate::escape::EgressPathSelection;
 
 impl UserConfig {
@@ -226,6 +227,32 @@ impl UserConfig {
                 ));
                 Ok(())
             }
+            "egress_max_rtt" => {
+                let rtt = g3_json::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                self.egress_max_rtt = Some(rtt);
+                Ok(())
+            }
+            "egress_area" => {
+                let area = g3_json::value::as_egress_area(v)
+                    .context(format!("invalid egress area value for key {k}"))?;
+                self.egress_area = Some(area);
+                Ok(())
+            }
+            "egress_isp" => {
+                let isp = g3_json::value::as_string(v)
+                    .context(format!("invalid string value for key {k}"))?;
+                self.egress_isp = Some(isp);
+                Ok(())
+            }
+            "egress_peer_selection" => {
+                let s = g3_json::value::as_string(v)
+                    .context(format!("invalid string value for key {k}"))?;
+                let mode = PeerSelectionMode::from_str(&s)
+                    .map_err(|_| anyhow!("unsupported peer selection mode {s} for key {k}"))?;
+                self.egress_peer_selection = Some(mode);
+                Ok(())
+            }
             _ => Err(anyhow!("invalid key {k}")),
         }
     }
@@ -28,12 +28,13 @@ use g3_types::acl_set::AclDstHostRuleSetBuilder;
 use g3_types::limit::RateLimitQuotaConfig;
 use g3_types::metrics::MetricsName;
 use g3_types::net::{
-    HttpKeepAliveConfig, TcpConnectConfig, TcpKeepAliveConfig, TcpMiscSockOpts,
+    EgressArea, HttpKeepAliveConfig, TcpConnectConfig, TcpKeepAliveConfig, TcpMiscSockOpts,
     TcpSockSpeedLimitConfig, UdpMiscSockOpts, UdpSockSpeedLimitConfig,
 };
 use g3_types::resolve::{ResolveRedirectionBuilder, ResolveStrategy};
 
 use super::{PasswordToken, UserAuditConfig, UserSiteConfig};
+use crate::config::escaper::proxy_float::PeerSelectionMode;
 use crate::escape::EgressPathSelection;
 
 mod json;
@@ -71,6 +72,10 @@ pub(crate) struct UserConfig {
     pub(crate) task_idle_max_count: i32,
     pub(crate) socks_use_udp_associate: bool,
     pub(crate) egress_path_selection: Option<EgressPathSelection>,
+    pub(crate) egress_max_rtt: Option<Duration>,
+    pub(crate) egress_area: Option<EgressArea>,
+    pub(crate) egress_isp: Option<String>,
+    pub(crate) egress_peer_selection: Option<PeerSelectionMode>,
     pub(crate) explicit_sites: BTreeMap<MetricsName, Arc<UserSiteConfig>>,
 }
 
@@ -107,6 +112,10 @@ impl Default for UserConfig {
             task_idle_max_count: 1,
             socks_use_udp_associate: false,
             egress_path_selection: None,
+            egress_max_rtt: None,
+            egress_area: None,
+            egress_isp: None,
+            egress_peer_selection: None,
             explicit_sites: BTreeMap::new(),
         }
     }
@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+use std::str::FromStr;
+
 use ahash::AHashMap;
 use anyhow::{anyhow, Context};
 use yaml_rust::{yaml, Yaml};
@@ -21,6 +23,7 @@ use yaml_rust::{yaml, Yaml};
 use g3_yaml::YamlDocPosition;
 
 use super::{PasswordToken, UserConfig, UserSiteConfig};
+use crate::config::escaper::proxy_float::PeerSelectionMode;
 use crate::escape::EgressPathSelection;
 
 impl UserConfig {
@@ -231,6 +234,32 @@ impl UserConfig {
                 ));
                 Ok(())
             }
+            "egress_max_rtt" => {
+                let rtt = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                self.egress_max_rtt = Some(rtt);
+                Ok(())
+            }
+            "egress_area" => {
+                let area = g3_yaml::value::as_egress_area(v)
+                    .context(format!("invalid egress area value for key {k}"))?;
+                self.egress_area = Some(area);
+                Ok(())
+            }
+            "egress_isp" => {
+                let isp = g3_yaml::value::as_string(v)
+                    .context(format!("invalid string value for key {k}"))?;
+                self.egress_isp = Some(isp);
+                Ok(())
+            }
+            "egress_peer_selection" => {
+                let s = g3_yaml::value::as_string(v)
+                    .context(format!("invalid string value for key {k}"))?;
+                let mode = PeerSelectionMode::from_str(&s)
+                    .map_err(|_| anyhow!("unsupported peer selection mode {s} for key {k}"))?;
+                self.egress_peer_selection = Some(mode);
+                Ok(())
+            }
             _ => Err(anyhow!("invalid key {k}")),
         }
     }
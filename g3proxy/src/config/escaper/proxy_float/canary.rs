@@ -0,0 +1,65 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use yaml_rust::Yaml;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PeerCanaryConfig {
+    pub(crate) sample: u32,
+    pub(crate) timeout: Duration,
+}
+
+impl Default for PeerCanaryConfig {
+    fn default() -> Self {
+        PeerCanaryConfig {
+            sample: 100,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PeerCanaryConfig {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let mut config = PeerCanaryConfig::default();
+        match v {
+            Yaml::Hash(map) => {
+                g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+                    "sample" => {
+                        config.sample = g3_yaml::value::as_u32(v)?;
+                        Ok(())
+                    }
+                    "timeout" => {
+                        config.timeout = g3_yaml::humanize::as_duration(v)
+                            .context(format!("invalid humanize duration value for key {k}"))?;
+                        Ok(())
+                    }
+                    _ => Err(anyhow!("invalid key {k}")),
+                })?;
+            }
+            Yaml::Integer(_) => {
+                config.sample = g3_yaml::value::as_u32(v)?;
+            }
+            _ => return Err(anyhow!("invalid yaml value type")),
+        }
+        if config.sample == 0 {
+            return Err(anyhow!("sample should not be 0"));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,72 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use yaml_rust::Yaml;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct PeerCircuitBreakerConfig {
+    pub(crate) failure_threshold: u32,
+    pub(crate) failure_window: Duration,
+    pub(crate) cooldown: Duration,
+}
+
+impl Default for PeerCircuitBreakerConfig {
+    fn default() -> Self {
+        PeerCircuitBreakerConfig {
+            failure_threshold: 5,
+            failure_window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl PeerCircuitBreakerConfig {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let mut config = PeerCircuitBreakerConfig::default();
+        match v {
+            Yaml::Hash(map) => {
+                g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+                    "failure_threshold" => {
+                        config.failure_threshold = g3_yaml::value::as_u32(v)?;
+                        Ok(())
+                    }
+                    "failure_window" => {
+                        config.failure_window = g3_yaml::humanize::as_duration(v)
+                            .context(format!("invalid humanize duration value for key {k}"))?;
+                        Ok(())
+                    }
+                    "cooldown" => {
+                        config.cooldown = g3_yaml::humanize::as_duration(v)
+                            .context(format!("invalid humanize duration value for key {k}"))?;
+                        Ok(())
+                    }
+                    _ => Err(anyhow!("invalid key {k}")),
+                })?;
+            }
+            Yaml::Integer(_) => {
+                config.failure_threshold = g3_yaml::value::as_u32(v)?;
+            }
+            _ => return Err(anyhow!("invalid yaml value type")),
+        }
+        if config.failure_threshold == 0 {
+            return Err(anyhow!("failure threshold should not be 0"));
+        }
+        Ok(config)
+    }
+}
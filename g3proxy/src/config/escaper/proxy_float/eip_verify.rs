@@ -0,0 +1,86 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use url::Url;
+use yaml_rust::Yaml;
+
+use g3_types::net::UpstreamAddr;
+
+const CONFIG_KEY_URL: &str = "url";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PeerEipVerifyConfig {
+    pub(crate) url: Url,
+    pub(crate) addr: UpstreamAddr,
+    pub(crate) sample: u32,
+    pub(crate) timeout: Duration,
+}
+
+impl PeerEipVerifyConfig {
+    fn new(url: Url) -> anyhow::Result<Self> {
+        if url.scheme() != "http" {
+            return Err(anyhow!("unsupported url scheme {}", url.scheme()));
+        }
+        let addr = UpstreamAddr::try_from(&url)?;
+        Ok(PeerEipVerifyConfig {
+            url,
+            addr,
+            sample: 100,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// the path and query to be used in the request line
+    pub(crate) fn request_target(&self) -> &str {
+        &self.url[url::Position::BeforePath..]
+    }
+
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        match v {
+            Yaml::Hash(map) => {
+                let v = g3_yaml::hash_get_required(map, CONFIG_KEY_URL)?;
+                let url = g3_yaml::value::as_url(v)
+                    .context(format!("invalid url value for key {CONFIG_KEY_URL}"))?;
+                let mut config = PeerEipVerifyConfig::new(url)?;
+                g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+                    CONFIG_KEY_URL => Ok(()),
+                    "sample" => {
+                        config.sample = g3_yaml::value::as_u32(v)?;
+                        Ok(())
+                    }
+                    "timeout" => {
+                        config.timeout = g3_yaml::humanize::as_duration(v)
+                            .context(format!("invalid humanize duration value for key {k}"))?;
+                        Ok(())
+                    }
+                    _ => Err(anyhow!("invalid key {k}")),
+                })?;
+                if config.sample == 0 {
+                    return Err(anyhow!("sample should not be 0"));
+                }
+                Ok(config)
+            }
+            Yaml::String(_) => {
+                let url = g3_yaml::value::as_url(v)?;
+                PeerEipVerifyConfig::new(url)
+            }
+            _ => Err(anyhow!("invalid yaml value type")),
+        }
+    }
+}
@@ -14,21 +14,25 @@
  * limitations under the License.
  */
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Context};
 use ascii::AsciiString;
+use http::HeaderName;
 use log::warn;
 use yaml_rust::{yaml, Yaml};
 
-use g3_types::metrics::{MetricsName, StaticMetricsTags};
+use g3_types::auth::{Password, Username};
+use g3_types::metrics::{MetricsName, MetricsTagName, StaticMetricsTags};
 use g3_types::net::{
     OpensslClientConfigBuilder, TcpKeepAliveConfig, TcpMiscSockOpts, UdpMiscSockOpts,
 };
+use g3_types::resolve::ResolveStrategy;
 use g3_yaml::YamlDocPosition;
 
 use super::{AnyEscaperConfig, EscaperConfig, EscaperConfigDiffAction};
@@ -36,8 +40,29 @@ use super::{AnyEscaperConfig, EscaperConfig, EscaperConfigDiffAction};
 pub(crate) mod source;
 pub(crate) use source::ProxyFloatSource;
 
+mod canary;
+pub(crate) use canary::PeerCanaryConfig;
+
+mod circuit_breaker;
+pub(crate) use circuit_breaker::PeerCircuitBreakerConfig;
+
+mod eip_verify;
+pub(crate) use eip_verify::PeerEipVerifyConfig;
+
+mod peer_limit;
+pub(crate) use peer_limit::{PeerCountLimitConfig, PeerKeepPriority};
+
+mod selection;
+pub(crate) use selection::{PeerAreaFallbackConfig, PeerSelectionCapConfig, PeerSelectionMode};
+
+mod user_policy;
+pub(crate) use user_policy::{PeerTagRule, UserPeerPolicyConfig};
+
 const ESCAPER_CONFIG_TYPE: &str = "ProxyFloat";
 
+const DEFAULT_EGRESS_PEER_RSP_HEADER: &str = "x-egress-peer";
+const PEER_AVOID_RECENT_MAX: usize = 64;
+
 #[derive(Clone, Eq, PartialEq)]
 pub(crate) struct ProxyFloatEscaperConfig {
     pub(crate) name: MetricsName,
@@ -45,6 +70,8 @@ pub(crate) struct ProxyFloatEscaperConfig {
     pub(crate) shared_logger: Option<AsciiString>,
     pub(crate) bind_v4: Option<IpAddr>,
     pub(crate) bind_v6: Option<IpAddr>,
+    pub(crate) resolver: MetricsName,
+    pub(crate) resolve_strategy: ResolveStrategy,
     pub(crate) tls_config: Option<OpensslClientConfigBuilder>,
     pub(crate) source: ProxyFloatSource,
     pub(crate) cache_file: Option<PathBuf>,
@@ -54,8 +81,36 @@ pub(crate) struct ProxyFloatEscaperConfig {
     pub(crate) tcp_misc_opts: TcpMiscSockOpts,
     pub(crate) udp_misc_opts: UdpMiscSockOpts,
     pub(crate) expire_guard_duration: chrono::Duration,
+    pub(crate) expire_grace_duration: Option<Duration>,
     pub(crate) peer_negotiation_timeout: Duration,
     pub(crate) extra_metrics_tags: Option<Arc<StaticMetricsTags>>,
+    pub(crate) egress_peer_rsp_header: Option<HeaderName>,
+    pub(crate) strip_egress_peer_rsp_header: bool,
+    pub(crate) min_ready_peers: usize,
+    pub(crate) min_ready_timeout: Duration,
+    pub(crate) strict_peer_id: bool,
+    pub(crate) egress_path_fallback: bool,
+    pub(crate) peer_count_limit: Option<PeerCountLimitConfig>,
+    pub(crate) peer_circuit_breaker: Option<PeerCircuitBreakerConfig>,
+    pub(crate) peer_metrics_tag_keys: Vec<MetricsTagName>,
+    pub(crate) peer_selection: PeerSelectionMode,
+    pub(crate) peer_avoid_recent: usize,
+    pub(crate) peer_max_age: Option<Duration>,
+    pub(crate) peer_selection_cap: Option<PeerSelectionCapConfig>,
+    pub(crate) peer_area_fallback: PeerAreaFallbackConfig,
+    pub(crate) peer_eip_verify: Option<PeerEipVerifyConfig>,
+    pub(crate) peer_canary: Option<PeerCanaryConfig>,
+    pub(crate) user_peer_policy: UserPeerPolicyConfig,
+    peer_resolvers: BTreeMap<String, MetricsName>,
+    peer_credentials: Arc<BTreeMap<String, (Username, Password)>>,
+    peer_credential_env_prefix: Option<String>,
+}
+
+#[cfg(test)]
+impl Default for ProxyFloatEscaperConfig {
+    fn default() -> Self {
+        ProxyFloatEscaperConfig::new(None)
+    }
 }
 
 impl ProxyFloatEscaperConfig {
@@ -66,6 +121,8 @@ impl ProxyFloatEscaperConfig {
             shared_logger: None,
             bind_v4: None,
             bind_v6: None,
+            resolver: MetricsName::default(),
+            resolve_strategy: Default::default(),
             tls_config: None,
             source: ProxyFloatSource::Passive,
             cache_file: None,
@@ -75,8 +132,29 @@ impl ProxyFloatEscaperConfig {
             tcp_misc_opts: Default::default(),
             udp_misc_opts: Default::default(),
             expire_guard_duration: chrono::Duration::seconds(5),
+            expire_grace_duration: None,
             peer_negotiation_timeout: Duration::from_secs(10),
             extra_metrics_tags: None,
+            egress_peer_rsp_header: None,
+            strip_egress_peer_rsp_header: false,
+            min_ready_peers: 0,
+            min_ready_timeout: Duration::from_secs(30),
+            strict_peer_id: false,
+            egress_path_fallback: false,
+            peer_count_limit: None,
+            peer_circuit_breaker: None,
+            peer_metrics_tag_keys: Vec::new(),
+            peer_selection: PeerSelectionMode::default(),
+            peer_avoid_recent: 0,
+            peer_max_age: None,
+            peer_selection_cap: None,
+            peer_area_fallback: PeerAreaFallbackConfig::default(),
+            peer_eip_verify: None,
+            peer_canary: None,
+            user_peer_policy: UserPeerPolicyConfig::default(),
+            peer_resolvers: BTreeMap::new(),
+            peer_credentials: Arc::new(BTreeMap::new()),
+            peer_credential_env_prefix: None,
         }
     }
 
@@ -120,6 +198,14 @@ impl ProxyFloatEscaperConfig {
                 self.bind_v6 = Some(IpAddr::V6(ip6));
                 Ok(())
             }
+            "resolver" => {
+                self.resolver = g3_yaml::value::as_metrics_name(v)?;
+                Ok(())
+            }
+            "resolve_strategy" => {
+                self.resolve_strategy = g3_yaml::value::as_resolve_strategy(v)?;
+                Ok(())
+            }
             "tls" | "tls_client" => {
                 if let Yaml::Boolean(enable) = v {
                     if *enable {
@@ -140,8 +226,8 @@ impl ProxyFloatEscaperConfig {
                 Ok(())
             }
             "source" => {
-                self.source =
-                    ProxyFloatSource::parse(v).context(format!("invalid value for key {k}"))?;
+                self.source = ProxyFloatSource::parse(v, self.position.as_ref())
+                    .context(format!("invalid value for key {k}"))?;
                 Ok(())
             }
             "cache" => {
@@ -184,15 +270,204 @@ impl ProxyFloatEscaperConfig {
                     .map_err(|e| anyhow!("invalid duration: {e}"))?;
                 Ok(())
             }
+            "expire_grace_duration" => {
+                let dur = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                self.expire_grace_duration = Some(dur);
+                Ok(())
+            }
             "peer_negotiation_timeout" => {
                 self.peer_negotiation_timeout = g3_yaml::humanize::as_duration(v)
                     .context(format!("invalid humanize duration value for key {k}"))?;
                 Ok(())
             }
+            "egress_peer_response_header" => {
+                if let Yaml::Boolean(enable) = v {
+                    if *enable {
+                        self.egress_peer_rsp_header =
+                            Some(HeaderName::from_static(DEFAULT_EGRESS_PEER_RSP_HEADER));
+                    } else {
+                        self.egress_peer_rsp_header = None;
+                    }
+                } else {
+                    let name = g3_yaml::value::as_http_header_name(v)
+                        .context(format!("invalid http header name value for key {k}"))?;
+                    self.egress_peer_rsp_header = Some(name);
+                }
+                Ok(())
+            }
+            "strip_egress_peer_response_header" => {
+                self.strip_egress_peer_rsp_header = g3_yaml::value::as_bool(v)?;
+                Ok(())
+            }
+            "min_ready_peers" => {
+                self.min_ready_peers = g3_yaml::value::as_usize(v)?;
+                Ok(())
+            }
+            "min_ready_timeout" => {
+                self.min_ready_timeout = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                Ok(())
+            }
+            "strict_peer_id" => {
+                self.strict_peer_id = g3_yaml::value::as_bool(v)?;
+                Ok(())
+            }
+            "egress_path_fallback" => {
+                self.egress_path_fallback = g3_yaml::value::as_bool(v)?;
+                Ok(())
+            }
+            "peer_count_limit" => {
+                let config = PeerCountLimitConfig::parse(v)
+                    .context(format!("invalid peer count limit value for key {k}"))?;
+                self.peer_count_limit = Some(config);
+                Ok(())
+            }
+            "peer_circuit_breaker" => {
+                if let Yaml::Boolean(enable) = v {
+                    self.peer_circuit_breaker = enable.then(PeerCircuitBreakerConfig::default);
+                } else {
+                    let config = PeerCircuitBreakerConfig::parse(v)
+                        .context(format!("invalid peer circuit breaker value for key {k}"))?;
+                    self.peer_circuit_breaker = Some(config);
+                }
+                Ok(())
+            }
+            "peer_metrics_tag_keys" => {
+                self.peer_metrics_tag_keys = g3_yaml::value::as_list(v, |v| {
+                    let s = g3_yaml::value::as_string(v)?;
+                    MetricsTagName::from_str(&s)
+                        .map_err(|e| anyhow!("invalid metrics tag name: {e}"))
+                })
+                .context(format!("invalid list of metrics tag name for key {k}"))?;
+                Ok(())
+            }
+            "peer_selection" => {
+                self.peer_selection = PeerSelectionMode::parse(v)
+                    .context(format!("invalid peer selection mode value for key {k}"))?;
+                Ok(())
+            }
+            "peer_avoid_recent" => {
+                let count = g3_yaml::value::as_usize(v)?;
+                if count > PEER_AVOID_RECENT_MAX {
+                    return Err(anyhow!(
+                        "the peer avoid recent count should not be greater than {PEER_AVOID_RECENT_MAX}"
+                    ));
+                }
+                self.peer_avoid_recent = count;
+                Ok(())
+            }
+            "peer_max_age" => {
+                let max_age = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                self.peer_max_age = Some(max_age);
+                Ok(())
+            }
+            "peer_selection_cap" => {
+                let config = PeerSelectionCapConfig::parse(v)
+                    .context(format!("invalid peer selection cap value for key {k}"))?;
+                self.peer_selection_cap = Some(config);
+                Ok(())
+            }
+            "peer_area_fallback" => {
+                self.peer_area_fallback = PeerAreaFallbackConfig::parse(v)
+                    .context(format!("invalid peer area fallback value for key {k}"))?;
+                Ok(())
+            }
+            "user_peer_policy" => {
+                self.user_peer_policy = UserPeerPolicyConfig::parse(v)
+                    .context(format!("invalid user peer policy value for key {k}"))?;
+                Ok(())
+            }
+            "peer_eip_verify" => {
+                let config = PeerEipVerifyConfig::parse(v)
+                    .context(format!("invalid peer eip verify value for key {k}"))?;
+                self.peer_eip_verify = Some(config);
+                Ok(())
+            }
+            "peer_canary" => {
+                let config = PeerCanaryConfig::parse(v)
+                    .context(format!("invalid peer canary value for key {k}"))?;
+                self.peer_canary = Some(config);
+                Ok(())
+            }
+            "peer_resolvers" => {
+                let Yaml::Hash(map) = v else {
+                    return Err(anyhow!("invalid map value for key {k}"));
+                };
+                for (name, v) in map {
+                    let name = g3_yaml::value::as_string(name)
+                        .context(format!("invalid peer resolver name for key {k}"))?;
+                    let resolver = g3_yaml::value::as_metrics_name(v).context(format!(
+                        "invalid resolver name value for peer resolver {name}"
+                    ))?;
+                    self.peer_resolvers.insert(name, resolver);
+                }
+                Ok(())
+            }
+            "peer_credential_file" => {
+                let lookup_dir = g3_daemon::config::get_lookup_dir(self.position.as_ref())?;
+                let path = g3_yaml::value::as_file_path(v, lookup_dir, false)
+                    .context(format!("invalid file path value for key {k}"))?;
+                let credentials = load_peer_credentials(&path).context(format!(
+                    "failed to load peer credentials from file {}",
+                    path.display()
+                ))?;
+                self.peer_credentials = Arc::new(credentials);
+                Ok(())
+            }
+            "peer_credential_env_prefix" => {
+                let prefix = g3_yaml::value::as_string(v)?;
+                if prefix.is_empty() {
+                    return Err(anyhow!("empty env var prefix is not allowed"));
+                }
+                self.peer_credential_env_prefix = Some(prefix);
+                Ok(())
+            }
             _ => Err(anyhow!("invalid key {k}")),
         }
     }
 
+    /// Get the resolver referenced by the specified name in `peer_resolvers`.
+    pub(crate) fn get_peer_resolver(&self, name: &str) -> anyhow::Result<&MetricsName> {
+        self.peer_resolvers
+            .get(name)
+            .ok_or_else(|| anyhow!("no peer resolver found with name {name}"))
+    }
+
+    /// Get the peer credential with the specified name.
+    ///
+    /// The credential will be looked up in `peer_credential_file` first,
+    /// and then in the environment variable with the same name, in *username:password* format,
+    /// if the name starts with `peer_credential_env_prefix`. As the names come from the peer feed,
+    /// the other environment variables should never be read.
+    pub(crate) fn get_peer_credential(&self, name: &str) -> anyhow::Result<(Username, Password)> {
+        if let Some((username, password)) = self.peer_credentials.get(name) {
+            return Ok((username.clone(), password.clone()));
+        }
+
+        if !self
+            .peer_credential_env_prefix
+            .as_ref()
+            .is_some_and(|prefix| name.starts_with(prefix.as_str()))
+        {
+            return Err(anyhow!("no credential found with name {name}"));
+        }
+        let Ok(value) = std::env::var(name) else {
+            return Err(anyhow!("no credential found with name {name}"));
+        };
+        let Some((username, password)) = value.split_once(':') else {
+            return Err(anyhow!(
+                "the value of env var {name} is not in username:password format"
+            ));
+        };
+        let username = Username::from_original(username)
+            .context(format!("invalid username in env var {name}"))?;
+        let password = Password::from_original(password)
+            .context(format!("invalid password in env var {name}"))?;
+        Ok((username, password))
+    }
+
     fn check(&mut self) -> anyhow::Result<()> {
         if self.name.is_empty() {
             return Err(anyhow!("name is not set"));
@@ -208,6 +483,32 @@ impl ProxyFloatEscaperConfig {
     }
 }
 
+fn load_peer_credentials(path: &Path) -> anyhow::Result<BTreeMap<String, (Username, Password)>> {
+    let content = std::fs::read_to_string(path).map_err(|e| anyhow!("failed to read: {e}"))?;
+    let doc: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| anyhow!("invalid json content: {e}"))?;
+    let serde_json::Value::Object(map) = doc else {
+        return Err(anyhow!("the root value should be a json map"));
+    };
+
+    let mut credentials = BTreeMap::new();
+    for (name, v) in map {
+        let serde_json::Value::Object(entry) = v else {
+            return Err(anyhow!(
+                "the value for credential {name} should be a json map"
+            ));
+        };
+        let username = g3_json::map_get_required(&entry, "username")
+            .and_then(g3_json::value::as_username)
+            .context(format!("invalid username for credential {name}"))?;
+        let password = g3_json::map_get_required(&entry, "password")
+            .and_then(g3_json::value::as_password)
+            .context(format!("invalid password for credential {name}"))?;
+        credentials.insert(name, (username, password));
+    }
+    Ok(credentials)
+}
+
 impl EscaperConfig for ProxyFloatEscaperConfig {
     fn name(&self) -> &MetricsName {
         &self.name
@@ -222,7 +523,7 @@ impl EscaperConfig for ProxyFloatEscaperConfig {
     }
 
     fn resolver(&self) -> &MetricsName {
-        Default::default()
+        &self.resolver
     }
 
     fn shared_logger(&self) -> Option<&str> {
@@ -245,3 +546,26 @@ impl EscaperConfig for ProxyFloatEscaperConfig {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_credential_env() {
+        std::env::set_var("G3_TEST_PEER_CRED_A", "user:pass");
+        std::env::set_var("OTHER_TEST_PEER_CRED_A", "user:pass");
+
+        let mut config = ProxyFloatEscaperConfig::default();
+        assert!(config.get_peer_credential("G3_TEST_PEER_CRED_A").is_err());
+
+        config.peer_credential_env_prefix = Some("G3_TEST_".to_string());
+        let (username, password) = config.get_peer_credential("G3_TEST_PEER_CRED_A").unwrap();
+        assert_eq!(username.as_original(), "user");
+        assert_eq!(password.as_original(), "pass");
+        assert!(config
+            .get_peer_credential("OTHER_TEST_PEER_CRED_A")
+            .is_err());
+        assert!(config.get_peer_credential("G3_TEST_PEER_CRED_B").is_err());
+    }
+}
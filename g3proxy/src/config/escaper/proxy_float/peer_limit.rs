@@ -0,0 +1,87 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use yaml_rust::Yaml;
+
+/// The peers to keep if the count exceeds the limit
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum PeerKeepPriority {
+    /// keep the peers with the longest remaining alive time
+    #[default]
+    AliveTime,
+    /// keep the peers with the highest weight
+    Weight,
+}
+
+impl FromStr for PeerKeepPriority {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "alive_time" => Ok(PeerKeepPriority::AliveTime),
+            "weight" => Ok(PeerKeepPriority::Weight),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The max count of peers in a peer set
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct PeerCountLimitConfig {
+    pub(crate) max_count: usize,
+    pub(crate) keep: PeerKeepPriority,
+}
+
+impl PeerCountLimitConfig {
+    fn new(max_count: usize) -> Self {
+        PeerCountLimitConfig {
+            max_count,
+            keep: PeerKeepPriority::default(),
+        }
+    }
+
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let config = match v {
+            Yaml::Hash(map) => {
+                let mut config = PeerCountLimitConfig::new(0);
+                g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+                    "max_count" => {
+                        config.max_count = g3_yaml::value::as_usize(v)?;
+                        Ok(())
+                    }
+                    "keep" => {
+                        let s = g3_yaml::value::as_string(v)
+                            .context(format!("invalid string value for key {k}"))?;
+                        config.keep = PeerKeepPriority::from_str(&s)
+                            .map_err(|_| anyhow!("unsupported keep priority {s}"))?;
+                        Ok(())
+                    }
+                    _ => Err(anyhow!("invalid key {k}")),
+                })?;
+                config
+            }
+            Yaml::Integer(_) => PeerCountLimitConfig::new(g3_yaml::value::as_usize(v)?),
+            _ => return Err(anyhow!("invalid yaml value type")),
+        };
+        if config.max_count == 0 {
+            return Err(anyhow!("max count should not be 0"));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use yaml_rust::Yaml;
+
+use g3_types::net::EgressArea;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum PeerSelectionMode {
+    #[default]
+    Random,
+    /// sample two peers at random and pick the one with the lower in-flight/weight ratio
+    PowerOfTwoChoices,
+    /// pick a random peer weighted by its recent connect success ratio
+    SuccessRate,
+    /// cycle through the peers in proportion to their weights, by smooth weighted round-robin
+    WeightedRoundRobin,
+    /// cycle through the peers in turn, in their order in the peer set
+    RoundRobin,
+    /// map the same client to the same peer, by consistent hashing of the user name or client ip
+    Sticky,
+}
+
+impl FromStr for PeerSelectionMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(PeerSelectionMode::Random),
+            "p2c" => Ok(PeerSelectionMode::PowerOfTwoChoices),
+            "success_rate" => Ok(PeerSelectionMode::SuccessRate),
+            "wrr" => Ok(PeerSelectionMode::WeightedRoundRobin),
+            "rr" => Ok(PeerSelectionMode::RoundRobin),
+            "sticky" => Ok(PeerSelectionMode::Sticky),
+            _ => Err(()),
+        }
+    }
+}
+
+impl PeerSelectionMode {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        if let Yaml::String(s) = v {
+            PeerSelectionMode::from_str(s).map_err(|_| anyhow!("unsupported selection mode {s}"))
+        } else {
+            Err(anyhow!(
+                "yaml value type for 'peer selection mode' should be 'string'"
+            ))
+        }
+    }
+}
+
+/// The max count of selections of each peer in a time window
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct PeerSelectionCapConfig {
+    pub(crate) max_selections: usize,
+    pub(crate) window: Duration,
+}
+
+impl Default for PeerSelectionCapConfig {
+    fn default() -> Self {
+        PeerSelectionCapConfig {
+            max_selections: 100,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PeerSelectionCapConfig {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let mut config = PeerSelectionCapConfig::default();
+        match v {
+            Yaml::Hash(map) => {
+                g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+                    "max_selections" => {
+                        config.max_selections = g3_yaml::value::as_usize(v)?;
+                        Ok(())
+                    }
+                    "window" => {
+                        config.window = g3_yaml::humanize::as_duration(v)
+                            .context(format!("invalid humanize duration value for key {k}"))?;
+                        Ok(())
+                    }
+                    _ => Err(anyhow!("invalid key {k}")),
+                })?;
+            }
+            Yaml::Integer(_) => {
+                config.max_selections = g3_yaml::value::as_usize(v)?;
+            }
+            _ => return Err(anyhow!("invalid yaml value type")),
+        }
+        if config.max_selections == 0 {
+            return Err(anyhow!("max selections should not be 0"));
+        }
+        if config.window.is_zero() {
+            return Err(anyhow!("window should not be zero"));
+        }
+        Ok(config)
+    }
+}
+
+/// The ordered fallback areas to use if no peer is available in the requested area
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct PeerAreaFallbackConfig {
+    inner: Vec<(EgressArea, Vec<EgressArea>)>,
+}
+
+impl PeerAreaFallbackConfig {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let Yaml::Hash(map) = v else {
+            return Err(anyhow!("invalid yaml value type, should be map"));
+        };
+
+        let mut inner = Vec::with_capacity(map.len());
+        g3_yaml::foreach_kv(map, |k, v| {
+            let area = EgressArea::from_str(k).map_err(|_| anyhow!("invalid egress area {k}"))?;
+            let fallback = g3_yaml::value::as_list(v, g3_yaml::value::as_egress_area)
+                .context(format!("invalid egress area list value for key {k}"))?;
+            if fallback.contains(&area) {
+                return Err(anyhow!("area {k} should not fallback to itself"));
+            }
+            if inner.iter().any(|(a, _)| a == &area) {
+                return Err(anyhow!("duplicate fallback config for area {k}"));
+            }
+            inner.push((area, fallback));
+            Ok(())
+        })?;
+        Ok(PeerAreaFallbackConfig { inner })
+    }
+
+    /// get the ordered fallback areas for the requested one
+    pub(crate) fn get(&self, area: &EgressArea) -> &[EgressArea] {
+        self.inner
+            .iter()
+            .find(|(a, _)| a == area)
+            .map(|(_, fallback)| fallback.as_slice())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,137 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use url::Url;
+use yaml_rust::{yaml, Yaml};
+
+use g3_types::net::{Host, OpensslClientConfigBuilder, UpstreamAddr};
+use g3_yaml::YamlDocPosition;
+
+const CONFIG_KEY_SOURCE_URL: &str = "url";
+
+const HTTP_DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HTTP_DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_DEFAULT_MAX_HEADER_SIZE: usize = 8192;
+const HTTP_DEFAULT_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ProxyFloatHttpSource {
+    pub(crate) url: Url,
+    pub(crate) addr: UpstreamAddr,
+    pub(crate) tls_client: Option<OpensslClientConfigBuilder>,
+    pub(crate) tls_name: Option<Host>,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) response_timeout: Duration,
+    pub(crate) max_header_size: usize,
+    pub(crate) max_body_size: usize,
+}
+
+impl ProxyFloatHttpSource {
+    fn new(url: Url) -> anyhow::Result<Self> {
+        let addr = UpstreamAddr::try_from(&url)?;
+        let tls_client = match url.scheme() {
+            "http" => None,
+            "https" => Some(OpensslClientConfigBuilder::with_cache_for_one_site()),
+            s => return Err(anyhow!("unsupported url scheme {s}")),
+        };
+        Ok(ProxyFloatHttpSource {
+            url,
+            addr,
+            tls_client,
+            tls_name: None,
+            connect_timeout: HTTP_DEFAULT_CONNECT_TIMEOUT,
+            response_timeout: HTTP_DEFAULT_RESPONSE_TIMEOUT,
+            max_header_size: HTTP_DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: HTTP_DEFAULT_MAX_BODY_SIZE,
+        })
+    }
+
+    pub(super) fn parse_map(
+        map: &yaml::Hash,
+        position: Option<&YamlDocPosition>,
+    ) -> anyhow::Result<Self> {
+        let v = g3_yaml::hash_get_required(map, CONFIG_KEY_SOURCE_URL)?;
+        let url = g3_yaml::value::as_url(v)
+            .context(format!("invalid url value for key {CONFIG_KEY_SOURCE_URL}"))?;
+        let mut config = ProxyFloatHttpSource::new(url)?;
+
+        g3_yaml::foreach_kv(map, |k, v| {
+            config
+                .set(k, v, position)
+                .context(format!("failed to parse key {k}"))
+        })?;
+
+        Ok(config)
+    }
+
+    pub(crate) fn parse_url(url: &Url) -> anyhow::Result<Self> {
+        ProxyFloatHttpSource::new(url.clone())
+    }
+
+    /// the path and query to be used in the request line
+    pub(crate) fn request_target(&self) -> &str {
+        &self.url[url::Position::BeforePath..]
+    }
+
+    fn set(&mut self, k: &str, v: &Yaml, position: Option<&YamlDocPosition>) -> anyhow::Result<()> {
+        match g3_yaml::key::normalize(k).as_str() {
+            super::CONFIG_KEY_SOURCE_TYPE => Ok(()),
+            CONFIG_KEY_SOURCE_URL => Ok(()),
+            "tls_client" => {
+                let lookup_dir = g3_daemon::config::get_lookup_dir(position)?;
+                let builder = g3_yaml::value::as_to_one_openssl_tls_client_config_builder(
+                    v,
+                    Some(lookup_dir),
+                )
+                .context(format!(
+                    "invalid openssl tls client config value for key {k}"
+                ))?;
+                self.tls_client = Some(builder);
+                Ok(())
+            }
+            "tls_name" => {
+                let name = g3_yaml::value::as_host(v)
+                    .context(format!("invalid tls server name value for key {k}"))?;
+                self.tls_name = Some(name);
+                Ok(())
+            }
+            "connect_timeout" => {
+                self.connect_timeout = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                Ok(())
+            }
+            "response_timeout" => {
+                self.response_timeout = g3_yaml::humanize::as_duration(v)
+                    .context(format!("invalid humanize duration value for key {k}"))?;
+                Ok(())
+            }
+            "max_header_size" => {
+                self.max_header_size = g3_yaml::humanize::as_usize(v)
+                    .context(format!("invalid humanize usize value for key {k}"))?;
+                Ok(())
+            }
+            "max_body_size" => {
+                self.max_body_size = g3_yaml::humanize::as_usize(v)
+                    .context(format!("invalid humanize usize value for key {k}"))?;
+                Ok(())
+            }
+            _ => Err(anyhow!("invalid key {}", k)),
+        }
+    }
+}
@@ -20,6 +20,9 @@ use anyhow::anyhow;
 use url::Url;
 use yaml_rust::Yaml;
 
+use g3_yaml::YamlDocPosition;
+
+pub(crate) mod http;
 pub(crate) mod redis;
 pub(crate) mod redis_cluster;
 
@@ -30,6 +33,7 @@ pub(crate) enum ProxyFloatSource {
     Passive,
     Redis(Arc<redis::ProxyFloatRedisSource>),
     RedisCluster(Arc<redis_cluster::ProxyFloatRedisClusterSource>),
+    Http(Arc<http::ProxyFloatHttpSource>),
 }
 
 impl ProxyFloatSource {
@@ -38,10 +42,11 @@ impl ProxyFloatSource {
             ProxyFloatSource::Passive => true,
             ProxyFloatSource::Redis(_) => true,
             ProxyFloatSource::RedisCluster(_) => true,
+            ProxyFloatSource::Http(_) => true,
         }
     }
 
-    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+    pub(super) fn parse(v: &Yaml, position: Option<&YamlDocPosition>) -> anyhow::Result<Self> {
         match v {
             Yaml::Hash(map) => {
                 let source_type = g3_yaml::hash_get_required_str(map, CONFIG_KEY_SOURCE_TYPE)?;
@@ -56,6 +61,10 @@ impl ProxyFloatSource {
                         let source = redis_cluster::ProxyFloatRedisClusterSource::parse_map(map)?;
                         Ok(ProxyFloatSource::RedisCluster(Arc::new(source)))
                     }
+                    "http" => {
+                        let source = http::ProxyFloatHttpSource::parse_map(map, position)?;
+                        Ok(ProxyFloatSource::Http(Arc::new(source)))
+                    }
                     _ => Err(anyhow!("unsupported source type {source_type}")),
                 }
             }
@@ -68,6 +77,10 @@ impl ProxyFloatSource {
                         let source = redis::ProxyFloatRedisSource::parse_url(&url)?;
                         Ok(ProxyFloatSource::Redis(Arc::new(source)))
                     }
+                    "http" | "https" => {
+                        let source = http::ProxyFloatHttpSource::parse_url(&url)?;
+                        Ok(ProxyFloatSource::Http(Arc::new(source)))
+                    }
                     _ => Err(anyhow!("unsupported url scheme: {scheme}")),
                 }
             }
@@ -0,0 +1,114 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Context};
+use yaml_rust::Yaml;
+
+/// The peer tag rule for a user.
+///
+/// A peer is permitted if none of the deny tags matches, and all of the allow tags match.
+/// A tag matches if the peer has the tag with one of the listed values.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct PeerTagRule {
+    allow: BTreeMap<String, BTreeSet<String>>,
+    deny: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PeerTagRule {
+    pub(crate) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let Yaml::Hash(map) = v else {
+            return Err(anyhow!("invalid yaml value type, should be map"));
+        };
+
+        let mut rule = PeerTagRule::default();
+        g3_yaml::foreach_kv(map, |k, v| match g3_yaml::key::normalize(k).as_str() {
+            "allow" => {
+                rule.allow =
+                    parse_tag_values(v).context(format!("invalid tags value for key {k}"))?;
+                Ok(())
+            }
+            "deny" => {
+                rule.deny =
+                    parse_tag_values(v).context(format!("invalid tags value for key {k}"))?;
+                Ok(())
+            }
+            _ => Err(anyhow!("invalid key {k}")),
+        })?;
+        if rule.allow.is_empty() && rule.deny.is_empty() {
+            return Err(anyhow!("neither allow nor deny tags is set"));
+        }
+        Ok(rule)
+    }
+
+    /// check if the peer with the tags returned by `get_tag` is permitted
+    pub(crate) fn permits<'a, F>(&self, get_tag: F) -> bool
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let matches = |name: &String, values: &BTreeSet<String>| {
+            get_tag(name).map(|v| values.contains(v)).unwrap_or(false)
+        };
+        !self.deny.iter().any(|(k, v)| matches(k, v))
+            && self.allow.iter().all(|(k, v)| matches(k, v))
+    }
+}
+
+fn parse_tag_values(v: &Yaml) -> anyhow::Result<BTreeMap<String, BTreeSet<String>>> {
+    let Yaml::Hash(map) = v else {
+        return Err(anyhow!("invalid yaml value type, should be map"));
+    };
+
+    let mut tags = BTreeMap::new();
+    g3_yaml::foreach_kv(map, |k, v| {
+        let values = g3_yaml::value::as_list(v, g3_yaml::value::as_string)
+            .context(format!("invalid string list value for tag {k}"))?;
+        if values.is_empty() {
+            return Err(anyhow!("no value set for tag {k}"));
+        }
+        tags.insert(k.to_string(), values.into_iter().collect());
+        Ok(())
+    })?;
+    Ok(tags)
+}
+
+/// The peer tag rules for each user, users without a rule can use all peers
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct UserPeerPolicyConfig {
+    inner: BTreeMap<String, PeerTagRule>,
+}
+
+impl UserPeerPolicyConfig {
+    pub(super) fn parse(v: &Yaml) -> anyhow::Result<Self> {
+        let Yaml::Hash(map) = v else {
+            return Err(anyhow!("invalid yaml value type, should be map"));
+        };
+
+        let mut inner = BTreeMap::new();
+        g3_yaml::foreach_kv(map, |k, v| {
+            let rule =
+                PeerTagRule::parse(v).context(format!("invalid peer tag rule for user {k}"))?;
+            inner.insert(k.to_string(), rule);
+            Ok(())
+        })?;
+        Ok(UserPeerPolicyConfig { inner })
+    }
+
+    pub(crate) fn get(&self, user: &str) -> Option<&PeerTagRule> {
+        self.inner.get(user)
+    }
+}
@@ -51,4 +51,22 @@ impl escaper_control::Server for EscaperControlImpl {
             Ok(())
         })
     }
+
+    fn simulate_selection(
+        &mut self,
+        params: escaper_control::SimulateSelectionParams,
+        mut results: escaper_control::SimulateSelectionResults,
+    ) -> Promise<(), capnp::Error> {
+        let data = pry!(pry!(pry!(params.get()).get_data()).to_str());
+        let mut builder = results.get().init_result();
+        match self.escaper.simulate_selection(data) {
+            Ok(report) => builder.set_ok(report.as_str()),
+            Err(e) => {
+                let mut ev = builder.init_err();
+                ev.set_code(-1);
+                ev.set_reason(format!("{e:?}").as_str());
+            }
+        }
+        Promise::ok(())
+    }
 }
@@ -48,7 +48,8 @@ pub(crate) use registry::{foreach as foreach_escaper, get_names, get_or_insert_d
 mod stats;
 pub(crate) use stats::{
     ArcEscaperInternalStats, ArcEscaperStats, EscaperForbiddenSnapshot, EscaperForbiddenStats,
-    EscaperInterfaceStats, EscaperInternalStats, EscaperStats, EscaperTcpStats, EscaperUdpStats,
+    EscaperInterfaceStats, EscaperInternalStats, EscaperStats, EscaperTaggedTcpIoStats,
+    EscaperTcpStats, EscaperUdpStats, PeerCanarySnapshot, PeerConnectSnapshot, PeerConnectStats,
     RouteEscaperSnapshot, RouteEscaperStats,
 };
 
@@ -153,6 +154,10 @@ pub(crate) trait Escaper: EscaperInternal {
     }
 
     async fn publish(&self, data: String) -> anyhow::Result<()>;
+    /// run the next proxy peer selection without sending any real traffic
+    fn simulate_selection(&self, _data: &str) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!("not implemented"))
+    }
 
     async fn tcp_setup_connection<'a>(
         &'a self,
@@ -0,0 +1,141 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use tokio::time::Instant;
+
+use g3_daemon::server::ClientConnectionInfo;
+use g3_types::metrics::MetricsName;
+use g3_types::net::UpstreamAddr;
+
+use super::peer::PeerSet;
+use super::ProxyFloatEscaperStats;
+use crate::config::escaper::proxy_float::PeerCanaryConfig;
+use crate::module::tcp_connect::TcpConnectTaskNotes;
+use crate::serve::ServerTaskNotes;
+
+pub(super) struct PeerCanaryMirror {
+    config: PeerCanaryConfig,
+    count: AtomicU64,
+}
+
+impl PeerCanaryMirror {
+    pub(super) fn new(config: &PeerCanaryConfig) -> Self {
+        PeerCanaryMirror {
+            config: config.clone(),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn sample(&self) -> bool {
+        self.count.fetch_add(1, Ordering::Relaxed) % self.config.sample as u64 == 0
+    }
+
+    /// mirror the tcp connect to `upstream` through a canary peer in a new probe connection
+    /// if this task is sampled, the probe connection will be closed once established.
+    /// Return true if mirrored, then the connect result of the live peer should also be recorded
+    pub(super) fn mirror(
+        &self,
+        escaper: &MetricsName,
+        peer_set: &PeerSet,
+        upstream: &UpstreamAddr,
+        task_notes: &ServerTaskNotes,
+        stats: &Arc<ProxyFloatEscaperStats>,
+    ) -> bool {
+        if !self.sample() {
+            return false;
+        }
+        let Some(peer) = peer_set.select_canary_peer(upstream.port()) else {
+            return false;
+        };
+
+        let escaper = escaper.clone();
+        let peer = Arc::clone(peer);
+        let upstream = upstream.clone();
+        let timeout = self.config.timeout;
+        let cc_info = ClientConnectionInfo::new(task_notes.client_addr(), task_notes.server_addr());
+        let stats = Arc::clone(stats);
+        tokio::spawn(async move {
+            let task_notes = ServerTaskNotes::new(cc_info, None, Duration::ZERO);
+            let mut tcp_notes = TcpConnectTaskNotes::new(upstream);
+            let start = Instant::now();
+            let r = tokio::time::timeout(
+                timeout,
+                peer.tcp_setup_connection(&mut tcp_notes, &task_notes, stats.clone()),
+            )
+            .await;
+            let connect_time = start.elapsed();
+            match r {
+                Ok(Ok(_)) => stats.canary_peer_connect.record(true, connect_time),
+                Ok(Err(e)) => {
+                    debug!(
+                        "escaper {escaper}: canary peer {} failed to connect to {}: {e}",
+                        peer.id(),
+                        tcp_notes.upstream
+                    );
+                    stats.canary_peer_connect.record(false, connect_time);
+                }
+                Err(_) => {
+                    debug!(
+                        "escaper {escaper}: canary peer {} timed out to connect to {}",
+                        peer.id(),
+                        tcp_notes.upstream
+                    );
+                    stats.canary_peer_connect.record(false, connect_time);
+                }
+            }
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn sample() {
+        let config = PeerCanaryConfig {
+            sample: 2,
+            timeout: Duration::from_secs(1),
+        };
+        let mirror = PeerCanaryMirror::new(&config);
+        assert!(mirror.sample());
+        assert!(!mirror.sample());
+        assert!(mirror.sample());
+    }
+
+    #[test]
+    fn no_canary_peer() {
+        let mirror = PeerCanaryMirror::new(&PeerCanaryConfig {
+            sample: 1,
+            timeout: Duration::from_secs(1),
+        });
+        let name = MetricsName::from_str("test").unwrap();
+        let stats = Arc::new(ProxyFloatEscaperStats::new(&name));
+        let addr = "127.0.0.1:1080".parse().unwrap();
+        let task_notes =
+            ServerTaskNotes::new(ClientConnectionInfo::new(addr, addr), None, Duration::ZERO);
+        let upstream = UpstreamAddr::from_str("example.net:443").unwrap();
+        assert!(!mirror.mirror(&name, &PeerSet::default(), &upstream, &task_notes, &stats));
+        assert_eq!(stats.canary_peer_connect.snapshot().attempt, 0);
+    }
+}
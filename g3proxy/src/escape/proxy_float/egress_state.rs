@@ -0,0 +1,135 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum EgressStateEvent {
+    /// no live peer is left, with the live peer count of the last peer set that worked
+    Down { last_live_count: usize },
+    /// live peers are back again
+    Restored { live_count: usize },
+}
+
+/// Edge triggered tracker for the "all live peers are gone" state of the escaper.
+///
+/// The live peer count is only sampled once for each peer feed generation on the
+/// success path, so the per-request cost is just a few atomic loads.
+pub(super) struct EgressState {
+    down: AtomicBool,
+    sampled_generation: AtomicU64,
+    last_live_count: AtomicUsize,
+    down_total: AtomicU64,
+    restored_total: AtomicU64,
+}
+
+impl Default for EgressState {
+    fn default() -> Self {
+        EgressState {
+            down: AtomicBool::new(false),
+            sampled_generation: AtomicU64::new(u64::MAX),
+            last_live_count: AtomicUsize::new(0),
+            down_total: AtomicU64::new(0),
+            restored_total: AtomicU64::new(0),
+        }
+    }
+}
+
+impl EgressState {
+    /// call this after a peer has been selected
+    pub(super) fn on_selected<F>(&self, generation: u64, live_count: F) -> Option<EgressStateEvent>
+    where
+        F: FnOnce() -> usize,
+    {
+        let was_down = self.down.load(Ordering::Relaxed);
+        if !was_down && self.sampled_generation.load(Ordering::Relaxed) == generation {
+            return None;
+        }
+
+        let live_count = live_count();
+        self.sampled_generation.store(generation, Ordering::Relaxed);
+        self.last_live_count.store(live_count, Ordering::Relaxed);
+        if was_down && self.down.swap(false, Ordering::Relaxed) {
+            self.restored_total.fetch_add(1, Ordering::Relaxed);
+            Some(EgressStateEvent::Restored { live_count })
+        } else {
+            None
+        }
+    }
+
+    /// call this after no peer can be selected
+    pub(super) fn on_unselectable(&self, live_count: usize) -> Option<EgressStateEvent> {
+        if live_count > 0 || self.down.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        self.down_total.fetch_add(1, Ordering::Relaxed);
+        Some(EgressStateEvent::Down {
+            last_live_count: self.last_live_count.load(Ordering::Relaxed),
+        })
+    }
+
+    pub(super) fn down_total(&self) -> u64 {
+        self.down_total.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn restored_total(&self) -> u64 {
+        self.restored_total.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_triggered() {
+        let state = EgressState::default();
+        assert_eq!(state.on_selected(1, || 3), None);
+        assert_eq!(state.on_selected(1, || unreachable!()), None);
+        assert_eq!(state.on_unselectable(2), None);
+
+        assert_eq!(
+            state.on_unselectable(0),
+            Some(EgressStateEvent::Down { last_live_count: 3 })
+        );
+        assert_eq!(state.on_unselectable(0), None);
+        assert_eq!(state.down_total(), 1);
+
+        assert_eq!(
+            state.on_selected(2, || 5),
+            Some(EgressStateEvent::Restored { live_count: 5 })
+        );
+        assert_eq!(state.on_selected(2, || unreachable!()), None);
+        assert_eq!(state.restored_total(), 1);
+
+        assert_eq!(
+            state.on_unselectable(0),
+            Some(EgressStateEvent::Down { last_live_count: 5 })
+        );
+        assert_eq!(state.down_total(), 2);
+    }
+
+    #[test]
+    fn resample_on_new_generation() {
+        let state = EgressState::default();
+        assert_eq!(state.on_selected(1, || 3), None);
+        assert_eq!(state.on_selected(2, || 1), None);
+        assert_eq!(
+            state.on_unselectable(0),
+            Some(EgressStateEvent::Down { last_live_count: 1 })
+        );
+    }
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use http::{Method, StatusCode};
+use log::{debug, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use g3_daemon::server::ClientConnectionInfo;
+use g3_http::client::HttpForwardRemoteResponse;
+use g3_http::HttpBodyReader;
+use g3_types::metrics::MetricsName;
+
+use super::peer::ArcNextProxyPeer;
+use super::ProxyFloatEscaperStats;
+use crate::config::escaper::proxy_float::PeerEipVerifyConfig;
+use crate::module::tcp_connect::TcpConnectTaskNotes;
+use crate::serve::ServerTaskNotes;
+
+const RSP_MAX_HEADER_SIZE: usize = 4096;
+const RSP_MAX_BODY_SIZE: usize = 256;
+
+pub(super) struct PeerEipVerifier {
+    config: PeerEipVerifyConfig,
+    count: AtomicU64,
+}
+
+impl PeerEipVerifier {
+    pub(super) fn new(config: &PeerEipVerifyConfig) -> Self {
+        PeerEipVerifier {
+            config: config.clone(),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn sample(&self) -> bool {
+        self.count.fetch_add(1, Ordering::Relaxed) % self.config.sample as u64 == 0
+    }
+
+    /// verify the egress ip of the peer in a new probe connection if this connection is sampled,
+    /// peers that have no declared egress ip will be skipped
+    pub(super) fn check(
+        self: &Arc<Self>,
+        escaper: &MetricsName,
+        peer: &ArcNextProxyPeer,
+        task_notes: &ServerTaskNotes,
+        stats: &Arc<ProxyFloatEscaperStats>,
+    ) {
+        let Some(declared) = peer.eip() else {
+            return;
+        };
+        if !self.sample() {
+            return;
+        }
+
+        let verifier = Arc::clone(self);
+        let escaper = escaper.clone();
+        let peer = Arc::clone(peer);
+        let cc_info = ClientConnectionInfo::new(task_notes.client_addr(), task_notes.server_addr());
+        let stats = Arc::clone(stats);
+        tokio::spawn(async move {
+            let task_notes = ServerTaskNotes::new(cc_info, None, Duration::ZERO);
+            let r = tokio::time::timeout(
+                verifier.config.timeout,
+                verifier.query(&peer, &task_notes, &stats),
+            )
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("timed out")));
+            match r {
+                Ok(observed) => {
+                    if observed.to_canonical() == declared.to_canonical() {
+                        stats.add_peer_eip_verified();
+                    } else {
+                        stats.add_peer_eip_mismatch();
+                        warn!(
+                            "escaper {escaper}: peer {} declared egress ip {declared} but {observed} is observed",
+                            peer.id()
+                        );
+                    }
+                }
+                Err(e) => debug!(
+                    "escaper {escaper}: failed to verify the egress ip of peer {}: {e:?}",
+                    peer.id()
+                ),
+            }
+        });
+    }
+
+    async fn query(
+        &self,
+        peer: &ArcNextProxyPeer,
+        task_notes: &ServerTaskNotes,
+        stats: &Arc<ProxyFloatEscaperStats>,
+    ) -> anyhow::Result<IpAddr> {
+        let mut tcp_notes = TcpConnectTaskNotes::new(self.config.addr.clone());
+        let (reader, writer) = peer
+            .tcp_setup_connection(&mut tcp_notes, task_notes, stats.clone())
+            .await
+            .map_err(|e| anyhow!("failed to connect to {}: {e}", self.config.addr))?;
+        fetch_ip(&self.config, reader, writer).await
+    }
+}
+
+async fn fetch_ip<R, W>(
+    config: &PeerEipVerifyConfig,
+    reader: R,
+    mut writer: W,
+) -> anyhow::Result<IpAddr>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let data = format!(
+        "GET {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        config.request_target(),
+        config.addr
+    );
+    writer
+        .write_all(data.as_bytes())
+        .await
+        .map_err(|e| anyhow!("failed to write request: {e:?}"))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| anyhow!("failed to write request: {e:?}"))?;
+
+    let mut reader = BufReader::new(reader);
+    let rsp =
+        HttpForwardRemoteResponse::parse(&mut reader, &Method::GET, false, RSP_MAX_HEADER_SIZE)
+            .await
+            .map_err(|e| anyhow!("failed to recv response: {e}"))?;
+    if rsp.code != StatusCode::OK {
+        return Err(anyhow!("unexpected response: {} {}", rsp.code, rsp.reason));
+    }
+    let Some(body_type) = rsp.body_type(&Method::GET) else {
+        return Err(anyhow!("no body found in response"));
+    };
+    let mut body_reader = HttpBodyReader::new(&mut reader, body_type, 2048);
+    let mut body = Vec::new();
+    (&mut body_reader)
+        .take(RSP_MAX_BODY_SIZE as u64 + 1)
+        .read_to_end(&mut body)
+        .await
+        .map_err(|e| anyhow!("failed to read response body: {e:?}"))?;
+    if body.len() > RSP_MAX_BODY_SIZE {
+        return Err(anyhow!(
+            "response body is larger than {RSP_MAX_BODY_SIZE} bytes"
+        ));
+    }
+
+    let body = std::str::from_utf8(&body).map_err(|_| anyhow!("the response body is not utf-8"))?;
+    body.trim()
+        .parse::<IpAddr>()
+        .map_err(|e| anyhow!("the response body is not a valid ip address: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_types::net::UpstreamAddr;
+    use tokio::io::AsyncBufReadExt;
+    use url::Url;
+
+    async fn serve_once(server: tokio::io::DuplexStream, rsp: &'static str) -> String {
+        let mut server = BufReader::new(server);
+        let mut request_line = String::new();
+        server.read_line(&mut request_line).await.unwrap();
+        loop {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+        }
+        server.write_all(rsp.as_bytes()).await.unwrap();
+        server.shutdown().await.unwrap();
+        request_line.trim_end().to_string()
+    }
+
+    #[tokio::test]
+    async fn fetch() {
+        let url = Url::parse("http://127.0.0.1:8080/ip?format=text").unwrap();
+        let config = PeerEipVerifyConfig {
+            addr: UpstreamAddr::try_from(&url).unwrap(),
+            url,
+            sample: 1,
+            timeout: Duration::from_secs(1),
+        };
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (r, w) = tokio::io::split(client);
+        let rsp = "HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\n192.0.2.10\r\n";
+        let (ip, req) = tokio::join!(fetch_ip(&config, r, w), serve_once(server, rsp));
+        assert_eq!(ip.unwrap(), IpAddr::from([192, 0, 2, 10]));
+        assert_eq!(req, "GET /ip?format=text HTTP/1.1");
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (r, w) = tokio::io::split(client);
+        let rsp = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let (ip, _) = tokio::join!(fetch_ip(&config, r, w), serve_once(server, rsp));
+        assert!(ip.is_err());
+    }
+}
@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use http::{HeaderName, Method};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use g3_http::client::{HttpForwardRemoteResponse, HttpResponseParseError};
+use g3_types::net::HttpHeaderValue;
+
+use super::ArcNextProxyPeer;
+use crate::auth::UserUpstreamTrafficStats;
+use crate::module::http_forward::{
+    ArcHttpForwardTaskRemoteStats, BoxHttpForwardReader, HttpForwardRead, HttpForwardTaskNotes,
+};
+
+/// Set or strip the egress peer header in responses received from the peer,
+/// and stop the reuse of the connection if the peer is expired
+pub(super) struct ProxyFloatHttpForwardReader {
+    inner: BoxHttpForwardReader,
+    header: Option<(HeaderName, Option<HttpHeaderValue>)>,
+    expire_check_peer: Option<ArcNextProxyPeer>,
+}
+
+impl ProxyFloatHttpForwardReader {
+    pub(super) fn new(inner: BoxHttpForwardReader) -> Self {
+        ProxyFloatHttpForwardReader {
+            inner,
+            header: None,
+            expire_check_peer: None,
+        }
+    }
+
+    pub(super) fn set_peer_header(&mut self, header_name: HeaderName, peer_id: &str, strip: bool) {
+        let header_value = if strip || peer_id.is_empty() {
+            None
+        } else {
+            HttpHeaderValue::from_str(peer_id).ok()
+        };
+        self.header = Some((header_name, header_value));
+    }
+
+    /// the connection won't be kept alive after the responses received when `peer` is expired
+    pub(super) fn set_expire_check(&mut self, peer: ArcNextProxyPeer) {
+        self.expire_check_peer = Some(peer);
+    }
+}
+
+impl AsyncRead for ProxyFloatHttpForwardReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncBufRead for ProxyFloatHttpForwardReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+#[async_trait]
+impl HttpForwardRead for ProxyFloatHttpForwardReader {
+    fn update_stats(
+        &mut self,
+        task_stats: &ArcHttpForwardTaskRemoteStats,
+        user_stats: Vec<Arc<UserUpstreamTrafficStats>>,
+    ) {
+        self.inner.update_stats(task_stats, user_stats);
+    }
+
+    async fn recv_response_header<'a>(
+        &'a mut self,
+        method: &Method,
+        keep_alive: bool,
+        max_header_size: usize,
+        http_notes: &'a mut HttpForwardTaskNotes,
+    ) -> Result<HttpForwardRemoteResponse, HttpResponseParseError> {
+        let mut rsp = self
+            .inner
+            .recv_response_header(method, keep_alive, max_header_size, http_notes)
+            .await?;
+        if let Some((name, value)) = &self.header {
+            rsp.end_to_end_headers.remove(name);
+            if let Some(value) = value {
+                rsp.end_to_end_headers.insert(name.clone(), value.clone());
+            }
+        }
+        if self
+            .expire_check_peer
+            .as_ref()
+            .is_some_and(|peer| peer.is_expired())
+        {
+            rsp.set_no_keep_alive();
+        }
+        Ok(rsp)
+    }
+}
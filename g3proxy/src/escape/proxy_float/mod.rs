@@ -15,22 +15,28 @@
  */
 
 use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use anyhow::{anyhow, Context};
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use futures_util::future::AbortHandle;
-use log::warn;
+use log::{debug, info, warn};
 use slog::Logger;
+use tokio::time::Instant;
 
 use g3_daemon::stat::remote::ArcTcpConnectionTaskRemoteStats;
+use g3_socks::SocksCommand;
 use g3_types::metrics::MetricsName;
-use g3_types::net::{Host, OpensslClientConfig, UpstreamAddr};
+use g3_types::net::{EgressArea, Host, OpensslClientConfig, UpstreamAddr};
 
 use super::{ArcEscaper, ArcEscaperStats, Escaper, EscaperInternal};
-use crate::config::escaper::proxy_float::ProxyFloatEscaperConfig;
+use crate::config::escaper::proxy_float::{
+    PeerSelectionMode, PeerTagRule, ProxyFloatEscaperConfig,
+};
 use crate::config::escaper::{AnyEscaperConfig, EscaperConfig};
+use crate::log::escape::egress_state::EscapeLogForEgressState;
 use crate::module::ftp_over_http::{
     AnyFtpConnectContextParam, ArcFtpTaskRemoteControlStats, ArcFtpTaskRemoteTransferStats,
     BoxFtpConnectContext, BoxFtpRemoteConnection, DenyFtpConnectContext,
@@ -49,10 +55,22 @@ use crate::module::udp_relay::{
 use crate::serve::ServerTaskNotes;
 
 mod stats;
-use stats::ProxyFloatEscaperStats;
+use stats::{ProxyFloatEscaperStats, ProxyFloatPeerTcpIoStats};
+
+mod http_forward;
+use http_forward::ProxyFloatHttpForwardReader;
+
+mod eip_verify;
+use eip_verify::PeerEipVerifier;
+
+mod canary;
+use canary::PeerCanaryMirror;
+
+mod egress_state;
+use egress_state::EgressStateEvent;
 
 mod peer;
-use peer::{ArcNextProxyPeer, PeerSet};
+use peer::{ArcNextProxyPeer, PeerSelectContext, PeerSet, RecentPeers, TaskAttrs};
 mod source;
 
 pub(super) struct ProxyFloatEscaper {
@@ -62,6 +80,11 @@ pub(super) struct ProxyFloatEscaper {
     peers: Arc<ArcSwap<PeerSet>>,
     tls_config: Option<Arc<OpensslClientConfig>>,
     escape_logger: Logger,
+    create_instant: Instant,
+    peers_ready: AtomicBool,
+    recent_peers: Option<RecentPeers>,
+    eip_verifier: Option<Arc<PeerEipVerifier>>,
+    canary_mirror: Option<PeerCanaryMirror>,
 }
 
 impl Drop for ProxyFloatEscaper {
@@ -107,7 +130,9 @@ impl ProxyFloatEscaper {
                 Arc::new(peers)
             }
         };
+        stats.set_peer_feed_generation(peers.generation());
         let peers = Arc::new(ArcSwap::new(peers));
+        stats.set_peer_set(&peers);
         let source_job_handler = source::new_job(
             Arc::clone(&config),
             Arc::clone(&stats),
@@ -117,6 +142,12 @@ impl ProxyFloatEscaper {
         )?;
 
         stats.set_extra_tags(config.extra_metrics_tags.clone());
+        let recent_peers = RecentPeers::new(config.peer_avoid_recent);
+        let eip_verifier = config
+            .peer_eip_verify
+            .as_ref()
+            .map(|c| Arc::new(PeerEipVerifier::new(c)));
+        let canary_mirror = config.peer_canary.as_ref().map(PeerCanaryMirror::new);
 
         let escaper = ProxyFloatEscaper {
             config,
@@ -125,6 +156,11 @@ impl ProxyFloatEscaper {
             peers,
             tls_config,
             escape_logger,
+            create_instant: Instant::now(),
+            peers_ready: AtomicBool::new(false),
+            recent_peers,
+            eip_verifier,
+            canary_mirror,
         };
 
         Ok(Arc::new(escaper))
@@ -149,29 +185,372 @@ impl ProxyFloatEscaper {
         }
     }
 
-    fn select_peer_from_escaper(&self) -> Option<ArcNextProxyPeer> {
+    fn check_peers_ready(&self, peer_set: &PeerSet) -> anyhow::Result<()> {
+        let required = self.config.min_ready_peers;
+        if required == 0 || self.peers_ready.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let current = peer_set.live_count();
+        if current >= required {
+            self.peers_ready.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        if self.create_instant.elapsed() >= self.config.min_ready_timeout {
+            warn!(
+                "escaper {} is not ready after {:?}: {current} live peers while {required} is required",
+                self.config.name, self.config.min_ready_timeout
+            );
+            self.peers_ready.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "peers not ready: {current} live peers while {required} is required"
+        ))
+    }
+
+    /// select a peer that matches the egress area and isp of the task,
+    /// the isp constraint will be dropped if no peer matches both,
+    /// the configured fallback areas will be tried in order if no peer is found in the area
+    fn select_constrained_peer(
+        &self,
+        peer_set: &PeerSet,
+        task_notes: &ServerTaskNotes,
+        upstream_port: Option<u16>,
+        command: SocksCommand,
+    ) -> Option<ArcNextProxyPeer> {
+        let area = task_notes.egress_area();
+        if let Some(peer) =
+            self.select_peer_in_area(peer_set, task_notes, upstream_port, command, area)
+        {
+            return Some(peer);
+        }
+
+        let requested = area?;
+        for fallback in self.config.peer_area_fallback.get(requested) {
+            if let Some(peer) = self.select_peer_in_area(
+                peer_set,
+                task_notes,
+                upstream_port,
+                command,
+                Some(fallback),
+            ) {
+                debug!(
+                    "escaper {}: fallback to area {fallback} as no peer is available in area {requested}",
+                    self.config.name
+                );
+                self.stats.add_area_fallback(requested);
+                return Some(peer);
+            }
+        }
+        None
+    }
+
+    fn select_peer_in_area(
+        &self,
+        peer_set: &PeerSet,
+        task_notes: &ServerTaskNotes,
+        upstream_port: Option<u16>,
+        command: SocksCommand,
+        area: Option<&EgressArea>,
+    ) -> Option<ArcNextProxyPeer> {
+        let mut query = peer_set
+            .query(upstream_port, command)
+            .restrict(self.user_peer_rule(task_notes));
+        if let Some(area) = area {
+            query = query.filter_area(area);
+        }
+        if let Some(isp) = task_notes.egress_isp() {
+            query = query.filter_isp(isp);
+            if area.is_some() {
+                query = query.relax_last();
+            }
+        }
+
+        if let Some(max_rtt) = task_notes.egress_max_rtt() {
+            return query.select_by_latency(max_rtt);
+        }
+
+        query.select(
+            self.selection_mode(task_notes),
+            &self.select_context(task_notes),
+        )
+    }
+
+    /// the selection mode requested by the task, or the escaper default one
+    fn selection_mode(&self, task_notes: &ServerTaskNotes) -> PeerSelectionMode {
+        task_notes
+            .egress_peer_selection()
+            .unwrap_or(self.config.peer_selection)
+    }
+
+    /// the peer tag rule for the user of the task
+    fn user_peer_rule(&self, task_notes: &ServerTaskNotes) -> Option<&PeerTagRule> {
+        let user = task_notes.user_ctx()?.user_name();
+        self.config.user_peer_policy.get(user)
+    }
+
+    fn select_context<'a>(&'a self, task_notes: &'a ServerTaskNotes) -> PeerSelectContext<'a> {
+        PeerSelectContext {
+            recent: self.recent_peers.as_ref(),
+            client_key: task_notes.client_addr().ip(),
+            user: task_notes.raw_user_name(),
+            max_age: self.config.peer_max_age,
+            peer_rule: self.user_peer_rule(task_notes),
+        }
+    }
+
+    /// select a peer for the upstream `port`, which may support the socks5 `command`
+    fn select_peer(
+        &self,
+        task_notes: &ServerTaskNotes,
+        upstream_port: Option<u16>,
+        command: SocksCommand,
+    ) -> anyhow::Result<ArcNextProxyPeer> {
         let peer_set = self.peers.load();
-        peer_set.select_random_peer()
+        self.check_peers_ready(&peer_set)?;
+
+        let r = self.select_peer_from_set(&peer_set, task_notes, upstream_port, command);
+        let egress_state = &self.stats.egress_state;
+        let event = if r.is_ok() {
+            egress_state.on_selected(peer_set.generation(), || peer_set.live_count())
+        } else {
+            egress_state.on_unselectable(peer_set.live_count())
+        };
+        if let Some(event) = event {
+            self.log_egress_state_event(&peer_set, event);
+        }
+        if let Ok(peer) = &r {
+            debug!(
+                "escaper {}: selected peer {} isp {} eip {} area {}",
+                self.config.name,
+                peer.id(),
+                peer.isp().unwrap_or("-"),
+                peer.eip()
+                    .map(|ip| ip.to_string())
+                    .as_deref()
+                    .unwrap_or("-"),
+                peer.area()
+                    .map(|area| area.to_string())
+                    .as_deref()
+                    .unwrap_or("-"),
+            );
+        }
+        r
     }
 
-    fn select_peer(&self, task_notes: &ServerTaskNotes) -> anyhow::Result<ArcNextProxyPeer> {
+    fn log_egress_state_event(&self, peer_set: &PeerSet, event: EgressStateEvent) {
+        let log = EscapeLogForEgressState {
+            feed_generation: peer_set.generation(),
+        };
+        match event {
+            EgressStateEvent::Down { last_live_count } => {
+                warn!(
+                    "escaper {}: egress down, no live peer left, the last peer set had {last_live_count} live peers",
+                    self.config.name
+                );
+                log.log_down(&self.escape_logger, last_live_count);
+            }
+            EgressStateEvent::Restored { live_count } => {
+                info!(
+                    "escaper {}: egress restored with {live_count} live peers",
+                    self.config.name
+                );
+                log.log_restored(&self.escape_logger, live_count);
+            }
+        }
+    }
+
+    fn select_peer_from_set(
+        &self,
+        peer_set: &PeerSet,
+        task_notes: &ServerTaskNotes,
+        upstream_port: Option<u16>,
+        command: SocksCommand,
+    ) -> anyhow::Result<ArcNextProxyPeer> {
         if let Some(path_selection) = task_notes.egress_path() {
             if let Some(id) = path_selection.select_matched_id(self.name().as_str()) {
-                let peer_set = self.peers.load();
+                if self.config.egress_path_fallback {
+                    let peer = peer_set
+                        .select_named_peer_or_any(
+                            &[id],
+                            upstream_port,
+                            command,
+                            self.user_peer_rule(task_notes),
+                        )
+                        .ok_or_else(|| anyhow!("no usable peer found for egress path {id}"))?;
+                    if peer.id() != id {
+                        debug!(
+                            "escaper {}: peer {id} in egress path is not usable, fallback to peer {}",
+                            self.config.name,
+                            peer.id()
+                        );
+                    }
+                    return Ok(peer);
+                }
+
                 let peer = peer_set
                     .select_named_peer(id)
                     .ok_or_else(|| anyhow!("no peer with id {id} found in local cache"))?;
-                return if peer.is_expired() {
+                return if peer.expired_for_new() {
                     Err(anyhow!("peer {id} is expired"))
+                } else if peer.is_deprecated() {
+                    Err(anyhow!("peer {id} is deprecated"))
+                } else if peer.is_canary() {
+                    Err(anyhow!("peer {id} is a canary peer"))
+                } else if peer.load().is_saturated() {
+                    Err(anyhow!("peer {id} reached the max connections limit"))
+                } else if !peer.allow_port(upstream_port) {
+                    Err(anyhow!("peer {id} does not allow the upstream port"))
+                } else if !peer.is_capable(command) {
+                    Err(anyhow!(
+                        "peer {id} does not support socks5 command {command}"
+                    ))
+                } else if self
+                    .user_peer_rule(task_notes)
+                    .is_some_and(|rule| !peer.tags().permitted_by(rule))
+                {
+                    self.stats.add_peer_policy_denied();
+                    Err(anyhow!(
+                        "peer {id} is not permitted for the user by the user peer policy"
+                    ))
+                } else if !peer.circuit_breaker().try_select(id) {
+                    Err(anyhow!(
+                        "peer {id} is temporarily unavailable by circuit breaker"
+                    ))
                 } else {
+                    peer.selection_cap().on_selected();
                     Ok(peer)
                 };
             }
         }
 
-        self.select_peer_from_escaper()
+        let r = self.select_matched_peer(peer_set, task_notes, upstream_port, command);
+        if r.is_err() {
+            if let Some(rule) = self.user_peer_rule(task_notes) {
+                if peer_set
+                    .query(upstream_port, command)
+                    .restrict(Some(rule))
+                    .is_empty()
+                    && !peer_set.query(upstream_port, command).is_empty()
+                {
+                    self.stats.add_peer_policy_denied();
+                    return Err(anyhow!(
+                        "no peer is permitted for the user by the user peer policy"
+                    ));
+                }
+            }
+        }
+        r
+    }
+
+    fn select_matched_peer(
+        &self,
+        peer_set: &PeerSet,
+        task_notes: &ServerTaskNotes,
+        upstream_port: Option<u16>,
+        command: SocksCommand,
+    ) -> anyhow::Result<ArcNextProxyPeer> {
+        if task_notes.egress_area().is_some() || task_notes.egress_isp().is_some() {
+            return self
+                .select_constrained_peer(peer_set, task_notes, upstream_port, command)
+                .ok_or_else(|| anyhow!("no peer matches the egress area and isp constraints"));
+        }
+
+        if let Some(max_rtt) = task_notes.egress_max_rtt() {
+            return peer_set
+                .query(upstream_port, command)
+                .restrict(self.user_peer_rule(task_notes))
+                .select_by_latency(max_rtt)
+                .ok_or_else(|| anyhow!("no peer can be selected from escaper config"));
+        }
+
+        peer_set
+            .select(
+                self.selection_mode(task_notes),
+                upstream_port,
+                command,
+                &self.select_context(task_notes),
+            )
             .ok_or_else(|| anyhow!("no peer can be selected from escaper config"))
     }
+
+    fn verify_peer_eip(&self, peer: &ArcNextProxyPeer, task_notes: &ServerTaskNotes) {
+        if let Some(verifier) = &self.eip_verifier {
+            verifier.check(&self.config.name, peer, task_notes, &self.stats);
+        }
+    }
+
+    /// mirror the connect of a sampled tcp connect task to a canary peer,
+    /// return true if mirrored
+    fn mirror_to_canary(
+        &self,
+        tcp_notes: &TcpConnectTaskNotes,
+        task_notes: &ServerTaskNotes,
+    ) -> bool {
+        let Some(mirror) = &self.canary_mirror else {
+            return false;
+        };
+        mirror.mirror(
+            &self.config.name,
+            &self.peers.load(),
+            &tcp_notes.upstream,
+            task_notes,
+            &self.stats,
+        )
+    }
+
+    /// select another peer to retry the connection setup which is reset by the previous one
+    fn select_retry_peer(
+        &self,
+        task_notes: &ServerTaskNotes,
+        tcp_notes: &TcpConnectTaskNotes,
+        prev_peer: &ArcNextProxyPeer,
+        e: TcpConnectError,
+    ) -> Result<ArcNextProxyPeer, TcpConnectError> {
+        let peer = match self.select_peer(
+            task_notes,
+            Some(tcp_notes.upstream.port()),
+            SocksCommand::TcpConnect,
+        ) {
+            Ok(peer) if peer.id() != prev_peer.id() => peer,
+            _ => return Err(e),
+        };
+        debug!(
+            "escaper {}: retry with peer {} as the connection to peer {} is reset: {e}",
+            self.config.name,
+            peer.id(),
+            prev_peer.id()
+        );
+        Ok(peer)
+    }
+
+    fn wrap_http_forward_connection(
+        &self,
+        peer: &ArcNextProxyPeer,
+        connection: BoxHttpForwardConnection,
+    ) -> BoxHttpForwardConnection {
+        // the reuse is not limited if no grace window is set
+        let check_expire = peer.expire_instant().is_some() && peer.expire_grace().is_some();
+        if self.config.egress_peer_rsp_header.is_none() && !check_expire {
+            return connection;
+        }
+        let (writer, reader) = connection;
+        let mut reader = ProxyFloatHttpForwardReader::new(reader);
+        if let Some(header_name) = &self.config.egress_peer_rsp_header {
+            reader.set_peer_header(
+                header_name.clone(),
+                peer.id(),
+                self.config.strip_egress_peer_rsp_header,
+            );
+        }
+        if check_expire {
+            reader.set_expire_check(peer.clone());
+        }
+        (writer, Box::new(reader))
+    }
 }
 
 #[async_trait]
@@ -200,6 +579,19 @@ impl Escaper for ProxyFloatEscaper {
         .await
     }
 
+    fn simulate_selection(&self, data: &str) -> anyhow::Result<String> {
+        let value = serde_json::from_str::<serde_json::Value>(data)
+            .map_err(|e| anyhow!("the data is not valid json: {e}"))?;
+        let requests = TaskAttrs::parse_json_list(&value)?;
+        let peer_set = self.peers.load();
+        let report = peer_set.simulate_selection(
+            self.config.peer_selection,
+            self.config.peer_max_age,
+            &requests,
+        );
+        Ok(report.to_json().to_string())
+    }
+
     async fn tcp_setup_connection<'a>(
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
@@ -209,10 +601,36 @@ impl Escaper for ProxyFloatEscaper {
         self.stats.interface.add_tcp_connect_attempted();
         tcp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(
+                task_notes,
+                Some(tcp_notes.upstream.port()),
+                SocksCommand::TcpConnect,
+            )
             .map_err(TcpConnectError::EscaperNotUsable)?;
-        peer.tcp_setup_connection(tcp_notes, task_notes, task_stats)
+        let mirrored = self.mirror_to_canary(tcp_notes, task_notes);
+        let start = Instant::now();
+        let (peer, r) = match peer
+            .tcp_setup_connection(tcp_notes, task_notes, task_stats.clone())
             .await
+        {
+            Err(e) if peer.reset_policy().retry(&e) => {
+                let peer = self.select_retry_peer(task_notes, tcp_notes, &peer, e)?;
+                let r = peer
+                    .tcp_setup_connection(tcp_notes, task_notes, task_stats)
+                    .await;
+                (peer, r)
+            }
+            r => (peer, r),
+        };
+        if mirrored {
+            self.stats
+                .canary_live_connect
+                .record(r.is_ok(), start.elapsed());
+        }
+        if r.is_ok() {
+            self.verify_peer_eip(&peer, task_notes);
+        }
+        r
     }
 
     async fn tls_setup_connection<'a>(
@@ -226,10 +644,35 @@ impl Escaper for ProxyFloatEscaper {
         self.stats.interface.add_tls_connect_attempted();
         tcp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(
+                task_notes,
+                Some(tcp_notes.upstream.port()),
+                SocksCommand::TcpConnect,
+            )
             .map_err(TcpConnectError::EscaperNotUsable)?;
-        peer.tls_setup_connection(tcp_notes, task_notes, task_stats, tls_config, tls_name)
+        let (peer, r) = match peer
+            .tls_setup_connection(
+                tcp_notes,
+                task_notes,
+                task_stats.clone(),
+                tls_config,
+                tls_name,
+            )
             .await
+        {
+            Err(e) if peer.reset_policy().retry(&e) => {
+                let peer = self.select_retry_peer(task_notes, tcp_notes, &peer, e)?;
+                let r = peer
+                    .tls_setup_connection(tcp_notes, task_notes, task_stats, tls_config, tls_name)
+                    .await;
+                (peer, r)
+            }
+            r => (peer, r),
+        };
+        if r.is_ok() {
+            self.verify_peer_eip(&peer, task_notes);
+        }
+        r
     }
 
     async fn udp_setup_connection<'a>(
@@ -241,7 +684,11 @@ impl Escaper for ProxyFloatEscaper {
         self.stats.interface.add_udp_connect_attempted();
         udp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(
+                task_notes,
+                udp_notes.upstream.as_ref().map(|u| u.port()),
+                SocksCommand::UdpAssociate,
+            )
             .map_err(UdpConnectError::EscaperNotUsable)?;
         peer.udp_setup_connection(udp_notes, task_notes, task_stats)
             .await
@@ -256,7 +703,7 @@ impl Escaper for ProxyFloatEscaper {
         self.stats.interface.add_udp_relay_session_attempted();
         udp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(task_notes, None, SocksCommand::UdpAssociate)
             .map_err(UdpRelaySetupError::EscaperNotUsable)?;
         peer.udp_setup_relay(udp_notes, task_notes, task_stats)
             .await
@@ -280,7 +727,7 @@ impl Escaper for ProxyFloatEscaper {
 #[async_trait]
 impl EscaperInternal for ProxyFloatEscaper {
     fn _resolver(&self) -> &MetricsName {
-        Default::default()
+        self.config.resolver()
     }
 
     fn _dependent_escaper(&self) -> Option<BTreeSet<MetricsName>> {
@@ -317,10 +764,27 @@ impl EscaperInternal for ProxyFloatEscaper {
         self.stats.interface.add_http_forward_connection_attempted();
         tcp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(
+                task_notes,
+                Some(tcp_notes.upstream.port()),
+                SocksCommand::TcpConnect,
+            )
             .map_err(TcpConnectError::EscaperNotUsable)?;
-        peer.new_http_forward_connection(tcp_notes, task_notes, task_stats)
+        let (peer, connection) = match peer
+            .new_http_forward_connection(tcp_notes, task_notes, task_stats.clone())
             .await
+        {
+            Ok(connection) => (peer, connection),
+            Err(e) if peer.reset_policy().retry(&e) => {
+                let peer = self.select_retry_peer(task_notes, tcp_notes, &peer, e)?;
+                let connection = peer
+                    .new_http_forward_connection(tcp_notes, task_notes, task_stats)
+                    .await?;
+                (peer, connection)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(self.wrap_http_forward_connection(&peer, connection))
     }
 
     async fn _new_https_forward_connection<'a>(
@@ -336,10 +800,35 @@ impl EscaperInternal for ProxyFloatEscaper {
             .add_https_forward_connection_attempted();
         tcp_notes.escaper.clone_from(&self.config.name);
         let peer = self
-            .select_peer(task_notes)
+            .select_peer(
+                task_notes,
+                Some(tcp_notes.upstream.port()),
+                SocksCommand::TcpConnect,
+            )
             .map_err(TcpConnectError::EscaperNotUsable)?;
-        peer.new_https_forward_connection(tcp_notes, task_notes, task_stats, tls_config, tls_name)
+        let (peer, connection) = match peer
+            .new_https_forward_connection(
+                tcp_notes,
+                task_notes,
+                task_stats.clone(),
+                tls_config,
+                tls_name,
+            )
             .await
+        {
+            Ok(connection) => (peer, connection),
+            Err(e) if peer.reset_policy().retry(&e) => {
+                let peer = self.select_retry_peer(task_notes, tcp_notes, &peer, e)?;
+                let connection = peer
+                    .new_https_forward_connection(
+                        tcp_notes, task_notes, task_stats, tls_config, tls_name,
+                    )
+                    .await?;
+                (peer, connection)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(self.wrap_http_forward_connection(&peer, connection))
     }
 
     async fn _new_ftp_control_connection<'a>(
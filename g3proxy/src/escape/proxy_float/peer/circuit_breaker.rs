@@ -0,0 +1,343 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::time::Instant;
+
+use crate::config::escaper::proxy_float::PeerCircuitBreakerConfig;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub(crate) enum PeerCircuitBreakerState {
+    Closed = 0,
+    Open = 1,
+    HalfOpen = 2,
+}
+
+impl PeerCircuitBreakerState {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => PeerCircuitBreakerState::Open,
+            2 => PeerCircuitBreakerState::HalfOpen,
+            _ => PeerCircuitBreakerState::Closed,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PeerCircuitBreakerState::Closed => "closed",
+            PeerCircuitBreakerState::Open => "open",
+            PeerCircuitBreakerState::HalfOpen => "half-open",
+        }
+    }
+}
+
+/// A circuit breaker based on consecutive connect failures to the peer.
+///
+/// All time values are stored as milliseconds since the creation of the breaker.
+pub(crate) struct PeerCircuitBreaker {
+    config: Option<PeerCircuitBreakerConfig>,
+    created: Instant,
+    state: AtomicU8,
+    failures: AtomicU32,
+    first_failure: AtomicU64,
+    /// the peer won't be selected before this time if not in closed state
+    retry_after: AtomicU64,
+}
+
+impl PeerCircuitBreaker {
+    pub(crate) fn new(config: Option<PeerCircuitBreakerConfig>) -> Self {
+        PeerCircuitBreaker {
+            config,
+            created: Instant::now(),
+            state: AtomicU8::new(PeerCircuitBreakerState::Closed as u8),
+            failures: AtomicU32::new(0),
+            first_failure: AtomicU64::new(0),
+            retry_after: AtomicU64::new(0),
+        }
+    }
+
+    fn now_millis(&self) -> u64 {
+        u64::try_from(self.created.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    fn duration_millis(dur: Duration) -> u64 {
+        u64::try_from(dur.as_millis()).unwrap_or(u64::MAX)
+    }
+
+    pub(crate) fn state(&self) -> PeerCircuitBreakerState {
+        PeerCircuitBreakerState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    /// check if the peer can be selected, without changing the state
+    pub(crate) fn is_selectable(&self) -> bool {
+        self.is_selectable_at(self.now_millis())
+    }
+
+    fn is_selectable_at(&self, now: u64) -> bool {
+        match self.state() {
+            PeerCircuitBreakerState::Closed => true,
+            _ => now >= self.retry_after.load(Ordering::Acquire),
+        }
+    }
+
+    /// try to select the peer, return false if it is not selectable
+    ///
+    /// If not in closed state, the probe is claimed by a single compare-exchange on the retry time,
+    /// so only one of the concurrent callers will get the peer after the cooldown period.
+    pub(crate) fn try_select(&self, peer_id: &str) -> bool {
+        self.try_select_at(peer_id, self.now_millis())
+    }
+
+    fn try_select_at(&self, peer_id: &str, now: u64) -> bool {
+        let Some(config) = &self.config else {
+            return true;
+        };
+        if self.state() == PeerCircuitBreakerState::Closed {
+            return true;
+        }
+        let retry_after = self.retry_after.load(Ordering::Acquire);
+        if now < retry_after {
+            return false;
+        }
+        // also retry if the previous probe has no result in time
+        if self
+            .retry_after
+            .compare_exchange(
+                retry_after,
+                now.saturating_add(Self::duration_millis(config.cooldown)),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        if self
+            .state
+            .compare_exchange(
+                PeerCircuitBreakerState::Open as u8,
+                PeerCircuitBreakerState::HalfOpen as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            info!("peer {peer_id}: circuit breaker is half-open, probing");
+        }
+        true
+    }
+
+    pub(crate) fn record_success(&self, peer_id: &str) {
+        if self.config.is_none() {
+            return;
+        }
+        self.failures.store(0, Ordering::Relaxed);
+        let old = self
+            .state
+            .swap(PeerCircuitBreakerState::Closed as u8, Ordering::AcqRel);
+        if old != PeerCircuitBreakerState::Closed as u8 {
+            info!("peer {peer_id}: circuit breaker is closed");
+        }
+    }
+
+    pub(crate) fn record_failure(&self, peer_id: &str) {
+        self.record_failure_at(peer_id, self.now_millis());
+    }
+
+    fn record_failure_at(&self, peer_id: &str, now: u64) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        match self.state() {
+            PeerCircuitBreakerState::Closed => {
+                let first_failure = self.first_failure.load(Ordering::Relaxed);
+                let failures = if self.failures.load(Ordering::Relaxed) == 0
+                    || now.saturating_sub(first_failure)
+                        > Self::duration_millis(config.failure_window)
+                {
+                    self.first_failure.store(now, Ordering::Relaxed);
+                    self.failures.store(1, Ordering::Relaxed);
+                    1
+                } else {
+                    self.failures.fetch_add(1, Ordering::Relaxed) + 1
+                };
+                if failures >= config.failure_threshold {
+                    self.open(config, now);
+                    warn!(
+                        "peer {peer_id}: circuit breaker is open after {failures} consecutive connect failures"
+                    );
+                }
+            }
+            PeerCircuitBreakerState::HalfOpen => {
+                self.open(config, now);
+                warn!("peer {peer_id}: circuit breaker is open again as the probe failed");
+            }
+            PeerCircuitBreakerState::Open => {}
+        }
+    }
+
+    /// take over the state of the breaker of the same peer in the previous peer set
+    pub(crate) fn inherit(&self, peer_id: &str, old: &PeerCircuitBreaker) {
+        if self.config.is_none() {
+            return;
+        }
+        let state = old.state();
+        if state == PeerCircuitBreakerState::Closed && old.failures.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let convert = |v: u64| {
+            let instant = old.created + Duration::from_millis(v);
+            Self::duration_millis(instant.saturating_duration_since(self.created))
+        };
+        self.failures
+            .store(old.failures.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.first_failure.store(
+            convert(old.first_failure.load(Ordering::Relaxed)),
+            Ordering::Relaxed,
+        );
+        self.retry_after.store(
+            convert(old.retry_after.load(Ordering::Acquire)),
+            Ordering::Release,
+        );
+        self.state.store(state as u8, Ordering::Release);
+        if state != PeerCircuitBreakerState::Closed {
+            debug!(
+                "peer {peer_id}: circuit breaker state {} kept after peer refresh",
+                state.as_str()
+            );
+        }
+    }
+
+    fn open(&self, config: &PeerCircuitBreakerConfig, now: u64) {
+        self.failures.store(0, Ordering::Relaxed);
+        self.retry_after.store(
+            now.saturating_add(Self::duration_millis(config.cooldown)),
+            Ordering::Release,
+        );
+        self.state
+            .store(PeerCircuitBreakerState::Open as u8, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_breaker() -> PeerCircuitBreaker {
+        PeerCircuitBreaker::new(Some(PeerCircuitBreakerConfig {
+            failure_threshold: 2,
+            failure_window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        }))
+    }
+
+    #[test]
+    fn open_and_close() {
+        let breaker = new_breaker();
+
+        breaker.record_failure_at("test", 0);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Closed);
+        breaker.record_failure_at("test", 1_000);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Open);
+        assert!(!breaker.is_selectable_at(2_000));
+
+        assert!(breaker.is_selectable_at(32_000));
+        assert!(breaker.try_select_at("test", 32_000));
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::HalfOpen);
+        // only one probe at a time
+        assert!(!breaker.is_selectable_at(33_000));
+        assert!(!breaker.try_select_at("test", 33_000));
+
+        breaker.record_failure_at("test", 34_000);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Open);
+        assert!(!breaker.is_selectable_at(63_000));
+
+        assert!(breaker.try_select_at("test", 65_000));
+        breaker.record_success("test");
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Closed);
+        assert!(breaker.is_selectable_at(65_000));
+    }
+
+    #[test]
+    fn race_probe() {
+        let breaker = new_breaker();
+        breaker.record_failure_at("test", 0);
+        breaker.record_failure_at("test", 0);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Open);
+
+        let barrier = std::sync::Barrier::new(2);
+        let selected = std::thread::scope(|s| {
+            let handles = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        breaker.try_select_at("test", 30_000)
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .filter(|v| *v)
+                .count()
+        });
+        assert_eq!(selected, 1);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::HalfOpen);
+
+        // the next probe after the previous one timed out
+        assert!(!breaker.try_select_at("test", 59_000));
+        assert!(breaker.try_select_at("test", 60_000));
+        assert!(!breaker.try_select_at("test", 60_000));
+    }
+
+    #[test]
+    fn failure_window() {
+        let breaker = new_breaker();
+
+        breaker.record_failure_at("test", 0);
+        breaker.record_failure_at("test", 11_000);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Closed);
+        breaker.record_failure_at("test", 12_000);
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Open);
+    }
+
+    #[test]
+    fn inherit() {
+        let old = new_breaker();
+        old.record_failure_at("test", 0);
+        old.record_failure_at("test", 0);
+        assert_eq!(old.state(), PeerCircuitBreakerState::Open);
+
+        let new = new_breaker();
+        new.inherit("test", &old);
+        assert_eq!(new.state(), PeerCircuitBreakerState::Open);
+        assert!(!new.is_selectable_at(0));
+        assert!(new.is_selectable_at(30_000));
+    }
+
+    #[test]
+    fn disabled() {
+        let breaker = PeerCircuitBreaker::new(None);
+        for i in 0..10 {
+            breaker.record_failure_at("test", i);
+        }
+        assert_eq!(breaker.state(), PeerCircuitBreakerState::Closed);
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::debug;
+use serde_json::Value;
+
+use g3_http::connect::HttpConnectRequest;
+use g3_resolver::{ResolveError, ResolveLocalError};
+use g3_types::metrics::MetricsName;
+use g3_types::net::{HappyEyeballsConfig, Host, UpstreamAddr};
+
+use super::ProxyFloatEscaperConfig;
+use crate::module::tcp_connect::TcpConnectError;
+use crate::resolve::HappyEyeballsResolveJob;
+
+#[derive(Clone, Copy, Default)]
+pub(super) struct ConnectTargetRewrite {
+    force_ip: bool,
+    force_hostname: bool,
+    strip_default_port: bool,
+}
+
+impl ConnectTargetRewrite {
+    pub(super) fn parse_json(v: &Value) -> anyhow::Result<Self> {
+        let mut rewrite = ConnectTargetRewrite::default();
+        match v {
+            Value::String(s) => rewrite.set_rule(s)?,
+            Value::Array(seq) => {
+                for (i, v) in seq.iter().enumerate() {
+                    let Value::String(s) = v else {
+                        return Err(anyhow!("invalid string value for #{i}"));
+                    };
+                    rewrite.set_rule(s)?;
+                }
+            }
+            _ => return Err(anyhow!("invalid string or array value")),
+        }
+        if rewrite.force_ip && rewrite.force_hostname {
+            return Err(anyhow!(
+                "force_ip and force_hostname should not be set together"
+            ));
+        }
+        Ok(rewrite)
+    }
+
+    fn set_rule(&mut self, rule: &str) -> anyhow::Result<()> {
+        match g3_json::key::normalize(rule).as_str() {
+            "force_ip" => self.force_ip = true,
+            "force_hostname" => self.force_hostname = true,
+            "strip_default_port" => self.strip_default_port = true,
+            _ => return Err(anyhow!("unsupported rewrite rule {rule}")),
+        }
+        Ok(())
+    }
+
+    pub(super) async fn build_request<'a>(
+        &self,
+        escaper_config: &ProxyFloatEscaperConfig,
+        resolver: Option<&MetricsName>,
+        upstream: &'a UpstreamAddr,
+        tls_name: Option<&Host>,
+        static_headers: &'a [String],
+    ) -> Result<ConnectTarget<'a>, TcpConnectError> {
+        let mut target = ConnectTarget {
+            upstream: Cow::Borrowed(upstream),
+            omit_port: false,
+            static_headers,
+        };
+
+        match upstream.host() {
+            Host::Domain(domain) if self.force_ip => {
+                let ip = resolve_ip(escaper_config, resolver, domain).await?;
+                target.upstream = Cow::Owned(UpstreamAddr::from_ip_and_port(ip, upstream.port()));
+            }
+            Host::Ip(_) if self.force_hostname => {
+                // the tls name is the only hostname we know for the target
+                if let Some(Host::Domain(domain)) = tls_name {
+                    target.upstream = Cow::Owned(UpstreamAddr::new(
+                        Host::Domain(domain.clone()),
+                        upstream.port(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        if self.strip_default_port && matches!(upstream.port(), 80 | 443) {
+            target.omit_port = true;
+        }
+        if target.omit_port || matches!(target.upstream, Cow::Owned(_)) {
+            debug!(
+                "rewrite CONNECT target {upstream} to {} (omit port: {})",
+                target.upstream, target.omit_port
+            );
+        }
+
+        Ok(target)
+    }
+}
+
+pub(super) struct ConnectTarget<'a> {
+    upstream: Cow<'a, UpstreamAddr>,
+    omit_port: bool,
+    static_headers: &'a [String],
+}
+
+impl<'a> ConnectTarget<'a> {
+    pub(super) fn request(&'a self) -> HttpConnectRequest<'a> {
+        let mut req = HttpConnectRequest::new(self.upstream.as_ref(), self.static_headers);
+        if self.omit_port {
+            req.omit_port();
+        }
+        req
+    }
+}
+
+/// resolve with the peer level resolver if set, or the escaper level one
+async fn resolve_ip(
+    config: &ProxyFloatEscaperConfig,
+    resolver: Option<&MetricsName>,
+    domain: &str,
+) -> Result<IpAddr, ResolveError> {
+    let resolver = resolver.unwrap_or(&config.resolver);
+    if resolver.is_empty() {
+        return Err(ResolveLocalError::NoResolverSet.into());
+    }
+    let resolver_handle =
+        crate::resolve::get_handle(resolver).map_err(|_| ResolveLocalError::NoResolverRunning)?;
+    let strategy = config.resolve_strategy;
+    let mut resolver_job =
+        HappyEyeballsResolveJob::new_dyn(strategy, &resolver_handle, Arc::from(domain))?;
+    let ips = resolver_job
+        .get_r1_or_first(
+            HappyEyeballsConfig::default().resolution_delay(),
+            usize::MAX,
+        )
+        .await?;
+    let ip = strategy
+        .pick_best(ips)
+        .ok_or(ResolveError::UnexpectedError(
+            "no upstream ip can be selected",
+        ))?;
+    debug!("resolved CONNECT target {domain} to {ip} by resolver {resolver}");
+    Ok(ip)
+}
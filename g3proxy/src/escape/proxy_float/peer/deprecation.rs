@@ -0,0 +1,158 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use g3_io_ext::{
+    ArcLimitedReaderStats, ArcLimitedWriterStats, LimitedReaderStats, LimitedWriterStats,
+};
+
+use crate::escape::proxy_float::ProxyFloatEscaperStats;
+
+/// The deprecation state of the peer set in the feed.
+///
+/// A deprecated peer won't be selected for new connections, but the alive ones are kept,
+/// and the traffic on them will be counted, so we know when it's safe to remove the peer.
+pub(crate) struct PeerDeprecation {
+    deprecated: bool,
+    /// shared with the alive connections, and with the new peer after reload
+    flag: ArcSwap<AtomicBool>,
+}
+
+impl Default for PeerDeprecation {
+    fn default() -> Self {
+        PeerDeprecation {
+            deprecated: false,
+            flag: ArcSwap::from_pointee(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl PeerDeprecation {
+    pub(super) fn set_deprecated(&mut self, deprecated: bool) {
+        self.deprecated = deprecated;
+        self.flag.load().store(deprecated, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn is_deprecated(&self) -> bool {
+        self.deprecated
+    }
+
+    /// wrap the io stats of a new connection, whose traffic will be counted in
+    /// the escaper stats once the peer is deprecated
+    pub(super) fn track(
+        &self,
+        r_stats: ArcLimitedReaderStats,
+        w_stats: ArcLimitedWriterStats,
+        escaper_stats: &Arc<ProxyFloatEscaperStats>,
+    ) -> (ArcLimitedReaderStats, ArcLimitedWriterStats) {
+        let flag = self.flag.load_full();
+        let r = DeprecatedPeerReaderStats {
+            inner: r_stats,
+            flag: flag.clone(),
+            escaper: escaper_stats.clone(),
+        };
+        let w = DeprecatedPeerWriterStats {
+            inner: w_stats,
+            flag,
+            escaper: escaper_stats.clone(),
+        };
+        (Arc::new(r), Arc::new(w))
+    }
+
+    /// share the flag with the alive connections of the old peer,
+    /// and update it to the current state
+    pub(super) fn inherit(&self, old: &PeerDeprecation) {
+        let flag = old.flag.load_full();
+        flag.store(self.deprecated, Ordering::Relaxed);
+        self.flag.store(flag);
+    }
+}
+
+struct DeprecatedPeerReaderStats {
+    inner: ArcLimitedReaderStats,
+    flag: Arc<AtomicBool>,
+    escaper: Arc<ProxyFloatEscaperStats>,
+}
+
+impl LimitedReaderStats for DeprecatedPeerReaderStats {
+    fn add_read_bytes(&self, size: usize) {
+        self.inner.add_read_bytes(size);
+        if self.flag.load(Ordering::Relaxed) {
+            self.escaper.deprecated_peer_tcp.add_in_bytes(size as u64);
+        }
+    }
+}
+
+struct DeprecatedPeerWriterStats {
+    inner: ArcLimitedWriterStats,
+    flag: Arc<AtomicBool>,
+    escaper: Arc<ProxyFloatEscaperStats>,
+}
+
+impl LimitedWriterStats for DeprecatedPeerWriterStats {
+    fn add_write_bytes(&self, size: usize) {
+        self.inner.add_write_bytes(size);
+        if self.flag.load(Ordering::Relaxed) {
+            self.escaper.deprecated_peer_tcp.add_out_bytes(size as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use g3_io_ext::{NilLimitedReaderStats, NilLimitedWriterStats};
+    use g3_types::metrics::MetricsName;
+
+    #[test]
+    fn deprecate_alive() {
+        let escaper_stats = Arc::new(ProxyFloatEscaperStats::new(
+            &MetricsName::from_str("test").unwrap(),
+        ));
+
+        let old = PeerDeprecation::default();
+        let (r_stats, w_stats) = old.track(
+            Arc::new(NilLimitedReaderStats::default()),
+            Arc::new(NilLimitedWriterStats::default()),
+            &escaper_stats,
+        );
+        r_stats.add_read_bytes(10);
+        assert_eq!(escaper_stats.deprecated_peer_tcp.snapshot().in_bytes, 0);
+
+        let mut new = PeerDeprecation::default();
+        new.set_deprecated(true);
+        assert!(new.is_deprecated());
+        new.inherit(&old);
+        r_stats.add_read_bytes(20);
+        w_stats.add_write_bytes(5);
+        let snapshot = escaper_stats.deprecated_peer_tcp.snapshot();
+        assert_eq!(snapshot.in_bytes, 20);
+        assert_eq!(snapshot.out_bytes, 5);
+
+        let mut restored = PeerDeprecation::default();
+        restored.set_deprecated(false);
+        restored.inherit(&new);
+        r_stats.add_read_bytes(20);
+        assert_eq!(escaper_stats.deprecated_peer_tcp.snapshot().in_bytes, 20);
+    }
+}
@@ -23,12 +23,13 @@ use tokio::net::tcp;
 use g3_daemon::stat::remote::{
     ArcTcpConnectionTaskRemoteStats, TcpConnectionTaskRemoteStatsWrapper,
 };
-use g3_http::connect::{HttpConnectRequest, HttpConnectResponse};
+use g3_http::connect::HttpConnectResponse;
 use g3_io_ext::{AggregatedIo, LimitedReader, LimitedWriter};
 use g3_openssl::{SslConnector, SslStream};
 use g3_types::net::{Host, OpensslClientConfig};
 
 use super::{NextProxyPeerInternal, ProxyFloatHttpPeer};
+use crate::escape::proxy_float::peer::PeerResetReader;
 use crate::log::escape::tls_handshake::{EscapeLogForTlsHandshake, TlsApplication};
 use crate::module::tcp_connect::{
     TcpConnectError, TcpConnectRemoteWrapperStats, TcpConnectResult, TcpConnectTaskNotes,
@@ -40,17 +41,28 @@ impl ProxyFloatHttpPeer {
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
+        tls_name: Option<&'a Host>,
     ) -> Result<
         (
-            BufReader<LimitedReader<tcp::OwnedReadHalf>>,
+            BufReader<LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>>,
             LimitedWriter<tcp::OwnedWriteHalf>,
         ),
         TcpConnectError,
     > {
         let (r, mut w) = self.tcp_new_connection(tcp_notes, task_notes).await?;
 
-        let req =
-            HttpConnectRequest::new(&tcp_notes.upstream, &self.shared_config.append_http_headers);
+        let connect_target = self
+            .shared_config
+            .connect_rewrite
+            .build_request(
+                &self.escaper_config,
+                self.shared_config.resolver.as_ref(),
+                &tcp_notes.upstream,
+                tls_name,
+                &self.shared_config.append_http_headers,
+            )
+            .await?;
+        let req = connect_target.request();
         req.send(&mut w)
             .await
             .map_err(TcpConnectError::NegotiationWriteFailed)?;
@@ -68,16 +80,17 @@ impl ProxyFloatHttpPeer {
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
+        tls_name: Option<&'a Host>,
     ) -> Result<
         (
-            BufReader<LimitedReader<tcp::OwnedReadHalf>>,
+            BufReader<LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>>,
             LimitedWriter<tcp::OwnedWriteHalf>,
         ),
         TcpConnectError,
     > {
         tokio::time::timeout(
             self.escaper_config.peer_negotiation_timeout,
-            self.http_connect_tcp_connect_to(tcp_notes, task_notes),
+            self.http_connect_tcp_connect_to(tcp_notes, task_notes, tls_name),
         )
         .await
         .map_err(|_| TcpConnectError::NegotiationPeerTimeout)?
@@ -90,7 +103,7 @@ impl ProxyFloatHttpPeer {
         task_stats: ArcTcpConnectionTaskRemoteStats,
     ) -> TcpConnectResult {
         let (mut r, mut w) = self
-            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes)
+            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes, None)
             .await?;
 
         // add in read buffered data
@@ -121,14 +134,14 @@ impl ProxyFloatHttpPeer {
     ) -> Result<
         SslStream<
             AggregatedIo<
-                BufReader<LimitedReader<tcp::OwnedReadHalf>>,
+                BufReader<LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>>,
                 LimitedWriter<tcp::OwnedWriteHalf>,
             >,
         >,
         TcpConnectError,
     > {
         let (ups_r, ups_w) = self
-            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes)
+            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes, Some(tls_name))
             .await?;
 
         let ssl = tls_config
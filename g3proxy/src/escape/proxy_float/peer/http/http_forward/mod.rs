@@ -56,13 +56,15 @@ impl ProxyFloatHttpPeer {
         ups_w.reset_stats(Arc::new(w_wrapper_stats) as _);
         let ups_r = LimitedBufReader::new_directed(ups_r, Arc::new(r_wrapper_stats) as _);
 
+        let keep_alive = self.keep_alive.track();
         let writer = HttpPeerHttpForwardWriter::new(
             ups_w,
             Some(Arc::clone(&self.escaper_stats)),
             &self.shared_config,
             tcp_notes.upstream.clone(),
+            keep_alive.clone(),
         );
-        let reader = HttpPeerHttpForwardReader::new(ups_r);
+        let reader = HttpPeerHttpForwardReader::new(ups_r, Some(keep_alive));
         Ok((Box::new(writer), Box::new(reader)))
     }
 
@@ -99,7 +101,7 @@ impl ProxyFloatHttpPeer {
         let ups_w = LimitedWriter::new_unlimited(ups_w, wrapper_stats as _);
 
         let writer = HttpPeerHttpRequestWriter::new(ups_w, None, &self.shared_config);
-        let reader = HttpPeerHttpForwardReader::new(ups_r);
+        let reader = HttpPeerHttpForwardReader::new(ups_r, None);
         Ok((Box::new(writer), Box::new(reader)))
     }
 }
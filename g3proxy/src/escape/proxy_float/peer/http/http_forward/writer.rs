@@ -30,6 +30,7 @@ use g3_types::net::UpstreamAddr;
 
 use super::{ProxyFloatEscaperStats, ProxyFloatHttpPeerSharedConfig};
 use crate::auth::UserUpstreamTrafficStats;
+use crate::escape::proxy_float::peer::PeerKeepAliveTracker;
 use crate::module::http_forward::{
     send_req_header_to_origin, send_req_header_via_proxy, ArcHttpForwardTaskRemoteStats,
     HttpForwardRemoteWrapperStats, HttpForwardTaskRemoteWrapperStats, HttpForwardWrite,
@@ -42,6 +43,7 @@ pin_project! {
         #[pin]
         inner: W,
         upstream: UpstreamAddr,
+        keep_alive: PeerKeepAliveTracker,
         escaper_stats: Option<Arc<ProxyFloatEscaperStats>>,
     }
 }
@@ -55,11 +57,13 @@ where
         escaper_stats: Option<Arc<ProxyFloatEscaperStats>>,
         config: &Arc<ProxyFloatHttpPeerSharedConfig>,
         upstream: UpstreamAddr,
+        keep_alive: PeerKeepAliveTracker,
     ) -> Self {
         HttpPeerHttpForwardWriter {
             config: Arc::clone(config),
             inner: ups_w,
             upstream,
+            keep_alive,
             escaper_stats,
         }
     }
@@ -96,6 +100,7 @@ where
 {
     fn prepare_new(&mut self, _task_notes: &ServerTaskNotes, upstream: &UpstreamAddr) {
         self.upstream = upstream.clone();
+        self.keep_alive.on_new_request();
     }
 
     fn update_stats(
@@ -124,14 +129,11 @@ where
                 return Err(io::Error::other("connection has expired"));
             }
         }
-        send_req_header_via_proxy(
-            &mut self.inner,
-            req,
-            &self.upstream,
-            &self.config.append_http_headers,
-            None,
-        )
-        .await
+        let append_headers = match &self.config.keep_alive_http_headers {
+            Some(headers) if req.keep_alive() => headers,
+            _ => &self.config.append_http_headers,
+        };
+        send_req_header_via_proxy(&mut self.inner, req, &self.upstream, append_headers, None).await
     }
 }
 
@@ -22,7 +22,7 @@ use tokio::io::{AsyncRead, AsyncWrite, BufReader};
 use g3_daemon::stat::remote::{
     ArcTcpConnectionTaskRemoteStats, TcpConnectionTaskRemoteStatsWrapper,
 };
-use g3_http::connect::{HttpConnectRequest, HttpConnectResponse};
+use g3_http::connect::HttpConnectResponse;
 use g3_io_ext::{AggregatedIo, LimitedReader, LimitedWriter};
 use g3_openssl::{SslConnector, SslStream};
 use g3_types::net::{Host, OpensslClientConfig};
@@ -37,11 +37,22 @@ impl ProxyFloatHttpsPeer {
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
+        tls_name: Option<&'a Host>,
     ) -> Result<(BufReader<impl AsyncRead>, impl AsyncWrite), TcpConnectError> {
         let (r, mut w) = self.tls_handshake_with(tcp_notes, task_notes).await?;
 
-        let req =
-            HttpConnectRequest::new(&tcp_notes.upstream, &self.shared_config.append_http_headers);
+        let connect_target = self
+            .shared_config
+            .connect_rewrite
+            .build_request(
+                &self.escaper_config,
+                self.shared_config.resolver.as_ref(),
+                &tcp_notes.upstream,
+                tls_name,
+                &self.shared_config.append_http_headers,
+            )
+            .await?;
+        let req = connect_target.request();
         req.send(&mut w)
             .await
             .map_err(TcpConnectError::NegotiationWriteFailed)?;
@@ -59,10 +70,11 @@ impl ProxyFloatHttpsPeer {
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
+        tls_name: Option<&'a Host>,
     ) -> Result<(BufReader<impl AsyncRead>, impl AsyncWrite), TcpConnectError> {
         tokio::time::timeout(
             self.escaper_config.peer_negotiation_timeout,
-            self.http_connect_tcp_connect_to(tcp_notes, task_notes),
+            self.http_connect_tcp_connect_to(tcp_notes, task_notes, tls_name),
         )
         .await
         .map_err(|_| TcpConnectError::NegotiationPeerTimeout)?
@@ -75,7 +87,7 @@ impl ProxyFloatHttpsPeer {
         task_stats: ArcTcpConnectionTaskRemoteStats,
     ) -> TcpConnectResult {
         let (r, w) = self
-            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes)
+            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes, None)
             .await?;
 
         // add task and user stats
@@ -106,7 +118,7 @@ impl ProxyFloatHttpsPeer {
     ) -> Result<SslStream<AggregatedIo<BufReader<impl AsyncRead>, impl AsyncWrite>>, TcpConnectError>
     {
         let (ups_r, ups_w) = self
-            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes)
+            .timed_http_connect_tcp_connect_to(tcp_notes, task_notes, Some(tls_name))
             .await?;
 
         let ssl = tls_config
@@ -54,9 +54,14 @@ impl ProxyFloatHttpsPeer {
         );
         let ups_w = LimitedWriter::new_unlimited(ups_w, wrapper_stats as _);
 
-        let writer =
-            HttpsPeerHttpForwardWriter::new(ups_w, &self.shared_config, tcp_notes.upstream.clone());
-        let reader = HttpsPeerHttpForwardReader::new(ups_r);
+        let keep_alive = self.keep_alive.track();
+        let writer = HttpsPeerHttpForwardWriter::new(
+            ups_w,
+            &self.shared_config,
+            tcp_notes.upstream.clone(),
+            keep_alive.clone(),
+        );
+        let reader = HttpsPeerHttpForwardReader::new(ups_r, Some(keep_alive));
         Ok((Box::new(writer), Box::new(reader)))
     }
 
@@ -93,7 +98,7 @@ impl ProxyFloatHttpsPeer {
         let ups_w = LimitedWriter::new_unlimited(ups_w, wrapper_stats as _);
 
         let writer = HttpsPeerHttpRequestWriter::new(ups_w, &self.shared_config);
-        let reader = HttpsPeerHttpForwardReader::new(ups_r);
+        let reader = HttpsPeerHttpForwardReader::new(ups_r, None);
         Ok((Box::new(writer), Box::new(reader)))
     }
 }
@@ -28,6 +28,7 @@ use g3_http::client::{HttpForwardRemoteResponse, HttpResponseParseError};
 use g3_io_ext::LimitedBufReader;
 
 use crate::auth::UserUpstreamTrafficStats;
+use crate::escape::proxy_float::peer::PeerKeepAliveTracker;
 use crate::module::http_forward::{
     ArcHttpForwardTaskRemoteStats, HttpForwardRead, HttpForwardTaskNotes,
     HttpForwardTaskRemoteWrapperStats,
@@ -37,6 +38,7 @@ pin_project! {
     pub(super) struct HttpsPeerHttpForwardReader<R: AsyncRead> {
         #[pin]
         inner: LimitedBufReader<R>,
+        keep_alive: Option<PeerKeepAliveTracker>,
     }
 }
 
@@ -44,8 +46,14 @@ impl<R> HttpsPeerHttpForwardReader<R>
 where
     R: AsyncRead + Unpin,
 {
-    pub(super) fn new(ups_r: LimitedBufReader<R>) -> Self {
-        HttpsPeerHttpForwardReader { inner: ups_r }
+    pub(super) fn new(
+        ups_r: LimitedBufReader<R>,
+        keep_alive: Option<PeerKeepAliveTracker>,
+    ) -> Self {
+        HttpsPeerHttpForwardReader {
+            inner: ups_r,
+            keep_alive,
+        }
     }
 
     async fn get_rsp_header(
@@ -58,6 +66,9 @@ where
         let rsp =
             HttpForwardRemoteResponse::parse(&mut self.inner, method, keep_alive, max_header_size)
                 .await?;
+        if let Some(tracker) = &self.keep_alive {
+            tracker.on_response(keep_alive, rsp.keep_alive());
+        }
         // TODO detect and set outgoing_addr and target_addr for supported remote proxies
         // set with the registered public ip by default
         http_notes.rsp_status = rsp.code;
@@ -30,6 +30,7 @@ use g3_types::net::UpstreamAddr;
 
 use super::ProxyFloatHttpsPeerSharedConfig;
 use crate::auth::UserUpstreamTrafficStats;
+use crate::escape::proxy_float::peer::PeerKeepAliveTracker;
 use crate::module::http_forward::{
     send_req_header_to_origin, send_req_header_via_proxy, ArcHttpForwardTaskRemoteStats,
     HttpForwardTaskRemoteWrapperStats, HttpForwardWrite,
@@ -42,6 +43,7 @@ pin_project! {
         #[pin]
         inner: W,
         upstream: UpstreamAddr,
+        keep_alive: PeerKeepAliveTracker,
     }
 }
 
@@ -53,11 +55,13 @@ where
         ups_w: W,
         config: &Arc<ProxyFloatHttpsPeerSharedConfig>,
         upstream: UpstreamAddr,
+        keep_alive: PeerKeepAliveTracker,
     ) -> Self {
         HttpsPeerHttpForwardWriter {
             config: Arc::clone(config),
             inner: ups_w,
             upstream,
+            keep_alive,
         }
     }
 }
@@ -93,6 +97,7 @@ where
 {
     fn prepare_new(&mut self, _task_notes: &ServerTaskNotes, upstream: &UpstreamAddr) {
         self.upstream = upstream.clone();
+        self.keep_alive.on_new_request();
     }
 
     fn update_stats(
@@ -115,14 +120,11 @@ where
                 return Err(io::Error::other("connection has expired"));
             }
         }
-        send_req_header_via_proxy(
-            &mut self.inner,
-            req,
-            &self.upstream,
-            &self.config.append_http_headers,
-            None,
-        )
-        .await
+        let append_headers = match &self.config.keep_alive_http_headers {
+            Some(headers) if req.keep_alive() => headers,
+            _ => &self.config.append_http_headers,
+        };
+        send_req_header_via_proxy(&mut self.inner, req, &self.upstream, append_headers, None).await
     }
 }
 
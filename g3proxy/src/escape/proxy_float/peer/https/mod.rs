@@ -16,21 +16,29 @@
 
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use log::warn;
 use serde_json::Value;
 use slog::Logger;
 use tokio::time::Instant;
 
 use g3_daemon::stat::remote::ArcTcpConnectionTaskRemoteStats;
 use g3_types::auth::{Password, Username};
-use g3_types::net::{EgressArea, EgressInfo, Host, OpensslClientConfig, TcpSockSpeedLimitConfig};
+use g3_types::metrics::MetricsName;
+use g3_types::net::{
+    EgressArea, EgressInfo, Host, OpensslClientConfig, OpensslProtocol, PortRange,
+    TcpSockSpeedLimitConfig,
+};
 
 use super::{
-    ArcNextProxyPeer, NextProxyPeer, NextProxyPeerInternal, ProxyFloatEscaperConfig,
-    ProxyFloatEscaperStats,
+    ArcNextProxyPeer, ConnectTargetRewrite, NextProxyPeer, NextProxyPeerInternal,
+    PeerCircuitBreaker, PeerDeprecation, PeerIpVersion, PeerKeepAlive, PeerLatency, PeerLoad,
+    PeerPortFilter, PeerResetPolicy, PeerSelectionCap, PeerSuccessRate, PeerTags,
+    ProxyFloatEscaperConfig, ProxyFloatEscaperStats,
 };
 use crate::module::http_forward::{ArcHttpForwardTaskRemoteStats, BoxHttpForwardConnection};
 use crate::module::tcp_connect::{TcpConnectError, TcpConnectResult, TcpConnectTaskNotes};
@@ -52,7 +60,18 @@ struct ProxyFloatHttpsPeerSharedConfig {
     tcp_conn_speed_limit: TcpSockSpeedLimitConfig,
     expire_datetime: Option<DateTime<Utc>>,
     expire_instant: Option<Instant>,
+    expire_grace: Option<Duration>,
     append_http_headers: Vec<String>,
+    /// the append headers along with the keep-alive hint, for keep-alive forward requests
+    keep_alive_http_headers: Option<Vec<String>>,
+    connect_rewrite: ConnectTargetRewrite,
+    resolver: Option<MetricsName>,
+    source_port_range: Option<PortRange>,
+    tcp_connect_timeout: Option<Duration>,
+    alt_addr: Option<SocketAddr>,
+    ip_version: PeerIpVersion,
+    feed_generation: u64,
+    refreshed_instant: Option<Instant>,
 }
 
 impl ProxyFloatHttpsPeerSharedConfig {
@@ -74,13 +93,29 @@ pub(super) struct ProxyFloatHttpsPeer {
     escaper_stats: Arc<ProxyFloatEscaperStats>,
     escape_logger: Logger,
     addr: SocketAddr,
+    id: String,
+    label: String,
     tls_config: Arc<OpensslClientConfig>,
     tls_name: Host,
+    tls_min_protocol: Option<OpensslProtocol>,
+    tls_max_protocol: Option<OpensslProtocol>,
+    tls_insecure: bool,
     username: Username,
     password: Password,
     egress_info: EgressInfo,
     http_connect_rsp_hdr_max_size: usize,
     shared_config: Arc<ProxyFloatHttpsPeerSharedConfig>,
+    port_filter: PeerPortFilter,
+    circuit_breaker: Arc<PeerCircuitBreaker>,
+    selection_cap: PeerSelectionCap,
+    reset_policy: PeerResetPolicy,
+    latency: PeerLatency,
+    success_rate: PeerSuccessRate,
+    load: PeerLoad,
+    deprecation: PeerDeprecation,
+    canary: bool,
+    tags: PeerTags,
+    keep_alive: PeerKeepAlive,
 }
 
 impl ProxyFloatHttpsPeer {
@@ -91,23 +126,76 @@ impl ProxyFloatHttpsPeer {
         addr: SocketAddr,
         tls_config: Arc<OpensslClientConfig>,
     ) -> ArcNextProxyPeer {
+        let circuit_breaker_config = escaper_config.peer_circuit_breaker;
+        let selection_cap_config = escaper_config.peer_selection_cap;
         Arc::new(ProxyFloatHttpsPeer {
             escaper_config,
             escaper_stats,
             escape_logger,
             addr,
+            id: String::new(),
+            label: String::new(),
             tls_config,
             tls_name: Host::Ip(addr.ip()),
+            tls_min_protocol: None,
+            tls_max_protocol: None,
+            tls_insecure: false,
             username: Username::empty(),
             password: Password::empty(),
             egress_info: Default::default(),
             http_connect_rsp_hdr_max_size: 4096,
             shared_config: Arc::new(Default::default()),
+            port_filter: PeerPortFilter::default(),
+            circuit_breaker: Arc::new(PeerCircuitBreaker::new(circuit_breaker_config)),
+            selection_cap: PeerSelectionCap::new(selection_cap_config),
+            reset_policy: PeerResetPolicy::default(),
+            latency: PeerLatency::default(),
+            success_rate: PeerSuccessRate::default(),
+            load: PeerLoad::default(),
+            deprecation: PeerDeprecation::default(),
+            canary: false,
+            tags: PeerTags::default(),
+            keep_alive: PeerKeepAlive::default(),
         })
     }
 }
 
+impl ProxyFloatHttpsPeer {
+    /// build a dedicated tls client config if the peer has its own protocol version policy
+    /// or has certificate verification disabled
+    fn build_tls_config(&mut self) -> anyhow::Result<()> {
+        let Some(builder) = &self.escaper_config.tls_config else {
+            return Err(anyhow!("no tls client config set for this escaper"));
+        };
+        let mut builder = builder.clone();
+        if let Some(protocol) = self.tls_min_protocol {
+            builder.set_min_protocol(protocol);
+        }
+        if let Some(protocol) = self.tls_max_protocol {
+            builder.set_max_protocol(protocol);
+        }
+        builder.set_insecure(self.tls_insecure);
+        builder
+            .check()
+            .context("invalid tls protocol version policy")?;
+        let tls_config = builder
+            .build()
+            .context("unable to build dedicated tls client config for this peer")?;
+        self.tls_config = Arc::new(tls_config);
+        Ok(())
+    }
+}
+
 impl NextProxyPeerInternal for ProxyFloatHttpsPeer {
+    fn set_id(&mut self, id: String) {
+        self.egress_info.id = Some(id.clone());
+        self.id = id;
+    }
+
+    fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+
     fn set_isp(&mut self, isp: String) {
         self.egress_info.isp = Some(isp);
     }
@@ -131,6 +219,56 @@ impl NextProxyPeerInternal for ProxyFloatHttpsPeer {
         shared_config.tcp_conn_speed_limit = speed_limit;
     }
 
+    fn set_port_filter(&mut self, filter: PeerPortFilter) {
+        self.port_filter = filter;
+    }
+
+    fn set_reset_policy(&mut self, policy: PeerResetPolicy) {
+        self.reset_policy = policy;
+    }
+
+    fn set_source_port_range(&mut self, port_range: PortRange) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.source_port_range = Some(port_range);
+    }
+
+    fn set_tcp_connect_timeout(&mut self, timeout: Duration) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.tcp_connect_timeout = Some(timeout);
+    }
+
+    fn set_alt_addr(&mut self, addr: SocketAddr) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.alt_addr = Some(addr);
+    }
+
+    fn set_ip_version(&mut self, ip_version: PeerIpVersion) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.ip_version = ip_version;
+    }
+
+    fn set_feed_generation(&mut self, generation: u64, refreshed: Instant) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.feed_generation = generation;
+        shared_config.refreshed_instant = Some(refreshed);
+    }
+
+    fn set_weight(&mut self, weight: f64) {
+        self.load.set_weight(weight);
+    }
+
+    fn set_max_connections(&mut self, max: usize) {
+        self.load.set_max_connections(max);
+    }
+
+    fn set_deprecated(&mut self, deprecated: bool) {
+        self.deprecation.set_deprecated(deprecated);
+    }
+
+    fn set_canary(&mut self, canary: bool) {
+        self.canary = canary;
+    }
+
     fn set_kv(&mut self, k: &str, v: &Value) -> anyhow::Result<()> {
         match k {
             "username" => {
@@ -148,10 +286,59 @@ impl NextProxyPeerInternal for ProxyFloatHttpsPeer {
                     .context(format!("invalid tls server name value for key {k}"))?;
                 Ok(())
             }
+            "tls_min_version" => {
+                let protocol = g3_json::value::as_openssl_protocol(v)
+                    .context(format!("invalid openssl protocol value for key {k}"))?;
+                self.tls_min_protocol = Some(protocol);
+                Ok(())
+            }
+            "tls_max_version" => {
+                let protocol = g3_json::value::as_openssl_protocol(v)
+                    .context(format!("invalid openssl protocol value for key {k}"))?;
+                self.tls_max_protocol = Some(protocol);
+                Ok(())
+            }
+            "tls_insecure" => {
+                self.tls_insecure = g3_json::value::as_bool(v)?;
+                Ok(())
+            }
+            "credential" => {
+                let name = g3_json::value::as_string(v)?;
+                let (username, password) = self
+                    .escaper_config
+                    .get_peer_credential(&name)
+                    .context(format!("failed to resolve credential for key {k}"))?;
+                self.username = username;
+                self.password = password;
+                Ok(())
+            }
+            "keep_alive_hint" => {
+                let enable = g3_json::value::as_bool(v)?;
+                self.keep_alive.set_hint(enable);
+                Ok(())
+            }
             "http_connect_rsp_header_max_size" => {
                 self.http_connect_rsp_hdr_max_size = g3_json::humanize::as_usize(v)?;
                 Ok(())
             }
+            "connect_target_rewrite" => {
+                let rewrite = ConnectTargetRewrite::parse_json(v)
+                    .context(format!("invalid connect target rewrite value for key {k}"))?;
+                let shared_config = Arc::make_mut(&mut self.shared_config);
+                shared_config.connect_rewrite = rewrite;
+                Ok(())
+            }
+            "resolver" => {
+                let name = g3_json::value::as_string(v)?;
+                let resolver = self
+                    .escaper_config
+                    .get_peer_resolver(&name)
+                    .context(format!("invalid value for key {k}"))?
+                    .clone();
+                let shared_config = Arc::make_mut(&mut self.shared_config);
+                shared_config.resolver = Some(resolver);
+                Ok(())
+            }
             "extra_append_headers" => {
                 if let Value::Object(map) = v {
                     let shared_config = Arc::make_mut(&mut self.shared_config);
@@ -166,30 +353,139 @@ impl NextProxyPeerInternal for ProxyFloatHttpsPeer {
                     Err(anyhow!("invalid map value for key {k}"))
                 }
             }
-            _ => Ok(()),
+            _ => {
+                self.tags.add(k, v);
+                Ok(())
+            }
         }
     }
 
     fn finalize(&mut self) -> anyhow::Result<()> {
         let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.expire_grace = self.escaper_config.expire_grace_duration;
         if !self.username.is_empty() {
             shared_config.set_user(&self.username, &self.password);
         }
+        if self.keep_alive.hint() {
+            let mut headers = shared_config.append_http_headers.clone();
+            headers.push("Proxy-Connection: Keep-Alive\r\n".to_string());
+            shared_config.keep_alive_http_headers = Some(headers);
+        }
         if self.tls_name.is_empty() {
             self.tls_name = Host::Ip(self.addr.ip());
         }
+        if self.tls_insecure {
+            warn!(
+                "escaper {}: tls certificate verification is DISABLED for peer {} ({}) at {}",
+                self.escaper_config.name, self.id, self.label, self.addr
+            );
+        }
+        if self.tls_min_protocol.is_some() || self.tls_max_protocol.is_some() || self.tls_insecure {
+            self.build_tls_config()?;
+        }
         Ok(())
     }
 
+    #[inline]
+    fn peer_type(&self) -> &'static str {
+        super::PEER_TYPE_HTTPS
+    }
+
+    #[inline]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    #[inline]
+    fn egress_info(&self) -> &EgressInfo {
+        &self.egress_info
+    }
+
     #[inline]
     fn expire_instant(&self) -> Option<Instant> {
         self.shared_config.expire_instant
     }
 
+    #[inline]
+    fn expire_grace(&self) -> Option<Duration> {
+        self.shared_config.expire_grace
+    }
+
+    #[inline]
+    fn feed_generation(&self) -> u64 {
+        self.shared_config.feed_generation
+    }
+
+    #[inline]
+    fn refreshed_instant(&self) -> Option<Instant> {
+        self.shared_config.refreshed_instant
+    }
+
     #[inline]
     fn escaper_stats(&self) -> &Arc<ProxyFloatEscaperStats> {
         &self.escaper_stats
     }
+
+    #[inline]
+    fn port_filter(&self) -> &PeerPortFilter {
+        &self.port_filter
+    }
+
+    #[inline]
+    fn circuit_breaker(&self) -> &PeerCircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    #[inline]
+    fn selection_cap(&self) -> &PeerSelectionCap {
+        &self.selection_cap
+    }
+
+    #[inline]
+    fn reset_policy(&self) -> &PeerResetPolicy {
+        &self.reset_policy
+    }
+
+    #[inline]
+    fn latency(&self) -> &PeerLatency {
+        &self.latency
+    }
+
+    fn success_rate(&self) -> &PeerSuccessRate {
+        &self.success_rate
+    }
+
+    fn load(&self) -> &PeerLoad {
+        &self.load
+    }
+
+    fn deprecation(&self) -> &PeerDeprecation {
+        &self.deprecation
+    }
+
+    fn is_canary(&self) -> bool {
+        self.canary
+    }
+
+    #[inline]
+    fn tags(&self) -> &PeerTags {
+        &self.tags
+    }
+
+    #[inline]
+    fn tags_mut(&mut self) -> &mut PeerTags {
+        &mut self.tags
+    }
+
+    #[inline]
+    fn keep_alive(&self) -> Option<&PeerKeepAlive> {
+        Some(&self.keep_alive)
+    }
 }
 
 #[async_trait]
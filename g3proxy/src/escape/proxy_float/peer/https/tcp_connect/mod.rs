@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 
 use tokio::net::{tcp, TcpStream};
@@ -22,7 +23,8 @@ use tokio::time::Instant;
 use g3_io_ext::{LimitedReader, LimitedWriter};
 use g3_types::net::ConnectError;
 
-use super::ProxyFloatHttpsPeer;
+use super::{NextProxyPeerInternal, ProxyFloatHttpsPeer};
+use crate::escape::proxy_float::peer::{connect_happy_eyeballs, PeerResetReader};
 use crate::log::escape::tcp_connect::EscapeLogForTcpConnect;
 use crate::module::tcp_connect::{TcpConnectError, TcpConnectTaskNotes};
 use crate::serve::ServerTaskNotes;
@@ -34,14 +36,31 @@ impl ProxyFloatHttpsPeer {
         bind: Option<IpAddr>,
     ) -> Result<TcpStream, TcpConnectError> {
         // use new socket every time, as we set bind_no_port
-        let sock = g3_socket::tcp::new_socket_to(
-            peer.ip(),
-            bind,
-            &self.escaper_config.tcp_keepalive,
-            &self.escaper_config.tcp_misc_opts,
-            true,
-        )
-        .map_err(TcpConnectError::SetupSocketFailed)?;
+        let sock = if let Some(port_range) = self.shared_config.source_port_range {
+            g3_socket::tcp::new_socket_in_range_to(
+                peer.ip(),
+                bind,
+                port_range,
+                &self.escaper_config.tcp_keepalive,
+                &self.escaper_config.tcp_misc_opts,
+                true,
+            )
+            .map_err(|e| {
+                if e.kind() == io::ErrorKind::AddrNotAvailable {
+                    self.escaper_stats.tcp.add_source_port_exhausted();
+                }
+                TcpConnectError::SetupSocketFailed(e)
+            })?
+        } else {
+            g3_socket::tcp::new_socket_to(
+                peer.ip(),
+                bind,
+                &self.escaper_config.tcp_keepalive,
+                &self.escaper_config.tcp_misc_opts,
+                true,
+            )
+            .map_err(TcpConnectError::SetupSocketFailed)?
+        };
         self.escaper_stats.tcp.add_connection_attempted();
         match sock.connect(peer).await {
             Ok(ups_stream) => {
@@ -57,24 +76,46 @@ impl ProxyFloatHttpsPeer {
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
     ) -> Result<TcpStream, TcpConnectError> {
-        let bind = match self.addr {
+        let bind_ip = |addr: SocketAddr| match addr {
             SocketAddr::V4(_) => self.escaper_config.bind_v4,
             SocketAddr::V6(_) => self.escaper_config.bind_v6,
         };
-        tcp_notes.bind = bind;
-        tcp_notes.next = Some(self.addr);
+        let (first, second) = self
+            .shared_config
+            .ip_version
+            .select_addrs(self.addr, self.shared_config.alt_addr);
+        tcp_notes.bind = bind_ip(first);
+        tcp_notes.next = Some(first);
         tcp_notes.expire = self.shared_config.expire_datetime;
+        tcp_notes.feed_generation = Some(self.shared_config.feed_generation);
         tcp_notes.egress = Some(self.egress_info.clone());
         tcp_notes.tries = 1;
         let instant_now = Instant::now();
+        let connect_timeout = self
+            .shared_config
+            .tcp_connect_timeout
+            .unwrap_or(self.escaper_config.tcp_connect_timeout);
         let ret = tokio::time::timeout(
-            self.escaper_config.tcp_connect_timeout,
-            self.try_connect_tcp(self.addr, tcp_notes.bind),
+            connect_timeout,
+            connect_happy_eyeballs(first, second, |addr| {
+                self.try_connect_tcp(addr, bind_ip(addr))
+            }),
         )
-        .await;
+        .await
+        .map(|(addr, r)| {
+            if addr != first {
+                tcp_notes.bind = bind_ip(addr);
+                tcp_notes.next = Some(addr);
+                tcp_notes.tries = 2;
+            }
+            r
+        });
         tcp_notes.duration = instant_now.elapsed();
         match ret {
             Ok(Ok(ups_stream)) => {
+                self.circuit_breaker.record_success(&self.id);
+                self.success_rate.record(true);
+                self.latency.record(tcp_notes.duration);
                 let local_addr = ups_stream
                     .local_addr()
                     .map_err(TcpConnectError::SetupSocketFailed)?;
@@ -82,6 +123,8 @@ impl ProxyFloatHttpsPeer {
                 Ok(ups_stream)
             }
             Ok(Err(e)) => {
+                self.circuit_breaker.record_failure(&self.id);
+                self.success_rate.record(false);
                 EscapeLogForTcpConnect {
                     tcp_notes,
                     task_id: &task_notes.id,
@@ -90,6 +133,8 @@ impl ProxyFloatHttpsPeer {
                 Err(e)
             }
             Err(_) => {
+                self.circuit_breaker.record_failure(&self.id);
+                self.success_rate.record(false);
                 let e = TcpConnectError::TimeoutByRule;
                 EscapeLogForTcpConnect {
                     tcp_notes,
@@ -107,26 +152,29 @@ impl ProxyFloatHttpsPeer {
         task_notes: &'a ServerTaskNotes,
     ) -> Result<
         (
-            LimitedReader<tcp::OwnedReadHalf>,
+            LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>,
             LimitedWriter<tcp::OwnedWriteHalf>,
         ),
         TcpConnectError,
     > {
+        let guard = self.acquire_connection()?;
         let stream = self.tcp_connect_to(tcp_notes, task_notes).await?;
         let (r, w) = stream.into_split();
+        let r = self.reset_policy.wrap(&self.id, &self.circuit_breaker, r);
+        let (r_stats, w_stats) = self.tcp_io_stats(guard);
 
         let limit_config = &self.shared_config.tcp_conn_speed_limit;
         let r = LimitedReader::new(
             r,
             limit_config.shift_millis,
             limit_config.max_south,
-            self.escaper_stats.clone() as _,
+            r_stats,
         );
         let w = LimitedWriter::new(
             w,
             limit_config.shift_millis,
             limit_config.max_north,
-            self.escaper_stats.clone() as _,
+            w_stats,
         );
 
         Ok((r, w))
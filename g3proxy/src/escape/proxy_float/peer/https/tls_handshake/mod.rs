@@ -50,6 +50,9 @@ impl ProxyFloatHttpsPeer {
 
         match tokio::time::timeout(self.tls_config.handshake_timeout, connector.connect()).await {
             Ok(Ok(stream)) => {
+                if self.tls_insecure {
+                    self.escaper_stats.add_peer_tls_insecure();
+                }
                 let (r, w) = tokio::io::split(stream);
                 Ok((r, w))
             }
@@ -0,0 +1,155 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use tokio::net::TcpStream;
+
+use g3_types::net::HappyEyeballsConfig;
+
+use crate::module::tcp_connect::TcpConnectError;
+
+/// The preferred IP address family when connecting to the peer
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum PeerIpVersion {
+    V4,
+    V6,
+    #[default]
+    Any,
+}
+
+impl FromStr for PeerIpVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "v4" => Ok(PeerIpVersion::V4),
+            "v6" => Ok(PeerIpVersion::V6),
+            "any" => Ok(PeerIpVersion::Any),
+            _ => Err(anyhow!("invalid ip version {s}")),
+        }
+    }
+}
+
+impl PeerIpVersion {
+    /// check if there is an address of the preferred family
+    pub(crate) fn is_available(self, addr: SocketAddr, alt_addr: Option<SocketAddr>) -> bool {
+        let matched = |a: SocketAddr| match self {
+            PeerIpVersion::V4 => a.is_ipv4(),
+            PeerIpVersion::V6 => a.is_ipv6(),
+            PeerIpVersion::Any => true,
+        };
+        matched(addr) || alt_addr.map(matched).unwrap_or(false)
+    }
+
+    /// get the address to connect to, and the second one to race with if happy eyeballs should be used.
+    /// Fallback to the address of the other family if the preferred one is not available
+    pub(crate) fn select_addrs(
+        self,
+        addr: SocketAddr,
+        alt_addr: Option<SocketAddr>,
+    ) -> (SocketAddr, Option<SocketAddr>) {
+        let Some(alt_addr) = alt_addr else {
+            return (addr, None);
+        };
+        let (v4, v6) = if addr.is_ipv4() {
+            (addr, alt_addr)
+        } else {
+            (alt_addr, addr)
+        };
+        match self {
+            PeerIpVersion::V4 => (v4, None),
+            PeerIpVersion::V6 => (v6, None),
+            PeerIpVersion::Any => (v6, Some(v4)),
+        }
+    }
+}
+
+/// connect to the first address, and start connecting to the second one if no success
+/// after the happy eyeballs connection attempt delay. The first successful one will be used.
+pub(super) async fn connect_happy_eyeballs<F, Fut>(
+    first: SocketAddr,
+    second: Option<SocketAddr>,
+    connect: F,
+) -> (SocketAddr, Result<TcpStream, TcpConnectError>)
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<TcpStream, TcpConnectError>>,
+{
+    let first_fut = connect(first);
+    let Some(second) = second else {
+        return (first, first_fut.await);
+    };
+    tokio::pin!(first_fut);
+
+    let delay = HappyEyeballsConfig::default().connection_attempt_delay();
+    let first_err = tokio::select! {
+        r = &mut first_fut => match r {
+            Ok(stream) => return (first, Ok(stream)),
+            Err(e) => Some(e),
+        },
+        _ = tokio::time::sleep(delay) => None,
+    };
+
+    let second_fut = connect(second);
+    tokio::pin!(second_fut);
+    match first_err {
+        Some(_) => (second, second_fut.await),
+        None => {
+            tokio::select! {
+                r = &mut first_fut => match r {
+                    Ok(stream) => (first, Ok(stream)),
+                    Err(_) => (second, second_fut.await),
+                },
+                r = &mut second_fut => match r {
+                    Ok(stream) => (second, Ok(stream)),
+                    Err(_) => (first, first_fut.await),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_addrs() {
+        let v4 = SocketAddr::from_str("192.0.2.1:1080").unwrap();
+        let v6 = SocketAddr::from_str("[2001:db8::1]:1080").unwrap();
+
+        assert_eq!(PeerIpVersion::V4.select_addrs(v4, Some(v6)), (v4, None));
+        assert_eq!(PeerIpVersion::V6.select_addrs(v4, Some(v6)), (v6, None));
+        assert_eq!(PeerIpVersion::V4.select_addrs(v6, Some(v4)), (v4, None));
+        assert_eq!(
+            PeerIpVersion::Any.select_addrs(v4, Some(v6)),
+            (v6, Some(v4))
+        );
+        assert_eq!(PeerIpVersion::V6.select_addrs(v4, None), (v4, None));
+
+        assert!(PeerIpVersion::V6.is_available(v4, Some(v6)));
+        assert!(!PeerIpVersion::V6.is_available(v4, None));
+        assert!(PeerIpVersion::Any.is_available(v4, None));
+
+        assert_eq!(PeerIpVersion::from_str("V6").unwrap(), PeerIpVersion::V6);
+        assert!(PeerIpVersion::from_str("ipv6").is_err());
+        assert!(PeerIpVersion::from_str("v5").is_err());
+    }
+}
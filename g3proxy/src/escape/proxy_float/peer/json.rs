@@ -20,6 +20,7 @@ use std::sync::Arc;
 
 use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
+use log::warn;
 use serde_json::Value;
 use slog::Logger;
 use tokio::time::Instant;
@@ -27,13 +28,21 @@ use tokio::time::Instant;
 use g3_types::net::OpensslClientConfig;
 
 use super::{
-    ArcNextProxyPeer, CONFIG_KEY_PEER_ADDR, CONFIG_KEY_PEER_AREA, CONFIG_KEY_PEER_EIP,
-    CONFIG_KEY_PEER_EXPIRE, CONFIG_KEY_PEER_ID, CONFIG_KEY_PEER_ISP,
+    ArcNextProxyPeer, PeerIpVersion, PeerPortFilter, PeerResetPolicy, CONFIG_KEY_PEER_ADDR,
+    CONFIG_KEY_PEER_ALLOWED_PORTS, CONFIG_KEY_PEER_ALT_ADDR, CONFIG_KEY_PEER_AREA,
+    CONFIG_KEY_PEER_CANARY, CONFIG_KEY_PEER_CONNECT_TIMEOUT, CONFIG_KEY_PEER_DENIED_PORTS,
+    CONFIG_KEY_PEER_DEPRECATED, CONFIG_KEY_PEER_EIP, CONFIG_KEY_PEER_EXPIRE, CONFIG_KEY_PEER_ID,
+    CONFIG_KEY_PEER_IP_VERSION, CONFIG_KEY_PEER_ISP, CONFIG_KEY_PEER_LABEL,
+    CONFIG_KEY_PEER_MAX_CONNECTIONS, CONFIG_KEY_PEER_RESET_AS_FAILURE,
+    CONFIG_KEY_PEER_RETRY_ON_RESET, CONFIG_KEY_PEER_SOURCE_PORT_RANGE,
     CONFIG_KEY_PEER_TCP_SOCK_SPEED_LIMIT, CONFIG_KEY_PEER_TYPE,
+    CONFIG_KEY_PEER_UDP_SOCK_SPEED_LIMIT, CONFIG_KEY_PEER_WEIGHT, PEER_CONNECT_TIMEOUT_MAX,
+    PEER_CONNECT_TIMEOUT_MIN, PEER_TYPE_HTTP, PEER_TYPE_HTTPS, PEER_TYPE_SOCKS5,
 };
 use crate::config::escaper::proxy_float::ProxyFloatEscaperConfig;
 use crate::escape::proxy_float::ProxyFloatEscaperStats;
 
+#[allow(clippy::too_many_arguments)]
 pub(super) fn do_parse_peer(
     value: &Value,
     escaper_config: &Arc<ProxyFloatEscaperConfig>,
@@ -42,6 +51,7 @@ pub(super) fn do_parse_peer(
     tls_config: Option<&Arc<OpensslClientConfig>>,
     instant_now: Instant,
     datetime_now: DateTime<Utc>,
+    generation: u64,
 ) -> anyhow::Result<Option<(String, ArcNextProxyPeer)>> {
     if let Value::Object(map) = value {
         let peer_type = g3_json::get_required_str(map, CONFIG_KEY_PEER_TYPE)?;
@@ -49,13 +59,13 @@ pub(super) fn do_parse_peer(
         let addr = SocketAddr::from_str(addr_str)
             .map_err(|e| anyhow!("invalid peer addr {addr_str}: {e}"))?;
         let mut peer = match peer_type {
-            "http" => super::http::ProxyFloatHttpPeer::new_obj(
+            PEER_TYPE_HTTP => super::http::ProxyFloatHttpPeer::new_obj(
                 Arc::clone(escaper_config),
                 Arc::clone(escaper_stats),
                 escape_logger.clone(),
                 addr,
             ),
-            "https" => {
+            PEER_TYPE_HTTPS => {
                 if let Some(tls_config) = tls_config {
                     super::https::ProxyFloatHttpsPeer::new_obj(
                         Arc::clone(escaper_config),
@@ -68,7 +78,7 @@ pub(super) fn do_parse_peer(
                     return Ok(None);
                 }
             }
-            "socks5" => super::socks5::ProxyFloatSocks5Peer::new_obj(
+            PEER_TYPE_SOCKS5 => super::socks5::ProxyFloatSocks5Peer::new_obj(
                 Arc::clone(escaper_config),
                 Arc::clone(escaper_stats),
                 escape_logger.clone(),
@@ -77,6 +87,11 @@ pub(super) fn do_parse_peer(
             _ => return Err(anyhow!("unsupported peer type {peer_type}")),
         };
         let mut peer_id = String::new();
+        let mut label = String::new();
+        let mut port_filter = PeerPortFilter::default();
+        let mut reset_policy = PeerResetPolicy::default();
+        let mut alt_addr: Option<SocketAddr> = None;
+        let mut ip_version = PeerIpVersion::default();
         let peer_mut = Arc::get_mut(&mut peer).unwrap();
         for (k, v) in map {
             match g3_json::key::normalize(k).as_str() {
@@ -84,6 +99,9 @@ pub(super) fn do_parse_peer(
                 CONFIG_KEY_PEER_ID => {
                     peer_id = g3_json::value::as_string(v)?;
                 }
+                CONFIG_KEY_PEER_LABEL => {
+                    label = g3_json::value::as_string(v)?;
+                }
                 CONFIG_KEY_PEER_ISP => {
                     if let Ok(isp) = g3_json::value::as_string(v) {
                         peer_mut.set_isp(isp);
@@ -125,11 +143,115 @@ pub(super) fn do_parse_peer(
                     let limit = g3_json::value::as_tcp_sock_speed_limit(v)?;
                     peer_mut.set_tcp_sock_speed_limit(limit);
                 }
+                CONFIG_KEY_PEER_UDP_SOCK_SPEED_LIMIT => {
+                    let limit = g3_json::value::as_udp_sock_speed_limit(v)?;
+                    peer_mut.set_udp_sock_speed_limit(limit);
+                }
+                CONFIG_KEY_PEER_ALLOWED_PORTS => {
+                    let ports = g3_json::value::as_ports(v)?;
+                    port_filter.set_allowed(ports);
+                }
+                CONFIG_KEY_PEER_DENIED_PORTS => {
+                    let ports = g3_json::value::as_ports(v)?;
+                    port_filter.set_denied(ports);
+                }
+                CONFIG_KEY_PEER_SOURCE_PORT_RANGE => {
+                    let port_range = g3_json::value::as_port_range(v)?;
+                    peer_mut.set_source_port_range(port_range);
+                }
+                CONFIG_KEY_PEER_WEIGHT => {
+                    let weight = g3_json::value::as_f64(v)?;
+                    if !(weight >= 0.0 && weight.is_finite()) {
+                        return Err(anyhow!("invalid peer weight {weight}"));
+                    }
+                    peer_mut.set_weight(weight);
+                }
+                CONFIG_KEY_PEER_MAX_CONNECTIONS => {
+                    let max = g3_json::value::as_usize(v)?;
+                    if max == 0 {
+                        return Err(anyhow!("max connections should not be 0"));
+                    }
+                    peer_mut.set_max_connections(max);
+                }
+                CONFIG_KEY_PEER_CONNECT_TIMEOUT => {
+                    let timeout = g3_json::humanize::as_duration(v)
+                        .context(format!("invalid humanize duration value for key {k}"))?;
+                    if !(PEER_CONNECT_TIMEOUT_MIN..=PEER_CONNECT_TIMEOUT_MAX).contains(&timeout) {
+                        return Err(anyhow!(
+                            "peer connect timeout {timeout:?} is out of range [{:?}, {:?}]",
+                            PEER_CONNECT_TIMEOUT_MIN,
+                            PEER_CONNECT_TIMEOUT_MAX
+                        ));
+                    }
+                    peer_mut.set_tcp_connect_timeout(timeout);
+                }
+                CONFIG_KEY_PEER_ALT_ADDR => {
+                    let alt_str = g3_json::value::as_string(v)?;
+                    let alt = SocketAddr::from_str(&alt_str)
+                        .map_err(|e| anyhow!("invalid peer alt addr {alt_str}: {e}"))?;
+                    if alt.is_ipv4() == addr.is_ipv4() {
+                        return Err(anyhow!(
+                            "peer alt addr {alt} should be of a different ip family with addr {addr}"
+                        ));
+                    }
+                    alt_addr = Some(alt);
+                    peer_mut.set_alt_addr(alt);
+                }
+                CONFIG_KEY_PEER_IP_VERSION => {
+                    let s = g3_json::value::as_string(v)?;
+                    ip_version = PeerIpVersion::from_str(&s)
+                        .context(format!("invalid ip version value for key {k}"))?;
+                    peer_mut.set_ip_version(ip_version);
+                }
+                CONFIG_KEY_PEER_RESET_AS_FAILURE => {
+                    let enable = g3_json::value::as_bool(v)?;
+                    reset_policy.set_as_failure(enable);
+                }
+                CONFIG_KEY_PEER_RETRY_ON_RESET => {
+                    let enable = g3_json::value::as_bool(v)?;
+                    reset_policy.set_retry(enable);
+                }
+                CONFIG_KEY_PEER_DEPRECATED => {
+                    let deprecated = g3_json::value::as_bool(v)?;
+                    peer_mut.set_deprecated(deprecated);
+                }
+                CONFIG_KEY_PEER_CANARY => {
+                    let canary = g3_json::value::as_bool(v)?;
+                    peer_mut.set_canary(canary);
+                }
                 _ => peer_mut
                     .set_kv(k, v)
                     .context(format!("failed to parse key {k}"))?,
             }
         }
+        if peer_id.is_empty() {
+            // use a synthetic id for unnamed peers, so they can be identified in logs
+            peer_mut.set_id(format!("{peer_type}://{addr}"));
+        } else {
+            peer_mut.set_id(peer_id.clone());
+        }
+        if label.is_empty() {
+            label = peer_mut.id().to_string();
+        }
+        peer_mut.set_label(label);
+        peer_mut.set_port_filter(port_filter);
+        peer_mut.set_reset_policy(reset_policy);
+        peer_mut.set_feed_generation(generation, instant_now);
+        if !ip_version.is_available(addr, alt_addr) {
+            warn!(
+                "escaper {}: no {ip_version:?} address for peer {} ({}), will fallback to {addr}",
+                escaper_config.name,
+                peer_mut.id(),
+                peer_mut.label()
+            );
+        }
+        if !escaper_config.peer_metrics_tag_keys.is_empty() {
+            peer_mut
+                .tags_mut()
+                .promote(&escaper_config.peer_metrics_tag_keys, escaper_stats);
+        }
+        let area = peer_mut.area().cloned();
+        peer_mut.tags_mut().bind_area(area.as_ref(), escaper_stats);
         peer_mut.finalize()?;
         Ok(Some((peer_id, peer)))
     } else {
@@ -0,0 +1,127 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+/// The keep-alive hint sent to the peer, and the reuse of http forward connections to it.
+#[derive(Default)]
+pub(crate) struct PeerKeepAlive {
+    hint: bool,
+    /// shared with the alive connections, and with the new peer after reload
+    counters: ArcSwap<PeerKeepAliveCounters>,
+}
+
+#[derive(Default)]
+struct PeerKeepAliveCounters {
+    fresh: AtomicU64,
+    reused: AtomicU64,
+    closed: AtomicU64,
+}
+
+impl PeerKeepAlive {
+    pub(super) fn set_hint(&mut self, enable: bool) {
+        self.hint = enable;
+    }
+
+    pub(super) fn hint(&self) -> bool {
+        self.hint
+    }
+
+    pub(super) fn fresh(&self) -> u64 {
+        self.counters.load().fresh.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn reused(&self) -> u64 {
+        self.counters.load().reused.load(Ordering::Relaxed)
+    }
+
+    /// the count of responses on which the peer closed the connection requested to be kept alive
+    pub(super) fn closed(&self) -> u64 {
+        self.counters.load().closed.load(Ordering::Relaxed)
+    }
+
+    /// count a new connection, and get the tracker for the requests that will be sent on it
+    pub(super) fn track(&self) -> PeerKeepAliveTracker {
+        let counters = self.counters.load_full();
+        counters.fresh.fetch_add(1, Ordering::Relaxed);
+        PeerKeepAliveTracker {
+            counters,
+            served: false,
+        }
+    }
+
+    pub(super) fn inherit(&self, old: &PeerKeepAlive) {
+        self.counters.store(old.counters.load_full());
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct PeerKeepAliveTracker {
+    counters: Arc<PeerKeepAliveCounters>,
+    served: bool,
+}
+
+impl PeerKeepAliveTracker {
+    /// should be called before sending each request on the connection
+    pub(super) fn on_new_request(&mut self) {
+        if self.served {
+            self.counters.reused.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.served = true;
+        }
+    }
+
+    pub(super) fn on_response(&self, req_keep_alive: bool, rsp_keep_alive: bool) {
+        if req_keep_alive && !rsp_keep_alive {
+            self.counters.closed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let keep_alive = PeerKeepAlive::default();
+
+        let mut tracker = keep_alive.track();
+        tracker.on_new_request();
+        tracker.on_response(true, true);
+        tracker.on_new_request();
+        tracker.on_response(true, false);
+        assert_eq!(keep_alive.fresh(), 1);
+        assert_eq!(keep_alive.reused(), 1);
+        assert_eq!(keep_alive.closed(), 1);
+
+        let mut tracker = keep_alive.track();
+        tracker.on_new_request();
+        tracker.on_response(false, false);
+        assert_eq!(keep_alive.fresh(), 2);
+        assert_eq!(keep_alive.reused(), 1);
+        assert_eq!(keep_alive.closed(), 1);
+
+        let new = PeerKeepAlive::default();
+        new.inherit(&keep_alive);
+        tracker.on_new_request();
+        assert_eq!(new.fresh(), 2);
+        assert_eq!(new.reused(), 2);
+    }
+}
@@ -0,0 +1,70 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The smoothed tcp connect time to the peer, which is used as an estimated RTT.
+#[derive(Default)]
+pub(crate) struct PeerLatency {
+    /// in microseconds, 0 means not measured yet
+    rtt: AtomicU64,
+}
+
+impl PeerLatency {
+    pub(super) fn record(&self, rtt: Duration) {
+        let sample = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX).max(1);
+        let _ = self
+            .rtt
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+                if old == 0 {
+                    Some(sample)
+                } else {
+                    // same as the smoothed RTT in TCP, 7/8 of the old value and 1/8 of the new one
+                    Some((old - old / 8).saturating_add(sample / 8).max(1))
+                }
+            });
+    }
+
+    pub(super) fn rtt(&self) -> Option<Duration> {
+        match self.rtt.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(Duration::from_micros(v)),
+        }
+    }
+
+    pub(super) fn inherit(&self, old: &PeerLatency) {
+        self.rtt
+            .store(old.rtt.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothed() {
+        let latency = PeerLatency::default();
+        assert!(latency.rtt().is_none());
+
+        latency.record(Duration::from_millis(80));
+        assert_eq!(latency.rtt(), Some(Duration::from_millis(80)));
+
+        latency.record(Duration::from_millis(160));
+        assert_eq!(latency.rtt(), Some(Duration::from_millis(90)));
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cmp::Ordering;
+
+use tokio::time::Instant;
+
+use crate::config::escaper::proxy_float::{PeerCountLimitConfig, PeerKeepPriority};
+
+/// compare the remaining alive time, items without an expire time are alive forever
+fn cmp_alive_time(a: Option<Instant>, b: Option<Instant>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => a.cmp(&b),
+    }
+}
+
+/// Keep the most useful `max_count` items by the configured priority and drop the rest,
+/// the count of dropped items will be returned.
+///
+/// The original order is kept for items that rank the same.
+pub(super) fn retain_most_useful<T, E, W>(
+    items: &mut Vec<T>,
+    limit: &PeerCountLimitConfig,
+    expire: E,
+    weight: W,
+) -> usize
+where
+    E: Fn(&T) -> Option<Instant>,
+    W: Fn(&T) -> f64,
+{
+    if items.len() <= limit.max_count {
+        return 0;
+    }
+    match limit.keep {
+        PeerKeepPriority::AliveTime => items.sort_by(|a, b| cmp_alive_time(expire(b), expire(a))),
+        PeerKeepPriority::Weight => items.sort_by(|a, b| weight(b).total_cmp(&weight(a))),
+    }
+    let evicted = items.len() - limit.max_count;
+    items.truncate(limit.max_count);
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limit(max_count: usize, keep: PeerKeepPriority) -> PeerCountLimitConfig {
+        PeerCountLimitConfig { max_count, keep }
+    }
+
+    #[test]
+    fn under_limit() {
+        let mut items = vec![(1, None, 1.0), (2, None, 2.0)];
+        let evicted = retain_most_useful(
+            &mut items,
+            &limit(2, PeerKeepPriority::Weight),
+            |v| v.1,
+            |v| v.2,
+        );
+        assert_eq!(evicted, 0);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].0, 1);
+    }
+
+    #[test]
+    fn keep_alive_time() {
+        let now = Instant::now();
+        let mut items = vec![
+            (1, Some(now + Duration::from_secs(10)), 1.0),
+            (2, None, 1.0),
+            (3, Some(now + Duration::from_secs(60)), 1.0),
+            (4, Some(now + Duration::from_secs(30)), 1.0),
+        ];
+        let evicted = retain_most_useful(
+            &mut items,
+            &limit(2, PeerKeepPriority::AliveTime),
+            |v| v.1,
+            |v| v.2,
+        );
+        assert_eq!(evicted, 2);
+        let ids = items.iter().map(|v| v.0).collect::<Vec<_>>();
+        assert_eq!(ids, [2, 3]);
+    }
+
+    #[test]
+    fn keep_weight() {
+        let mut items = vec![
+            (1, None, 1.0),
+            (2, None, 3.0),
+            (3, None, 1.0),
+            (4, None, 2.0),
+        ];
+        let evicted = retain_most_useful(
+            &mut items,
+            &limit(3, PeerKeepPriority::Weight),
+            |v| v.1,
+            |v| v.2,
+        );
+        assert_eq!(evicted, 1);
+        let ids = items.iter().map(|v| v.0).collect::<Vec<_>>();
+        assert_eq!(ids, [2, 4, 1]);
+    }
+}
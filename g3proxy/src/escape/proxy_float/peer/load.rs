@@ -0,0 +1,169 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use g3_io_ext::{ArcLimitedReaderStats, LimitedReaderStats};
+
+/// The configured weight and the count of alive connections of the peer, with an optional limit.
+pub(crate) struct PeerLoad {
+    weight: f64,
+    max_connections: Option<usize>,
+    /// shared with the alive connections, and with the new peer after reload
+    in_flight: ArcSwap<AtomicUsize>,
+}
+
+impl Default for PeerLoad {
+    fn default() -> Self {
+        PeerLoad {
+            weight: 1.0,
+            max_connections: None,
+            in_flight: ArcSwap::from_pointee(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl PeerLoad {
+    pub(super) fn set_weight(&mut self, weight: f64) {
+        self.weight = weight;
+    }
+
+    pub(super) fn set_max_connections(&mut self, max: usize) {
+        self.max_connections = Some(max);
+    }
+
+    pub(super) fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub(super) fn in_flight(&self) -> usize {
+        self.in_flight.load().load(Ordering::Relaxed)
+    }
+
+    /// the load relative to the capacity, the one being selected is counted in
+    pub(super) fn ratio(&self) -> f64 {
+        (self.in_flight() + 1) as f64 / self.weight
+    }
+
+    /// check if the max connections limit is reached
+    pub(crate) fn is_saturated(&self) -> bool {
+        self.max_connections
+            .map(|max| self.in_flight() >= max)
+            .unwrap_or(false)
+    }
+
+    /// count a new connection, which will be released when the returned guard is dropped.
+    /// None will be returned if the max connections limit is reached.
+    pub(super) fn try_acquire(&self) -> Option<PeerConnectionGuard> {
+        let in_flight = self.in_flight.load_full();
+        if let Some(max) = self.max_connections {
+            in_flight
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    (n < max).then_some(n + 1)
+                })
+                .ok()?;
+        } else {
+            in_flight.fetch_add(1, Ordering::AcqRel);
+        }
+        Some(PeerConnectionGuard { in_flight })
+    }
+
+    /// wrap the reader stats of a new connection, so the connection will be counted as in-flight
+    /// until the reader is closed
+    pub(super) fn track(
+        &self,
+        stats: ArcLimitedReaderStats,
+        guard: PeerConnectionGuard,
+    ) -> ArcLimitedReaderStats {
+        Arc::new(InFlightReaderStats {
+            inner: stats,
+            _guard: guard,
+        })
+    }
+
+    pub(super) fn inherit(&self, old: &PeerLoad) {
+        self.in_flight.store(old.in_flight.load_full());
+    }
+}
+
+pub(crate) struct PeerConnectionGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for PeerConnectionGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+struct InFlightReaderStats {
+    inner: ArcLimitedReaderStats,
+    _guard: PeerConnectionGuard,
+}
+
+impl LimitedReaderStats for InFlightReaderStats {
+    fn add_read_bytes(&self, size: usize) {
+        self.inner.add_read_bytes(size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_io_ext::NilLimitedReaderStats;
+
+    #[test]
+    fn in_flight() {
+        let mut load = PeerLoad::default();
+        load.set_weight(2.0);
+        assert_eq!(load.ratio(), 0.5);
+
+        let guard = load.try_acquire().unwrap();
+        let stats = load.track(Arc::new(NilLimitedReaderStats::default()), guard);
+        assert_eq!(load.in_flight(), 1);
+        assert_eq!(load.ratio(), 1.0);
+
+        let new_load = PeerLoad::default();
+        new_load.inherit(&load);
+        assert_eq!(new_load.in_flight(), 1);
+
+        drop(stats);
+        assert_eq!(load.in_flight(), 0);
+        assert_eq!(new_load.in_flight(), 0);
+    }
+
+    #[test]
+    fn max_connections() {
+        let mut load = PeerLoad::default();
+        load.set_max_connections(2);
+        assert!(!load.is_saturated());
+
+        let g1 = load.try_acquire().unwrap();
+        let g2 = load.try_acquire().unwrap();
+        assert!(load.is_saturated());
+        assert!(load.try_acquire().is_none());
+        assert_eq!(load.in_flight(), 2);
+
+        drop(g1);
+        assert!(!load.is_saturated());
+        let _g3 = load.try_acquire().unwrap();
+        drop(g2);
+        assert_eq!(load.in_flight(), 1);
+    }
+}
@@ -14,23 +14,34 @@
  * limitations under the License.
  */
 
-use std::net::IpAddr;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 
 use ahash::AHashMap;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use rand::seq::IteratorRandom;
+use log::{debug, info, warn};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
 use serde_json::Value;
 use slog::Logger;
 use tokio::time::Instant;
 
 use g3_daemon::stat::remote::ArcTcpConnectionTaskRemoteStats;
-use g3_types::net::{EgressArea, Host, OpensslClientConfig, TcpSockSpeedLimitConfig};
+use g3_io_ext::{ArcLimitedReaderStats, ArcLimitedWriterStats};
+use g3_socks::SocksCommand;
+use g3_types::net::{
+    EgressArea, EgressInfo, Host, OpensslClientConfig, PortRange, TcpSockSpeedLimitConfig,
+    UdpSockSpeedLimitConfig,
+};
 
 use super::{ProxyFloatEscaperConfig, ProxyFloatEscaperStats};
 use crate::auth::UserUpstreamTrafficStats;
+use crate::config::escaper::proxy_float::PeerCountLimitConfig;
 use crate::escape::EscaperStats;
 use crate::module::http_forward::{ArcHttpForwardTaskRemoteStats, BoxHttpForwardConnection};
 use crate::module::tcp_connect::{TcpConnectError, TcpConnectResult, TcpConnectTaskNotes};
@@ -44,38 +55,242 @@ use crate::serve::ServerTaskNotes;
 
 mod json;
 
+mod connect_rewrite;
+use connect_rewrite::ConnectTargetRewrite;
+
+mod ip_version;
+use ip_version::{connect_happy_eyeballs, PeerIpVersion};
+
+mod port_filter;
+use port_filter::PeerPortFilter;
+
+mod circuit_breaker;
+use circuit_breaker::PeerCircuitBreaker;
+
+mod latency;
+use latency::PeerLatency;
+
+mod success_rate;
+use success_rate::PeerSuccessRate;
+
+mod selection_cap;
+use selection_cap::PeerSelectionCap;
+
+mod tags;
+use tags::PeerTags;
+
+mod load;
+use load::{PeerConnectionGuard, PeerLoad};
+
+mod deprecation;
+use deprecation::PeerDeprecation;
+
+mod reset;
+use reset::{PeerResetPolicy, PeerResetReader};
+
+mod udp_assoc;
+use udp_assoc::{PeerUdpAssociationGuard, PeerUdpAssociations};
+
+mod keep_alive;
+use keep_alive::{PeerKeepAlive, PeerKeepAliveTracker};
+
+mod socks5_capability;
+use socks5_capability::PeerSocks5Capability;
+
+mod recent;
+pub(super) use recent::RecentPeers;
+
+mod wrr;
+use wrr::PeerWrrState;
+
+mod rr;
+use rr::PeerRrState;
+
+mod sticky;
+use sticky::PeerHashRing;
+
+mod limit;
+
+mod simulate;
+pub(super) use simulate::TaskAttrs;
+
+mod query;
+pub(super) use query::PeerSelectContext;
+
 mod http;
 mod https;
 mod socks5;
 
+const PEER_TYPE_HTTP: &str = "http";
+const PEER_TYPE_HTTPS: &str = "https";
+const PEER_TYPE_SOCKS5: &str = "socks5";
+const ALL_PEER_TYPES: [&str; 3] = [PEER_TYPE_HTTP, PEER_TYPE_HTTPS, PEER_TYPE_SOCKS5];
+
 const CONFIG_KEY_PEER_TYPE: &str = "type";
 const CONFIG_KEY_PEER_ID: &str = "id";
+const CONFIG_KEY_PEER_LABEL: &str = "label";
 const CONFIG_KEY_PEER_ADDR: &str = "addr";
 const CONFIG_KEY_PEER_EXPIRE: &str = "expire";
 const CONFIG_KEY_PEER_ISP: &str = "isp";
 const CONFIG_KEY_PEER_EIP: &str = "eip";
 const CONFIG_KEY_PEER_AREA: &str = "area";
 const CONFIG_KEY_PEER_TCP_SOCK_SPEED_LIMIT: &str = "tcp_sock_speed_limit";
+const CONFIG_KEY_PEER_UDP_SOCK_SPEED_LIMIT: &str = "udp_sock_speed_limit";
+const CONFIG_KEY_PEER_ALLOWED_PORTS: &str = "allowed_ports";
+const CONFIG_KEY_PEER_DENIED_PORTS: &str = "denied_ports";
+const CONFIG_KEY_PEER_SOURCE_PORT_RANGE: &str = "source_port_range";
+const CONFIG_KEY_PEER_WEIGHT: &str = "weight";
+const CONFIG_KEY_PEER_MAX_CONNECTIONS: &str = "max_connections";
+const CONFIG_KEY_PEER_CONNECT_TIMEOUT: &str = "connect_timeout";
+const CONFIG_KEY_PEER_ALT_ADDR: &str = "alt_addr";
+const CONFIG_KEY_PEER_IP_VERSION: &str = "ip_version";
+const CONFIG_KEY_PEER_RESET_AS_FAILURE: &str = "reset_as_failure";
+const CONFIG_KEY_PEER_RETRY_ON_RESET: &str = "retry_on_reset";
+const CONFIG_KEY_PEER_DEPRECATED: &str = "deprecated";
+const CONFIG_KEY_PEER_CANARY: &str = "canary";
+
+const PEER_CONNECT_TIMEOUT_MIN: Duration = Duration::from_millis(100);
+const PEER_CONNECT_TIMEOUT_MAX: Duration = Duration::from_secs(300);
 
 pub(super) trait NextProxyPeerInternal {
+    fn set_id(&mut self, id: String);
+    fn set_label(&mut self, label: String);
     fn set_isp(&mut self, isp: String);
     fn set_eip(&mut self, eip: IpAddr);
     fn set_area(&mut self, area: EgressArea);
     fn set_expire(&mut self, expire_datetime: DateTime<Utc>, expire_instant: Instant);
     fn set_tcp_sock_speed_limit(&mut self, speed_limit: TcpSockSpeedLimitConfig);
+    fn set_port_filter(&mut self, filter: PeerPortFilter);
+    fn set_reset_policy(&mut self, policy: PeerResetPolicy);
+    fn set_source_port_range(&mut self, port_range: PortRange);
+    fn set_weight(&mut self, weight: f64);
+    fn set_max_connections(&mut self, max: usize);
+    fn set_deprecated(&mut self, deprecated: bool);
+    fn set_canary(&mut self, canary: bool);
+    fn set_tcp_connect_timeout(&mut self, timeout: Duration);
+    fn set_alt_addr(&mut self, addr: SocketAddr);
+    fn set_ip_version(&mut self, ip_version: PeerIpVersion);
+    fn set_feed_generation(&mut self, generation: u64, refreshed: Instant);
     fn set_kv(&mut self, k: &str, v: &Value) -> anyhow::Result<()>;
     fn finalize(&mut self) -> anyhow::Result<()>;
 
+    fn peer_type(&self) -> &'static str;
+    fn id(&self) -> &str;
+    fn label(&self) -> &str;
+    fn egress_info(&self) -> &EgressInfo;
     fn expire_instant(&self) -> Option<Instant>;
+    /// the grace window after expiry, in which the established connections can still be reused
+    fn expire_grace(&self) -> Option<Duration>;
+    fn feed_generation(&self) -> u64;
+    fn refreshed_instant(&self) -> Option<Instant>;
     fn escaper_stats(&self) -> &Arc<ProxyFloatEscaperStats>;
+    fn port_filter(&self) -> &PeerPortFilter;
+    fn circuit_breaker(&self) -> &PeerCircuitBreaker;
+    fn selection_cap(&self) -> &PeerSelectionCap;
+    fn reset_policy(&self) -> &PeerResetPolicy;
+    fn latency(&self) -> &PeerLatency;
+    fn success_rate(&self) -> &PeerSuccessRate;
+    fn load(&self) -> &PeerLoad;
+    fn deprecation(&self) -> &PeerDeprecation;
+    /// canary peers only receive the mirrored connections, and won't be selected for tasks
+    fn is_canary(&self) -> bool;
+    fn tags(&self) -> &PeerTags;
+    fn tags_mut(&mut self) -> &mut PeerTags;
 
-    fn is_expired(&self) -> bool {
+    /// only used by the peer types that support udp
+    fn set_udp_sock_speed_limit(&mut self, _speed_limit: UdpSockSpeedLimitConfig) {}
+
+    fn udp_associations(&self) -> Option<&PeerUdpAssociations> {
+        None
+    }
+
+    fn keep_alive(&self) -> Option<&PeerKeepAlive> {
+        None
+    }
+
+    fn socks5_capability(&self) -> Option<&PeerSocks5Capability> {
+        None
+    }
+
+    /// the declared isp of the egress ip, if set in the peer record
+    fn isp(&self) -> Option<&str> {
+        self.egress_info().isp.as_deref()
+    }
+
+    /// the declared egress ip, if set in the peer record
+    fn eip(&self) -> Option<IpAddr> {
+        self.egress_info().ip
+    }
+
+    /// the declared area of the egress ip, if set in the peer record
+    fn area(&self) -> Option<&EgressArea> {
+        self.egress_info().area.as_ref()
+    }
+
+    /// check if the peer may support the socks5 `command`, always true for non socks5 peers
+    fn is_capable(&self, command: SocksCommand) -> bool {
+        self.socks5_capability()
+            .map(|c| c.is_capable(command))
+            .unwrap_or(true)
+    }
+
+    fn tcp_io_stats(
+        &self,
+        guard: PeerConnectionGuard,
+    ) -> (ArcLimitedReaderStats, ArcLimitedWriterStats) {
+        let (r_stats, w_stats) = self.tags().tcp_io_stats(self.escaper_stats());
+        let (r_stats, w_stats) = self
+            .deprecation()
+            .track(r_stats, w_stats, self.escaper_stats());
+        (self.load().track(r_stats, guard), w_stats)
+    }
+
+    /// count a new tcp connection to the peer, fail if the max connections limit is reached
+    fn acquire_connection(&self) -> Result<PeerConnectionGuard, TcpConnectError> {
+        self.load().try_acquire().ok_or_else(|| {
+            TcpConnectError::EscaperNotUsable(anyhow!(
+                "peer {} reached the max connections limit",
+                self.id()
+            ))
+        })
+    }
+
+    fn allow_port(&self, port: Option<u16>) -> bool {
+        match port {
+            Some(port) => self.port_filter().check(port),
+            None => true,
+        }
+    }
+
+    /// check if the peer record is refreshed within `max_age`
+    fn is_fresh(&self, max_age: Duration) -> bool {
+        self.refreshed_instant()
+            .map(|instant| instant.elapsed() <= max_age)
+            .unwrap_or(false)
+    }
+
+    #[inline]
+    fn is_deprecated(&self) -> bool {
+        self.deprecation().is_deprecated()
+    }
+
+    /// check if the peer should not be selected for new connections
+    fn expired_for_new(&self) -> bool {
         if let Some(expire) = self.expire_instant() {
             expire.checked_duration_since(Instant::now()).is_none()
         } else {
             false
         }
     }
+
+    /// check if the established connections to the peer should not be reused,
+    /// which is the same as `expired_for_new` if no grace window is set
+    fn is_expired(&self) -> bool {
+        match (self.expire_instant(), self.expire_grace()) {
+            (Some(expire), Some(grace)) => Instant::now() >= expire + grace,
+            _ => self.expired_for_new(),
+        }
+    }
     fn expected_alive_minutes(&self) -> u64 {
         if let Some(expire) = self.expire_instant() {
             expire
@@ -160,8 +375,13 @@ pub(super) fn parse_peers(
     escape_logger: &Logger,
     records: &[Value],
     tls_config: Option<&Arc<OpensslClientConfig>>,
+    generation: u64,
 ) -> anyhow::Result<PeerSet> {
-    let mut peer_set = PeerSet::default();
+    let mut peer_set = PeerSet {
+        generation,
+        ..Default::default()
+    };
+    let mut duplicated_count = 0usize;
 
     let instant_now = Instant::now();
     let datetime_now = Utc::now();
@@ -175,26 +395,89 @@ pub(super) fn parse_peers(
             tls_config,
             instant_now,
             datetime_now,
+            generation,
         )
         .context(format!("invalid value for record #{i}"))?
         {
             if peer_id.is_empty() {
                 peer_set.push_unnamed(peer);
+            } else if peer_set.named.contains_key(&peer_id) {
+                if escaper_config.strict_peer_id {
+                    return Err(anyhow!("duplicate peer id {peer_id} in record #{i}"));
+                }
+                warn!(
+                    "escaper {}: duplicate peer id {peer_id} in record #{i}, the previous one will be replaced",
+                    escaper_config.name
+                );
+                duplicated_count += 1;
+                peer_set.insert_named(peer_id, peer);
             } else {
                 peer_set.insert_named(peer_id, peer);
             }
         }
     }
+    if duplicated_count > 0 {
+        escaper_stats.add_peer_duplicated(duplicated_count);
+        warn!(
+            "escaper {}: found {duplicated_count} duplicate peer ids in {} records",
+            escaper_config.name,
+            records.len()
+        );
+    }
+    if let Some(limit) = &escaper_config.peer_count_limit {
+        let evicted = peer_set.apply_count_limit(limit);
+        if evicted > 0 {
+            warn!(
+                "escaper {}: {evicted} peers evicted as the count exceeds the limit {}",
+                escaper_config.name, limit.max_count
+            );
+            escaper_stats.add_peer_evicted(evicted);
+        }
+    }
+    peer_set.build_rr_order();
+    peer_set.build_ring();
     Ok(peer_set)
 }
 
+pub(super) struct PeerAgeStats {
+    pub(super) count: usize,
+    pub(super) min_age: Duration,
+    pub(super) max_age: Duration,
+}
+
 #[derive(Default)]
 pub(super) struct PeerSet {
     unnamed: Vec<ArcNextProxyPeer>,
     named: AHashMap<String, ArcNextProxyPeer>,
+    generation: u64,
+    /// reset on peer set change, as it's not inherited
+    wrr: PeerWrrState,
+    /// reset on peer set change, as it's not inherited
+    rr: PeerRrState,
+    /// all the peers in the round-robin order, rebuilt on peer set change
+    rr_order: Vec<ArcNextProxyPeer>,
+    /// all the peers, rebuilt on peer set change
+    ring: PeerHashRing<ArcNextProxyPeer>,
 }
 
 impl PeerSet {
+    /// the generation of the peer feed that supplied this set, 0 if no feed is loaded yet
+    #[inline]
+    pub(super) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn build_rr_order(&mut self) {
+        self.rr_order = rr::stable_order(&self.unnamed, self.named.iter());
+    }
+
+    fn build_ring(&mut self) {
+        self.ring = PeerHashRing::new(
+            self.unnamed.iter().chain(self.named.values()).cloned(),
+            |p| if p.id().is_empty() { p.label() } else { p.id() },
+        );
+    }
+
     fn push_unnamed(&mut self, peer: ArcNextProxyPeer) {
         self.unnamed.push(peer);
     }
@@ -203,13 +486,110 @@ impl PeerSet {
         self.named.insert(id, peer);
     }
 
-    pub(super) fn select_random_peer(&self) -> Option<ArcNextProxyPeer> {
+    /// drop the less useful peers if the count exceeds the limit, return the evicted count
+    fn apply_count_limit(&mut self, limit: &PeerCountLimitConfig) -> usize {
+        if self.unnamed.len() + self.named.len() <= limit.max_count {
+            return 0;
+        }
+        let mut all = self
+            .unnamed
+            .drain(..)
+            .map(|p| (None, p))
+            .chain(self.named.drain().map(|(id, p)| (Some(id), p)))
+            .collect::<Vec<_>>();
+        let evicted = limit::retain_most_useful(
+            &mut all,
+            limit,
+            |(_, p)| p.expire_instant(),
+            |(_, p)| p.load().weight(),
+        );
+        for (id, peer) in all {
+            match id {
+                Some(id) => self.insert_named(id, peer),
+                None => self.push_unnamed(peer),
+            }
+        }
+        evicted
+    }
+
+    fn usable_peers(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+    ) -> impl Iterator<Item = &ArcNextProxyPeer> {
+        self.unnamed
+            .iter()
+            .chain(self.named.values())
+            .filter(move |p| is_usable(p, port, command))
+    }
+
+    /// the count and the age range of peers for each feed generation
+    pub(super) fn age_distribution(&self) -> BTreeMap<u64, PeerAgeStats> {
+        let mut map: BTreeMap<u64, PeerAgeStats> = BTreeMap::new();
+        for peer in self.unnamed.iter().chain(self.named.values()) {
+            let age = peer
+                .refreshed_instant()
+                .map(|instant| instant.elapsed())
+                .unwrap_or_default();
+            match map.get_mut(&peer.feed_generation()) {
+                Some(stats) => {
+                    stats.count += 1;
+                    stats.min_age = stats.min_age.min(age);
+                    stats.max_age = stats.max_age.max(age);
+                }
+                None => {
+                    map.insert(
+                        peer.feed_generation(),
+                        PeerAgeStats {
+                            count: 1,
+                            min_age: age,
+                            max_age: age,
+                        },
+                    );
+                }
+            }
+        }
+        map
+    }
+
+    pub(super) fn live_count(&self) -> usize {
         self.unnamed
             .iter()
             .chain(self.named.values())
-            .filter(|p| !p.is_expired())
+            .filter(|p| !p.expired_for_new() && !p.is_canary())
+            .count()
+    }
+
+    /// the count of live and expired peers for each peer type, including the absent ones
+    pub(super) fn count_live_expired(&self) -> Vec<(&'static str, usize, usize)> {
+        let mut counts = ALL_PEER_TYPES.map(|t| (t, 0, 0));
+        for peer in self.unnamed.iter().chain(self.named.values()) {
+            let Some(c) = counts.iter_mut().find(|c| c.0 == peer.peer_type()) else {
+                continue;
+            };
+            if peer.expired_for_new() {
+                c.2 += 1;
+            } else {
+                c.1 += 1;
+            }
+        }
+        counts.to_vec()
+    }
+
+    /// select a random canary peer for the upstream `port` to mirror a connection
+    pub(super) fn select_canary_peer(&self, port: u16) -> Option<&ArcNextProxyPeer> {
+        self.unnamed
+            .iter()
+            .chain(self.named.values())
+            .filter(|p| {
+                p.is_canary()
+                    && !p.expired_for_new()
+                    && !p.is_deprecated()
+                    && p.circuit_breaker().is_selectable()
+                    && !p.load().is_saturated()
+                    && p.port_filter().allow(port)
+            })
             .choose(&mut rand::thread_rng())
-            .cloned()
     }
 
     pub(super) fn select_stable_peer(&self) -> Option<&ArcNextProxyPeer> {
@@ -222,8 +602,255 @@ impl PeerSet {
         None
     }
 
-    #[inline]
-    pub(super) fn select_named_peer(&self, id: &str) -> Option<ArcNextProxyPeer> {
-        self.named.get(id).cloned()
+    pub(super) fn inherit_runtime_state(&self, old: &PeerSet) {
+        for (id, peer) in &self.named {
+            if let Some(old_peer) = old.named.get(id) {
+                peer.circuit_breaker()
+                    .inherit(id, old_peer.circuit_breaker());
+                peer.latency().inherit(old_peer.latency());
+                peer.success_rate().inherit(old_peer.success_rate());
+                peer.selection_cap().inherit(old_peer.selection_cap());
+                peer.load().inherit(old_peer.load());
+                peer.deprecation().inherit(old_peer.deprecation());
+                peer.reset_policy().inherit(old_peer.reset_policy());
+                if let (Some(assoc), Some(old_assoc)) =
+                    (peer.udp_associations(), old_peer.udp_associations())
+                {
+                    assoc.inherit(old_assoc);
+                }
+                if let (Some(keep_alive), Some(old_keep_alive)) =
+                    (peer.keep_alive(), old_peer.keep_alive())
+                {
+                    keep_alive.inherit(old_keep_alive);
+                }
+            }
+        }
+    }
+
+    pub(super) fn log_runtime_stats(&self, escaper: &str) {
+        for peer in self.unnamed.iter().chain(self.named.values()) {
+            let selections = peer.selection_cap().total();
+            if selections > 0 {
+                debug!(
+                    "escaper {escaper}: peer {} ({}) has been selected {selections} times",
+                    peer.id(),
+                    peer.label()
+                );
+            }
+            if let Some(rtt) = peer.latency().rtt() {
+                debug!(
+                    "escaper {escaper}: peer {} ({}) RTT {rtt:?}",
+                    peer.id(),
+                    peer.label()
+                );
+            }
+            let success_ratio = peer.success_rate().ratio();
+            if success_ratio < 1.0 {
+                debug!(
+                    "escaper {escaper}: peer {} ({}) connect success ratio {success_ratio:.3}, selection weight {:.3}",
+                    peer.id(),
+                    peer.label(),
+                    selection_weight(peer)
+                );
+            }
+            let in_flight = peer.load().in_flight();
+            if in_flight > 0 && peer.is_deprecated() {
+                info!(
+                    "escaper {escaper}: deprecated peer {} ({}) still has {in_flight} in-flight connections",
+                    peer.id(),
+                    peer.label()
+                );
+            } else if in_flight > 0 {
+                debug!(
+                    "escaper {escaper}: peer {} ({}) has {in_flight} in-flight connections",
+                    peer.id(),
+                    peer.label()
+                );
+            }
+            let resets = peer.reset_policy().count();
+            if resets > 0 {
+                info!(
+                    "escaper {escaper}: peer {} ({}) has {resets} connections reset",
+                    peer.id(),
+                    peer.label()
+                );
+            }
+            if let Some(assoc) = peer.udp_associations() {
+                let rejected = assoc.rejected();
+                if rejected > 0 {
+                    info!(
+                        "escaper {escaper}: peer {} ({}) rejected {rejected} udp associations as {} are alive, limit {:?}",
+                        peer.id(),
+                        peer.label(),
+                        assoc.alive(),
+                        assoc.max()
+                    );
+                }
+            }
+            if let Some(keep_alive) = peer.keep_alive() {
+                let fresh = keep_alive.fresh();
+                if fresh > 0 {
+                    debug!(
+                        "escaper {escaper}: peer {} ({}) has {fresh} fresh and {} reused http forward connections, closed {} times",
+                        peer.id(),
+                        peer.label(),
+                        keep_alive.reused(),
+                        keep_alive.closed()
+                    );
+                }
+            }
+            if peer.is_canary() {
+                info!(
+                    "escaper {escaper}: canary peer {} ({}) connect success ratio {:.3}, RTT {:?}",
+                    peer.id(),
+                    peer.label(),
+                    peer.success_rate().ratio(),
+                    peer.latency().rtt()
+                );
+            }
+            if let Some(capability) = peer.socks5_capability() {
+                if capability.is_known() {
+                    debug!(
+                        "escaper {escaper}: peer {} ({}) socks5 capability: supported {:#05b}, unsupported {:#05b}",
+                        peer.id(),
+                        peer.label(),
+                        capability.supported(),
+                        capability.unsupported()
+                    );
+                }
+            }
+            let rejected = peer.port_filter().rejected();
+            if rejected > 0 {
+                info!(
+                    "escaper {escaper}: peer {} ({}) skipped {rejected} times for disallowed upstream port",
+                    peer.id(),
+                    peer.label()
+                );
+            }
+        }
+    }
+}
+
+/// build a peer set from the json `records` with the default escaper config, for tests only
+#[cfg(test)]
+pub(super) fn build_test_peer_set(records: Value) -> PeerSet {
+    build_test_peer_set_with(ProxyFloatEscaperConfig::default(), records)
+}
+
+/// build a peer set from the json `records` with `escaper_config`, for tests only
+#[cfg(test)]
+pub(super) fn build_test_peer_set_with(
+    escaper_config: ProxyFloatEscaperConfig,
+    records: Value,
+) -> PeerSet {
+    use std::str::FromStr;
+
+    use g3_types::metrics::MetricsName;
+
+    let escaper_config = Arc::new(escaper_config);
+    let escaper_stats = Arc::new(ProxyFloatEscaperStats::new(
+        &MetricsName::from_str("test").unwrap(),
+    ));
+    let logger = Logger::root(slog::Discard, slog::o!());
+    let Value::Array(records) = records else {
+        panic!("records should be a json array");
+    };
+    parse_peers(&escaper_config, &escaper_stats, &logger, &records, None, 1).unwrap()
+}
+
+fn is_usable(peer: &ArcNextProxyPeer, port: Option<u16>, command: SocksCommand) -> bool {
+    !peer.expired_for_new()
+        && !peer.is_deprecated()
+        && !peer.is_canary()
+        && peer.circuit_breaker().is_selectable()
+        && !peer.load().is_saturated()
+        && peer.is_capable(command)
+        && peer.allow_port(port)
+}
+
+/// select a random peer in proportion to its configured weight,
+/// or None if there is no peer with a positive weight
+fn pick_random_peer<'a, I>(peers: I) -> Option<&'a ArcNextProxyPeer>
+where
+    I: Iterator<Item = &'a ArcNextProxyPeer>,
+{
+    let peers = peers.collect::<Vec<_>>();
+    let index = WeightedIndex::new(peers.iter().map(|p| p.load().weight())).ok()?;
+    peers.get(index.sample(&mut rand::thread_rng())).copied()
+}
+
+fn pick_peer_by_latency<'a, I>(peers: I, max_rtt: Duration) -> Option<&'a ArcNextProxyPeer>
+where
+    I: Iterator<Item = &'a ArcNextProxyPeer>,
+{
+    let peers = peers.map(|p| (p, p.latency().rtt())).collect::<Vec<_>>();
+    peers
+        .iter()
+        .filter(|(_, rtt)| rtt.map(|v| v <= max_rtt).unwrap_or(false))
+        .choose(&mut rand::thread_rng())
+        .or_else(|| {
+            peers
+                .iter()
+                .filter(|(_, rtt)| rtt.is_some())
+                .min_by_key(|(_, rtt)| *rtt)
+        })
+        // no RTT measured yet
+        .or_else(|| peers.iter().choose(&mut rand::thread_rng()))
+        .map(|(p, _)| *p)
+}
+
+/// the configured weight scaled by the recent connect success ratio
+fn selection_weight(peer: &ArcNextProxyPeer) -> f64 {
+    peer.load().weight() * peer.success_rate().weight()
+}
+
+fn pick_peer_by_success_rate<'a, I>(peers: I) -> Option<&'a ArcNextProxyPeer>
+where
+    I: Iterator<Item = &'a ArcNextProxyPeer>,
+{
+    let mut rng = rand::thread_rng();
+    let peers = peers.collect::<Vec<_>>();
+    peers
+        .choose_weighted(&mut rng, |p| selection_weight(p))
+        .ok()
+        .or_else(|| peers.choose(&mut rng))
+        .copied()
+}
+
+fn pick_peer_wrr<'a, I>(peers: I, state: &PeerWrrState) -> Option<&'a ArcNextProxyPeer>
+where
+    I: Iterator<Item = &'a ArcNextProxyPeer>,
+{
+    state.pick(
+        peers,
+        |p| Arc::as_ptr(p).cast::<()>() as usize,
+        |p| p.load().weight(),
+    )
+}
+
+fn pick_peer_p2c<'a, I>(peers: I) -> Option<&'a ArcNextProxyPeer>
+where
+    I: Iterator<Item = &'a ArcNextProxyPeer>,
+{
+    let mut rng = rand::thread_rng();
+    let peers = peers.choose_multiple(&mut rng, 2);
+    match peers.as_slice() {
+        [] => None,
+        [p] => Some(*p),
+        [a, b] => {
+            let ratio_a = a.load().ratio();
+            let ratio_b = b.load().ratio();
+            if ratio_a < ratio_b {
+                Some(*a)
+            } else if ratio_b < ratio_a {
+                Some(*b)
+            } else if rng.gen_bool(0.5) {
+                // the order returned by choose_multiple is not random
+                Some(*a)
+            } else {
+                Some(*b)
+            }
+        }
+        _ => unreachable!(),
     }
 }
@@ -0,0 +1,85 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use g3_types::net::Ports;
+
+#[derive(Default)]
+pub(crate) struct PeerPortFilter {
+    allowed: Option<Ports>,
+    denied: Option<Ports>,
+    rejected: AtomicU64,
+}
+
+impl PeerPortFilter {
+    pub(super) fn set_allowed(&mut self, ports: Ports) {
+        self.allowed = Some(ports);
+    }
+
+    pub(super) fn set_denied(&mut self, ports: Ports) {
+        self.denied = Some(ports);
+    }
+
+    /// check the upstream port without counting the rejection
+    pub(super) fn allow(&self, port: u16) -> bool {
+        if let Some(denied) = &self.denied {
+            if denied.contains(port) {
+                return false;
+            }
+        }
+        if let Some(allowed) = &self.allowed {
+            return allowed.contains(port);
+        }
+        true
+    }
+
+    /// check the upstream port, and count the rejection if not allowed
+    pub(super) fn check(&self, port: u16) -> bool {
+        if self.allow(port) {
+            true
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    pub(super) fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn allowed_and_denied() {
+        let mut filter = PeerPortFilter::default();
+        assert!(filter.check(80));
+
+        filter.set_allowed(Ports::from_str("443,8000-9000").unwrap());
+        assert!(filter.check(443));
+        assert!(filter.check(8443));
+        assert!(!filter.check(80));
+
+        filter.set_denied(Ports::from_str("8443").unwrap());
+        assert!(!filter.check(8443));
+        assert!(filter.check(8080));
+        assert_eq!(filter.rejected(), 2);
+    }
+}
@@ -0,0 +1,668 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ahash::AHashSet;
+
+use g3_socks::SocksCommand;
+use g3_types::net::EgressArea;
+
+use super::{ArcNextProxyPeer, PeerSet, RecentPeers};
+use crate::config::escaper::proxy_float::{PeerSelectionMode, PeerTagRule};
+
+/// The per-call inputs of the selection strategies, other than the candidate peers
+pub(crate) struct PeerSelectContext<'a> {
+    /// the recently selected peers to avoid, only used in random mode
+    pub(crate) recent: Option<&'a RecentPeers>,
+    pub(crate) client_key: IpAddr,
+    /// the user name of the task, preferred over `client_key` in sticky mode
+    pub(crate) user: Option<&'a str>,
+    /// prefer the peers refreshed within this duration, only used in random mode
+    pub(crate) max_age: Option<Duration>,
+    /// only the peers permitted by this rule can be selected
+    pub(crate) peer_rule: Option<&'a PeerTagRule>,
+}
+
+impl PeerSelectContext<'_> {
+    /// the key to map the client to the peer in sticky mode
+    fn sticky_key(&self) -> Cow<'_, str> {
+        match self.user {
+            Some(user) => Cow::Borrowed(user),
+            None => Cow::Owned(self.client_key.to_string()),
+        }
+    }
+}
+
+/// A chained peer filter on the usable peers of a peer set
+///
+/// Each filter narrows the candidates, and the selection will return `None` if nothing left,
+/// unless `relax_last` is called to fall back to the candidates before the last filter.
+pub(crate) struct PeerQuery<'a> {
+    peers: Vec<&'a ArcNextProxyPeer>,
+    before_last: Option<Vec<&'a ArcNextProxyPeer>>,
+    set: &'a PeerSet,
+}
+
+fn peer_key(peer: &ArcNextProxyPeer) -> usize {
+    Arc::as_ptr(peer).cast::<()>() as usize
+}
+
+impl<'a> PeerQuery<'a> {
+    fn filter<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ArcNextProxyPeer) -> bool,
+    {
+        let matched = self.peers.iter().copied().filter(|p| f(p)).collect();
+        self.before_last = Some(std::mem::replace(&mut self.peers, matched));
+        self
+    }
+
+    /// keep only the peers permitted by `rule`, this can't be relaxed
+    pub(crate) fn restrict(mut self, rule: Option<&PeerTagRule>) -> Self {
+        if let Some(rule) = rule {
+            self.peers.retain(|p| p.tags().permitted_by(rule));
+            self.before_last = None;
+        }
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// the keys of the candidates, to check the peers of the whole set against them
+    fn candidate_keys(&self) -> AHashSet<usize> {
+        self.peers.iter().map(|p| peer_key(p)).collect()
+    }
+
+    /// keep the peers within `area`, e.g. area `us` matches peers in `us/ca`
+    pub(crate) fn filter_area(self, area: &EgressArea) -> Self {
+        self.filter(|p| p.area().map(|v| area.contains(v)).unwrap_or(false))
+    }
+
+    /// keep the peers of `isp`, case-insensitive
+    pub(crate) fn filter_isp(self, isp: &str) -> Self {
+        self.filter(|p| {
+            p.isp()
+                .map(|v| v.eq_ignore_ascii_case(isp))
+                .unwrap_or(false)
+        })
+    }
+
+    /// keep the peers refreshed within `max_age`
+    pub(crate) fn filter_fresh(self, max_age: Duration) -> Self {
+        self.filter(|p| p.is_fresh(max_age))
+    }
+
+    /// keep the peers which have not reached the selection cap of the current window
+    pub(crate) fn filter_uncapped(self) -> Self {
+        self.filter(|p| !p.selection_cap().is_capped())
+    }
+
+    /// drop the last filter if no peer is left after it
+    pub(crate) fn relax_last(mut self) -> Self {
+        if self.peers.is_empty() {
+            if let Some(peers) = self.before_last.take() {
+                self.peers = peers;
+            }
+        }
+        self
+    }
+
+    /// pick a peer from the candidates by `pick`, and pick again without it
+    /// if its circuit breaker probe is claimed by a concurrent selection
+    fn select_with<F>(mut self, pick: F) -> Option<ArcNextProxyPeer>
+    where
+        F: Fn(&Self) -> Option<&'a ArcNextProxyPeer>,
+    {
+        loop {
+            let peer = pick(&self)?;
+            if peer.circuit_breaker().try_select(peer.id()) {
+                peer.selection_cap().on_selected();
+                return Some(peer.clone());
+            }
+            let key = peer_key(peer);
+            self.peers.retain(|p| peer_key(p) != key);
+        }
+    }
+
+    pub(crate) fn select_random(self) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| super::pick_random_peer(q.peers.iter().copied()))
+    }
+
+    /// select a random peer which is not recently selected by this client,
+    /// or from all the candidates if all of them are recently used
+    pub(crate) fn select_random_avoiding_recent(
+        self,
+        recent: &RecentPeers,
+        client_key: IpAddr,
+    ) -> Option<ArcNextProxyPeer> {
+        recent.select_with(
+            client_key,
+            |recent_ids| {
+                self.select_with(|q| {
+                    super::pick_random_peer(
+                        q.peers
+                            .iter()
+                            .copied()
+                            .filter(|p| !recent_ids.iter().any(|id| id == p.id())),
+                    )
+                    .or_else(|| super::pick_random_peer(q.peers.iter().copied()))
+                })
+            },
+            |p| p.id(),
+        )
+    }
+
+    /// select a random peer whose RTT is not larger than `max_rtt`,
+    /// or the fastest one if no such peer found
+    pub(crate) fn select_by_latency(self, max_rtt: Duration) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| super::pick_peer_by_latency(q.peers.iter().copied(), max_rtt))
+    }
+
+    /// sample two peers at random and select the one with the lower in-flight/weight ratio
+    pub(crate) fn select_p2c(self) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| super::pick_peer_p2c(q.peers.iter().copied()))
+    }
+
+    /// select the candidates in turn in proportion to their weights
+    pub(crate) fn select_wrr(self) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| super::pick_peer_wrr(q.peers.iter().copied(), &q.set.wrr))
+    }
+
+    /// select the candidates in turn, in the stable round-robin order of the peer set
+    ///
+    /// The cursor is advanced past the peers that are not candidates, such as the expired ones,
+    /// so the order of the remaining peers is kept when some peers are expired.
+    pub(crate) fn select_rr(self) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| {
+            let candidates = q.candidate_keys();
+            q.set
+                .rr
+                .pick(&q.set.rr_order, |p| candidates.contains(&peer_key(p)))
+        })
+    }
+
+    /// select the candidate mapped to `key` on the hash ring of all peers,
+    /// or the next candidate in ring order
+    pub(crate) fn select_sticky(self, key: &str) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| {
+            let candidates = q.candidate_keys();
+            q.set.ring.pick(key, |p| candidates.contains(&peer_key(p)))
+        })
+    }
+
+    /// select the first candidate of the named peers `ids`, the missing or unusable ones will be
+    /// skipped, or a random candidate if none of them can be selected
+    pub(crate) fn select_named_or_random(self, ids: &[&str]) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| {
+            ids.iter()
+                .filter_map(|id| q.set.named.get(*id))
+                .find(|p| q.peers.iter().any(|c| Arc::ptr_eq(c, p)))
+                .or_else(|| super::pick_random_peer(q.peers.iter().copied()))
+        })
+    }
+
+    /// select a random peer weighted by its recent connect success ratio
+    pub(crate) fn select_by_success_rate(self) -> Option<ArcNextProxyPeer> {
+        self.select_with(|q| super::pick_peer_by_success_rate(q.peers.iter().copied()))
+    }
+
+    /// select from the candidates by the strategy of `mode`
+    ///
+    /// In random mode, the fresh peers and the uncapped peers will be preferred,
+    /// and the recently selected peers of the client will be avoided if possible.
+    pub(crate) fn select(
+        self,
+        mode: PeerSelectionMode,
+        ctx: &PeerSelectContext<'_>,
+    ) -> Option<ArcNextProxyPeer> {
+        match mode {
+            PeerSelectionMode::Random => {
+                let query = match ctx.max_age {
+                    Some(max_age) => self.filter_fresh(max_age).relax_last(),
+                    None => self,
+                }
+                .filter_uncapped()
+                .relax_last();
+                match ctx.recent {
+                    Some(recent) => query.select_random_avoiding_recent(recent, ctx.client_key),
+                    None => query.select_random(),
+                }
+            }
+            PeerSelectionMode::PowerOfTwoChoices => self.select_p2c(),
+            PeerSelectionMode::SuccessRate => self.select_by_success_rate(),
+            PeerSelectionMode::WeightedRoundRobin => self.select_wrr(),
+            PeerSelectionMode::RoundRobin => self.select_rr(),
+            PeerSelectionMode::Sticky => self.select_sticky(&ctx.sticky_key()),
+        }
+    }
+}
+
+impl PeerSet {
+    /// start a query on the usable peers for the upstream `port` and the socks5 `command`
+    pub(crate) fn query(&self, port: Option<u16>, command: SocksCommand) -> PeerQuery<'_> {
+        PeerQuery {
+            peers: self.usable_peers(port, command).collect(),
+            before_last: None,
+            set: self,
+        }
+    }
+
+    /// select from the usable peers for the upstream `port` and the socks5 `command`
+    /// by the strategy of `mode`, see [`PeerQuery::select`]
+    pub(crate) fn select(
+        &self,
+        mode: PeerSelectionMode,
+        port: Option<u16>,
+        command: SocksCommand,
+        ctx: &PeerSelectContext<'_>,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command)
+            .restrict(ctx.peer_rule)
+            .select(mode, ctx)
+    }
+
+    #[inline]
+    pub(crate) fn select_named_peer(&self, id: &str) -> Option<ArcNextProxyPeer> {
+        self.named.get(id).cloned()
+    }
+
+    /// select the first usable one of the named peers `ids`, the missing ids will be skipped,
+    /// or a random usable peer if none of them is usable
+    pub(crate) fn select_named_peer_or_any(
+        &self,
+        ids: &[&str],
+        port: Option<u16>,
+        command: SocksCommand,
+        rule: Option<&PeerTagRule>,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command)
+            .restrict(rule)
+            .select_named_or_random(ids)
+    }
+}
+
+/// The convenience wrappers of the single selection strategies, without the user peer policy
+#[allow(unused)]
+impl PeerSet {
+    /// select a random usable peer, preferring the uncapped ones
+    ///
+    /// The peers with consecutive connect failures are skipped by their circuit breakers,
+    /// which only work if `peer_circuit_breaker` is set in the escaper config.
+    pub(crate) fn select_random_peer(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command)
+            .filter_uncapped()
+            .relax_last()
+            .select_random()
+    }
+
+    /// select a random peer refreshed within `max_age`,
+    /// or from the stale ones if none of the usable peers is fresh
+    pub(crate) fn select_fresh_peer(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+        max_age: Duration,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command)
+            .filter_fresh(max_age)
+            .relax_last()
+            .filter_uncapped()
+            .relax_last()
+            .select_random()
+    }
+
+    /// select a random peer which is not recently selected by this client,
+    /// or from all usable peers if all of them are recently used
+    pub(crate) fn select_random_peer_avoiding_recent(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+        recent: &RecentPeers,
+        client_key: IpAddr,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command)
+            .filter_uncapped()
+            .relax_last()
+            .select_random_avoiding_recent(recent, client_key)
+    }
+
+    /// select a random peer whose RTT is not larger than `max_rtt`,
+    /// or the fastest one if no such peer found
+    pub(crate) fn select_peer_by_latency(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+        max_rtt: Duration,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command).select_by_latency(max_rtt)
+    }
+
+    /// sample two peers at random and select the one with the lower in-flight/weight ratio
+    pub(crate) fn select_peer_p2c(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command).select_p2c()
+    }
+
+    /// select the peers in turn in proportion to their weights
+    pub(crate) fn select_peer_wrr(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command).select_wrr()
+    }
+
+    /// select a random peer weighted by its recent connect success ratio
+    pub(crate) fn select_peer_by_success_rate(
+        &self,
+        port: Option<u16>,
+        command: SocksCommand,
+    ) -> Option<ArcNextProxyPeer> {
+        self.query(port, command).select_by_success_rate()
+    }
+
+    /// select the usable peers in turn, in the stable round-robin order of the peer set
+    pub(crate) fn select_rr_peer(&self) -> Option<ArcNextProxyPeer> {
+        self.query(None, SocksCommand::TcpConnect).select_rr()
+    }
+
+    /// select the usable peer mapped to `key` on the hash ring, or the next usable one
+    pub(crate) fn select_sticky_peer(&self, key: &str) -> Option<ArcNextProxyPeer> {
+        self.query(None, SocksCommand::TcpConnect)
+            .select_sticky(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::super::{build_test_peer_set, build_test_peer_set_with};
+    use crate::config::escaper::proxy_float::{PeerCircuitBreakerConfig, ProxyFloatEscaperConfig};
+
+    fn selected_id(peer: Option<ArcNextProxyPeer>) -> Option<String> {
+        peer.map(|p| p.id().to_string())
+    }
+
+    #[test]
+    fn named_or_any_missing() {
+        let peer_set = build_test_peer_set(json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a"},
+        ]));
+
+        let peer = peer_set.select_named_peer_or_any(&["x"], None, SocksCommand::TcpConnect, None);
+        assert_eq!(selected_id(peer).as_deref(), Some("a"));
+        let peer =
+            peer_set.select_named_peer_or_any(&["x", "a"], None, SocksCommand::TcpConnect, None);
+        assert_eq!(selected_id(peer).as_deref(), Some("a"));
+
+        let peer_set = build_test_peer_set(json!([]));
+        let peer = peer_set.select_named_peer_or_any(&["x"], None, SocksCommand::TcpConnect, None);
+        assert!(peer.is_none());
+    }
+
+    #[test]
+    fn named_or_any_unusable() {
+        let peer_set = build_test_peer_set(json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a", "deprecated": true},
+            {"type": "http", "addr": "127.0.0.1:1002", "id": "b", "denied_ports": "443"},
+            {"type": "http", "addr": "127.0.0.1:1003", "id": "c"},
+        ]));
+
+        // a is deprecated
+        let peer = peer_set.select_named_peer_or_any(&["a"], None, SocksCommand::TcpConnect, None);
+        assert_ne!(selected_id(peer).as_deref(), Some("a"));
+        let peer =
+            peer_set.select_named_peer_or_any(&["a", "b"], None, SocksCommand::TcpConnect, None);
+        assert_eq!(selected_id(peer).as_deref(), Some("b"));
+
+        // b doesn't allow the port, and the fallback should not pick the unusable ones
+        for _ in 0..10 {
+            let peer = peer_set.select_named_peer_or_any(
+                &["a", "b"],
+                Some(443),
+                SocksCommand::TcpConnect,
+                None,
+            );
+            assert_eq!(selected_id(peer).as_deref(), Some("c"));
+        }
+    }
+
+    #[test]
+    fn rr_and_sticky_wrappers() {
+        let peer_set = build_test_peer_set(json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a"},
+            {"type": "http", "addr": "127.0.0.1:1002", "id": "b", "deprecated": true},
+            {"type": "http", "addr": "127.0.0.1:1003", "id": "c"},
+        ]));
+
+        let ids = (0..4)
+            .map(|_| selected_id(peer_set.select_rr_peer()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "c", "a", "c"]);
+
+        let first = selected_id(peer_set.select_sticky_peer("client")).unwrap();
+        assert_ne!(first, "b");
+        for _ in 0..4 {
+            let id = selected_id(peer_set.select_sticky_peer("client")).unwrap();
+            assert_eq!(id, first);
+        }
+    }
+
+    #[test]
+    fn random_skip_failed() {
+        let records = json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a"},
+            {"type": "http", "addr": "127.0.0.1:1002", "id": "b"},
+        ]);
+        let mut escaper_config = ProxyFloatEscaperConfig::default();
+        escaper_config.peer_circuit_breaker = Some(PeerCircuitBreakerConfig {
+            failure_threshold: 2,
+            ..Default::default()
+        });
+        let peer_set = build_test_peer_set_with(escaper_config, records.clone());
+        let a = peer_set.select_named_peer("a").unwrap();
+        a.circuit_breaker().record_failure("a");
+        a.circuit_breaker().record_failure("a");
+        for _ in 0..10 {
+            let peer = peer_set.select_random_peer(None, SocksCommand::TcpConnect);
+            assert_eq!(selected_id(peer).as_deref(), Some("b"));
+        }
+
+        // no gating without the circuit breaker config
+        let peer_set = build_test_peer_set(records);
+        let a = peer_set.select_named_peer("a").unwrap();
+        a.circuit_breaker().record_failure("a");
+        a.circuit_breaker().record_failure("a");
+        assert!(a.circuit_breaker().is_selectable());
+    }
+
+    #[test]
+    fn random_zero_weight() {
+        let peer_set = build_test_peer_set(json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a", "weight": 0},
+            {"type": "http", "addr": "127.0.0.1:1002", "id": "b", "weight": 0.0},
+        ]));
+        assert!(peer_set
+            .select_random_peer(None, SocksCommand::TcpConnect)
+            .is_none());
+
+        let peer_set = build_test_peer_set(json!([
+            {"type": "http", "addr": "127.0.0.1:1001", "id": "a", "weight": 0},
+            {"type": "http", "addr": "127.0.0.1:1002", "id": "b", "weight": 2},
+        ]));
+        for _ in 0..10 {
+            let peer = peer_set.select_random_peer(None, SocksCommand::TcpConnect);
+            assert_eq!(selected_id(peer).as_deref(), Some("b"));
+        }
+    }
+
+    fn area_peer_set() -> PeerSet {
+        build_test_peer_set(json!([
+            {
+                "type": "http", "addr": "127.0.0.1:1001", "id": "a",
+                "area": "us/ca", "isp": "comcast", "region": "eu",
+            },
+            {
+                "type": "http", "addr": "127.0.0.1:1002", "id": "b",
+                "area": "us", "isp": "att", "region": "us",
+            },
+            {
+                "type": "http", "addr": "127.0.0.1:1003", "id": "c",
+                "area": "cn", "isp": "Comcast", "region": "eu",
+            },
+        ]))
+    }
+
+    fn eu_rule() -> PeerTagRule {
+        let doc = yaml_rust::YamlLoader::load_from_str("allow:\n  region: eu").unwrap();
+        PeerTagRule::parse(&doc[0]).unwrap()
+    }
+
+    #[test]
+    fn chained_filters() {
+        let peer_set = area_peer_set();
+        let us = EgressArea::from_str("us").unwrap();
+
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_area(&us)
+            .filter_isp("COMCAST");
+        assert_eq!(selected_id(query.select_random()).as_deref(), Some("a"));
+
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_isp("comcast")
+            .filter_area(&EgressArea::from_str("cn").unwrap());
+        assert_eq!(selected_id(query.select_random()).as_deref(), Some("c"));
+
+        // empty intersection
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_area(&us)
+            .filter_isp("verizon");
+        assert!(query.is_empty());
+        assert!(query.select_random().is_none());
+    }
+
+    #[test]
+    fn relax_last_filter() {
+        let peer_set = area_peer_set();
+        let us = EgressArea::from_str("us").unwrap();
+
+        for _ in 0..10 {
+            let query = peer_set
+                .query(None, SocksCommand::TcpConnect)
+                .filter_area(&us)
+                .filter_isp("verizon")
+                .relax_last();
+            let id = selected_id(query.select_random()).unwrap();
+            assert!(id == "a" || id == "b");
+        }
+
+        // only the last one will be relaxed
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_area(&EgressArea::from_str("jp").unwrap())
+            .filter_isp("att")
+            .relax_last();
+        assert!(query.is_empty());
+
+        // not relaxed if not empty
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_isp("att")
+            .relax_last();
+        assert_eq!(selected_id(query.select_random()).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn restrict_not_relaxable() {
+        let peer_set = area_peer_set();
+        let rule = eu_rule();
+
+        // b is the only one in us with isp att, but it's not in region eu
+        let query = peer_set
+            .query(None, SocksCommand::TcpConnect)
+            .filter_isp("att")
+            .restrict(Some(&rule))
+            .relax_last();
+        assert!(query.is_empty());
+        assert!(query.select_random().is_none());
+
+        // relax to the restricted peers only
+        for _ in 0..10 {
+            let query = peer_set
+                .query(None, SocksCommand::TcpConnect)
+                .restrict(Some(&rule))
+                .filter_isp("att")
+                .relax_last();
+            let id = selected_id(query.select_random()).unwrap();
+            assert!(id == "a" || id == "c");
+        }
+    }
+
+    #[test]
+    fn retry_claimed_probe() {
+        let mut escaper_config = ProxyFloatEscaperConfig::default();
+        escaper_config.peer_circuit_breaker = Some(PeerCircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let peer_set = build_test_peer_set_with(
+            escaper_config,
+            json!([
+                {"type": "http", "addr": "127.0.0.1:1001", "id": "a"},
+                {"type": "http", "addr": "127.0.0.1:1002", "id": "b"},
+            ]),
+        );
+        let a = peer_set.select_named_peer("a").unwrap();
+        a.circuit_breaker().record_failure("a");
+        std::thread::sleep(Duration::from_millis(60));
+
+        // a is selectable in both queries, but only one of them can claim the probe
+        let q1 = peer_set.query(None, SocksCommand::TcpConnect);
+        let q2 = peer_set.query(None, SocksCommand::TcpConnect);
+        assert_eq!(q1.peers.len(), 2);
+        assert_eq!(q2.peers.len(), 2);
+        assert_eq!(
+            selected_id(q1.select_named_or_random(&["a"])).as_deref(),
+            Some("a")
+        );
+        assert_eq!(
+            selected_id(q2.select_named_or_random(&["a"])).as_deref(),
+            Some("b")
+        );
+    }
+}
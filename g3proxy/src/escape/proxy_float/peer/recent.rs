@@ -0,0 +1,96 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+const MAX_TRACKED_CLIENTS: usize = 16384;
+
+/// The recently selected peer ids of each client, with both the count of clients and peers bounded
+pub(crate) struct RecentPeers {
+    count: usize,
+    clients: Mutex<LruCache<IpAddr, VecDeque<String>>>,
+}
+
+impl RecentPeers {
+    pub(crate) fn new(count: usize) -> Option<Self> {
+        if count == 0 {
+            return None;
+        }
+        let cap = NonZeroUsize::new(MAX_TRACKED_CLIENTS).unwrap();
+        Some(RecentPeers {
+            count,
+            clients: Mutex::new(LruCache::new(cap)),
+        })
+    }
+
+    /// run the selection with the recently used peers of this client,
+    /// and then add the selected one to the recent list
+    pub(super) fn select_with<R, F, I>(&self, client_key: IpAddr, select: F, id_of: I) -> Option<R>
+    where
+        F: FnOnce(&VecDeque<String>) -> Option<R>,
+        I: Fn(&R) -> &str,
+    {
+        let mut clients = self.clients.lock().unwrap();
+        let recent = clients.get_or_insert_mut(client_key, || VecDeque::with_capacity(self.count));
+        let selected = select(recent)?;
+        let id = id_of(&selected);
+        if let Some(i) = recent.iter().position(|v| v == id) {
+            recent.remove(i);
+        } else if recent.len() >= self.count {
+            recent.pop_front();
+        }
+        recent.push_back(id.to_string());
+        Some(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn recent_list() {
+        assert!(RecentPeers::new(0).is_none());
+
+        let recent = RecentPeers::new(2).unwrap();
+        let client = IpAddr::from_str("192.0.2.1").unwrap();
+        let other = IpAddr::from_str("192.0.2.2").unwrap();
+
+        let peers = ["a", "b", "c"];
+        let pick = |recent: &VecDeque<String>| {
+            peers
+                .iter()
+                .copied()
+                .find(|p| !recent.iter().any(|r| r == p))
+        };
+        fn id_of<'a>(s: &'a &str) -> &'a str {
+            s
+        }
+
+        assert_eq!(recent.select_with(client, pick, id_of), Some("a"));
+        assert_eq!(recent.select_with(client, pick, id_of), Some("b"));
+        assert_eq!(recent.select_with(client, pick, id_of), Some("c"));
+        // only the last 2 are kept
+        assert_eq!(recent.select_with(client, pick, id_of), Some("a"));
+        assert_eq!(recent.select_with(other, pick, id_of), Some("a"));
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use arc_swap::ArcSwap;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use g3_types::net::ConnectError;
+
+use super::PeerCircuitBreaker;
+use crate::module::tcp_connect::TcpConnectError;
+
+/// The handling of connection resets from the peer.
+#[derive(Default)]
+pub(crate) struct PeerResetPolicy {
+    as_failure: bool,
+    retry: bool,
+    /// shared with the alive connections, and with the new peer after reload
+    count: ArcSwap<AtomicU64>,
+}
+
+impl PeerResetPolicy {
+    pub(super) fn set_as_failure(&mut self, enable: bool) {
+        self.as_failure = enable;
+    }
+
+    pub(super) fn set_retry(&mut self, enable: bool) {
+        self.retry = enable;
+    }
+
+    pub(super) fn count(&self) -> u64 {
+        self.count.load().load(Ordering::Relaxed)
+    }
+
+    /// check if the connection setup should be retried with another peer
+    pub(crate) fn retry(&self, e: &TcpConnectError) -> bool {
+        self.retry && is_reset_error(e)
+    }
+
+    /// wrap the reader of a new connection, which will report the first reset seen on it
+    pub(super) fn wrap<R>(
+        &self,
+        peer_id: &str,
+        circuit_breaker: &Arc<PeerCircuitBreaker>,
+        inner: R,
+    ) -> PeerResetReader<R> {
+        let notifier = PeerResetNotifier {
+            peer_id: peer_id.to_string(),
+            count: self.count.load_full(),
+            circuit_breaker: self.as_failure.then(|| Arc::clone(circuit_breaker)),
+        };
+        PeerResetReader {
+            inner,
+            notifier: Some(notifier),
+        }
+    }
+
+    pub(super) fn inherit(&self, old: &PeerResetPolicy) {
+        self.count.store(old.count.load_full());
+    }
+}
+
+fn is_reset_error(e: &TcpConnectError) -> bool {
+    match e {
+        TcpConnectError::ConnectFailed(ConnectError::ConnectionReset) => true,
+        TcpConnectError::NegotiationReadFailed(e) | TcpConnectError::NegotiationWriteFailed(e) => {
+            e.kind() == io::ErrorKind::ConnectionReset
+        }
+        _ => false,
+    }
+}
+
+struct PeerResetNotifier {
+    peer_id: String,
+    count: Arc<AtomicU64>,
+    circuit_breaker: Option<Arc<PeerCircuitBreaker>>,
+}
+
+impl PeerResetNotifier {
+    fn notify(self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if let Some(circuit_breaker) = self.circuit_breaker {
+            circuit_breaker.record_failure(&self.peer_id);
+        }
+    }
+}
+
+pub(crate) struct PeerResetReader<R> {
+    inner: R,
+    notifier: Option<PeerResetNotifier>,
+}
+
+impl<R> PeerResetReader<R> {
+    pub(super) fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> AsyncRead for PeerResetReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match ready!(Pin::new(&mut self.inner).poll_read(cx, buf)) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(e) => {
+                if e.kind() == io::ErrorKind::ConnectionReset {
+                    if let Some(notifier) = self.notifier.take() {
+                        notifier.notify();
+                    }
+                }
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+
+    use crate::config::escaper::proxy_float::PeerCircuitBreakerConfig;
+
+    struct ResetReader;
+
+    impl AsyncRead for ResetReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::Error::from(io::ErrorKind::ConnectionReset)))
+        }
+    }
+
+    fn new_breaker() -> Arc<PeerCircuitBreaker> {
+        Arc::new(PeerCircuitBreaker::new(Some(PeerCircuitBreakerConfig {
+            failure_threshold: 1,
+            failure_window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        })))
+    }
+
+    #[tokio::test]
+    async fn count_only() {
+        let policy = PeerResetPolicy::default();
+        let breaker = new_breaker();
+
+        let mut r = policy.wrap("test", &breaker, ResetReader);
+        let mut buf = [0u8; 16];
+        assert!(r.read(&mut buf).await.is_err());
+        assert!(r.read(&mut buf).await.is_err());
+        assert_eq!(policy.count(), 1);
+        assert!(breaker.is_selectable());
+
+        let new_policy = PeerResetPolicy::default();
+        new_policy.inherit(&policy);
+        let mut r = policy.wrap("test", &breaker, ResetReader);
+        assert!(r.read(&mut buf).await.is_err());
+        assert_eq!(new_policy.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn as_failure() {
+        let mut policy = PeerResetPolicy::default();
+        policy.set_as_failure(true);
+        let breaker = new_breaker();
+
+        let mut r = policy.wrap("test", &breaker, ResetReader);
+        let mut buf = [0u8; 16];
+        assert!(r.read(&mut buf).await.is_err());
+        assert_eq!(policy.count(), 1);
+        assert!(!breaker.is_selectable());
+    }
+
+    #[test]
+    fn retry() {
+        let mut policy = PeerResetPolicy::default();
+        let e = TcpConnectError::NegotiationReadFailed(io::ErrorKind::ConnectionReset.into());
+        assert!(!policy.retry(&e));
+
+        policy.set_retry(true);
+        assert!(policy.retry(&e));
+        assert!(!policy.retry(&TcpConnectError::NegotiationPeerTimeout));
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The cursor of the round-robin selection
+///
+/// The state is bound to the peer set, so the cycle will start over on peer set change.
+/// The candidates are picked in the order built by [`stable_order`], so the cycling order is
+/// reproducible across runs for the same peers.
+#[derive(Default)]
+pub(crate) struct PeerRrState {
+    cursor: AtomicUsize,
+}
+
+impl PeerRrState {
+    /// a copy of the current state, which can be used without affecting this one
+    pub(super) fn snapshot(&self) -> Self {
+        PeerRrState {
+            cursor: AtomicUsize::new(self.cursor.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// pick the next item, the ones not `usable` will be skipped
+    pub(super) fn pick<'a, T, F>(&self, items: &'a [T], usable: F) -> Option<&'a T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        if items.is_empty() {
+            return None;
+        }
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed) % items.len();
+        for i in 0..items.len() {
+            let index = (start + i) % items.len();
+            let item = &items[index];
+            if usable(item) {
+                if i > 0 {
+                    // continue from the one after the picked
+                    self.cursor.store(index + 1, Ordering::Relaxed);
+                }
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+/// The order of the round-robin selection, the unnamed items in their feed order,
+/// followed by the named items sorted by name.
+///
+/// The named items should not be used in the iteration order of their hash map,
+/// as it differs between processes.
+pub(super) fn stable_order<'a, T, I>(unnamed: &[T], named: I) -> Vec<T>
+where
+    T: Clone + 'a,
+    I: Iterator<Item = (&'a String, &'a T)>,
+{
+    let mut named = named.collect::<Vec<_>>();
+    named.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    unnamed
+        .iter()
+        .cloned()
+        .chain(named.into_iter().map(|(_, item)| item.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_expired() {
+        let state = PeerRrState::default();
+        // the expire time of each peer
+        let peers = [('a', 4), ('b', 2), ('c', 6)];
+        let picked = (0..8)
+            .map(|now| {
+                state
+                    .pick(&peers, |p| p.1 > now)
+                    .map(|p| p.0)
+                    .unwrap_or('-')
+            })
+            .collect::<String>();
+        assert_eq!(picked, "abcacc--");
+
+        assert!(state.pick(&[] as &[(char, i32)], |_| true).is_none());
+    }
+
+    #[test]
+    fn same_order() {
+        let build = |ids: &[&str]| {
+            let named = ids
+                .iter()
+                .map(|id| (id.to_string(), id.to_uppercase()))
+                .collect::<ahash::AHashMap<_, _>>();
+            let unnamed = ["u1".to_string(), "u0".to_string()];
+            let order = stable_order(&unnamed, named.iter());
+            let state = PeerRrState::default();
+            (0..8)
+                .map(|_| state.pick(&order, |_| true).unwrap().clone())
+                .collect::<Vec<_>>()
+        };
+        let first = build(&["d", "a", "c", "b", "e"]);
+        let second = build(&["b", "e", "a", "d", "c"]);
+        assert_eq!(first, ["u1", "u0", "A", "B", "C", "D", "E", "u1"]);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,176 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use tokio::time::Instant;
+
+use crate::config::escaper::proxy_float::PeerSelectionCapConfig;
+
+/// The count of selections of the peer, with an optional cap in each time window.
+///
+/// All time values are stored as milliseconds since the creation of the counts.
+pub(crate) struct PeerSelectionCap {
+    config: Option<PeerSelectionCapConfig>,
+    /// shared with the new peer after reload
+    counts: ArcSwap<PeerSelectionCounts>,
+}
+
+struct PeerSelectionCounts {
+    created: Instant,
+    total: AtomicU64,
+    window_start: AtomicU64,
+    window_count: AtomicUsize,
+}
+
+impl Default for PeerSelectionCounts {
+    fn default() -> Self {
+        PeerSelectionCounts {
+            created: Instant::now(),
+            total: AtomicU64::new(0),
+            window_start: AtomicU64::new(0),
+            window_count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl PeerSelectionCounts {
+    fn now_millis(&self) -> u64 {
+        u64::try_from(self.created.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+fn duration_millis(dur: Duration) -> u64 {
+    u64::try_from(dur.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl PeerSelectionCap {
+    pub(crate) fn new(config: Option<PeerSelectionCapConfig>) -> Self {
+        PeerSelectionCap {
+            config,
+            counts: ArcSwap::from_pointee(PeerSelectionCounts::default()),
+        }
+    }
+
+    /// the count of all selections of the peer
+    pub(super) fn total(&self) -> u64 {
+        self.counts.load().total.load(Ordering::Relaxed)
+    }
+
+    /// check if the cap of the current window has been reached
+    pub(super) fn is_capped(&self) -> bool {
+        let counts = self.counts.load();
+        self.is_capped_at(&counts, counts.now_millis())
+    }
+
+    fn is_capped_at(&self, counts: &PeerSelectionCounts, now: u64) -> bool {
+        let Some(config) = &self.config else {
+            return false;
+        };
+        let start = counts.window_start.load(Ordering::Acquire);
+        if now.saturating_sub(start) >= duration_millis(config.window) {
+            return false;
+        }
+        counts.window_count.load(Ordering::Relaxed) >= config.max_selections
+    }
+
+    pub(crate) fn on_selected(&self) {
+        let counts = self.counts.load();
+        self.on_selected_at(&counts, counts.now_millis());
+    }
+
+    fn on_selected_at(&self, counts: &PeerSelectionCounts, now: u64) {
+        counts.total.fetch_add(1, Ordering::Relaxed);
+        let Some(config) = &self.config else {
+            return;
+        };
+        let start = counts.window_start.load(Ordering::Acquire);
+        if now.saturating_sub(start) >= duration_millis(config.window)
+            && counts
+                .window_start
+                .compare_exchange(start, now, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            // only the one rolled the window resets the count
+            counts.window_count.store(1, Ordering::Relaxed);
+            return;
+        }
+        counts.window_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn inherit(&self, old: &PeerSelectionCap) {
+        self.counts.store(old.counts.load_full());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_cap() -> PeerSelectionCap {
+        PeerSelectionCap::new(Some(PeerSelectionCapConfig {
+            max_selections: 2,
+            window: Duration::from_secs(10),
+        }))
+    }
+
+    #[test]
+    fn capped() {
+        let cap = new_cap();
+        let counts = cap.counts.load();
+
+        cap.on_selected_at(&counts, 0);
+        assert!(!cap.is_capped_at(&counts, 0));
+        cap.on_selected_at(&counts, 1_000);
+        assert!(cap.is_capped_at(&counts, 1_000));
+        assert!(cap.is_capped_at(&counts, 9_999));
+
+        // the window rolls
+        assert!(!cap.is_capped_at(&counts, 10_000));
+        cap.on_selected_at(&counts, 12_000);
+        assert!(!cap.is_capped_at(&counts, 12_000));
+        cap.on_selected_at(&counts, 13_000);
+        assert!(cap.is_capped_at(&counts, 21_999));
+        assert!(!cap.is_capped_at(&counts, 22_000));
+
+        assert_eq!(cap.total(), 4);
+    }
+
+    #[test]
+    fn disabled() {
+        let cap = PeerSelectionCap::new(None);
+        for _ in 0..10 {
+            cap.on_selected();
+        }
+        assert!(!cap.is_capped());
+        assert_eq!(cap.total(), 10);
+    }
+
+    #[test]
+    fn inherit() {
+        let old = new_cap();
+        old.on_selected();
+        old.on_selected();
+        assert!(old.is_capped());
+
+        let new = new_cap();
+        new.inherit(&old);
+        assert!(new.is_capped());
+        assert_eq!(new.total(), 2);
+    }
+}
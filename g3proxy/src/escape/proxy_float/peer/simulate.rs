@@ -0,0 +1,350 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde_json::{Map, Value};
+
+use super::{ArcNextProxyPeer, PeerAgeStats, PeerRrState, PeerSet, PeerWrrState};
+use crate::config::escaper::proxy_float::PeerSelectionMode;
+
+/// The task attributes that will be used in peer selection
+#[derive(Default)]
+pub(crate) struct TaskAttrs {
+    port: Option<u16>,
+    peer_id: Option<String>,
+    max_rtt: Option<Duration>,
+    client: Option<String>,
+    count: usize,
+}
+
+impl TaskAttrs {
+    fn parse_json(value: &Value) -> anyhow::Result<Self> {
+        let Value::Object(map) = value else {
+            return Err(anyhow!("task attrs should be a json map"));
+        };
+
+        let mut attrs = TaskAttrs {
+            count: 1,
+            ..Default::default()
+        };
+        for (k, v) in map {
+            match g3_json::key::normalize(k).as_str() {
+                "port" => {
+                    let port = g3_json::value::as_u16(v)
+                        .context(format!("invalid u16 value for key {k}"))?;
+                    attrs.port = Some(port);
+                }
+                "peer_id" => {
+                    let id = g3_json::value::as_string(v)
+                        .context(format!("invalid string value for key {k}"))?;
+                    attrs.peer_id = Some(id);
+                }
+                "max_rtt" => {
+                    let rtt = g3_json::humanize::as_duration(v)
+                        .context(format!("invalid humanize duration value for key {k}"))?;
+                    attrs.max_rtt = Some(rtt);
+                }
+                "client" => {
+                    let client = g3_json::value::as_string(v)
+                        .context(format!("invalid string value for key {k}"))?;
+                    attrs.client = Some(client);
+                }
+                "count" => {
+                    attrs.count = g3_json::value::as_usize(v)
+                        .context(format!("invalid usize value for key {k}"))?;
+                }
+                _ => return Err(anyhow!("invalid key {k}")),
+            }
+        }
+        Ok(attrs)
+    }
+
+    /// parse a json array of task attrs, or a single one
+    pub(crate) fn parse_json_list(value: &Value) -> anyhow::Result<Vec<Self>> {
+        if let Value::Array(seq) = value {
+            let mut list = Vec::with_capacity(seq.len());
+            for (i, v) in seq.iter().enumerate() {
+                let attrs =
+                    TaskAttrs::parse_json(v).context(format!("invalid value for element #{i}"))?;
+                list.push(attrs);
+            }
+            Ok(list)
+        } else {
+            let attrs = TaskAttrs::parse_json(value)?;
+            Ok(vec![attrs])
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SelectionReport {
+    generation: u64,
+    hit: usize,
+    miss: usize,
+    peers: BTreeMap<String, usize>,
+    labels: BTreeMap<String, String>,
+    generations: BTreeMap<u64, PeerAgeStats>,
+    /// the real selection count of each peer, not including the simulated ones
+    selections: BTreeMap<String, u64>,
+    /// the current selection weight of each peer, only set for the success rate mode
+    weights: BTreeMap<String, f64>,
+    /// the (supported, unsupported) socks5 command bitmaps of each probed peer
+    socks5_capabilities: BTreeMap<String, (u8, u8)>,
+}
+
+impl SelectionReport {
+    fn add_hit(&mut self, peer_id: &str, peer_label: &str) {
+        self.hit += 1;
+        match self.peers.get_mut(peer_id) {
+            Some(n) => *n += 1,
+            None => {
+                self.peers.insert(peer_id.to_string(), 1);
+                self.labels
+                    .insert(peer_id.to_string(), peer_label.to_string());
+            }
+        }
+    }
+
+    fn add_miss(&mut self) {
+        self.miss += 1;
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("generation".to_string(), self.generation.into());
+        map.insert("total".to_string(), (self.hit + self.miss).into());
+        map.insert("hit".to_string(), self.hit.into());
+        map.insert("miss".to_string(), self.miss.into());
+        let peers = self
+            .peers
+            .iter()
+            .map(|(id, n)| (id.clone(), Value::from(*n)))
+            .collect::<Map<String, Value>>();
+        map.insert("peers".to_string(), Value::Object(peers));
+        let labels = self
+            .labels
+            .iter()
+            .map(|(id, label)| (id.clone(), Value::from(label.as_str())))
+            .collect::<Map<String, Value>>();
+        map.insert("labels".to_string(), Value::Object(labels));
+        let generations = self
+            .generations
+            .iter()
+            .map(|(generation, stats)| {
+                let mut m = Map::new();
+                m.insert("count".to_string(), stats.count.into());
+                m.insert("min_age".to_string(), stats.min_age.as_secs().into());
+                m.insert("max_age".to_string(), stats.max_age.as_secs().into());
+                (generation.to_string(), Value::Object(m))
+            })
+            .collect::<Map<String, Value>>();
+        map.insert("generations".to_string(), Value::Object(generations));
+        let selections = self
+            .selections
+            .iter()
+            .map(|(id, n)| (id.clone(), Value::from(*n)))
+            .collect::<Map<String, Value>>();
+        map.insert("selections".to_string(), Value::Object(selections));
+        if !self.weights.is_empty() {
+            let weights = self
+                .weights
+                .iter()
+                .map(|(id, w)| (id.clone(), Value::from(*w)))
+                .collect::<Map<String, Value>>();
+            map.insert("weights".to_string(), Value::Object(weights));
+        }
+        if !self.socks5_capabilities.is_empty() {
+            let capabilities = self
+                .socks5_capabilities
+                .iter()
+                .map(|(id, (supported, unsupported))| {
+                    let mut m = Map::new();
+                    m.insert("supported".to_string(), (*supported).into());
+                    m.insert("unsupported".to_string(), (*unsupported).into());
+                    (id.clone(), Value::Object(m))
+                })
+                .collect::<Map<String, Value>>();
+            map.insert(
+                "socks5_capabilities".to_string(),
+                Value::Object(capabilities),
+            );
+        }
+        Value::Object(map)
+    }
+}
+
+impl PeerSet {
+    /// the same as `usable_peers`, but without counting the port rejections
+    fn usable_peers_quiet(&self, port: Option<u16>) -> impl Iterator<Item = &ArcNextProxyPeer> {
+        self.unnamed
+            .iter()
+            .chain(self.named.values())
+            .filter(move |p| {
+                !p.expired_for_new()
+                    && !p.is_deprecated()
+                    && !p.is_canary()
+                    && p.circuit_breaker().is_selectable()
+                    && !p.load().is_saturated()
+                    && port.map(|port| p.port_filter().allow(port)).unwrap_or(true)
+            })
+    }
+
+    fn simulate_one(
+        &self,
+        mode: PeerSelectionMode,
+        max_age: Option<Duration>,
+        wrr: &PeerWrrState,
+        rr: &PeerRrState,
+        attrs: &TaskAttrs,
+    ) -> Option<&ArcNextProxyPeer> {
+        if let Some(id) = &attrs.peer_id {
+            // the same as the egress path selection, no fallback if not usable
+            return self
+                .named
+                .get(id)
+                .filter(|p| {
+                    !p.expired_for_new()
+                        && !p.is_deprecated()
+                        && p.circuit_breaker().is_selectable()
+                })
+                .filter(|p| {
+                    attrs
+                        .port
+                        .map(|port| p.port_filter().allow(port))
+                        .unwrap_or(true)
+                });
+        }
+
+        let mut peers: Vec<&ArcNextProxyPeer> = self.usable_peers_quiet(attrs.port).collect();
+        if let Some(max_age) = max_age {
+            if peers.iter().any(|p| p.is_fresh(max_age)) {
+                peers.retain(|p| p.is_fresh(max_age));
+            }
+        }
+        if let Some(max_rtt) = attrs.max_rtt {
+            return super::pick_peer_by_latency(peers.into_iter(), max_rtt);
+        }
+        match mode {
+            PeerSelectionMode::Random => super::pick_random_peer(peers.into_iter()),
+            PeerSelectionMode::PowerOfTwoChoices => super::pick_peer_p2c(peers.into_iter()),
+            PeerSelectionMode::SuccessRate => super::pick_peer_by_success_rate(peers.into_iter()),
+            PeerSelectionMode::WeightedRoundRobin => super::pick_peer_wrr(peers.into_iter(), wrr),
+            PeerSelectionMode::RoundRobin => {
+                rr.pick(&self.rr_order, |p| peers.iter().any(|c| Arc::ptr_eq(*c, p)))
+            }
+            PeerSelectionMode::Sticky => {
+                let key = attrs.client.as_deref().unwrap_or_default();
+                self.ring
+                    .pick(key, |p| peers.iter().any(|c| Arc::ptr_eq(*c, p)))
+            }
+        }
+    }
+
+    /// run the selection logic without connecting to the peers or changing their runtime state,
+    /// and report the hit/miss counts and the distribution of the selected peers
+    pub(crate) fn simulate_selection(
+        &self,
+        mode: PeerSelectionMode,
+        max_age: Option<Duration>,
+        requests: &[TaskAttrs],
+    ) -> SelectionReport {
+        let mut report = SelectionReport {
+            generation: self.generation,
+            generations: self.age_distribution(),
+            selections: self
+                .named
+                .iter()
+                .map(|(id, p)| (id.clone(), p.selection_cap().total()))
+                .filter(|(_, n)| *n > 0)
+                .collect(),
+            socks5_capabilities: self
+                .named
+                .iter()
+                .filter_map(|(id, p)| {
+                    let capability = p.socks5_capability().filter(|c| c.is_known())?;
+                    Some((
+                        id.clone(),
+                        (capability.supported(), capability.unsupported()),
+                    ))
+                })
+                .collect(),
+            ..Default::default()
+        };
+        if mode == PeerSelectionMode::SuccessRate {
+            report.weights = self
+                .named
+                .iter()
+                .map(|(id, p)| (id.clone(), super::selection_weight(p)))
+                .collect();
+        }
+        // continue from the current round-robin state, without changing it
+        let wrr = self.wrr.snapshot();
+        let rr = self.rr.snapshot();
+        for attrs in requests {
+            for _ in 0..attrs.count {
+                match self.simulate_one(mode, max_age, &wrr, &rr, attrs) {
+                    Some(peer) => report.add_hit(peer.id(), peer.label()),
+                    None => report.add_miss(),
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_task_attrs() {
+        let v = serde_json::json!([
+            {"port": 443, "count": 10},
+            {"peer_id": "p1", "max_rtt": "100ms"},
+        ]);
+        let list = TaskAttrs::parse_json_list(&v).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].port, Some(443));
+        assert_eq!(list[0].count, 10);
+        assert_eq!(list[1].peer_id.as_deref(), Some("p1"));
+        assert_eq!(list[1].max_rtt, Some(Duration::from_millis(100)));
+        assert_eq!(list[1].count, 1);
+
+        let v = serde_json::json!({"area": "cn"});
+        assert!(TaskAttrs::parse_json_list(&v).is_err());
+    }
+
+    #[test]
+    fn empty_peer_set() {
+        let peer_set = PeerSet::default();
+        let list = vec![TaskAttrs {
+            count: 3,
+            ..Default::default()
+        }];
+        let report = peer_set.simulate_selection(PeerSelectionMode::Random, None, &list);
+        let v = report.to_json();
+        assert_eq!(v["generation"], 0);
+        assert_eq!(v["total"], 3);
+        assert_eq!(v["hit"], 0);
+        assert_eq!(v["miss"], 3);
+        assert!(v["labels"].as_object().unwrap().is_empty());
+        assert!(v["generations"].as_object().unwrap().is_empty());
+    }
+}
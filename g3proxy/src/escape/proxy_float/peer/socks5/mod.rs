@@ -17,6 +17,7 @@
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ahash::AHashMap;
 use anyhow::{anyhow, Context};
@@ -29,12 +30,14 @@ use tokio::time::Instant;
 use g3_daemon::stat::remote::ArcTcpConnectionTaskRemoteStats;
 use g3_types::auth::{Password, Username};
 use g3_types::net::{
-    EgressArea, EgressInfo, Host, OpensslClientConfig, SocksAuth, TcpSockSpeedLimitConfig,
-    UdpSockSpeedLimitConfig,
+    EgressArea, EgressInfo, Host, OpensslClientConfig, PortRange, SocksAuth,
+    TcpSockSpeedLimitConfig, UdpSockSpeedLimitConfig,
 };
 
 use super::{
-    ArcNextProxyPeer, NextProxyPeer, NextProxyPeerInternal, ProxyFloatEscaperConfig,
+    ArcNextProxyPeer, NextProxyPeer, NextProxyPeerInternal, PeerCircuitBreaker, PeerDeprecation,
+    PeerIpVersion, PeerLatency, PeerLoad, PeerPortFilter, PeerResetPolicy, PeerSelectionCap,
+    PeerSocks5Capability, PeerSuccessRate, PeerTags, PeerUdpAssociations, ProxyFloatEscaperConfig,
     ProxyFloatEscaperStats,
 };
 use crate::module::http_forward::{ArcHttpForwardTaskRemoteStats, BoxHttpForwardConnection};
@@ -58,7 +61,14 @@ struct ProxyFloatSocks5PeerSharedConfig {
     tcp_sock_speed_limit: TcpSockSpeedLimitConfig,
     expire_datetime: Option<DateTime<Utc>>,
     expire_instant: Option<Instant>,
+    expire_grace: Option<Duration>,
     auth_info: SocksAuth,
+    source_port_range: Option<PortRange>,
+    tcp_connect_timeout: Option<Duration>,
+    alt_addr: Option<SocketAddr>,
+    ip_version: PeerIpVersion,
+    feed_generation: u64,
+    refreshed_instant: Option<Instant>,
 }
 
 impl Default for ProxyFloatSocks5PeerSharedConfig {
@@ -67,7 +77,14 @@ impl Default for ProxyFloatSocks5PeerSharedConfig {
             tcp_sock_speed_limit: Default::default(),
             expire_datetime: None,
             expire_instant: None,
+            expire_grace: None,
             auth_info: SocksAuth::None,
+            source_port_range: None,
+            tcp_connect_timeout: None,
+            alt_addr: None,
+            ip_version: PeerIpVersion::default(),
+            feed_generation: 0,
+            refreshed_instant: None,
         }
     }
 }
@@ -83,12 +100,26 @@ pub(super) struct ProxyFloatSocks5Peer {
     escaper_stats: Arc<ProxyFloatEscaperStats>,
     escape_logger: Logger,
     addr: SocketAddr,
+    id: String,
+    label: String,
     username: Username,
     password: Password,
     egress_info: EgressInfo,
     shared_config: Arc<ProxyFloatSocks5PeerSharedConfig>,
+    port_filter: PeerPortFilter,
+    circuit_breaker: Arc<PeerCircuitBreaker>,
+    selection_cap: PeerSelectionCap,
+    reset_policy: PeerResetPolicy,
+    latency: PeerLatency,
+    success_rate: PeerSuccessRate,
+    load: PeerLoad,
+    deprecation: PeerDeprecation,
+    canary: bool,
+    tags: PeerTags,
     transmute_udp_peer_ip: Option<AHashMap<IpAddr, IpAddr>>,
     udp_sock_speed_limit: UdpSockSpeedLimitConfig,
+    udp_associations: PeerUdpAssociations,
+    capability: PeerSocks5Capability,
 }
 
 impl ProxyFloatSocks5Peer {
@@ -98,17 +129,33 @@ impl ProxyFloatSocks5Peer {
         escape_logger: Logger,
         addr: SocketAddr,
     ) -> ArcNextProxyPeer {
+        let circuit_breaker_config = escaper_config.peer_circuit_breaker;
+        let selection_cap_config = escaper_config.peer_selection_cap;
         Arc::new(ProxyFloatSocks5Peer {
             escaper_config,
             escaper_stats,
             escape_logger,
             addr,
+            id: String::new(),
+            label: String::new(),
             username: Username::empty(),
             password: Password::empty(),
             egress_info: Default::default(),
             shared_config: Arc::new(Default::default()),
+            port_filter: PeerPortFilter::default(),
+            circuit_breaker: Arc::new(PeerCircuitBreaker::new(circuit_breaker_config)),
+            selection_cap: PeerSelectionCap::new(selection_cap_config),
+            reset_policy: PeerResetPolicy::default(),
+            latency: PeerLatency::default(),
+            success_rate: PeerSuccessRate::default(),
+            load: PeerLoad::default(),
+            deprecation: PeerDeprecation::default(),
+            canary: false,
+            tags: PeerTags::default(),
             transmute_udp_peer_ip: None,
             udp_sock_speed_limit: Default::default(),
+            udp_associations: PeerUdpAssociations::default(),
+            capability: PeerSocks5Capability::default(),
         })
     }
 
@@ -129,6 +176,15 @@ impl ProxyFloatSocks5Peer {
 }
 
 impl NextProxyPeerInternal for ProxyFloatSocks5Peer {
+    fn set_id(&mut self, id: String) {
+        self.egress_info.id = Some(id.clone());
+        self.id = id;
+    }
+
+    fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+
     fn set_isp(&mut self, isp: String) {
         self.egress_info.isp = Some(isp);
     }
@@ -152,6 +208,60 @@ impl NextProxyPeerInternal for ProxyFloatSocks5Peer {
         shared_config.tcp_sock_speed_limit = speed_limit;
     }
 
+    fn set_udp_sock_speed_limit(&mut self, speed_limit: UdpSockSpeedLimitConfig) {
+        self.udp_sock_speed_limit = speed_limit;
+    }
+
+    fn set_port_filter(&mut self, filter: PeerPortFilter) {
+        self.port_filter = filter;
+    }
+
+    fn set_reset_policy(&mut self, policy: PeerResetPolicy) {
+        self.reset_policy = policy;
+    }
+
+    fn set_source_port_range(&mut self, port_range: PortRange) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.source_port_range = Some(port_range);
+    }
+
+    fn set_tcp_connect_timeout(&mut self, timeout: Duration) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.tcp_connect_timeout = Some(timeout);
+    }
+
+    fn set_alt_addr(&mut self, addr: SocketAddr) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.alt_addr = Some(addr);
+    }
+
+    fn set_ip_version(&mut self, ip_version: PeerIpVersion) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.ip_version = ip_version;
+    }
+
+    fn set_feed_generation(&mut self, generation: u64, refreshed: Instant) {
+        let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.feed_generation = generation;
+        shared_config.refreshed_instant = Some(refreshed);
+    }
+
+    fn set_weight(&mut self, weight: f64) {
+        self.load.set_weight(weight);
+    }
+
+    fn set_max_connections(&mut self, max: usize) {
+        self.load.set_max_connections(max);
+    }
+
+    fn set_deprecated(&mut self, deprecated: bool) {
+        self.deprecation.set_deprecated(deprecated);
+    }
+
+    fn set_canary(&mut self, canary: bool) {
+        self.canary = canary;
+    }
+
     fn set_kv(&mut self, k: &str, v: &Value) -> anyhow::Result<()> {
         match k {
             "username" => {
@@ -184,31 +294,135 @@ impl NextProxyPeerInternal for ProxyFloatSocks5Peer {
                 }
                 Ok(())
             }
-            "udp_sock_speed_limit" => {
-                self.udp_sock_speed_limit = g3_json::value::as_udp_sock_speed_limit(v)?;
+            "max_udp_associations" => {
+                let max = g3_json::value::as_usize(v)?;
+                if max == 0 {
+                    return Err(anyhow!("max udp associations should not be 0"));
+                }
+                self.udp_associations.set_max(max);
+                Ok(())
+            }
+            _ => {
+                self.tags.add(k, v);
                 Ok(())
             }
-            _ => Ok(()),
         }
     }
 
     fn finalize(&mut self) -> anyhow::Result<()> {
         let shared_config = Arc::make_mut(&mut self.shared_config);
+        shared_config.expire_grace = self.escaper_config.expire_grace_duration;
         if !self.username.is_empty() {
             shared_config.set_user(&self.username, &self.password);
         }
         Ok(())
     }
 
+    #[inline]
+    fn peer_type(&self) -> &'static str {
+        super::PEER_TYPE_SOCKS5
+    }
+
+    #[inline]
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    #[inline]
+    fn egress_info(&self) -> &EgressInfo {
+        &self.egress_info
+    }
+
     #[inline]
     fn expire_instant(&self) -> Option<Instant> {
         self.shared_config.expire_instant
     }
 
+    #[inline]
+    fn expire_grace(&self) -> Option<Duration> {
+        self.shared_config.expire_grace
+    }
+
+    #[inline]
+    fn feed_generation(&self) -> u64 {
+        self.shared_config.feed_generation
+    }
+
+    #[inline]
+    fn refreshed_instant(&self) -> Option<Instant> {
+        self.shared_config.refreshed_instant
+    }
+
     #[inline]
     fn escaper_stats(&self) -> &Arc<ProxyFloatEscaperStats> {
         &self.escaper_stats
     }
+
+    #[inline]
+    fn port_filter(&self) -> &PeerPortFilter {
+        &self.port_filter
+    }
+
+    #[inline]
+    fn circuit_breaker(&self) -> &PeerCircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    #[inline]
+    fn selection_cap(&self) -> &PeerSelectionCap {
+        &self.selection_cap
+    }
+
+    #[inline]
+    fn reset_policy(&self) -> &PeerResetPolicy {
+        &self.reset_policy
+    }
+
+    #[inline]
+    fn latency(&self) -> &PeerLatency {
+        &self.latency
+    }
+
+    fn success_rate(&self) -> &PeerSuccessRate {
+        &self.success_rate
+    }
+
+    fn load(&self) -> &PeerLoad {
+        &self.load
+    }
+
+    fn deprecation(&self) -> &PeerDeprecation {
+        &self.deprecation
+    }
+
+    fn is_canary(&self) -> bool {
+        self.canary
+    }
+
+    #[inline]
+    fn tags(&self) -> &PeerTags {
+        &self.tags
+    }
+
+    #[inline]
+    fn tags_mut(&mut self) -> &mut PeerTags {
+        &mut self.tags
+    }
+
+    #[inline]
+    fn udp_associations(&self) -> Option<&PeerUdpAssociations> {
+        Some(&self.udp_associations)
+    }
+
+    #[inline]
+    fn socks5_capability(&self) -> Option<&PeerSocks5Capability> {
+        Some(&self.capability)
+    }
 }
 
 #[async_trait]
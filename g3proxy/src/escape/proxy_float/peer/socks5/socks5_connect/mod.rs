@@ -19,6 +19,7 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 
 use anyhow::anyhow;
+use log::info;
 use tokio::io::AsyncReadExt;
 use tokio::net::{tcp, UdpSocket};
 use tokio::sync::oneshot;
@@ -28,10 +29,11 @@ use g3_daemon::stat::remote::{
 };
 use g3_io_ext::{AggregatedIo, LimitedReader, LimitedWriter};
 use g3_openssl::{SslConnector, SslStream};
-use g3_socks::v5;
+use g3_socks::{v5, SocksCommand, SocksConnectError};
 use g3_types::net::{Host, OpensslClientConfig, SocketBufferConfig};
 
 use super::{NextProxyPeerInternal, ProxyFloatSocks5Peer};
+use crate::escape::proxy_float::peer::{PeerResetReader, PeerUdpAssociationGuard};
 use crate::log::escape::tls_handshake::{EscapeLogForTlsHandshake, TlsApplication};
 use crate::module::tcp_connect::{
     TcpConnectError, TcpConnectRemoteWrapperStats, TcpConnectResult, TcpConnectTaskNotes,
@@ -39,13 +41,29 @@ use crate::module::tcp_connect::{
 use crate::serve::ServerTaskNotes;
 
 impl ProxyFloatSocks5Peer {
+    /// learn the capability from the reply, other errors tell nothing about the command
+    fn record_capability<T>(&self, command: SocksCommand, r: &Result<T, SocksConnectError>) {
+        let supported = match r {
+            Ok(_) => true,
+            Err(SocksConnectError::CommandNotSupported) => false,
+            Err(_) => return,
+        };
+        if self.capability.record(command, supported) {
+            info!(
+                "escaper {}: peer {} ({}) does not support socks5 command {command}, \
+                it will be skipped for such tasks",
+                self.escaper_config.name, self.id, self.label
+            );
+        }
+    }
+
     pub(super) async fn socks5_connect_tcp_connect_to<'a>(
         &'a self,
         tcp_notes: &'a mut TcpConnectTaskNotes,
         task_notes: &'a ServerTaskNotes,
     ) -> Result<
         (
-            LimitedReader<tcp::OwnedReadHalf>,
+            LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>,
             LimitedWriter<tcp::OwnedWriteHalf>,
         ),
         TcpConnectError,
@@ -57,7 +75,9 @@ impl ProxyFloatSocks5Peer {
             &self.shared_config.auth_info,
             &tcp_notes.upstream,
         )
-        .await?;
+        .await;
+        self.record_capability(SocksCommand::TcpConnect, &outgoing_addr);
+        let outgoing_addr = outgoing_addr?;
         // no need to replace the ip with registered public address.
         // prefer to use the one returned directly by remote proxy
         tcp_notes.chained.outgoing_addr = Some(outgoing_addr);
@@ -72,7 +92,7 @@ impl ProxyFloatSocks5Peer {
         task_notes: &'a ServerTaskNotes,
     ) -> Result<
         (
-            LimitedReader<tcp::OwnedReadHalf>,
+            LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>,
             LimitedWriter<tcp::OwnedWriteHalf>,
         ),
         TcpConnectError,
@@ -85,10 +105,12 @@ impl ProxyFloatSocks5Peer {
         .map_err(|_| TcpConnectError::NegotiationPeerTimeout)?
     }
 
-    /// setup udp associate with remote proxy
+    /// setup udp associate with remote proxy, the association will be counted until the tcp
+    /// connection is closed
     /// return (socket, listen_addr, peer_addr)
     pub(super) async fn socks5_udp_associate(
         &self,
+        udp_assoc: PeerUdpAssociationGuard,
         buf_conf: SocketBufferConfig,
         tcp_notes: &mut TcpConnectTaskNotes,
         task_notes: &ServerTaskNotes,
@@ -125,8 +147,9 @@ impl ProxyFloatSocks5Peer {
             &self.shared_config.auth_info,
             send_udp_addr,
         )
-        .await
-        .map_err(io::Error::other)?;
+        .await;
+        self.record_capability(SocksCommand::UdpAssociate, &peer_udp_addr);
+        let peer_udp_addr = peer_udp_addr.map_err(io::Error::other)?;
         let peer_udp_addr = self.transmute_udp_peer_addr(peer_udp_addr, peer_tcp_addr.ip());
         let socket = g3_socket::udp::new_std_socket_to(
             peer_udp_addr,
@@ -138,12 +161,13 @@ impl ProxyFloatSocks5Peer {
         socket.connect(peer_udp_addr).await?;
         let listen_addr = socket.local_addr()?;
 
-        let r = r.into_inner();
+        let r = r.into_inner().into_inner();
         let w = w.into_inner();
         let stream = r.reunite(w).map_err(io::Error::other)?;
 
         let (mut tcp_close_sender, tcp_close_receiver) = oneshot::channel::<Option<io::Error>>();
         tokio::spawn(async move {
+            let _udp_assoc = udp_assoc;
             let mut tcp_stream = stream;
             let mut buf = [0u8; 4];
 
@@ -167,6 +191,7 @@ impl ProxyFloatSocks5Peer {
 
     pub(super) async fn timed_socks5_udp_associate(
         &self,
+        udp_assoc: PeerUdpAssociationGuard,
         buf_conf: SocketBufferConfig,
         tcp_notes: &mut TcpConnectTaskNotes,
         task_notes: &ServerTaskNotes,
@@ -181,7 +206,7 @@ impl ProxyFloatSocks5Peer {
     > {
         tokio::time::timeout(
             self.escaper_config.peer_negotiation_timeout,
-            self.socks5_udp_associate(buf_conf, tcp_notes, task_notes),
+            self.socks5_udp_associate(udp_assoc, buf_conf, tcp_notes, task_notes),
         )
         .await
         .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "peer negotiation timeout"))?
@@ -216,7 +241,10 @@ impl ProxyFloatSocks5Peer {
         tls_application: TlsApplication,
     ) -> Result<
         SslStream<
-            AggregatedIo<LimitedReader<tcp::OwnedReadHalf>, LimitedWriter<tcp::OwnedWriteHalf>>,
+            AggregatedIo<
+                LimitedReader<PeerResetReader<tcp::OwnedReadHalf>>,
+                LimitedWriter<tcp::OwnedWriteHalf>,
+            >,
         >,
         TcpConnectError,
     > {
@@ -16,6 +16,8 @@
 
 use std::sync::Arc;
 
+use anyhow::anyhow;
+
 use g3_io_ext::{LimitedUdpRecv, LimitedUdpSend};
 
 use super::{NextProxyPeerInternal, ProxyFloatSocks5Peer};
@@ -41,9 +43,16 @@ impl ProxyFloatSocks5Peer {
             .as_ref()
             .ok_or(UdpConnectError::NoUpstreamSupplied)?;
 
+        let udp_assoc = self.udp_associations.acquire().ok_or_else(|| {
+            UdpConnectError::EscaperNotUsable(anyhow!(
+                "peer {} reached the max udp associations limit",
+                self.id
+            ))
+        })?;
+
         let mut tcp_notes = TcpConnectTaskNotes::empty();
         let (tcp_close_receiver, udp_socket, udp_local_addr, udp_peer_addr) = self
-            .timed_socks5_udp_associate(udp_notes.buf_conf, &mut tcp_notes, task_notes)
+            .timed_socks5_udp_associate(udp_assoc, udp_notes.buf_conf, &mut tcp_notes, task_notes)
             .await
             .map_err(UdpConnectError::SetupSocketFailed)?;
 
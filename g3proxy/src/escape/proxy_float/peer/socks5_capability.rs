@@ -0,0 +1,92 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use g3_socks::SocksCommand;
+
+/// The socks5 commands known to be supported or not by the peer, learned from the negotiations.
+///
+/// The bit for each command is `1 << (code - 1)`, so CONNECT is 1, BIND is 2 and UDP ASSOCIATE is 4.
+/// It's not inherited on peer set update, so the refreshed peer records will be probed again.
+#[derive(Default)]
+pub(crate) struct PeerSocks5Capability {
+    supported: AtomicU8,
+    unsupported: AtomicU8,
+}
+
+impl PeerSocks5Capability {
+    fn bit(command: SocksCommand) -> u8 {
+        1 << (command.code() - 1)
+    }
+
+    /// record the negotiation result of `command`, return true if it's newly found unsupported
+    pub(crate) fn record(&self, command: SocksCommand, supported: bool) -> bool {
+        let bit = Self::bit(command);
+        if supported {
+            self.supported.fetch_or(bit, Ordering::Relaxed);
+            self.unsupported.fetch_and(!bit, Ordering::Relaxed);
+            false
+        } else {
+            self.supported.fetch_and(!bit, Ordering::Relaxed);
+            self.unsupported.fetch_or(bit, Ordering::Relaxed) & bit == 0
+        }
+    }
+
+    /// check if `command` may be supported, which is true unless it has been rejected by the peer
+    pub(crate) fn is_capable(&self, command: SocksCommand) -> bool {
+        self.unsupported.load(Ordering::Relaxed) & Self::bit(command) == 0
+    }
+
+    pub(crate) fn supported(&self) -> u8 {
+        self.supported.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn unsupported(&self) -> u8 {
+        self.unsupported.load(Ordering::Relaxed)
+    }
+
+    /// check if any command has been probed
+    pub(crate) fn is_known(&self) -> bool {
+        self.supported() | self.unsupported() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let cap = PeerSocks5Capability::default();
+        assert!(!cap.is_known());
+        assert!(cap.is_capable(SocksCommand::UdpAssociate));
+
+        assert!(!cap.record(SocksCommand::TcpConnect, true));
+        assert!(cap.record(SocksCommand::UdpAssociate, false));
+        assert!(!cap.record(SocksCommand::UdpAssociate, false));
+        assert_eq!(cap.supported(), 1);
+        assert_eq!(cap.unsupported(), 4);
+        assert!(cap.is_capable(SocksCommand::TcpConnect));
+        assert!(cap.is_capable(SocksCommand::TcpBind));
+        assert!(!cap.is_capable(SocksCommand::UdpAssociate));
+
+        assert!(!cap.record(SocksCommand::UdpAssociate, true));
+        assert_eq!(cap.supported(), 5);
+        assert_eq!(cap.unsupported(), 0);
+        assert!(cap.is_capable(SocksCommand::UdpAssociate));
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::hash::Hasher;
+
+use fnv::FnvHasher;
+
+/// A consistent hash ring with one point for each item, keyed by a stable hash of the item name.
+///
+/// Adding or removing an item only remaps the keys that are mapped to that item,
+/// and the keys of an unusable item will be moved to the next usable one in ring order.
+pub(crate) struct PeerHashRing<T> {
+    points: Vec<(u64, T)>,
+}
+
+impl<T> Default for PeerHashRing<T> {
+    fn default() -> Self {
+        PeerHashRing { points: Vec::new() }
+    }
+}
+
+/// FNV-1a, which is stable across processes, unlike the hashers with random state
+fn stable_hash(s: &str) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(s.as_bytes());
+    hasher.finish()
+}
+
+impl<T> PeerHashRing<T> {
+    pub(super) fn new<I, N>(items: I, name: N) -> Self
+    where
+        I: Iterator<Item = T>,
+        N: Fn(&T) -> &str,
+    {
+        let mut points = items
+            .map(|item| (stable_hash(name(&item)), item))
+            .collect::<Vec<_>>();
+        // sort by the name on hash collision, so the order is the same for the same items
+        points.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| name(&a.1).cmp(name(&b.1))));
+        PeerHashRing { points }
+    }
+
+    /// pick the first usable item at or after the position of `key` on the ring
+    pub(super) fn pick<F>(&self, key: &str, usable: F) -> Option<&T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let hash = stable_hash(key);
+        let start = self.points.partition_point(|(h, _)| *h < hash);
+        self.points[start..]
+            .iter()
+            .chain(self.points[..start].iter())
+            .map(|(_, item)| item)
+            .find(|item| usable(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sticky() {
+        let names = ["a", "b", "c", "d", "e"];
+        let ring = PeerHashRing::new(names.iter().copied(), |s| s);
+        let keys = (0..100).map(|i| format!("10.0.0.{i}")).collect::<Vec<_>>();
+        let picked = keys
+            .iter()
+            .map(|k| *ring.pick(k, |_| true).unwrap())
+            .collect::<Vec<_>>();
+        for (k, p) in keys.iter().zip(picked.iter()) {
+            assert_eq!(ring.pick(k, |_| true), Some(p));
+        }
+
+        // only the keys of the removed one are remapped
+        let ring = PeerHashRing::new(names.iter().copied().filter(|s| *s != "c"), |s| s);
+        for (k, p) in keys.iter().zip(picked.iter()) {
+            let new = *ring.pick(k, |_| true).unwrap();
+            if *p != "c" {
+                assert_eq!(new, *p);
+            }
+        }
+
+        // the same if the one is unusable
+        let ring = PeerHashRing::new(names.iter().copied(), |s| s);
+        for (k, p) in keys.iter().zip(picked.iter()) {
+            let new = *ring.pick(k, |s| *s != "c").unwrap();
+            assert_ne!(new, "c");
+            if *p != "c" {
+                assert_eq!(new, *p);
+            }
+        }
+
+        assert!(ring.pick("10.0.0.1", |_| false).is_none());
+        assert!(PeerHashRing::<&str>::default().pick("", |_| true).is_none());
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const RATIO_SCALE: u32 = 1 << 20;
+/// the min ratio used as the selection weight, so failing peers still get some traffic to recover
+const RATIO_FLOOR: f64 = 0.05;
+
+/// The smoothed tcp connect success ratio of the peer.
+pub(crate) struct PeerSuccessRate {
+    /// in units of 1/RATIO_SCALE, new peers are considered as fully successful
+    ratio: AtomicU32,
+}
+
+impl Default for PeerSuccessRate {
+    fn default() -> Self {
+        PeerSuccessRate {
+            ratio: AtomicU32::new(RATIO_SCALE),
+        }
+    }
+}
+
+impl PeerSuccessRate {
+    pub(super) fn record(&self, success: bool) {
+        let _ = self
+            .ratio
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+                // 15/16 of the old value and 1/16 of the new one
+                let new = old - old / 16;
+                if success {
+                    Some(new + RATIO_SCALE / 16)
+                } else {
+                    Some(new)
+                }
+            });
+    }
+
+    pub(super) fn ratio(&self) -> f64 {
+        self.ratio.load(Ordering::Relaxed) as f64 / RATIO_SCALE as f64
+    }
+
+    /// the ratio to be used as the selection weight, which is not less than the floor value
+    pub(super) fn weight(&self) -> f64 {
+        self.ratio().max(RATIO_FLOOR)
+    }
+
+    pub(super) fn inherit(&self, old: &PeerSuccessRate) {
+        self.ratio
+            .store(old.ratio.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothed() {
+        let rate = PeerSuccessRate::default();
+        assert_eq!(rate.ratio(), 1.0);
+
+        rate.record(true);
+        assert_eq!(rate.ratio(), 1.0);
+
+        rate.record(false);
+        assert_eq!(rate.ratio(), 0.9375);
+
+        for _ in 0..100 {
+            rate.record(false);
+        }
+        assert!(rate.ratio() < 0.01);
+        assert_eq!(rate.weight(), RATIO_FLOOR);
+
+        rate.record(true);
+        assert!(rate.ratio() > 0.0625);
+
+        let new_rate = PeerSuccessRate::default();
+        new_rate.inherit(&rate);
+        assert_eq!(new_rate.ratio(), rate.ratio());
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use g3_io_ext::{ArcLimitedReaderStats, ArcLimitedWriterStats};
+use g3_types::metrics::{MetricsTagName, MetricsTagValue, StaticMetricsTags};
+use g3_types::net::EgressArea;
+
+use crate::config::escaper::proxy_float::PeerTagRule;
+use crate::escape::proxy_float::{ProxyFloatEscaperStats, ProxyFloatPeerTcpIoStats};
+use crate::escape::EscaperTaggedTcpIoStats;
+
+/// Custom tags set by the unknown keys in the peer record.
+#[derive(Default)]
+pub(crate) struct PeerTags {
+    tags: BTreeMap<String, String>,
+    tagged_io_stats: Option<Arc<EscaperTaggedTcpIoStats>>,
+    area_io_stats: Option<Arc<EscaperTaggedTcpIoStats>>,
+    tcp_io_stats: Option<Arc<ProxyFloatPeerTcpIoStats>>,
+}
+
+impl PeerTags {
+    /// only scalar values will be used, others will be ignored
+    pub(super) fn add(&mut self, k: &str, v: &Value) {
+        let value = match v {
+            Value::String(s) => s.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            _ => return,
+        };
+        self.tags.insert(k.to_string(), value);
+    }
+
+    pub(crate) fn permitted_by(&self, rule: &PeerTagRule) -> bool {
+        rule.permits(|k| self.tags.get(k).map(|v| v.as_str()))
+    }
+
+    /// promote the tags with keys in `keys` to metrics tags,
+    /// tags with values that are not valid metrics tag values will be skipped
+    pub(super) fn promote(
+        &mut self,
+        keys: &[MetricsTagName],
+        escaper_stats: &Arc<ProxyFloatEscaperStats>,
+    ) {
+        let mut metrics_tags = StaticMetricsTags::new();
+        for name in keys {
+            let Some(value) = self.tags.get(name.as_str()) else {
+                continue;
+            };
+            if let Ok(value) = MetricsTagValue::from_str(value) {
+                metrics_tags.insert(name.clone(), value);
+            }
+        }
+        if metrics_tags.is_empty() {
+            return;
+        }
+
+        let tagged = escaper_stats.fetch_tagged_tcp_io_stats(metrics_tags);
+        self.tagged_io_stats = Some(tagged);
+        self.update_tcp_io_stats(escaper_stats);
+    }
+
+    /// aggregate the tcp io stats to the egress area of the peer
+    pub(super) fn bind_area(
+        &mut self,
+        area: Option<&EgressArea>,
+        escaper_stats: &Arc<ProxyFloatEscaperStats>,
+    ) {
+        self.area_io_stats = Some(escaper_stats.fetch_area_tcp_io_stats(area));
+        self.update_tcp_io_stats(escaper_stats);
+    }
+
+    fn update_tcp_io_stats(&mut self, escaper_stats: &Arc<ProxyFloatEscaperStats>) {
+        self.tcp_io_stats = Some(Arc::new(ProxyFloatPeerTcpIoStats::new(
+            Arc::clone(escaper_stats),
+            self.tagged_io_stats.clone(),
+            self.area_io_stats.clone(),
+        )));
+    }
+
+    pub(super) fn tcp_io_stats(
+        &self,
+        escaper_stats: &Arc<ProxyFloatEscaperStats>,
+    ) -> (ArcLimitedReaderStats, ArcLimitedWriterStats) {
+        if let Some(stats) = &self.tcp_io_stats {
+            (stats.clone(), stats.clone())
+        } else {
+            (escaper_stats.clone(), escaper_stats.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_types::metrics::MetricsName;
+
+    use crate::escape::EscaperStats;
+
+    #[test]
+    fn promote() {
+        let escaper_stats = Arc::new(ProxyFloatEscaperStats::new(
+            &MetricsName::from_str("test").unwrap(),
+        ));
+        let keys = vec![
+            MetricsTagName::from_str("dc").unwrap(),
+            MetricsTagName::from_str("tier").unwrap(),
+        ];
+
+        let mut tags = PeerTags::default();
+        tags.add("dc", &Value::String("dc-1".to_string()));
+        tags.add("contract", &Value::String("c-1".to_string()));
+        tags.add("tier", &Value::from(2));
+        tags.add("nested", &serde_json::json!({"a": 1}));
+        assert_eq!(tags.tags.get("tier").unwrap(), "2");
+        assert!(!tags.tags.contains_key("nested"));
+        tags.promote(&keys, &escaper_stats);
+        assert!(tags.tcp_io_stats.is_some());
+
+        let mut tags2 = PeerTags::default();
+        tags2.add("tier", &Value::from(2));
+        tags2.add("dc", &Value::String("dc-1".to_string()));
+        tags2.promote(&keys, &escaper_stats);
+
+        let mut tags3 = PeerTags::default();
+        tags3.add("contract", &Value::String("c-1".to_string()));
+        tags3.promote(&keys, &escaper_stats);
+        assert!(tags3.tcp_io_stats.is_none());
+
+        let all = escaper_stats.tagged_tcp_io_stats();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].tags().len(), 2);
+    }
+
+    #[test]
+    fn permitted_by() {
+        let doc = yaml_rust::YamlLoader::load_from_str(
+            r#"
+              allow:
+                region: [eu, us]
+              deny:
+                provider: p1
+            "#,
+        )
+        .unwrap();
+        let rule = PeerTagRule::parse(&doc[0]).unwrap();
+
+        let mut tags = PeerTags::default();
+        assert!(!tags.permitted_by(&rule));
+        tags.add("region", &Value::String("eu".to_string()));
+        assert!(tags.permitted_by(&rule));
+        tags.add("provider", &Value::String("p2".to_string()));
+        assert!(tags.permitted_by(&rule));
+        tags.add("provider", &Value::String("p1".to_string()));
+        assert!(!tags.permitted_by(&rule));
+
+        let mut tags = PeerTags::default();
+        tags.add("region", &Value::String("ap".to_string()));
+        assert!(!tags.permitted_by(&rule));
+    }
+
+    #[test]
+    fn bind_area() {
+        let escaper_stats = Arc::new(ProxyFloatEscaperStats::new(
+            &MetricsName::from_str("test").unwrap(),
+        ));
+        let us = EgressArea::from_str("us/ca").unwrap();
+
+        let mut tags1 = PeerTags::default();
+        tags1.bind_area(Some(&us), &escaper_stats);
+        assert!(tags1.tcp_io_stats.is_some());
+        let mut tags2 = PeerTags::default();
+        tags2.bind_area(Some(&us), &escaper_stats);
+        let mut tags3 = PeerTags::default();
+        tags3.bind_area(None, &escaper_stats);
+
+        let (r_stats, _) = tags1.tcp_io_stats(&escaper_stats);
+        r_stats.add_read_bytes(10);
+        let (r_stats, _) = tags2.tcp_io_stats(&escaper_stats);
+        r_stats.add_read_bytes(20);
+
+        let all = escaper_stats.area_tcp_io_stats();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].io.snapshot().in_bytes, 30);
+        let unknown = MetricsTagValue::from_str("__unknown__").unwrap();
+        assert_eq!(all[1].tags().values().next(), Some(&unknown));
+        assert_eq!(escaper_stats.tcp_io_snapshot().unwrap().in_bytes, 30);
+    }
+}
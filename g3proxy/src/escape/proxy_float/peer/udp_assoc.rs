@@ -0,0 +1,119 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+/// The count of alive udp associations of the peer, with an optional limit.
+#[derive(Default)]
+pub(crate) struct PeerUdpAssociations {
+    max: Option<usize>,
+    /// shared with the alive associations, and with the new peer after reload
+    alive: ArcSwap<AtomicUsize>,
+    rejected: AtomicU64,
+}
+
+impl PeerUdpAssociations {
+    pub(super) fn set_max(&mut self, max: usize) {
+        self.max = Some(max);
+    }
+
+    pub(super) fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    pub(super) fn alive(&self) -> usize {
+        self.alive.load().load(Ordering::Relaxed)
+    }
+
+    pub(super) fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// count a new association, which will be released when the returned guard is dropped.
+    /// None will be returned, and the rejection will be counted, if the limit is reached.
+    pub(super) fn acquire(&self) -> Option<PeerUdpAssociationGuard> {
+        let alive = self.alive.load_full();
+        if let Some(max) = self.max {
+            if alive
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    (n < max).then_some(n + 1)
+                })
+                .is_err()
+            {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        } else {
+            alive.fetch_add(1, Ordering::AcqRel);
+        }
+        Some(PeerUdpAssociationGuard { alive })
+    }
+
+    pub(super) fn inherit(&self, old: &PeerUdpAssociations) {
+        self.alive.store(old.alive.load_full());
+    }
+}
+
+pub(crate) struct PeerUdpAssociationGuard {
+    alive: Arc<AtomicUsize>,
+}
+
+impl Drop for PeerUdpAssociationGuard {
+    fn drop(&mut self) {
+        self.alive.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit() {
+        let mut assoc = PeerUdpAssociations::default();
+        assoc.set_max(2);
+
+        let g1 = assoc.acquire().unwrap();
+        let _g2 = assoc.acquire().unwrap();
+        assert!(assoc.acquire().is_none());
+        assert_eq!(assoc.alive(), 2);
+        assert_eq!(assoc.rejected(), 1);
+
+        drop(g1);
+        assert_eq!(assoc.alive(), 1);
+        let _g3 = assoc.acquire().unwrap();
+        assert!(assoc.acquire().is_none());
+        assert_eq!(assoc.rejected(), 2);
+    }
+
+    #[test]
+    fn inherit() {
+        let assoc = PeerUdpAssociations::default();
+        let g1 = assoc.acquire().unwrap();
+
+        let mut new_assoc = PeerUdpAssociations::default();
+        new_assoc.set_max(1);
+        new_assoc.inherit(&assoc);
+        assert!(new_assoc.acquire().is_none());
+
+        drop(g1);
+        assert_eq!(new_assoc.alive(), 0);
+        assert!(new_assoc.acquire().is_some());
+    }
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Mutex;
+
+use ahash::AHashMap;
+
+/// The current weights of the smooth weighted round-robin selection, which is the same as nginx.
+///
+/// The state is bound to the peer set, so all the current weights will be reset on peer set
+/// change, and the cycle will start over with the new peers.
+#[derive(Default)]
+pub(crate) struct PeerWrrState {
+    current: Mutex<AHashMap<usize, f64>>,
+}
+
+impl PeerWrrState {
+    /// a copy of the current state, which can be used without affecting this one
+    pub(super) fn snapshot(&self) -> Self {
+        PeerWrrState {
+            current: Mutex::new(self.current.lock().unwrap().clone()),
+        }
+    }
+
+    /// pick one item, `key` should be unique for each item, and `weight` should be positive
+    pub(super) fn pick<T, I, K, W>(&self, items: I, key: K, weight: W) -> Option<T>
+    where
+        I: Iterator<Item = T>,
+        K: Fn(&T) -> usize,
+        W: Fn(&T) -> f64,
+    {
+        let mut current = self.current.lock().unwrap();
+        let mut total = 0.0;
+        let mut selected: Option<(T, usize, f64)> = None;
+        for item in items {
+            let k = key(&item);
+            let w = weight(&item);
+            total += w;
+            let v = current.entry(k).or_insert(0.0);
+            *v += w;
+            let v = *v;
+            if selected
+                .as_ref()
+                .map(|(_, _, max)| v > *max)
+                .unwrap_or(true)
+            {
+                selected = Some((item, k, v));
+            }
+        }
+        let (item, k, _) = selected?;
+        if let Some(v) = current.get_mut(&k) {
+            *v -= total;
+        }
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth() {
+        let state = PeerWrrState::default();
+        let peers = [('a', 5.0), ('b', 1.0), ('c', 1.0)];
+        let picked = (0..7)
+            .map(|_| {
+                state
+                    .pick(peers.iter(), |p| p.0 as usize, |p| p.1)
+                    .unwrap()
+                    .0
+            })
+            .collect::<String>();
+        assert_eq!(picked, "aabacaa");
+
+        let snapshot = state.snapshot();
+        let next = snapshot.pick(peers.iter(), |p| p.0 as usize, |p| p.1);
+        assert_eq!(next.unwrap().0, 'a');
+        assert_eq!(
+            state
+                .pick(peers.iter(), |p| p.0 as usize, |p| p.1)
+                .unwrap()
+                .0,
+            'a'
+        );
+
+        assert!(state
+            .pick(
+                std::iter::empty::<&(char, f64)>(),
+                |p| p.0 as usize,
+                |p| p.1
+            )
+            .is_none());
+    }
+}
@@ -0,0 +1,306 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use http::{header, Method, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+
+use g3_http::client::HttpForwardRemoteResponse;
+use g3_http::HttpBodyReader;
+use g3_openssl::SslConnector;
+use g3_types::net::OpensslClientConfig;
+
+use crate::config::escaper::proxy_float::source::http::ProxyFloatHttpSource;
+
+const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+fn is_msgpack_content_type(value: &str) -> bool {
+    let media_type = value.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case(MSGPACK_CONTENT_TYPE)
+        || media_type.eq_ignore_ascii_case("application/x-msgpack")
+}
+
+/// The validators of the last fetched feed, and the tls client config to use.
+#[derive(Default)]
+pub(super) struct HttpSourceState {
+    tls_client: Option<OpensslClientConfig>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl HttpSourceState {
+    /// force a full fetch next time, should be called if the fetched feed is not usable
+    pub(super) fn clear_validators(&mut self) {
+        self.etag = None;
+        self.last_modified = None;
+    }
+
+    fn tls_client(
+        &mut self,
+        source: &ProxyFloatHttpSource,
+    ) -> anyhow::Result<Option<&OpensslClientConfig>> {
+        let Some(builder) = &source.tls_client else {
+            return Ok(None);
+        };
+        if self.tls_client.is_none() {
+            let tls_client = builder
+                .build()
+                .context("failed to build tls client config")?;
+            self.tls_client = Some(tls_client);
+        }
+        Ok(self.tls_client.as_ref())
+    }
+}
+
+/// fetch the peer records, or None if not modified since the last fetch
+pub(super) async fn fetch_records(
+    source: &Arc<ProxyFloatHttpSource>,
+    state: &mut HttpSourceState,
+) -> anyhow::Result<Option<Vec<serde_json::Value>>> {
+    let stream = tokio::time::timeout(
+        source.connect_timeout,
+        TcpStream::connect(source.addr.to_string()),
+    )
+    .await
+    .map_err(|_| anyhow!("timeout to connect to {}", source.addr))?
+    .map_err(|e| anyhow!("failed to connect to {}: {e}", source.addr))?;
+
+    if let Some(tls_client) = state.tls_client(source)? {
+        let tls_name = source
+            .tls_name
+            .as_ref()
+            .unwrap_or_else(|| source.addr.host());
+        let ssl = tls_client.build_ssl(tls_name, source.addr.port())?;
+        let connector = SslConnector::new(ssl, stream)
+            .map_err(|e| anyhow!("failed to create tls connector: {e}"))?;
+        let stream = tokio::time::timeout(tls_client.handshake_timeout, connector.connect())
+            .await
+            .map_err(|_| anyhow!("tls handshake with {} timed out", source.addr))?
+            .map_err(|e| anyhow!("tls handshake with {} failed: {e}", source.addr))?;
+        fetch_with_timeout(source, state, stream).await
+    } else {
+        fetch_with_timeout(source, state, stream).await
+    }
+}
+
+async fn fetch_with_timeout<S>(
+    source: &ProxyFloatHttpSource,
+    state: &mut HttpSourceState,
+    stream: S,
+) -> anyhow::Result<Option<Vec<serde_json::Value>>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tokio::time::timeout(source.response_timeout, fetch(source, state, stream))
+        .await
+        .map_err(|_| anyhow!("timeout to fetch peers from {}", source.url))?
+}
+
+async fn fetch<S>(
+    source: &ProxyFloatHttpSource,
+    state: &mut HttpSourceState,
+    stream: S,
+) -> anyhow::Result<Option<Vec<serde_json::Value>>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufStream::new(stream);
+
+    let mut data = format!(
+        "GET {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Accept: application/json, {MSGPACK_CONTENT_TYPE}\r\n\
+         Connection: close\r\n",
+        source.request_target(),
+        source.addr
+    );
+    if let Some(etag) = &state.etag {
+        data.push_str(&format!("If-None-Match: {etag}\r\n"));
+    }
+    if let Some(last_modified) = &state.last_modified {
+        data.push_str(&format!("If-Modified-Since: {last_modified}\r\n"));
+    }
+    data.push_str("\r\n");
+    stream
+        .write_all(data.as_bytes())
+        .await
+        .map_err(|e| anyhow!("failed to write request: {e:?}"))?;
+    stream
+        .flush()
+        .await
+        .map_err(|e| anyhow!("failed to write request: {e:?}"))?;
+
+    let rsp =
+        HttpForwardRemoteResponse::parse(&mut stream, &Method::GET, false, source.max_header_size)
+            .await
+            .map_err(|e| anyhow!("failed to recv response: {e}"))?;
+    if rsp.code == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if rsp.code != StatusCode::OK {
+        return Err(anyhow!("unexpected response: {} {}", rsp.code, rsp.reason));
+    }
+
+    let Some(body_type) = rsp.body_type(&Method::GET) else {
+        return Err(anyhow!("no body found in response"));
+    };
+    let mut body_reader = HttpBodyReader::new(&mut stream, body_type, 2048);
+    let mut body = Vec::new();
+    (&mut body_reader)
+        .take(source.max_body_size as u64 + 1)
+        .read_to_end(&mut body)
+        .await
+        .map_err(|e| anyhow!("failed to read response body: {e:?}"))?;
+    if body.len() > source.max_body_size {
+        return Err(anyhow!(
+            "response body is larger than {} bytes",
+            source.max_body_size
+        ));
+    }
+
+    let is_msgpack = rsp
+        .end_to_end_headers
+        .get(header::CONTENT_TYPE)
+        .map(|v| is_msgpack_content_type(v.to_str()))
+        .unwrap_or(false);
+    let records = if is_msgpack {
+        super::decode_msgpack_records(&body).context("invalid msgpack response body")?
+    } else {
+        let obj = serde_json::from_slice(&body)
+            .map_err(|e| anyhow!("the response body is not valid json: {e}"))?;
+        match obj {
+            serde_json::Value::Array(v) => v,
+            serde_json::Value::Object(_) => vec![obj],
+            _ => return Err(anyhow!("invalid json data type in response body")),
+        }
+    };
+
+    state.etag = rsp
+        .end_to_end_headers
+        .get(header::ETAG)
+        .map(|v| v.to_str().to_string());
+    state.last_modified = rsp
+        .end_to_end_headers
+        .get(header::LAST_MODIFIED)
+        .map(|v| v.to_str().to_string());
+    Ok(Some(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use url::Url;
+
+    fn http_source() -> Arc<ProxyFloatHttpSource> {
+        let url = Url::parse("http://127.0.0.1:8080/peers?region=1").unwrap();
+        Arc::new(ProxyFloatHttpSource::parse_url(&url).unwrap())
+    }
+
+    /// serve one request and return the request header lines
+    async fn serve_once(server: tokio::io::DuplexStream, rsp: impl AsRef<[u8]>) -> Vec<String> {
+        let mut server = BufReader::new(server);
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        server.write_all(rsp.as_ref()).await.unwrap();
+        server.shutdown().await.unwrap();
+        lines
+    }
+
+    #[tokio::test]
+    async fn etag_cache() {
+        let source = http_source();
+        assert_eq!(
+            source.url,
+            Url::parse("http://127.0.0.1:8080/peers?region=1").unwrap()
+        );
+        let mut state = HttpSourceState::default();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let rsp = "HTTP/1.1 200 OK\r\n\
+                   ETag: \"v1\"\r\n\
+                   Content-Length: 41\r\n\
+                   \r\n\
+                   [{\"type\":\"http\",\"addr\":\"127.0.0.1:3128\"}]";
+        let (records, req) =
+            tokio::join!(fetch(&source, &mut state, client), serve_once(server, rsp));
+        assert_eq!(records.unwrap().unwrap().len(), 1);
+        assert_eq!(req[0], "GET /peers?region=1 HTTP/1.1");
+        assert!(!req.iter().any(|l| l.starts_with("If-None-Match")));
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+
+        let (client, server) = tokio::io::duplex(4096);
+        let rsp = "HTTP/1.1 304 Not Modified\r\n\r\n";
+        let (records, req) =
+            tokio::join!(fetch(&source, &mut state, client), serve_once(server, rsp));
+        assert!(records.unwrap().is_none());
+        assert!(req.iter().any(|l| l == "If-None-Match: \"v1\""));
+
+        state.clear_validators();
+        assert!(state.etag.is_none());
+    }
+
+    #[tokio::test]
+    async fn msgpack_body() {
+        let source = http_source();
+        let mut state = HttpSourceState::default();
+
+        let body = rmp_serde::to_vec(&serde_json::json!([
+            {"type": "http", "addr": "127.0.0.1:3128"},
+            {"type": "socks5", "addr": "127.0.0.1:1080"},
+        ]))
+        .unwrap();
+        let mut rsp = format!(
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: application/msgpack\r\n\
+             Content-Length: {}\r\n\
+             \r\n",
+            body.len()
+        )
+        .into_bytes();
+        rsp.extend_from_slice(&body);
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (records, req) =
+            tokio::join!(fetch(&source, &mut state, client), serve_once(server, rsp));
+        let records = records.unwrap().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["addr"], "127.0.0.1:1080");
+        assert!(req
+            .iter()
+            .any(|l| l == "Accept: application/json, application/msgpack"));
+    }
+
+    #[test]
+    fn msgpack_content_type() {
+        assert!(is_msgpack_content_type("application/msgpack"));
+        assert!(is_msgpack_content_type(
+            "Application/X-MsgPack; charset=binary"
+        ));
+        assert!(!is_msgpack_content_type("application/json"));
+    }
+}
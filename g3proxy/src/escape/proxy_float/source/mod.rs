@@ -19,7 +19,7 @@ use std::sync::Arc;
 use anyhow::anyhow;
 use arc_swap::ArcSwap;
 use futures_util::future::{AbortHandle, Abortable};
-use log::warn;
+use log::{debug, warn};
 use slog::Logger;
 
 use g3_types::net::OpensslClientConfig;
@@ -28,6 +28,7 @@ use super::{PeerSet, ProxyFloatEscaperStats};
 use crate::config::escaper::proxy_float::{ProxyFloatEscaperConfig, ProxyFloatSource};
 
 mod file;
+mod http;
 mod redis;
 mod redis_cluster;
 
@@ -39,7 +40,8 @@ pub(super) async fn load_cached_peers(
 ) -> anyhow::Result<PeerSet> {
     if let Some(cache_file) = &config.cache_file {
         let records = file::load_peers_from_cache(cache_file).await?;
-        super::peer::parse_peers(config, stats, escape_logger, &records, tls_config)
+        // the cached peers are always the first generation since startup
+        super::peer::parse_peers(config, stats, escape_logger, &records, tls_config, 1)
     } else {
         Ok(PeerSet::default())
     }
@@ -53,10 +55,26 @@ async fn parse_and_save_peers(
     tls_config: Option<&Arc<OpensslClientConfig>>,
     records: Vec<serde_json::Value>,
 ) -> anyhow::Result<()> {
-    let peers = super::peer::parse_peers(config, stats, escape_logger, &records, tls_config)
-        .map_err(|e| anyhow!("failed to parse peers: {e:?}"))?;
+    let generation = container.load().generation() + 1;
+    let peers = super::peer::parse_peers(
+        config,
+        stats,
+        escape_logger,
+        &records,
+        tls_config,
+        generation,
+    )
+    .map_err(|e| anyhow!("failed to parse peers: {e:?}"))?;
+    peers.inherit_runtime_state(&container.load());
 
-    container.store(Arc::new(peers));
+    let old_peers = container.swap(Arc::new(peers));
+    stats.set_peer_feed_generation(generation);
+    debug!(
+        "escaper {}: peer feed generation {generation} is active, replacing generation {}",
+        config.name,
+        old_peers.generation()
+    );
+    old_peers.log_runtime_stats(config.name.as_str());
     if let Some(cache_file) = &config.cache_file {
         file::save_peers_to_cache(cache_file, records)
             .await
@@ -65,6 +83,17 @@ async fn parse_and_save_peers(
     Ok(())
 }
 
+/// decode the peer records from MessagePack, which is more compact than json for large feeds
+fn decode_msgpack_records(data: &[u8]) -> anyhow::Result<Vec<serde_json::Value>> {
+    let obj =
+        rmp_serde::from_slice(data).map_err(|e| anyhow!("the data is not valid msgpack: {e}"))?;
+    match obj {
+        serde_json::Value::Array(v) => Ok(v),
+        serde_json::Value::Object(_) => Ok(vec![obj]),
+        _ => Err(anyhow!("invalid msgpack data type")),
+    }
+}
+
 pub(super) async fn publish_peers(
     config: &Arc<ProxyFloatEscaperConfig>,
     stats: &Arc<ProxyFloatEscaperStats>,
@@ -102,6 +131,11 @@ pub(super) fn new_job(
     let f = async move {
         let mut interval = tokio::time::interval(config.refresh_interval);
         interval.tick().await; // will tick immediately
+        if !matches!(config.source, ProxyFloatSource::Passive) {
+            // the staleness will be counted since now if no fetch succeeded
+            stats.mark_peers_fetched();
+        }
+        let mut http_state = http::HttpSourceState::default();
         loop {
             let result = match &config.source {
                 ProxyFloatSource::Passive => {
@@ -109,14 +143,17 @@ pub(super) fn new_job(
                     interval.tick().await;
                     continue;
                 }
-                ProxyFloatSource::Redis(config) => redis::fetch_records(config).await,
+                ProxyFloatSource::Redis(config) => redis::fetch_records(config).await.map(Some),
                 ProxyFloatSource::RedisCluster(config) => {
-                    redis_cluster::fetch_records(config).await
+                    redis_cluster::fetch_records(config).await.map(Some)
+                }
+                ProxyFloatSource::Http(config) => {
+                    http::fetch_records(config, &mut http_state).await
                 }
             };
             match result {
-                Ok(records) => {
-                    if let Err(e) = parse_and_save_peers(
+                Ok(Some(records)) => {
+                    match parse_and_save_peers(
                         &config,
                         &stats,
                         &escape_logger,
@@ -126,9 +163,17 @@ pub(super) fn new_job(
                     )
                     .await
                     {
-                        warn!("failed to update peers for escaper {}: {e:?}", config.name);
+                        Ok(_) => stats.mark_peers_fetched(),
+                        Err(e) => {
+                            http_state.clear_validators();
+                            warn!("failed to update peers for escaper {}: {e:?}", config.name);
+                        }
                     }
                 }
+                Ok(None) => {
+                    // not modified, keep the current peers
+                    stats.mark_peers_fetched();
+                }
                 Err(e) => warn!("failed to fetch peers for escaper {}: {e:?}", config.name),
             }
 
@@ -14,22 +14,35 @@
  * limitations under the License.
  */
 
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 
-use arc_swap::ArcSwapOption;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use once_cell::sync::Lazy;
 
 use g3_daemon::stat::remote::TcpConnectionTaskRemoteStats;
 use g3_io_ext::{LimitedReaderStats, LimitedWriterStats};
-use g3_types::metrics::{MetricsName, StaticMetricsTags};
-use g3_types::stats::{StatId, TcpIoSnapshot, UdpIoSnapshot};
+use g3_types::metrics::{MetricsName, MetricsTagName, MetricsTagValue, StaticMetricsTags};
+use g3_types::net::EgressArea;
+use g3_types::stats::{StatId, TcpIoSnapshot, TcpIoStats, UdpIoSnapshot};
 
+use super::egress_state::EgressState;
+use super::peer::PeerSet;
 use crate::escape::{
-    EscaperInterfaceStats, EscaperInternalStats, EscaperStats, EscaperTcpStats, EscaperUdpStats,
+    EscaperInterfaceStats, EscaperInternalStats, EscaperStats, EscaperTaggedTcpIoStats,
+    EscaperTcpStats, EscaperUdpStats, PeerCanarySnapshot, PeerConnectStats,
 };
 use crate::module::http_forward::HttpForwardTaskRemoteStats;
 use crate::module::udp_connect::UdpConnectTaskRemoteStats;
 use crate::module::udp_relay::UdpRelayTaskRemoteStats;
 
+const EGRESS_AREA_UNKNOWN: &str = "__unknown__";
+
+static TAG_KEY_EGRESS_AREA: Lazy<MetricsTagName> =
+    Lazy::new(|| MetricsTagName::from_str("area").unwrap());
+
 pub(crate) struct ProxyFloatEscaperStats {
     name: MetricsName,
     id: StatId,
@@ -37,6 +50,26 @@ pub(crate) struct ProxyFloatEscaperStats {
     pub(crate) interface: EscaperInterfaceStats,
     pub(crate) tcp: EscaperTcpStats,
     pub(crate) udp: EscaperUdpStats,
+    /// tcp io on the alive connections of deprecated peers
+    pub(crate) deprecated_peer_tcp: TcpIoStats,
+    tagged_tcp: Mutex<Vec<Arc<EscaperTaggedTcpIoStats>>>,
+    area_tcp: Mutex<Vec<Arc<EscaperTaggedTcpIoStats>>>,
+    area_fallback: Mutex<Vec<(String, u64)>>,
+    peers_fetched: Mutex<Option<Instant>>,
+    /// the peer set in use by the current escaper, for the live / expired peer gauges
+    peer_set: Mutex<Weak<ArcSwap<PeerSet>>>,
+    peer_tls_insecure: AtomicU64,
+    peer_eip_verified: AtomicU64,
+    peer_eip_mismatch: AtomicU64,
+    peer_feed_generation: AtomicU64,
+    peer_evicted: AtomicU64,
+    peer_duplicated: AtomicU64,
+    peer_policy_denied: AtomicU64,
+    /// connects through the canary peers for the sampled tasks
+    pub(super) canary_peer_connect: PeerConnectStats,
+    /// connects through the selected live peers for the same sampled tasks
+    pub(super) canary_live_connect: PeerConnectStats,
+    pub(super) egress_state: EgressState,
 }
 
 impl ProxyFloatEscaperStats {
@@ -48,12 +81,120 @@ impl ProxyFloatEscaperStats {
             interface: EscaperInterfaceStats::default(),
             tcp: EscaperTcpStats::default(),
             udp: EscaperUdpStats::default(),
+            deprecated_peer_tcp: TcpIoStats::default(),
+            tagged_tcp: Mutex::new(Vec::new()),
+            area_tcp: Mutex::new(Vec::new()),
+            area_fallback: Mutex::new(Vec::new()),
+            peers_fetched: Mutex::new(None),
+            peer_set: Mutex::new(Weak::new()),
+            peer_tls_insecure: AtomicU64::new(0),
+            peer_eip_verified: AtomicU64::new(0),
+            peer_eip_mismatch: AtomicU64::new(0),
+            peer_feed_generation: AtomicU64::new(0),
+            peer_evicted: AtomicU64::new(0),
+            peer_duplicated: AtomicU64::new(0),
+            peer_policy_denied: AtomicU64::new(0),
+            canary_peer_connect: PeerConnectStats::default(),
+            canary_live_connect: PeerConnectStats::default(),
+            egress_state: EgressState::default(),
         }
     }
 
     pub(crate) fn set_extra_tags(&self, tags: Option<Arc<StaticMetricsTags>>) {
         self.extra_metrics_tags.store(tags);
     }
+
+    /// the peers from the source are up to date now
+    pub(crate) fn mark_peers_fetched(&self) {
+        *self.peers_fetched.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub(super) fn set_peer_set(&self, peers: &Arc<ArcSwap<PeerSet>>) {
+        *self.peer_set.lock().unwrap() = Arc::downgrade(peers);
+    }
+
+    pub(crate) fn set_peer_feed_generation(&self, generation: u64) {
+        self.peer_feed_generation
+            .store(generation, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_peer_tls_insecure(&self) {
+        self.peer_tls_insecure.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_peer_eip_verified(&self) {
+        self.peer_eip_verified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_peer_eip_mismatch(&self) {
+        self.peer_eip_mismatch.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// count the peers dropped from a feed as the peer count limit is exceeded
+    pub(crate) fn add_peer_evicted(&self, count: usize) {
+        self.peer_evicted.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// count the peers replaced by later ones with the same id in a feed
+    pub(crate) fn add_peer_duplicated(&self, count: usize) {
+        self.peer_duplicated
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// count a selection that failed as no peer is permitted for the user
+    pub(crate) fn add_peer_policy_denied(&self) {
+        self.peer_policy_denied.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// count a selection that fell back to another area of the requested one
+    pub(crate) fn add_area_fallback(&self, requested: &EgressArea) {
+        let area = requested.to_string();
+        let mut all = self.area_fallback.lock().unwrap();
+        // the count should be small as the fallback areas are limited by config
+        match all.iter_mut().find(|(a, _)| a == &area) {
+            Some((_, count)) => *count += 1,
+            None => all.push((area, 1)),
+        }
+    }
+
+    /// get the shared tcp io stats for peers with the same metrics tags
+    pub(crate) fn fetch_tagged_tcp_io_stats(
+        &self,
+        tags: StaticMetricsTags,
+    ) -> Arc<EscaperTaggedTcpIoStats> {
+        let mut all = self.tagged_tcp.lock().unwrap();
+        // the count should be small as the tag keys are limited by config
+        if let Some(stats) = all.iter().find(|s| s.tags().eq(&tags)) {
+            return Arc::clone(stats);
+        }
+        let stats = Arc::new(EscaperTaggedTcpIoStats::new(tags));
+        all.push(Arc::clone(&stats));
+        stats
+    }
+
+    /// get the shared tcp io stats for peers in the same egress area,
+    /// peers without a valid area will share the `__unknown__` one
+    pub(crate) fn fetch_area_tcp_io_stats(
+        &self,
+        area: Option<&EgressArea>,
+    ) -> Arc<EscaperTaggedTcpIoStats> {
+        let value = area
+            .and_then(|area| MetricsTagValue::from_str(&area.to_string()).ok())
+            .unwrap_or_else(|| MetricsTagValue::from_str(EGRESS_AREA_UNKNOWN).unwrap());
+
+        let mut all = self.area_tcp.lock().unwrap();
+        if let Some(stats) = all
+            .iter()
+            .find(|s| s.tags().get(&*TAG_KEY_EGRESS_AREA) == Some(&value))
+        {
+            return Arc::clone(stats);
+        }
+        let mut tags = StaticMetricsTags::new();
+        tags.insert(TAG_KEY_EGRESS_AREA.clone(), value);
+        let stats = Arc::new(EscaperTaggedTcpIoStats::new(tags));
+        all.push(Arc::clone(&stats));
+        stats
+    }
 }
 
 impl EscaperInternalStats for ProxyFloatEscaperStats {
@@ -97,6 +238,42 @@ impl EscaperStats for ProxyFloatEscaperStats {
         self.tcp.get_connection_established()
     }
 
+    fn get_source_port_exhausted(&self) -> u64 {
+        self.tcp.get_source_port_exhausted()
+    }
+
+    fn get_peer_tls_insecure(&self) -> u64 {
+        self.peer_tls_insecure.load(Ordering::Relaxed)
+    }
+
+    fn get_peer_eip_verified(&self) -> u64 {
+        self.peer_eip_verified.load(Ordering::Relaxed)
+    }
+
+    fn get_peer_eip_mismatch(&self) -> u64 {
+        self.peer_eip_mismatch.load(Ordering::Relaxed)
+    }
+
+    fn get_peer_evicted(&self) -> u64 {
+        self.peer_evicted.load(Ordering::Relaxed)
+    }
+
+    fn get_peer_duplicated(&self) -> u64 {
+        self.peer_duplicated.load(Ordering::Relaxed)
+    }
+
+    fn get_peer_policy_denied(&self) -> u64 {
+        self.peer_policy_denied.load(Ordering::Relaxed)
+    }
+
+    fn get_egress_down(&self) -> u64 {
+        self.egress_state.down_total()
+    }
+
+    fn get_egress_restored(&self) -> u64 {
+        self.egress_state.restored_total()
+    }
+
     fn tcp_io_snapshot(&self) -> Option<TcpIoSnapshot> {
         Some(self.tcp.io.snapshot())
     }
@@ -104,6 +281,95 @@ impl EscaperStats for ProxyFloatEscaperStats {
     fn udp_io_snapshot(&self) -> Option<UdpIoSnapshot> {
         Some(self.udp.io.snapshot())
     }
+
+    fn tagged_tcp_io_stats(&self) -> Vec<Arc<EscaperTaggedTcpIoStats>> {
+        self.tagged_tcp.lock().unwrap().clone()
+    }
+
+    fn area_tcp_io_stats(&self) -> Vec<Arc<EscaperTaggedTcpIoStats>> {
+        self.area_tcp.lock().unwrap().clone()
+    }
+
+    fn deprecated_peer_tcp_io_snapshot(&self) -> Option<TcpIoSnapshot> {
+        Some(self.deprecated_peer_tcp.snapshot())
+    }
+
+    fn peer_area_fallback_counts(&self) -> Vec<(String, u64)> {
+        self.area_fallback.lock().unwrap().clone()
+    }
+
+    fn peer_canary_snapshot(&self) -> Option<PeerCanarySnapshot> {
+        Some(PeerCanarySnapshot {
+            canary: self.canary_peer_connect.snapshot(),
+            live: self.canary_live_connect.snapshot(),
+        })
+    }
+
+    fn peer_source_staleness(&self) -> Option<Duration> {
+        self.peers_fetched.lock().unwrap().map(|t| t.elapsed())
+    }
+
+    fn peer_feed_generation(&self) -> Option<u64> {
+        match self.peer_feed_generation.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    fn peer_live_expired_counts(&self) -> Vec<(&'static str, usize, usize)> {
+        let peer_set = self.peer_set.lock().unwrap().upgrade();
+        peer_set
+            .map(|peers| peers.load().count_live_expired())
+            .unwrap_or_default()
+    }
+}
+
+/// tcp io stats for peers with metrics tags or egress area,
+/// the escaper level stats will also be updated
+pub(crate) struct ProxyFloatPeerTcpIoStats {
+    escaper: Arc<ProxyFloatEscaperStats>,
+    tagged: Option<Arc<EscaperTaggedTcpIoStats>>,
+    area: Option<Arc<EscaperTaggedTcpIoStats>>,
+}
+
+impl ProxyFloatPeerTcpIoStats {
+    pub(crate) fn new(
+        escaper: Arc<ProxyFloatEscaperStats>,
+        tagged: Option<Arc<EscaperTaggedTcpIoStats>>,
+        area: Option<Arc<EscaperTaggedTcpIoStats>>,
+    ) -> Self {
+        ProxyFloatPeerTcpIoStats {
+            escaper,
+            tagged,
+            area,
+        }
+    }
+}
+
+impl LimitedReaderStats for ProxyFloatPeerTcpIoStats {
+    fn add_read_bytes(&self, size: usize) {
+        let size = size as u64;
+        self.escaper.tcp.io.add_in_bytes(size);
+        if let Some(tagged) = &self.tagged {
+            tagged.io.add_in_bytes(size);
+        }
+        if let Some(area) = &self.area {
+            area.io.add_in_bytes(size);
+        }
+    }
+}
+
+impl LimitedWriterStats for ProxyFloatPeerTcpIoStats {
+    fn add_write_bytes(&self, size: usize) {
+        let size = size as u64;
+        self.escaper.tcp.io.add_out_bytes(size);
+        if let Some(tagged) = &self.tagged {
+            tagged.io.add_out_bytes(size);
+        }
+        if let Some(area) = &self.area {
+            area.io.add_out_bytes(size);
+        }
+    }
 }
 
 impl LimitedReaderStats for ProxyFloatEscaperStats {
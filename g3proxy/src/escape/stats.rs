@@ -16,6 +16,7 @@
 
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use arc_swap::ArcSwapOption;
 
@@ -40,6 +41,51 @@ pub(crate) trait EscaperStats: EscaperInternalStats {
     fn get_conn_attempted(&self) -> u64;
     fn get_conn_established(&self) -> u64;
 
+    /// count for connection attempts failed as no source port is available
+    fn get_source_port_exhausted(&self) -> u64 {
+        0
+    }
+
+    /// count for tls handshakes with peers that skipped the certificate verification
+    fn get_peer_tls_insecure(&self) -> u64 {
+        0
+    }
+
+    /// count for sampled peer egress ip verifications that matched the declared one
+    fn get_peer_eip_verified(&self) -> u64 {
+        0
+    }
+
+    /// count for sampled peer egress ip verifications that differ from the declared one
+    fn get_peer_eip_mismatch(&self) -> u64 {
+        0
+    }
+
+    /// count for peers dropped from the peer feed as the peer count limit is exceeded
+    fn get_peer_evicted(&self) -> u64 {
+        0
+    }
+
+    /// count for peers replaced by later ones with the same id in the peer feed
+    fn get_peer_duplicated(&self) -> u64 {
+        0
+    }
+
+    /// count for the peer selections failed as no peer is permitted for the user
+    fn get_peer_policy_denied(&self) -> u64 {
+        0
+    }
+
+    /// count for the transitions to the state that no live peer is left
+    fn get_egress_down(&self) -> u64 {
+        0
+    }
+
+    /// count for the transitions back from the state that no live peer is left
+    fn get_egress_restored(&self) -> u64 {
+        0
+    }
+
     fn tcp_io_snapshot(&self) -> Option<TcpIoSnapshot> {
         None
     }
@@ -48,9 +94,49 @@ pub(crate) trait EscaperStats: EscaperInternalStats {
         None
     }
 
+    /// tcp io stats for connections with more metrics tags
+    fn tagged_tcp_io_stats(&self) -> Vec<Arc<EscaperTaggedTcpIoStats>> {
+        Vec::new()
+    }
+
+    /// tcp io stats for connections aggregated by the egress area of the peers
+    fn area_tcp_io_stats(&self) -> Vec<Arc<EscaperTaggedTcpIoStats>> {
+        Vec::new()
+    }
+
+    /// tcp io stats for the alive connections of deprecated peers
+    fn deprecated_peer_tcp_io_snapshot(&self) -> Option<TcpIoSnapshot> {
+        None
+    }
+
+    /// count for peer selections that fell back to other areas, by the requested egress area
+    fn peer_area_fallback_counts(&self) -> Vec<(String, u64)> {
+        Vec::new()
+    }
+
     fn forbidden_snapshot(&self) -> Option<EscaperForbiddenSnapshot> {
         None
     }
+
+    /// connect results of the tasks sampled for mirroring to canary peers
+    fn peer_canary_snapshot(&self) -> Option<PeerCanarySnapshot> {
+        None
+    }
+
+    /// the time since the peers were last fetched from the dynamic source
+    fn peer_source_staleness(&self) -> Option<Duration> {
+        None
+    }
+
+    /// the generation of the peer feed that is currently in use
+    fn peer_feed_generation(&self) -> Option<u64> {
+        None
+    }
+
+    /// the count of live and expired peers for each peer type
+    fn peer_live_expired_counts(&self) -> Vec<(&'static str, usize, usize)> {
+        Vec::new()
+    }
 }
 
 pub(crate) type ArcEscaperInternalStats = Arc<dyn EscaperInternalStats + Send + Sync>;
@@ -61,6 +147,47 @@ pub(crate) struct EscaperForbiddenSnapshot {
     pub(crate) ip_blocked: u64,
 }
 
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PeerConnectSnapshot {
+    pub(crate) attempt: u64,
+    pub(crate) success: u64,
+    /// the total connect time of the successful ones, in microseconds
+    pub(crate) connect_time: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct PeerConnectStats {
+    attempt: AtomicU64,
+    success: AtomicU64,
+    connect_time: AtomicU64,
+}
+
+impl PeerConnectStats {
+    pub(crate) fn record(&self, success: bool, connect_time: Duration) {
+        self.attempt.fetch_add(1, Ordering::Relaxed);
+        if success {
+            self.success.fetch_add(1, Ordering::Relaxed);
+            let micros = u64::try_from(connect_time.as_micros()).unwrap_or(u64::MAX);
+            self.connect_time.fetch_add(micros, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> PeerConnectSnapshot {
+        PeerConnectSnapshot {
+            attempt: self.attempt.load(Ordering::Relaxed),
+            success: self.success.load(Ordering::Relaxed),
+            connect_time: self.connect_time.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// the connect results through the canary peers, and through the live peers selected for the same tasks
+#[derive(Default)]
+pub(crate) struct PeerCanarySnapshot {
+    pub(crate) canary: PeerConnectSnapshot,
+    pub(crate) live: PeerConnectSnapshot,
+}
+
 #[derive(Default)]
 pub(crate) struct EscaperForbiddenStats {
     ip_blocked: AtomicU64,
@@ -163,6 +290,7 @@ impl EscaperInterfaceStats {
 pub(crate) struct EscaperTcpStats {
     connection_attempted: AtomicU64,
     connection_established: AtomicU64,
+    source_port_exhausted: AtomicU64,
     pub(crate) io: TcpIoStats,
 }
 
@@ -182,6 +310,42 @@ impl EscaperTcpStats {
     pub(crate) fn get_connection_established(&self) -> u64 {
         self.connection_established.load(Ordering::Relaxed)
     }
+
+    pub(crate) fn add_source_port_exhausted(&self) {
+        self.source_port_exhausted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get_source_port_exhausted(&self) -> u64 {
+        self.source_port_exhausted.load(Ordering::Relaxed)
+    }
+}
+
+/// The tcp io stats for a subset of the connections of an escaper,
+/// which will be emitted with more metrics tags.
+pub(crate) struct EscaperTaggedTcpIoStats {
+    id: StatId,
+    tags: StaticMetricsTags,
+    pub(crate) io: TcpIoStats,
+}
+
+impl EscaperTaggedTcpIoStats {
+    pub(crate) fn new(tags: StaticMetricsTags) -> Self {
+        EscaperTaggedTcpIoStats {
+            id: StatId::new(),
+            tags,
+            io: TcpIoStats::default(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn stat_id(&self) -> StatId {
+        self.id
+    }
+
+    #[inline]
+    pub(crate) fn tags(&self) -> &StaticMetricsTags {
+        &self.tags
+    }
 }
 
 #[derive(Default)]
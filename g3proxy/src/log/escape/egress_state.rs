@@ -0,0 +1,41 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use slog::{slog_info, Logger};
+
+pub(crate) struct EscapeLogForEgressState {
+    pub(crate) feed_generation: u64,
+}
+
+impl EscapeLogForEgressState {
+    pub(crate) fn log_down(&self, logger: &Logger, last_live_count: usize) {
+        slog_info!(logger, "egress down: no live peer left";
+            "escape_type" => "EgressState",
+            "event" => "down",
+            "next_feed_generation" => self.feed_generation,
+            "last_live_peers" => last_live_count,
+        )
+    }
+
+    pub(crate) fn log_restored(&self, logger: &Logger, live_count: usize) {
+        slog_info!(logger, "egress restored: live peers are back";
+            "escape_type" => "EgressState",
+            "event" => "restored",
+            "next_feed_generation" => self.feed_generation,
+            "live_peers" => live_count,
+        )
+    }
+}
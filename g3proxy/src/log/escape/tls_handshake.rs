@@ -56,6 +56,7 @@ impl EscapeLogForTlsHandshake<'_> {
             "next_bound_addr" => self.tcp_notes.local,
             "next_peer_addr" => self.tcp_notes.next,
             "next_expire" => self.tcp_notes.expire.as_ref().map(LtDateTime),
+            "next_feed_generation" => self.tcp_notes.feed_generation,
             "tls_name" => LtHost(self.tls_name),
             "tls_peer" => LtUpstreamAddr(self.tls_peer),
             "tls_application" => self.tls_application.as_str(),
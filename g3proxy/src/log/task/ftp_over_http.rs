@@ -58,6 +58,7 @@ impl TaskLogForFtpOverHttp<'_> {
             "escaper" => self.ftp_notes.control_tcp_notes.escaper.as_str(),
             "next_bind_ip" => self.ftp_notes.control_tcp_notes.bind.map(LtIpAddr),
             "next_expire" => self.ftp_notes.control_tcp_notes.expire.as_ref().map(LtDateTime),
+            "next_feed_generation" => self.ftp_notes.control_tcp_notes.feed_generation,
             "ftp_c_bound_addr" => self.ftp_notes.control_tcp_notes.local,
             "ftp_c_peer_addr" => self.ftp_notes.control_tcp_notes.next,
             "ftp_c_connect_tries" => self.ftp_notes.control_tcp_notes.tries,
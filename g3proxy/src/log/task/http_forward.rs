@@ -19,7 +19,7 @@ use std::time::Duration;
 use slog::{slog_info, Logger};
 
 use g3_slog_types::{
-    LtDateTime, LtDuration, LtHttpMethod, LtHttpUri, LtIpAddr, LtUpstreamAddr, LtUuid,
+    LtDateTime, LtDuration, LtEgressArea, LtHttpMethod, LtHttpUri, LtIpAddr, LtUpstreamAddr, LtUuid,
 };
 
 use crate::module::http_forward::HttpForwardTaskNotes;
@@ -60,6 +60,11 @@ impl TaskLogForHttpForward<'_> {
             "next_bound_addr" => self.tcp_notes.local,
             "next_peer_addr" => self.tcp_notes.next,
             "next_expire" => self.tcp_notes.expire.as_ref().map(LtDateTime),
+            "next_feed_generation" => self.tcp_notes.feed_generation,
+            "egress_id" => self.tcp_notes.egress.as_ref().and_then(|v| v.id.as_deref()),
+            "egress_isp" => self.tcp_notes.egress.as_ref().and_then(|v| v.isp.as_deref()),
+            "egress_area" => self.tcp_notes.egress.as_ref().and_then(|v| v.area.as_ref()).map(LtEgressArea),
+            "egress_ip" => self.tcp_notes.egress.as_ref().and_then(|v| v.ip).map(LtIpAddr),
             "tcp_connect_tries" => self.tcp_notes.tries,
             "tcp_connect_spend" => LtDuration(self.tcp_notes.duration),
             "reason" => e.brief(),
@@ -169,6 +169,9 @@ impl From<SocksConnectError> for TcpConnectError {
             }
             SocksConnectError::InvalidProtocol(_) => TcpConnectError::NegotiationProtocolErr,
             SocksConnectError::PeerTimeout => TcpConnectError::NegotiationPeerTimeout,
+            SocksConnectError::CommandNotSupported => TcpConnectError::NegotiationRejected(
+                "command not supported by remote proxy".to_string(),
+            ),
             SocksConnectError::RequestFailed(s) => TcpConnectError::NegotiationRejected(s),
         }
     }
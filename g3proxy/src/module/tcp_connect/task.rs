@@ -45,6 +45,7 @@ pub(crate) struct TcpConnectTaskNotes {
     pub(crate) tries: usize,
     pub(crate) local: Option<SocketAddr>,
     pub(crate) expire: Option<DateTime<Utc>>,
+    pub(crate) feed_generation: Option<u64>,
     pub(crate) egress: Option<EgressInfo>,
     pub(crate) chained: TcpConnectChainedNotes,
     pub(crate) duration: Duration,
@@ -60,6 +61,7 @@ impl TcpConnectTaskNotes {
             tries: 0,
             local: None,
             expire: None,
+            feed_generation: None,
             egress: None,
             chained: Default::default(),
             duration: Duration::ZERO,
@@ -82,6 +84,7 @@ impl TcpConnectTaskNotes {
         self.tries = 0;
         self.local = None;
         self.expire = None;
+        self.feed_generation = None;
         self.egress = None;
         self.chained.reset();
         self.duration = Duration::ZERO;
@@ -94,6 +97,7 @@ impl TcpConnectTaskNotes {
         self.tries = other.tries;
         self.local = other.local;
         self.expire = other.expire;
+        self.feed_generation = other.feed_generation;
         self.egress.clone_from(&other.egress);
         self.chained.clone_from(&other.chained);
         self.duration = other.duration;
@@ -23,8 +23,10 @@ use uuid::Uuid;
 
 use g3_daemon::server::ClientConnectionInfo;
 use g3_types::limit::GaugeSemaphorePermit;
+use g3_types::net::EgressArea;
 
 use crate::auth::UserContext;
+use crate::config::escaper::proxy_float::PeerSelectionMode;
 use crate::escape::EgressPathSelection;
 
 #[derive(Clone, Copy)]
@@ -143,6 +145,34 @@ impl ServerTaskNotes {
             .or(self.egress_path_selection.as_ref())
     }
 
+    /// the max RTT of the egress peer, for latency sensitive tasks
+    pub(crate) fn egress_max_rtt(&self) -> Option<Duration> {
+        self.user_ctx
+            .as_ref()
+            .and_then(|ctx| ctx.user_config().egress_max_rtt)
+    }
+
+    /// the area of the egress peer, for peer selection
+    pub(crate) fn egress_area(&self) -> Option<&EgressArea> {
+        self.user_ctx
+            .as_ref()
+            .and_then(|ctx| ctx.user_config().egress_area.as_ref())
+    }
+
+    /// the isp of the egress peer, for peer selection
+    pub(crate) fn egress_isp(&self) -> Option<&str> {
+        self.user_ctx
+            .as_ref()
+            .and_then(|ctx| ctx.user_config().egress_isp.as_deref())
+    }
+
+    /// the peer selection mode to use instead of the escaper default one
+    pub(crate) fn egress_peer_selection(&self) -> Option<PeerSelectionMode> {
+        self.user_ctx
+            .as_ref()
+            .and_then(|ctx| ctx.user_config().egress_peer_selection)
+    }
+
     #[inline]
     pub(crate) fn task_created_instant(&self) -> Instant {
         self.create_ins
@@ -28,18 +28,47 @@ use g3_types::stats::{StatId, TcpIoSnapshot, UdpIoSnapshot};
 
 use super::TAG_KEY_ESCAPER;
 use crate::escape::{
-    ArcEscaperStats, EscaperForbiddenSnapshot, RouteEscaperSnapshot, RouteEscaperStats,
+    ArcEscaperStats, EscaperForbiddenSnapshot, PeerCanarySnapshot, PeerConnectSnapshot,
+    RouteEscaperSnapshot, RouteEscaperStats,
 };
 
 const METRIC_NAME_ESCAPER_TASK_TOTAL: &str = "escaper.task.total";
 const METRIC_NAME_ESCAPER_CONN_ATTEMPT: &str = "escaper.connection.attempt";
 const METRIC_NAME_ESCAPER_CONN_ESTABLISH: &str = "escaper.connection.establish";
+const METRIC_NAME_ESCAPER_SOURCE_PORT_EXHAUSTED: &str = "escaper.connection.source_port_exhausted";
 const METRIC_NAME_ESCAPER_IO_IN_BYTES: &str = "escaper.traffic.in.bytes";
 const METRIC_NAME_ESCAPER_IO_IN_PACKETS: &str = "escaper.traffic.in.packets";
 const METRIC_NAME_ESCAPER_IO_OUT_BYTES: &str = "escaper.traffic.out.bytes";
 const METRIC_NAME_ESCAPER_IO_OUT_PACKETS: &str = "escaper.traffic.out.packets";
+const METRIC_NAME_ESCAPER_PEER_IO_IN_BYTES: &str = "escaper.peer.traffic.in.bytes";
+const METRIC_NAME_ESCAPER_PEER_IO_OUT_BYTES: &str = "escaper.peer.traffic.out.bytes";
+const METRIC_NAME_ESCAPER_PEER_TLS_INSECURE: &str = "escaper.peer.tls.insecure";
+const METRIC_NAME_ESCAPER_PEER_EIP_VERIFIED: &str = "escaper.peer.eip.verified";
+const METRIC_NAME_ESCAPER_PEER_EIP_MISMATCH: &str = "escaper.peer.eip.mismatch";
+const METRIC_NAME_ESCAPER_PEER_EVICTED: &str = "escaper.peer.evicted";
+const METRIC_NAME_ESCAPER_PEER_DUPLICATED: &str = "escaper.peer.duplicated";
+const METRIC_NAME_ESCAPER_PEER_POLICY_DENIED: &str = "escaper.peer.policy_denied";
+const METRIC_NAME_ESCAPER_EGRESS_DOWN: &str = "escaper.egress.down";
+const METRIC_NAME_ESCAPER_EGRESS_RESTORED: &str = "escaper.egress.restored";
+const METRIC_NAME_ESCAPER_PEER_SOURCE_STALENESS: &str = "escaper.peer.source.staleness";
+const METRIC_NAME_ESCAPER_PEER_FEED_GENERATION: &str = "escaper.peer.feed.generation";
+const METRIC_NAME_ESCAPER_PEER_LIVE: &str = "escaper.peer.live";
+const METRIC_NAME_ESCAPER_PEER_EXPIRED: &str = "escaper.peer.expired";
+const METRIC_NAME_ESCAPER_EGRESS_BYTES: &str = "escaper.egress.bytes";
+const METRIC_NAME_ESCAPER_PEER_DEPRECATED_IO_IN_BYTES: &str =
+    "escaper.peer.deprecated.traffic.in.bytes";
+const METRIC_NAME_ESCAPER_PEER_DEPRECATED_IO_OUT_BYTES: &str =
+    "escaper.peer.deprecated.traffic.out.bytes";
+const METRIC_NAME_ESCAPER_PEER_AREA_FALLBACK: &str = "escaper.peer.area_fallback";
+const METRIC_NAME_ESCAPER_PEER_CANARY_ATTEMPT: &str = "escaper.peer.canary.attempt";
+const METRIC_NAME_ESCAPER_PEER_CANARY_SUCCESS: &str = "escaper.peer.canary.success";
+const METRIC_NAME_ESCAPER_PEER_CANARY_CONNECT_TIME: &str = "escaper.peer.canary.connect_time";
 const METRIC_NAME_ESCAPER_FORBIDDEN_IP_BLOCKED: &str = "escaper.forbidden.ip_blocked";
 
+const TAG_KEY_AREA: &str = "area";
+const TAG_KEY_ROLE: &str = "role";
+const TAG_KEY_PEER_TYPE: &str = "peer_type";
+
 const METRIC_NAME_ROUTE_REQUEST_PASSED: &str = "route.request.passed";
 const METRIC_NAME_ROUTE_REQUEST_FAILED: &str = "route.request.failed";
 
@@ -69,8 +98,22 @@ struct EscaperSnapshotStats {
     task_total: u64,
     conn_attempt: u64,
     conn_establish: u64,
+    source_port_exhausted: u64,
+    peer_tls_insecure: u64,
+    peer_eip_verified: u64,
+    peer_eip_mismatch: u64,
+    peer_evicted: u64,
+    peer_duplicated: u64,
+    peer_policy_denied: u64,
+    egress_down: u64,
+    egress_restored: u64,
     tcp: TcpIoSnapshot,
     udp: UdpIoSnapshot,
+    tagged_tcp: AHashMap<StatId, TcpIoSnapshot>,
+    area_tcp: AHashMap<StatId, TcpIoSnapshot>,
+    area_fallback: AHashMap<String, u64>,
+    deprecated_peer_tcp: TcpIoSnapshot,
+    peer_canary: PeerCanarySnapshot,
     forbidden: EscaperForbiddenSnapshot,
 }
 
@@ -148,6 +191,132 @@ fn emit_escaper_stats(
         .send();
     snap.conn_establish = new_value;
 
+    let new_value = stats.get_source_port_exhausted();
+    if new_value != 0 || snap.source_port_exhausted != 0 {
+        let diff_value = new_value.wrapping_sub(snap.source_port_exhausted);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_SOURCE_PORT_EXHAUSTED,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.source_port_exhausted = new_value;
+    }
+
+    let new_value = stats.get_peer_tls_insecure();
+    if new_value != 0 || snap.peer_tls_insecure != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_tls_insecure);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_TLS_INSECURE,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.peer_tls_insecure = new_value;
+    }
+
+    let new_value = stats.get_peer_eip_verified();
+    if new_value != 0 || snap.peer_eip_verified != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_eip_verified);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_EIP_VERIFIED,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.peer_eip_verified = new_value;
+    }
+
+    let new_value = stats.get_peer_eip_mismatch();
+    if new_value != 0 || snap.peer_eip_mismatch != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_eip_mismatch);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_EIP_MISMATCH,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.peer_eip_mismatch = new_value;
+    }
+
+    let new_value = stats.get_peer_evicted();
+    if new_value != 0 || snap.peer_evicted != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_evicted);
+        client
+            .count_with_tags(METRIC_NAME_ESCAPER_PEER_EVICTED, diff_value, &common_tags)
+            .send();
+        snap.peer_evicted = new_value;
+    }
+
+    let new_value = stats.get_peer_duplicated();
+    if new_value != 0 || snap.peer_duplicated != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_duplicated);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_DUPLICATED,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.peer_duplicated = new_value;
+    }
+
+    let new_value = stats.get_peer_policy_denied();
+    if new_value != 0 || snap.peer_policy_denied != 0 {
+        let diff_value = new_value.wrapping_sub(snap.peer_policy_denied);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_POLICY_DENIED,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.peer_policy_denied = new_value;
+    }
+
+    let new_value = stats.get_egress_down();
+    if new_value != 0 || snap.egress_down != 0 {
+        let diff_value = new_value.wrapping_sub(snap.egress_down);
+        client
+            .count_with_tags(METRIC_NAME_ESCAPER_EGRESS_DOWN, diff_value, &common_tags)
+            .send();
+        snap.egress_down = new_value;
+    }
+
+    let new_value = stats.get_egress_restored();
+    if new_value != 0 || snap.egress_restored != 0 {
+        let diff_value = new_value.wrapping_sub(snap.egress_restored);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_EGRESS_RESTORED,
+                diff_value,
+                &common_tags,
+            )
+            .send();
+        snap.egress_restored = new_value;
+    }
+
+    if let Some(canary_stats) = stats.peer_canary_snapshot() {
+        emit_peer_connect_stats(
+            client,
+            canary_stats.canary,
+            &mut snap.peer_canary.canary,
+            &common_tags,
+            "canary",
+        );
+        emit_peer_connect_stats(
+            client,
+            canary_stats.live,
+            &mut snap.peer_canary.live,
+            &common_tags,
+            "live",
+        );
+    }
+
     if let Some(forbidden_stats) = stats.forbidden_snapshot() {
         emit_forbidden_stats(client, forbidden_stats, &mut snap.forbidden, &common_tags);
     }
@@ -159,6 +328,118 @@ fn emit_escaper_stats(
     if let Some(udp_io_stats) = stats.udp_io_snapshot() {
         emit_udp_io_to_statsd(client, udp_io_stats, &mut snap.udp, &common_tags);
     }
+
+    if let Some(staleness) = stats.peer_source_staleness() {
+        client
+            .gauge_with_tags(
+                METRIC_NAME_ESCAPER_PEER_SOURCE_STALENESS,
+                staleness.as_secs(),
+                &common_tags,
+            )
+            .send();
+    }
+
+    if let Some(generation) = stats.peer_feed_generation() {
+        client
+            .gauge_with_tags(
+                METRIC_NAME_ESCAPER_PEER_FEED_GENERATION,
+                generation,
+                &common_tags,
+            )
+            .send();
+    }
+
+    for (peer_type, live, expired) in stats.peer_live_expired_counts() {
+        client
+            .gauge_with_tags(METRIC_NAME_ESCAPER_PEER_LIVE, live, &common_tags)
+            .with_tag(TAG_KEY_PEER_TYPE, peer_type)
+            .send();
+        client
+            .gauge_with_tags(METRIC_NAME_ESCAPER_PEER_EXPIRED, expired, &common_tags)
+            .with_tag(TAG_KEY_PEER_TYPE, peer_type)
+            .send();
+    }
+
+    for tagged_stats in stats.tagged_tcp_io_stats() {
+        let mut tags = common_tags.clone();
+        tags.add_static_tags(tagged_stats.tags());
+        let snap = snap.tagged_tcp.entry(tagged_stats.stat_id()).or_default();
+        emit_tagged_tcp_io_to_statsd(client, tagged_stats.io.snapshot(), snap, &tags);
+    }
+
+    for area_stats in stats.area_tcp_io_stats() {
+        let mut tags = common_tags.clone();
+        tags.add_static_tags(area_stats.tags());
+        let snap = snap.area_tcp.entry(area_stats.stat_id()).or_default();
+        emit_area_tcp_io_to_statsd(client, area_stats.io.snapshot(), snap, &tags);
+    }
+
+    if let Some(io_stats) = stats.deprecated_peer_tcp_io_snapshot() {
+        emit_deprecated_peer_tcp_io_to_statsd(
+            client,
+            io_stats,
+            &mut snap.deprecated_peer_tcp,
+            &common_tags,
+        );
+    }
+
+    for (area, new_value) in stats.peer_area_fallback_counts() {
+        let snap_value = snap.area_fallback.entry(area.clone()).or_default();
+        let diff_value = new_value.wrapping_sub(*snap_value);
+        client
+            .count_with_tags(
+                METRIC_NAME_ESCAPER_PEER_AREA_FALLBACK,
+                diff_value,
+                &common_tags,
+            )
+            .with_tag(TAG_KEY_AREA, &area)
+            .send();
+        *snap_value = new_value;
+    }
+}
+
+fn emit_peer_connect_stats(
+    client: &mut StatsdClient,
+    stats: PeerConnectSnapshot,
+    snap: &mut PeerConnectSnapshot,
+    common_tags: &StatsdTagGroup,
+    role: &str,
+) {
+    if stats.attempt == 0 && snap.attempt == 0 {
+        return;
+    }
+
+    let diff_value = stats.attempt.wrapping_sub(snap.attempt);
+    client
+        .count_with_tags(
+            METRIC_NAME_ESCAPER_PEER_CANARY_ATTEMPT,
+            diff_value,
+            common_tags,
+        )
+        .with_tag(TAG_KEY_ROLE, role)
+        .send();
+
+    let diff_value = stats.success.wrapping_sub(snap.success);
+    client
+        .count_with_tags(
+            METRIC_NAME_ESCAPER_PEER_CANARY_SUCCESS,
+            diff_value,
+            common_tags,
+        )
+        .with_tag(TAG_KEY_ROLE, role)
+        .send();
+
+    let diff_value = stats.connect_time.wrapping_sub(snap.connect_time);
+    client
+        .count_with_tags(
+            METRIC_NAME_ESCAPER_PEER_CANARY_CONNECT_TIME,
+            diff_value,
+            common_tags,
+        )
+        .with_tag(TAG_KEY_ROLE, role)
+        .send();
+
+    *snap = stats;
 }
 
 fn emit_forbidden_stats(
@@ -207,6 +488,80 @@ fn emit_tcp_io_to_statsd(
     emit_field!(in_bytes, METRIC_NAME_ESCAPER_IO_IN_BYTES);
 }
 
+fn emit_tagged_tcp_io_to_statsd(
+    client: &mut StatsdClient,
+    stats: TcpIoSnapshot,
+    snap: &mut TcpIoSnapshot,
+    tags: &StatsdTagGroup,
+) {
+    if stats.out_bytes == 0 && snap.out_bytes == 0 {
+        return;
+    }
+
+    macro_rules! emit_field {
+        ($field:ident, $name:expr) => {
+            let new_value = stats.$field;
+            let diff_value = new_value.wrapping_sub(snap.$field);
+            client
+                .count_with_tags($name, diff_value, tags)
+                .with_tag(TAG_KEY_TRANSPORT, TRANSPORT_TYPE_TCP)
+                .send();
+            snap.$field = new_value;
+        };
+    }
+
+    emit_field!(out_bytes, METRIC_NAME_ESCAPER_PEER_IO_OUT_BYTES);
+    emit_field!(in_bytes, METRIC_NAME_ESCAPER_PEER_IO_IN_BYTES);
+}
+
+fn emit_deprecated_peer_tcp_io_to_statsd(
+    client: &mut StatsdClient,
+    stats: TcpIoSnapshot,
+    snap: &mut TcpIoSnapshot,
+    common_tags: &StatsdTagGroup,
+) {
+    if stats.out_bytes == 0 && snap.out_bytes == 0 {
+        return;
+    }
+
+    macro_rules! emit_field {
+        ($field:ident, $name:expr) => {
+            let new_value = stats.$field;
+            let diff_value = new_value.wrapping_sub(snap.$field);
+            client
+                .count_with_tags($name, diff_value, common_tags)
+                .with_tag(TAG_KEY_TRANSPORT, TRANSPORT_TYPE_TCP)
+                .send();
+            snap.$field = new_value;
+        };
+    }
+
+    emit_field!(out_bytes, METRIC_NAME_ESCAPER_PEER_DEPRECATED_IO_OUT_BYTES);
+    emit_field!(in_bytes, METRIC_NAME_ESCAPER_PEER_DEPRECATED_IO_IN_BYTES);
+}
+
+/// emit the total bytes in both directions
+fn emit_area_tcp_io_to_statsd(
+    client: &mut StatsdClient,
+    stats: TcpIoSnapshot,
+    snap: &mut TcpIoSnapshot,
+    tags: &StatsdTagGroup,
+) {
+    if stats.out_bytes == 0 && snap.out_bytes == 0 {
+        return;
+    }
+
+    let diff_value = stats
+        .in_bytes
+        .wrapping_sub(snap.in_bytes)
+        .wrapping_add(stats.out_bytes.wrapping_sub(snap.out_bytes));
+    client
+        .count_with_tags(METRIC_NAME_ESCAPER_EGRESS_BYTES, diff_value, tags)
+        .with_tag(TAG_KEY_TRANSPORT, TRANSPORT_TYPE_TCP)
+        .send();
+    *snap = stats;
+}
+
 fn emit_udp_io_to_statsd(
     client: &mut StatsdClient,
     stats: UdpIoSnapshot,
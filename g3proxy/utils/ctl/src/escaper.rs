@@ -36,34 +36,41 @@ const SUBCOMMAND_PUBLISH: &str = "publish";
 const SUBCOMMAND_PUBLISH_ARG_FILE: &str = "file";
 const SUBCOMMAND_PUBLISH_ARG_DATA: &str = "data";
 
+const SUBCOMMAND_SIMULATE_SELECTION: &str = "simulate-selection";
+
+fn add_json_data_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new(SUBCOMMAND_PUBLISH_ARG_FILE)
+            .value_name("FILE PATH")
+            .num_args(1)
+            .value_hint(ValueHint::FilePath)
+            .value_parser(value_parser!(PathBuf))
+            .required_unless_present(SUBCOMMAND_PUBLISH_ARG_DATA)
+            .conflicts_with(SUBCOMMAND_PUBLISH_ARG_DATA)
+            .short('f')
+            .long("file"),
+    )
+    .arg(
+        Arg::new(SUBCOMMAND_PUBLISH_ARG_DATA)
+            .value_name("JSON DATA")
+            .num_args(1)
+            .required_unless_present(SUBCOMMAND_PUBLISH_ARG_FILE)
+            .conflicts_with(SUBCOMMAND_PUBLISH_ARG_FILE),
+    )
+}
+
 pub fn command() -> Command {
     Command::new(COMMAND)
         .arg(Arg::new(COMMAND_ARG_NAME).required(true).num_args(1))
         .subcommand_required(true)
-        .subcommand(
-            Command::new(SUBCOMMAND_PUBLISH)
-                .arg(
-                    Arg::new(SUBCOMMAND_PUBLISH_ARG_FILE)
-                        .value_name("FILE PATH")
-                        .num_args(1)
-                        .value_hint(ValueHint::FilePath)
-                        .value_parser(value_parser!(PathBuf))
-                        .required_unless_present(SUBCOMMAND_PUBLISH_ARG_DATA)
-                        .conflicts_with(SUBCOMMAND_PUBLISH_ARG_DATA)
-                        .short('f')
-                        .long("file"),
-                )
-                .arg(
-                    Arg::new(SUBCOMMAND_PUBLISH_ARG_DATA)
-                        .value_name("JSON DATA")
-                        .num_args(1)
-                        .required_unless_present(SUBCOMMAND_PUBLISH_ARG_FILE)
-                        .conflicts_with(SUBCOMMAND_PUBLISH_ARG_FILE),
-                ),
-        )
+        .subcommand(add_json_data_args(Command::new(SUBCOMMAND_PUBLISH)))
+        .subcommand(add_json_data_args(
+            Command::new(SUBCOMMAND_SIMULATE_SELECTION)
+                .about("Simulate next proxy peer selection without sending real traffic"),
+        ))
 }
 
-async fn publish(client: &escaper_control::Client, args: &ArgMatches) -> CommandResult<()> {
+async fn read_json_data(args: &ArgMatches) -> CommandResult<String> {
     let data = if let Some(file) = args.get_one::<PathBuf>(SUBCOMMAND_PUBLISH_ARG_FILE) {
         tokio::fs::read_to_string(file).await.map_err(|e| {
             CommandError::Cli(anyhow!(
@@ -79,16 +86,31 @@ async fn publish(client: &escaper_control::Client, args: &ArgMatches) -> Command
 
     if let Err(e) = serde_json::Value::from_str(&data) {
         return Err(CommandError::Cli(anyhow!(
-            "the data to publish is not valid json: {e:?}"
+            "the input data is not valid json: {e:?}"
         )));
     }
+    Ok(data)
+}
 
+async fn publish(client: &escaper_control::Client, args: &ArgMatches) -> CommandResult<()> {
+    let data = read_json_data(args).await?;
     let mut req = client.publish_request();
     req.get().set_data(data.as_str());
     let rsp = req.send().promise.await?;
     parse_operation_result(rsp.get()?.get_result()?)
 }
 
+async fn simulate_selection(
+    client: &escaper_control::Client,
+    args: &ArgMatches,
+) -> CommandResult<()> {
+    let data = read_json_data(args).await?;
+    let mut req = client.simulate_selection_request();
+    req.get().set_data(data.as_str());
+    let rsp = req.send().promise.await?;
+    parse_operation_result(rsp.get()?.get_result()?)
+}
+
 pub async fn run(client: &proc_control::Client, args: &ArgMatches) -> CommandResult<()> {
     let name = args.get_one::<String>(COMMAND_ARG_NAME).unwrap();
 
@@ -99,6 +121,11 @@ pub async fn run(client: &proc_control::Client, args: &ArgMatches) -> CommandRes
                 .and_then(|escaper| async move { publish(&escaper, args).await })
                 .await
         }
+        SUBCOMMAND_SIMULATE_SELECTION => {
+            super::proc::get_escaper(client, name)
+                .and_then(|escaper| async move { simulate_selection(&escaper, args).await })
+                .await
+        }
         _ => unreachable!(),
     }
 }
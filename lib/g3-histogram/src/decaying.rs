@@ -0,0 +1,235 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hdrhistogram::{Counter, CreationError, Histogram, RecordError};
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+
+use crate::{HistogramRecorder, HistogramStats};
+
+#[derive(Debug, Error)]
+pub enum DecayingCreationError {
+    #[error("out of range(0.0-1.0] decay factor {0}")]
+    InvalidDecay(f64),
+    #[error("invalid histogram: {0}")]
+    InvalidHistogram(#[from] CreationError),
+}
+
+/// A histogram whose counts are scaled down by a decay factor on each refresh interval,
+/// so the recent values weigh more than the old ones, which approximates a sliding window.
+///
+/// The counts are integers, so the scaled counts are truncated. A count will reach 0 once it
+/// is less than `1 / decay`, which makes the tail of the distribution (the buckets with small
+/// counts) fade faster than a real exponential decay. Use a decay factor close to 1 and a
+/// short refresh interval if the precision of the rare values matters.
+pub struct DecayingHistogram<T: Counter> {
+    refresh_interval: Duration,
+    decay: f64,
+    inner: Histogram<T>,
+    receiver: mpsc::UnboundedReceiver<T>,
+}
+
+impl<T: Counter> DecayingHistogram<T> {
+    /// Create a new histogram with the decay factor, which should be in range (0, 1].
+    pub fn new(
+        refresh_interval: Duration,
+        decay: f64,
+    ) -> Result<(Self, HistogramRecorder<T>), DecayingCreationError> {
+        DecayingHistogram::with_sigfig(refresh_interval, decay, 3)
+    }
+
+    pub fn with_sigfig(
+        refresh_interval: Duration,
+        decay: f64,
+        sigfig: u8,
+    ) -> Result<(Self, HistogramRecorder<T>), DecayingCreationError> {
+        let inner = Histogram::new(sigfig)?;
+        DecayingHistogram::with_inner(refresh_interval, decay, inner)
+    }
+
+    pub fn new_with_max(
+        refresh_interval: Duration,
+        decay: f64,
+        high: u64,
+        sigfig: u8,
+    ) -> Result<(Self, HistogramRecorder<T>), DecayingCreationError> {
+        let inner = Histogram::new_with_max(high, sigfig)?;
+        DecayingHistogram::with_inner(refresh_interval, decay, inner)
+    }
+
+    pub fn new_with_bounds(
+        refresh_interval: Duration,
+        decay: f64,
+        low: u64,
+        high: u64,
+        sigfig: u8,
+    ) -> Result<(Self, HistogramRecorder<T>), DecayingCreationError> {
+        let inner = Histogram::new_with_bounds(low, high, sigfig)?;
+        DecayingHistogram::with_inner(refresh_interval, decay, inner)
+    }
+
+    fn with_inner(
+        refresh_interval: Duration,
+        decay: f64,
+        inner: Histogram<T>,
+    ) -> Result<(Self, HistogramRecorder<T>), DecayingCreationError> {
+        if !(decay > 0.0 && decay <= 1.0) {
+            return Err(DecayingCreationError::InvalidDecay(decay));
+        }
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Ok((
+            DecayingHistogram {
+                refresh_interval,
+                decay,
+                inner,
+                receiver,
+            },
+            HistogramRecorder::new(sender),
+        ))
+    }
+
+    pub fn auto(&mut self, enabled: bool) {
+        self.inner.auto(enabled);
+    }
+
+    pub fn refresh(&mut self) -> Result<(), RecordError> {
+        use mpsc::error::TryRecvError;
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(v) => self.inner.record(v.as_u64())?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Scale down the counts of all recorded values by the decay factor
+    pub fn decay(&mut self) {
+        if self.decay >= 1.0 || self.inner.is_empty() {
+            return;
+        }
+
+        let mut decayed = Histogram::<T>::new_from(&self.inner);
+        for v in self.inner.iter_recorded() {
+            let count = (v.count_at_value().as_f64() * self.decay) as u64;
+            if let Some(count) = T::from_u64(count).filter(|c| !c.is_zero()) {
+                // the value is in range of the old histogram, so it won't fail
+                let _ = decayed.record_n(v.value_iterated_to(), count);
+            }
+        }
+        self.inner = decayed;
+    }
+
+    pub fn inner(&self) -> &Histogram<T> {
+        &self.inner
+    }
+}
+
+impl<T> DecayingHistogram<T>
+where
+    T: Counter + Send + 'static,
+{
+    pub fn spawn_refresh(mut self, stats: Arc<HistogramStats>, handle: Option<Handle>) {
+        let handle = handle.unwrap_or_else(Handle::current);
+        handle.spawn(async move {
+            const BATCH_SIZE: usize = 16;
+            let mut buf = Vec::with_capacity(BATCH_SIZE);
+            let mut refresh_interval = tokio::time::interval(self.refresh_interval);
+
+            loop {
+                tokio::select! {
+                    biased;
+
+                    n = self.receiver.recv_many(&mut buf, BATCH_SIZE) => {
+                        if n == 0 {
+                            break;
+                        }
+                        for v in buf.iter().take(n) {
+                            let _ = self.inner.record(v.as_u64());
+                        }
+                        buf.clear();
+                    }
+                    _ = refresh_interval.tick() => {
+                        // publish even if empty, or the stats stay stale after all values faded
+                        stats.update(&self.inner);
+                        self.decay();
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_values_fade() {
+        let (mut histogram, recorder) =
+            DecayingHistogram::<u64>::new(Duration::from_secs(1), 0.5).unwrap();
+        for _ in 0..100 {
+            recorder.record(10).unwrap();
+        }
+        histogram.refresh().unwrap();
+        assert_eq!(histogram.inner().len(), 100);
+
+        histogram.decay();
+        assert_eq!(histogram.inner().len(), 50);
+        for _ in 0..100 {
+            recorder.record(1000).unwrap();
+        }
+        histogram.refresh().unwrap();
+        assert_eq!(histogram.inner().len(), 150);
+        assert_eq!(histogram.inner().value_at_quantile(0.5), 1000);
+
+        // 50 -> 25 -> 12 -> 6 -> 3 -> 1 -> 0
+        for _ in 0..6 {
+            histogram.decay();
+        }
+        assert_eq!(histogram.inner().min(), 1000);
+        assert_eq!(histogram.inner().len(), 1);
+
+        histogram.decay();
+        assert!(histogram.inner().is_empty());
+    }
+
+    #[test]
+    fn no_decay() {
+        let (mut histogram, recorder) =
+            DecayingHistogram::<u64>::new(Duration::from_secs(1), 1.0).unwrap();
+        recorder.record(10).unwrap();
+        histogram.refresh().unwrap();
+        histogram.decay();
+        assert_eq!(histogram.inner().len(), 1);
+    }
+
+    #[test]
+    fn invalid_decay() {
+        for decay in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(matches!(
+                DecayingHistogram::<u64>::new(Duration::from_secs(1), decay),
+                Err(DecayingCreationError::InvalidDecay(_))
+            ));
+        }
+    }
+}
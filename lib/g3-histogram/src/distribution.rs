@@ -0,0 +1,122 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+
+use hdrhistogram::{Counter, Histogram};
+
+/// Write the histogram in the percentile distribution text format used by HdrHistogram,
+/// which is the same as the output of `outputPercentileDistribution` in the Java version.
+///
+/// The recorded values will be divided by `value_scale` in the output, so for a duration
+/// histogram recorded in nanoseconds, set it to 1_000_000.0 to output in milliseconds.
+pub fn write_percentile_distribution<T, W>(
+    histogram: &Histogram<T>,
+    writer: &mut W,
+    ticks_per_half_distance: u32,
+    value_scale: f64,
+) -> io::Result<()>
+where
+    T: Counter,
+    W: io::Write,
+{
+    writeln!(
+        writer,
+        "{:>12} {:>14} {:>10} {:>14}\n",
+        "Value", "Percentile", "TotalCount", "1/(1-Percentile)"
+    )?;
+
+    let mut total_count: u64 = 0;
+    for v in histogram.iter_quantiles(ticks_per_half_distance) {
+        total_count += v.count_since_last_iteration();
+        let value = v.value_iterated_to() as f64 / value_scale;
+        let quantile = v.quantile_iterated_to();
+        if quantile < 1.0 {
+            writeln!(
+                writer,
+                "{value:12.3} {quantile:2.12} {total_count:10} {:14.2}",
+                1.0 / (1.0 - quantile)
+            )?;
+        } else {
+            writeln!(writer, "{value:12.3} {quantile:2.12} {total_count:10}")?;
+        }
+    }
+
+    let mean = histogram.mean() / value_scale;
+    let stdev = histogram.stdev() / value_scale;
+    let max = histogram.max() as f64 / value_scale;
+    // the same as the sub bucket count calculation in HdrHistogram
+    let largest_single_unit = 2 * 10_u64.pow(histogram.sigfig() as u32);
+    let sub_buckets = largest_single_unit.next_power_of_two();
+    writeln!(
+        writer,
+        "#[Mean    = {mean:12.3}, StdDeviation   = {stdev:12.3}]"
+    )?;
+    writeln!(
+        writer,
+        "#[Max     = {max:12.3}, Total count    = {:12}]",
+        histogram.len()
+    )?;
+    writeln!(
+        writer,
+        "#[Buckets = {:12}, SubBuckets     = {sub_buckets:12}]",
+        histogram.buckets()
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_distribution() {
+        let mut h = Histogram::<u64>::new(3).unwrap();
+        for v in 1..=1000 {
+            h.record(v * 1000).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        write_percentile_distribution(&h, &mut buf, 5, 1000.0).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        let mut lines = s.lines();
+        assert_eq!(
+            lines.next(),
+            Some("       Value     Percentile TotalCount 1/(1-Percentile)")
+        );
+        assert_eq!(lines.next(), Some(""));
+        assert_eq!(
+            lines.next(),
+            Some("       1.000 0.000000000000          1           1.00")
+        );
+
+        let lines: Vec<&str> = s.lines().collect();
+        let n = lines.len();
+        assert_eq!(lines[n - 4], "    1000.447 1.000000000000       1000");
+        assert_eq!(
+            lines[n - 3],
+            "#[Mean    =      500.505, StdDeviation   =      288.676]"
+        );
+        assert_eq!(
+            lines[n - 2],
+            "#[Max     =     1000.447, Total count    =         1000]"
+        );
+        assert_eq!(
+            lines[n - 1],
+            "#[Buckets =           10, SubBuckets     =         2048]"
+        );
+    }
+}
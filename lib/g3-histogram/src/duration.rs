@@ -0,0 +1,143 @@
+/*
+ * Copyright 2024 ByteDance and/or its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hdrhistogram::{CreationError, Histogram, RecordError};
+use tokio::sync::mpsc;
+
+use crate::{HistogramRecorder, HistogramStats, KeepingHistogram};
+
+fn duration_to_nanos(dur: Duration) -> u64 {
+    u64::try_from(dur.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// A [KeepingHistogram] which records [Duration] values at nanosecond resolution.
+pub struct DurationHistogram {
+    inner: KeepingHistogram<u64>,
+}
+
+impl DurationHistogram {
+    pub fn new() -> (Self, DurationHistogramRecorder) {
+        let (inner, recorder) = KeepingHistogram::new();
+        (
+            DurationHistogram { inner },
+            DurationHistogramRecorder { inner: recorder },
+        )
+    }
+
+    pub fn new_with_max(
+        high: Duration,
+        sigfig: u8,
+    ) -> Result<(Self, DurationHistogramRecorder), CreationError> {
+        let (inner, recorder) = KeepingHistogram::new_with_max(duration_to_nanos(high), sigfig)?;
+        Ok((
+            DurationHistogram { inner },
+            DurationHistogramRecorder { inner: recorder },
+        ))
+    }
+
+    /// Clamp out of range values to the max trackable value instead of returning error.
+    pub fn clamp_out_of_range(&mut self, enabled: bool) {
+        self.inner.clamp_out_of_range(enabled);
+    }
+
+    pub fn out_of_range(&self) -> u64 {
+        self.inner.out_of_range()
+    }
+
+    pub fn refresh(&mut self) -> Result<(), RecordError> {
+        self.inner.refresh()
+    }
+
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.inner.inner().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.inner().is_empty()
+    }
+
+    pub fn min(&self) -> Duration {
+        Duration::from_nanos(self.inner.inner().min())
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.inner.inner().max())
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_nanos(self.inner.inner().mean() as u64)
+    }
+
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        Duration::from_nanos(self.inner.inner().value_at_quantile(quantile))
+    }
+
+    /// Get the raw histogram, all values in it are in nanoseconds.
+    pub fn inner(&self) -> &Histogram<u64> {
+        self.inner.inner()
+    }
+
+    pub fn spawn_refresh(self, stats: Arc<HistogramStats>) {
+        self.inner.spawn_refresh(stats);
+    }
+}
+
+#[derive(Clone)]
+pub struct DurationHistogramRecorder {
+    inner: HistogramRecorder<u64>,
+}
+
+impl DurationHistogramRecorder {
+    pub fn record(&self, dur: Duration) -> Result<(), mpsc::error::SendError<u64>> {
+        self.inner.record(duration_to_nanos(dur))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_read() {
+        let (mut histogram, recorder) = DurationHistogram::new();
+        recorder.record(Duration::from_millis(1)).unwrap();
+        recorder.record(Duration::from_micros(10)).unwrap();
+        histogram.refresh().unwrap();
+        assert_eq!(histogram.len(), 2);
+        assert_eq!(histogram.inner().min(), 10_000);
+        assert!(histogram.min() >= Duration::from_micros(10));
+        assert!(histogram.min() < Duration::from_micros(11));
+        assert!(histogram.max() >= Duration::from_millis(1));
+        assert!(histogram.value_at_quantile(1.0) >= Duration::from_millis(1));
+    }
+
+    #[test]
+    fn clamp_max() {
+        let (mut histogram, recorder) =
+            DurationHistogram::new_with_max(Duration::from_secs(1), 3).unwrap();
+        histogram.clamp_out_of_range(true);
+        recorder.record(Duration::from_secs(10)).unwrap();
+        histogram.refresh().unwrap();
+        assert_eq!(histogram.out_of_range(), 1);
+        assert!(histogram.max() >= Duration::from_secs(1));
+        assert!(histogram.max() < Duration::from_secs(2));
+    }
+}
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use hdrhistogram::{Counter, CreationError, Histogram, RecordError};
@@ -24,6 +25,42 @@ use crate::{HistogramRecorder, HistogramStats};
 pub struct KeepingHistogram<T: Counter> {
     inner: Histogram<T>,
     receiver: mpsc::UnboundedReceiver<T>,
+    clamp_out_of_range: bool,
+    out_of_range: u64,
+    tagged: Option<TaggedSources<T>>,
+}
+
+struct TaggedSources<T: Counter> {
+    /// a weak one, so the channel will be closed after all tagged recorders dropped
+    sender: mpsc::WeakUnboundedSender<(u32, T)>,
+    receiver: mpsc::UnboundedReceiver<(u32, T)>,
+    counts: HashMap<u32, u64>,
+}
+
+impl<T: Counter> TaggedSources<T> {
+    fn new() -> (Self, mpsc::UnboundedSender<(u32, T)>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let tagged = TaggedSources {
+            sender: sender.downgrade(),
+            receiver,
+            counts: HashMap::new(),
+        };
+        (tagged, sender)
+    }
+
+    fn sender(&mut self) -> mpsc::UnboundedSender<(u32, T)> {
+        if let Some(sender) = self.sender.upgrade() {
+            return sender;
+        }
+        // all previous recorders dropped, move the pending values to a new channel
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut old_receiver = std::mem::replace(&mut self.receiver, receiver);
+        while let Ok(v) = old_receiver.try_recv() {
+            let _ = sender.send(v);
+        }
+        self.sender = sender.downgrade();
+        sender
+    }
 }
 
 impl<T: Counter> KeepingHistogram<T> {
@@ -35,7 +72,13 @@ impl<T: Counter> KeepingHistogram<T> {
         let inner = Histogram::new(sigfig)?;
         let (sender, receiver) = mpsc::unbounded_channel();
         Ok((
-            KeepingHistogram { inner, receiver },
+            KeepingHistogram {
+                inner,
+                receiver,
+                clamp_out_of_range: false,
+                out_of_range: 0,
+                tagged: None,
+            },
             HistogramRecorder::new(sender),
         ))
     }
@@ -47,7 +90,13 @@ impl<T: Counter> KeepingHistogram<T> {
         let inner = Histogram::new_with_max(high, sigfig)?;
         let (sender, receiver) = mpsc::unbounded_channel();
         Ok((
-            KeepingHistogram { inner, receiver },
+            KeepingHistogram {
+                inner,
+                receiver,
+                clamp_out_of_range: false,
+                out_of_range: 0,
+                tagged: None,
+            },
             HistogramRecorder::new(sender),
         ))
     }
@@ -60,7 +109,13 @@ impl<T: Counter> KeepingHistogram<T> {
         let inner = Histogram::new_with_bounds(low, high, sigfig)?;
         let (sender, receiver) = mpsc::unbounded_channel();
         Ok((
-            KeepingHistogram { inner, receiver },
+            KeepingHistogram {
+                inner,
+                receiver,
+                clamp_out_of_range: false,
+                out_of_range: 0,
+                tagged: None,
+            },
             HistogramRecorder::new(sender),
         ))
     }
@@ -69,16 +124,78 @@ impl<T: Counter> KeepingHistogram<T> {
         self.inner.auto(enabled);
     }
 
+    /// Clamp out of range values to the max trackable value instead of returning error.
+    ///
+    /// The number of clamped values can be got by calling `out_of_range()`.
+    pub fn clamp_out_of_range(&mut self, enabled: bool) {
+        self.clamp_out_of_range = enabled;
+    }
+
+    pub fn out_of_range(&self) -> u64 {
+        self.out_of_range
+    }
+
+    /// Create a new recorder which carries the source id.
+    ///
+    /// Values recorded by it will be merged into the same histogram,
+    /// with the count of each source kept, which can be got by calling `per_source_count()`,
+    /// or `HistogramStats::per_source_count()` after `spawn_refresh()`.
+    pub fn tagged_recorder(&mut self, source_id: u32) -> HistogramRecorder<T> {
+        let sender = match &mut self.tagged {
+            Some(tagged) => tagged.sender(),
+            None => {
+                let (tagged, sender) = TaggedSources::new();
+                self.tagged = Some(tagged);
+                sender
+            }
+        };
+        HistogramRecorder::new_tagged(source_id, sender)
+    }
+
+    /// Get the count of values recorded by tagged recorders with this source id
+    pub fn per_source_count(&self, source_id: u32) -> u64 {
+        self.tagged
+            .as_ref()
+            .and_then(|tagged| tagged.counts.get(&source_id).copied())
+            .unwrap_or(0)
+    }
+
+    fn record_tagged(&mut self, source_id: u32, v: u64) -> Result<(), RecordError> {
+        if let Some(tagged) = &mut self.tagged {
+            *tagged.counts.entry(source_id).or_insert(0) += 1;
+        }
+        self.record(v)
+    }
+
+    fn record(&mut self, v: u64) -> Result<(), RecordError> {
+        match self.inner.record(v) {
+            Ok(_) => Ok(()),
+            Err(RecordError::ValueOutOfRangeResizeDisabled) if self.clamp_out_of_range => {
+                self.out_of_range += 1;
+                self.inner.record(self.inner.high())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn refresh(&mut self) -> Result<(), RecordError> {
         use mpsc::error::TryRecvError;
 
         loop {
             match self.receiver.try_recv() {
-                Ok(v) => self.inner.record(v.as_u64())?,
-                Err(TryRecvError::Empty) => return Ok(()),
-                Err(TryRecvError::Disconnected) => return Ok(()),
+                Ok(v) => self.record(v.as_u64())?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break,
             }
         }
+        while let Some(tagged) = &mut self.tagged {
+            match tagged.receiver.try_recv() {
+                Ok((id, v)) => self.record_tagged(id, v.as_u64())?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        Ok(())
     }
 
     pub fn inner(&self) -> &Histogram<T> {
@@ -95,17 +212,130 @@ where
             const BATCH_SIZE: usize = 16;
 
             let mut buf = Vec::with_capacity(BATCH_SIZE);
-            loop {
-                let count = self.receiver.recv_many(&mut buf, BATCH_SIZE).await;
-                if count == 0 {
-                    break;
+            let Some(mut tagged) = self.tagged.take() else {
+                loop {
+                    let count = self.receiver.recv_many(&mut buf, BATCH_SIZE).await;
+                    if count == 0 {
+                        break;
+                    }
+                    for v in buf.iter().take(count) {
+                        let _ = self.record(v.as_u64());
+                    }
+                    buf.clear();
+                    stats.update(self.inner());
                 }
-                for v in buf.iter().take(count) {
-                    let _ = self.inner.record(v.as_u64());
+                return;
+            };
+
+            // stop only after both channels closed and drained
+            let mut tagged_buf = Vec::with_capacity(BATCH_SIZE);
+            let mut plain_closed = false;
+            let mut tagged_closed = false;
+            while !plain_closed || !tagged_closed {
+                tokio::select! {
+                    count = self.receiver.recv_many(&mut buf, BATCH_SIZE), if !plain_closed => {
+                        if count == 0 {
+                            plain_closed = true;
+                            continue;
+                        }
+                        for v in buf.iter().take(count) {
+                            let _ = self.record(v.as_u64());
+                        }
+                        buf.clear();
+                    }
+                    count = tagged.receiver.recv_many(&mut tagged_buf, BATCH_SIZE), if !tagged_closed => {
+                        if count == 0 {
+                            tagged_closed = true;
+                            continue;
+                        }
+                        for (id, v) in tagged_buf.iter().take(count) {
+                            *tagged.counts.entry(*id).or_insert(0) += 1;
+                            let _ = self.record(v.as_u64());
+                        }
+                        tagged_buf.clear();
+                        stats.update_source_counts(&tagged.counts);
+                    }
                 }
-                buf.clear();
                 stats.update(self.inner());
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_strict() {
+        let (mut histogram, recorder) = KeepingHistogram::<u64>::new_with_max(1000, 3).unwrap();
+        recorder.record(1_000_000).unwrap();
+        recorder.record(10).unwrap();
+        assert!(histogram.refresh().is_err());
+        assert_eq!(histogram.out_of_range(), 0);
+    }
+
+    #[test]
+    fn refresh_clamp() {
+        let (mut histogram, recorder) = KeepingHistogram::<u64>::new_with_max(1000, 3).unwrap();
+        histogram.clamp_out_of_range(true);
+        recorder.record(1_000_000).unwrap();
+        recorder.record(10).unwrap();
+        histogram.refresh().unwrap();
+        assert_eq!(histogram.out_of_range(), 1);
+        assert_eq!(histogram.inner().len(), 2);
+        assert!(histogram.inner().max() >= 1000);
+    }
+
+    #[test]
+    fn tagged_sources() {
+        let (mut histogram, recorder) = KeepingHistogram::<u64>::new();
+        let r1 = histogram.tagged_recorder(1);
+        let r2 = histogram.tagged_recorder(2);
+        assert_eq!(recorder.source_id(), None);
+        assert_eq!(r2.source_id(), Some(2));
+
+        recorder.record(5).unwrap();
+        r1.record(10).unwrap();
+        r2.record(20).unwrap();
+        r2.record(30).unwrap();
+        histogram.refresh().unwrap();
+
+        assert_eq!(histogram.inner().len(), 4);
+        assert_eq!(histogram.per_source_count(1), 1);
+        assert_eq!(histogram.per_source_count(2), 2);
+        assert_eq!(histogram.per_source_count(3), 0);
+    }
+
+    #[tokio::test]
+    async fn spawn_refresh_tagged() {
+        let (mut histogram, recorder) = KeepingHistogram::<u64>::new();
+        let r1 = histogram.tagged_recorder(1);
+        r1.record(10).unwrap();
+        let stats = Arc::new(HistogramStats::new());
+        histogram.spawn_refresh(stats.clone());
+
+        recorder.record(5).unwrap();
+        drop(recorder);
+        tokio::task::yield_now().await;
+        r1.record(20).unwrap();
+        r1.record(30).unwrap();
+        drop(r1);
+
+        for _ in 0..100 {
+            if stats.per_source_count(1) == 3 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(stats.per_source_count(1), 3);
+        assert_eq!(stats.per_source_count(2), 0);
+        let mut max = 0.0;
+        stats.foreach_stat(|_, name, v| {
+            if name == "max" {
+                max = v;
+            }
+        });
+        assert!(max >= 30.0);
+    }
+}
@@ -23,6 +23,12 @@ pub use rotating::RotatingHistogram;
 mod keeping;
 pub use keeping::KeepingHistogram;
 
+mod decaying;
+pub use decaying::{DecayingCreationError, DecayingHistogram};
+
+mod duration;
+pub use duration::{DurationHistogram, DurationHistogramRecorder};
+
 mod stats;
 pub use stats::HistogramStats;
 
@@ -31,3 +37,6 @@ pub use quantile::Quantile;
 
 mod config;
 pub use config::HistogramMetricsConfig;
+
+mod distribution;
+pub use distribution::write_percentile_distribution;
@@ -17,17 +17,44 @@
 use hdrhistogram::Counter;
 use tokio::sync::mpsc;
 
+#[derive(Clone)]
+enum RecorderSender<T: Counter> {
+    Plain(mpsc::UnboundedSender<T>),
+    Tagged(u32, mpsc::UnboundedSender<(u32, T)>),
+}
+
 #[derive(Clone)]
 pub struct HistogramRecorder<T: Counter> {
-    sender: mpsc::UnboundedSender<T>,
+    sender: RecorderSender<T>,
 }
 
 impl<T: Counter> HistogramRecorder<T> {
     pub(crate) fn new(sender: mpsc::UnboundedSender<T>) -> Self {
-        HistogramRecorder { sender }
+        HistogramRecorder {
+            sender: RecorderSender::Plain(sender),
+        }
+    }
+
+    pub(crate) fn new_tagged(source_id: u32, sender: mpsc::UnboundedSender<(u32, T)>) -> Self {
+        HistogramRecorder {
+            sender: RecorderSender::Tagged(source_id, sender),
+        }
     }
 
     pub fn record(&self, v: T) -> Result<(), mpsc::error::SendError<T>> {
-        self.sender.send(v)
+        match &self.sender {
+            RecorderSender::Plain(sender) => sender.send(v),
+            RecorderSender::Tagged(id, sender) => sender
+                .send((*id, v))
+                .map_err(|mpsc::error::SendError((_, v))| mpsc::error::SendError(v)),
+        }
+    }
+
+    /// The source id of this recorder, if created by `KeepingHistogram::tagged_recorder`
+    pub fn source_id(&self) -> Option<u32> {
+        match &self.sender {
+            RecorderSender::Plain(_) => None,
+            RecorderSender::Tagged(id, _) => Some(*id),
+        }
     }
 }
@@ -14,7 +14,9 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 
 use hdrhistogram::{Counter, Histogram};
 use portable_atomic::AtomicF64;
@@ -40,6 +42,7 @@ pub struct HistogramStats {
     max: AtomicU64,
     mean: AtomicF64,
     quantile: Vec<HistogramQuantileStats>,
+    source_counts: Mutex<HashMap<u32, u64>>,
 }
 
 impl HistogramStats {
@@ -49,6 +52,7 @@ impl HistogramStats {
             max: AtomicU64::new(0),
             mean: AtomicF64::new(0.0_f64),
             quantile: Vec::with_capacity(8),
+            source_counts: Mutex::new(HashMap::new()),
         }
     }
 
@@ -80,6 +84,21 @@ impl HistogramStats {
         }
     }
 
+    pub(crate) fn update_source_counts(&self, counts: &HashMap<u32, u64>) {
+        self.source_counts.lock().unwrap().clone_from(counts);
+    }
+
+    /// Get the count of values recorded by tagged recorders with this source id,
+    /// only updated by `KeepingHistogram::spawn_refresh()`
+    pub fn per_source_count(&self, source_id: u32) -> u64 {
+        self.source_counts
+            .lock()
+            .unwrap()
+            .get(&source_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
     pub fn foreach_stat<F>(&self, mut call: F)
     where
         F: FnMut(Option<f64>, &str, f64),
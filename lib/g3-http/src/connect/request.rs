@@ -15,16 +15,18 @@
  */
 
 use std::io;
+use std::net::IpAddr;
 
 use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
 
-use g3_types::net::UpstreamAddr;
+use g3_types::net::{Host, UpstreamAddr};
 
 /// the extra header lines should end with \r\n
 pub struct HttpConnectRequest<'a> {
     host: &'a UpstreamAddr,
     static_headers: &'a [String],
     dyn_headers: Vec<String>,
+    omit_port: bool,
 }
 
 impl<'a> HttpConnectRequest<'a> {
@@ -33,6 +35,23 @@ impl<'a> HttpConnectRequest<'a> {
             host,
             static_headers,
             dyn_headers: Vec::new(),
+            omit_port: false,
+        }
+    }
+
+    /// send the authority without the port part
+    pub fn omit_port(&mut self) {
+        self.omit_port = true;
+    }
+
+    fn authority(&self) -> String {
+        if self.omit_port {
+            match self.host.host() {
+                Host::Ip(IpAddr::V6(ip6)) => format!("[{ip6}]"),
+                host => host.to_string(),
+            }
+        } else {
+            self.host.to_string()
         }
     }
 
@@ -46,12 +65,13 @@ impl<'a> HttpConnectRequest<'a> {
     where
         W: AsyncWrite + Unpin,
     {
+        let authority = self.authority();
         let mut buf_writer = BufWriter::new(writer);
         buf_writer
-            .write_all(format!("CONNECT {} HTTP/1.1\r\n", self.host).as_bytes())
+            .write_all(format!("CONNECT {authority} HTTP/1.1\r\n").as_bytes())
             .await?;
         buf_writer
-            .write_all(format!("Host: {}\r\n", self.host).as_bytes())
+            .write_all(format!("Host: {authority}\r\n").as_bytes())
             .await?;
         buf_writer.write_all(b"Connection: keep-alive\r\n").await?;
         for line in self.static_headers {
@@ -59,7 +59,8 @@ pub use self::openssl::as_openssl_tlcp_certificate_pair;
 #[cfg(feature = "openssl")]
 pub use self::openssl::{
     as_openssl_certificate_pair, as_openssl_certificates, as_openssl_private_key,
-    as_to_many_openssl_tls_client_config_builder, as_to_one_openssl_tls_client_config_builder,
+    as_openssl_protocol, as_to_many_openssl_tls_client_config_builder,
+    as_to_one_openssl_tls_client_config_builder,
 };
 
 #[cfg(feature = "route")]
@@ -24,7 +24,7 @@ mod udp;
 mod http;
 
 pub use base::{as_domain, as_egress_area, as_host, as_ipaddr, as_upstream_addr};
-pub use ports::as_ports;
+pub use ports::{as_port_range, as_ports};
 pub use proxy::as_proxy_request_type;
 pub use tcp::{as_tcp_connect_config, as_tcp_keepalive_config, as_tcp_misc_sock_opts};
 pub use udp::as_udp_misc_sock_opts;
@@ -123,6 +123,11 @@ pub fn as_tcp_misc_sock_opts(v: &Value) -> anyhow::Result<TcpMiscSockOpts> {
                         .context(format!("invalid u32 value for key {k}"))?;
                     config.netfilter_mark = Some(mark);
                 }
+                "quick_ack" => {
+                    let quick_ack = crate::value::as_bool(v)
+                        .context(format!("invalid bool value for key {k}"))?;
+                    config.quick_ack = Some(quick_ack);
+                }
                 _ => return Err(anyhow!("invalid key {k}")),
             }
         }
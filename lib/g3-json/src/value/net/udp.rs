@@ -40,6 +40,11 @@ pub fn as_udp_misc_sock_opts(v: &Value) -> anyhow::Result<UdpMiscSockOpts> {
                         .context(format!("invalid u32 value for key {k}"))?;
                     config.netfilter_mark = Some(mark);
                 }
+                "busy_poll" => {
+                    let usecs = crate::value::as_u32(v)
+                        .context(format!("invalid u32 value for key {k}"))?;
+                    config.busy_poll = Some(usecs);
+                }
                 _ => return Err(anyhow!("invalid key {k}")),
             }
         }
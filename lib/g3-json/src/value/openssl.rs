@@ -142,7 +142,7 @@ pub fn as_openssl_tlcp_certificate_pair(
     }
 }
 
-fn as_openssl_protocol(value: &Value) -> anyhow::Result<OpensslProtocol> {
+pub fn as_openssl_protocol(value: &Value) -> anyhow::Result<OpensslProtocol> {
     if let Value::String(s) = value {
         OpensslProtocol::from_str(s)
     } else {
@@ -94,3 +94,40 @@ pub fn as_udp_sock_speed_limit(v: &Value) -> anyhow::Result<UdpSockSpeedLimitCon
     config.validate()?;
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn udp_sock_speed_limit() {
+        let config = as_udp_sock_speed_limit(&json!("10M")).unwrap();
+        assert_eq!(
+            config.shift_millis,
+            g3_types::net::RATE_LIMIT_SHIFT_MILLIS_DEFAULT
+        );
+        assert_eq!(config.max_north_bytes, 10_000_000);
+        assert_eq!(config.max_south_bytes, 10_000_000);
+        assert_eq!(config.max_north_packets, 0);
+        assert_eq!(config.max_south_packets, 0);
+
+        let config = as_udp_sock_speed_limit(&json!({
+            "shift": 8,
+            "upload_packets": 100,
+            "download_packets": 200,
+            "upload_bytes": "1K",
+            "download_bytes": 2048,
+        }))
+        .unwrap();
+        assert_eq!(config.shift_millis, 8);
+        assert_eq!(config.max_north_packets, 100);
+        assert_eq!(config.max_south_packets, 200);
+        assert_eq!(config.max_north_bytes, 1000);
+        assert_eq!(config.max_south_bytes, 2048);
+
+        assert!(as_udp_sock_speed_limit(&json!({"shift": 100})).is_err());
+        assert!(as_udp_sock_speed_limit(&json!({"upload": 100})).is_err());
+        assert!(as_udp_sock_speed_limit(&json!(true)).is_err());
+    }
+}
@@ -21,7 +21,7 @@ mod duration;
 pub use duration::LtDuration;
 
 mod net;
-pub use net::{LtHost, LtIpAddr, LtUpstreamAddr};
+pub use net::{LtEgressArea, LtHost, LtIpAddr, LtUpstreamAddr};
 
 mod uuid;
 pub use self::uuid::LtUuid;
@@ -18,7 +18,7 @@ use std::net::IpAddr;
 
 use slog::{Record, Serializer, Value};
 
-use g3_types::net::{Host, UpstreamAddr};
+use g3_types::net::{EgressArea, Host, UpstreamAddr};
 
 pub struct LtIpAddr(pub IpAddr);
 
@@ -50,6 +50,19 @@ impl<'a> Value for LtUpstreamAddr<'a> {
     }
 }
 
+pub struct LtEgressArea<'a>(pub &'a EgressArea);
+
+impl<'a> Value for LtEgressArea<'a> {
+    fn serialize(
+        &self,
+        _record: &Record,
+        key: slog::Key,
+        serializer: &mut dyn Serializer,
+    ) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self.0))
+    }
+}
+
 pub struct LtHost<'a>(pub &'a Host);
 
 impl<'a> Value for LtHost<'a> {
@@ -18,7 +18,7 @@
 mod sockopt;
 
 mod raw;
-pub use raw::RawSocket;
+pub use raw::{RawSocket, SockOptChange, TcpInfo};
 
 pub mod tcp;
 pub mod udp;
@@ -14,9 +14,12 @@
  * limitations under the License.
  */
 
-use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, io};
 
-use socket2::Socket;
+use socket2::{Domain, Socket};
 
 use g3_types::net::{SocketBufferConfig, TcpMiscSockOpts, UdpMiscSockOpts};
 
@@ -25,16 +28,235 @@ mod unix;
 #[cfg(windows)]
 mod windows;
 
+/// The TCP_INFO fields of a tcp socket that are useful for diagnosis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TcpInfo {
+    /// the smoothed round trip time
+    pub rtt: Duration,
+    /// the mean deviation of the round trip time
+    pub rtt_var: Duration,
+    /// the count of segments currently being retransmitted
+    pub retrans: u32,
+    /// the count of segments retransmitted over the lifetime of the connection
+    pub total_retrans: u32,
+    /// the count of segments considered lost
+    pub lost: u32,
+    /// the congestion window in segments
+    pub snd_cwnd: u32,
+}
+
+/// The leading fields of `struct tcp_info` in linux/tcp.h, which have been stable since
+/// Linux 2.6. The libc crate in use doesn't provide it for Linux.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LinuxTcpInfo {
+    tcpi_state: u8,
+    tcpi_ca_state: u8,
+    tcpi_retransmits: u8,
+    tcpi_probes: u8,
+    tcpi_backoff: u8,
+    tcpi_options: u8,
+    tcpi_wscale: u8,
+    tcpi_app_limited: u8,
+
+    tcpi_rto: u32,
+    tcpi_ato: u32,
+    tcpi_snd_mss: u32,
+    tcpi_rcv_mss: u32,
+
+    tcpi_unacked: u32,
+    tcpi_sacked: u32,
+    tcpi_lost: u32,
+    tcpi_retrans: u32,
+    tcpi_fackets: u32,
+
+    tcpi_last_data_sent: u32,
+    tcpi_last_ack_sent: u32,
+    tcpi_last_data_recv: u32,
+    tcpi_last_ack_recv: u32,
+
+    tcpi_pmtu: u32,
+    tcpi_rcv_ssthresh: u32,
+    tcpi_rtt: u32,
+    tcpi_rttvar: u32,
+    tcpi_snd_ssthresh: u32,
+    tcpi_snd_cwnd: u32,
+    tcpi_advmss: u32,
+    tcpi_reordering: u32,
+
+    tcpi_rcv_rtt: u32,
+    tcpi_rcv_space: u32,
+
+    tcpi_total_retrans: u32,
+}
+
+/// A socket option changed by [`RawSocket::apply_tcp_config_diff`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SockOptChange {
+    pub name: &'static str,
+    /// the value before the change, `None` if it can't be read on this platform
+    pub old: Option<u32>,
+    pub new: u32,
+}
+
+impl fmt::Display for SockOptChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.old {
+            Some(old) => write!(f, "{}: {old} -> {}", self.name, self.new),
+            None => write!(f, "{}: ? -> {}", self.name, self.new),
+        }
+    }
+}
+
+/// Set the option only if the current value differs, the option will always be set
+/// if the current value can't be read.
+fn apply_sockopt_diff<G, S>(
+    changes: &mut Vec<SockOptChange>,
+    name: &'static str,
+    new: u32,
+    get: G,
+    set: S,
+) -> io::Result<()>
+where
+    G: FnOnce() -> io::Result<u32>,
+    S: FnOnce(u32) -> io::Result<()>,
+{
+    let old = get().ok();
+    if old == Some(new) {
+        return Ok(());
+    }
+    set(new)?;
+    changes.push(SockOptChange { name, old, new });
+    Ok(())
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn set_tclass_v6(socket: &Socket, tclass: u32) -> io::Result<()> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn set_tclass_v6(_socket: &Socket, _tclass: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPV6_TCLASS is not supported on this platform",
+    ))
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+fn tclass_v6(socket: &Socket) -> io::Result<u32> {
+    socket.tclass_v6()
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+fn tclass_v6(_socket: &Socket) -> io::Result<u32> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPV6_TCLASS is not supported on this platform",
+    ))
+}
+
+type SockOptRollback<'a> = Box<dyn FnOnce() -> io::Result<()> + 'a>;
+
+/// Set the option and record how to restore the old value, nothing will be recorded
+/// if the current value can't be read.
+///
+/// The rollback will be recorded even if the set fails, as the set may be partially applied.
+fn apply_sockopt_undoable<'a, G, S>(
+    rollback: &mut Vec<SockOptRollback<'a>>,
+    new: u32,
+    get: G,
+    set: S,
+) -> io::Result<()>
+where
+    G: FnOnce() -> io::Result<u32>,
+    S: Fn(u32) -> io::Result<()> + 'a,
+{
+    let old = get().ok();
+    let r = set(new);
+    if let Some(old) = old {
+        rollback.push(Box::new(move || set(old)));
+    }
+    r
+}
+
+/// A borrowed view of a socket for setting socket options.
+///
+/// The socket will not be closed on drop, unless it's created by [`RawSocket::try_dup`],
+/// in which case it will be closed after the last clone of it dropped.
 #[derive(Debug)]
 pub struct RawSocket {
+    /// always a borrowed one, which should be released without closing on drop
     inner: Option<Socket>,
+    /// the address family, taken once on creation as it won't change
+    domain: Option<Domain>,
+    /// the owned socket shared by all clones of the one created by `try_dup`
+    owned: Option<Arc<Socket>>,
 }
 
 impl RawSocket {
+    fn with_borrowed(socket: Socket) -> Self {
+        let domain = socket.local_addr().ok().map(|addr| addr.domain());
+        RawSocket {
+            inner: Some(socket),
+            domain,
+            owned: None,
+        }
+    }
+
+    /// Get the backing socket, `NotConnected` error will be returned if there is none,
+    /// so it can be told apart from the errors returned by the socket calls.
     fn get_inner(&self) -> io::Result<&Socket> {
-        self.inner
-            .as_ref()
-            .ok_or_else(|| io::Error::other("no socket set"))
+        self.inner.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "raw socket has no backing fd")
+        })
+    }
+
+    /// Duplicate the socket into a new fd, which will be owned by the returned value
+    /// and be closed on drop.
+    ///
+    /// Both fds refer to the same underlying socket, so socket options set through one of them
+    /// will also be seen through the other. Only the per-fd attributes, such as the close-on-exec
+    /// flag, are separated.
+    pub fn try_dup(&self) -> io::Result<RawSocket> {
+        let socket = self.get_inner()?;
+        let dup = Arc::new(socket.try_clone()?);
+        let mut raw_socket = RawSocket::from(dup.as_ref());
+        raw_socket.owned = Some(dup);
+        Ok(raw_socket)
     }
 
     pub fn set_buf_opts(&self, buf_conf: SocketBufferConfig) -> io::Result<()> {
@@ -64,30 +286,904 @@ impl RawSocket {
             socket.set_mss(mss)?;
         }
         if let Some(ttl) = misc_opts.time_to_live {
-            socket.set_ttl(ttl)?;
+            self.set_hop_limit(socket, ttl)?;
         }
         if let Some(tos) = misc_opts.type_of_service {
-            socket.set_tos(tos as u32)?;
+            self.set_traffic_class(socket, tos as u32)?;
         }
         #[cfg(target_os = "linux")]
         if let Some(mark) = misc_opts.netfilter_mark {
             socket.set_mark(mark)?;
         }
+        if let Some(quick_ack) = misc_opts.quick_ack {
+            self.set_quick_ack(quick_ack)?;
+        }
+        Ok(())
+    }
+
+    /// The same as [`RawSocket::set_tcp_misc_opts`], but only the options that differ from the
+    /// current values will be set, and the changed ones will be returned.
+    ///
+    /// Note that the value of TCP_MAXSEG read from a connected socket is the current MSS in use,
+    /// which may differ from the configured one, so it may be reported as changed every time.
+    pub fn apply_tcp_config_diff(
+        &self,
+        misc_opts: &TcpMiscSockOpts,
+        default_set_nodelay: bool,
+    ) -> io::Result<Vec<SockOptChange>> {
+        let socket = self.get_inner()?;
+        let mut changes = Vec::new();
+        let no_delay = misc_opts
+            .no_delay
+            .or_else(|| default_set_nodelay.then_some(true));
+        if let Some(no_delay) = no_delay {
+            apply_sockopt_diff(
+                &mut changes,
+                "TCP_NODELAY",
+                u32::from(no_delay),
+                || socket.nodelay().map(u32::from),
+                |v| socket.set_nodelay(v != 0),
+            )?;
+        }
+        #[cfg(unix)]
+        if let Some(mss) = misc_opts.max_segment_size {
+            apply_sockopt_diff(
+                &mut changes,
+                "TCP_MAXSEG",
+                mss,
+                || socket.mss(),
+                |v| socket.set_mss(v),
+            )?;
+        }
+        if let Some(ttl) = misc_opts.time_to_live {
+            let name = if self.is_ipv6()? {
+                "IPV6_UNICAST_HOPS"
+            } else {
+                "IP_TTL"
+            };
+            apply_sockopt_diff(
+                &mut changes,
+                name,
+                ttl,
+                || self.hop_limit(socket),
+                |v| self.set_hop_limit(socket, v),
+            )?;
+        }
+        if let Some(tos) = misc_opts.type_of_service {
+            let name = if self.is_ipv6()? {
+                "IPV6_TCLASS"
+            } else {
+                "IP_TOS"
+            };
+            apply_sockopt_diff(
+                &mut changes,
+                name,
+                tos as u32,
+                || self.traffic_class(socket),
+                |v| self.set_traffic_class(socket, v),
+            )?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(mark) = misc_opts.netfilter_mark {
+            apply_sockopt_diff(
+                &mut changes,
+                "SO_MARK",
+                mark,
+                || socket.mark(),
+                |v| socket.set_mark(v),
+            )?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(quick_ack) = misc_opts.quick_ack {
+            apply_sockopt_diff(
+                &mut changes,
+                "TCP_QUICKACK",
+                u32::from(quick_ack),
+                || self.quick_ack().map(u32::from),
+                |v| self.set_quick_ack(v != 0),
+            )?;
+        }
+        Ok(changes)
+    }
+
+    /// The same as [`RawSocket::set_tcp_misc_opts`], but the options that have already been set
+    /// will be restored to the values read before, if any of the options fails to be set.
+    ///
+    /// The rollback is best-effort, errors in it will be ignored, and the original error will be
+    /// returned. Some values can't be restored exactly:
+    ///  - TCP_MAXSEG: the value read from a connected socket is the current MSS in use,
+    ///    which may differ from the configured one.
+    ///  - IP_TTL and IP_TOS: for dual stack inet6 sockets, only the IPv6 values can be read back,
+    ///    and they will be used to restore both.
+    ///  - TCP_QUICKACK: it can only be read on Linux, and the kernel may also clear it at any time,
+    ///    so it won't be restored on other platforms.
+    pub fn apply_tcp_opts_atomic(
+        &self,
+        misc_opts: &TcpMiscSockOpts,
+        default_set_nodelay: bool,
+    ) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        let mut rollback = Vec::new();
+        if let Err(e) =
+            self.apply_tcp_opts_undoable(socket, misc_opts, default_set_nodelay, &mut rollback)
+        {
+            for restore in rollback.into_iter().rev() {
+                let _ = restore();
+            }
+            return Err(e);
+        }
         Ok(())
     }
 
+    fn apply_tcp_opts_undoable<'a>(
+        &'a self,
+        socket: &'a Socket,
+        misc_opts: &TcpMiscSockOpts,
+        default_set_nodelay: bool,
+        rollback: &mut Vec<SockOptRollback<'a>>,
+    ) -> io::Result<()> {
+        let no_delay = misc_opts
+            .no_delay
+            .or_else(|| default_set_nodelay.then_some(true));
+        if let Some(no_delay) = no_delay {
+            apply_sockopt_undoable(
+                rollback,
+                u32::from(no_delay),
+                || socket.nodelay().map(u32::from),
+                |v| socket.set_nodelay(v != 0),
+            )?;
+        }
+        #[cfg(unix)]
+        if let Some(mss) = misc_opts.max_segment_size {
+            apply_sockopt_undoable(rollback, mss, || socket.mss(), |v| socket.set_mss(v))?;
+        }
+        if let Some(ttl) = misc_opts.time_to_live {
+            apply_sockopt_undoable(
+                rollback,
+                ttl,
+                || self.hop_limit(socket),
+                |v| self.set_hop_limit(socket, v),
+            )?;
+        }
+        if let Some(tos) = misc_opts.type_of_service {
+            apply_sockopt_undoable(
+                rollback,
+                tos as u32,
+                || self.traffic_class(socket),
+                |v| self.set_traffic_class(socket, v),
+            )?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(mark) = misc_opts.netfilter_mark {
+            apply_sockopt_undoable(rollback, mark, || socket.mark(), |v| socket.set_mark(v))?;
+        }
+        if let Some(quick_ack) = misc_opts.quick_ack {
+            apply_sockopt_undoable(
+                rollback,
+                u32::from(quick_ack),
+                || self.quick_ack().map(u32::from),
+                |v| self.set_quick_ack(v != 0),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Set the udp misc socket options.
+    ///
+    /// The permission error of SO_BUSY_POLL will be ignored silently, all other errors
+    /// will be returned.
     pub fn set_udp_misc_opts(&self, misc_opts: UdpMiscSockOpts) -> io::Result<()> {
         let socket = self.get_inner()?;
         if let Some(ttl) = misc_opts.time_to_live {
-            socket.set_ttl(ttl)?;
+            self.set_hop_limit(socket, ttl)?;
         }
         if let Some(tos) = misc_opts.type_of_service {
-            socket.set_tos(tos as u32)?;
+            self.set_traffic_class(socket, tos as u32)?;
         }
         #[cfg(target_os = "linux")]
         if let Some(mark) = misc_opts.netfilter_mark {
             socket.set_mark(mark)?;
         }
+        #[cfg(target_os = "linux")]
+        if let Some(usecs) = misc_opts.busy_poll {
+            // it's not fatal if we have no privilege to set a larger value than the sysctl one
+            if let Err(e) = self.set_busy_poll(usecs) {
+                if e.kind() != io::ErrorKind::PermissionDenied {
+                    return Err(e);
+                }
+            }
+        }
         Ok(())
     }
+
+    /// Get SO_RCVBUF of the socket.
+    ///
+    /// Note that Linux doubles the value set, to leave space for the bookkeeping overhead,
+    /// and the doubled value will be returned.
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        let socket = self.get_inner()?;
+        socket.recv_buffer_size()
+    }
+
+    /// Get SO_SNDBUF of the socket, the same doubling as SO_RCVBUF applies on Linux.
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        let socket = self.get_inner()?;
+        socket.send_buffer_size()
+    }
+
+    /// Get IP_TTL for inet sockets, or IPV6_UNICAST_HOPS for inet6 sockets
+    pub fn ttl(&self) -> io::Result<u32> {
+        let socket = self.get_inner()?;
+        self.hop_limit(socket)
+    }
+
+    /// Get IP_TOS for inet sockets, or IPV6_TCLASS for inet6 sockets
+    pub fn tos(&self) -> io::Result<u32> {
+        let socket = self.get_inner()?;
+        self.traffic_class(socket)
+    }
+
+    /// Get TCP_NODELAY of the socket
+    pub fn nodelay(&self) -> io::Result<bool> {
+        let socket = self.get_inner()?;
+        socket.nodelay()
+    }
+
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, iface: Ipv4Addr) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.join_multicast_v4(&group, &iface)
+    }
+
+    pub fn leave_multicast_v4(&self, group: Ipv4Addr, iface: Ipv4Addr) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.leave_multicast_v4(&group, &iface)
+    }
+
+    pub fn join_multicast_v6(&self, group: Ipv6Addr, ifindex: u32) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.join_multicast_v6(&group, ifindex)
+    }
+
+    pub fn leave_multicast_v6(&self, group: Ipv6Addr, ifindex: u32) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.leave_multicast_v6(&group, ifindex)
+    }
+
+    /// Get the address family of the socket.
+    ///
+    /// The family is taken from the local address on creation, so it also works for unbound
+    /// sockets.
+    pub fn domain(&self) -> io::Result<Domain> {
+        let socket = self.get_inner()?;
+        match self.domain {
+            Some(domain) => Ok(domain),
+            // get the real error
+            None => socket.local_addr().map(|addr| addr.domain()),
+        }
+    }
+
+    fn is_ipv6(&self) -> io::Result<bool> {
+        Ok(self.domain()? == Domain::IPV6)
+    }
+
+    /// Check if this is an inet6 socket that also accepts IPv4 traffic,
+    /// i.e. IPV6_V6ONLY is not set. Always `false` for inet sockets.
+    pub fn is_dual_stack(&self) -> io::Result<bool> {
+        if !self.is_ipv6()? {
+            return Ok(false);
+        }
+        let socket = self.get_inner()?;
+        socket.only_v6().map(|v| !v)
+    }
+
+    /// Set IPV6_V6ONLY on the socket, which should be called before bind.
+    ///
+    /// `InvalidInput` error will be returned if this is not an inet6 socket.
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        if !self.is_ipv6()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "IPV6_V6ONLY is only valid for inet6 sockets",
+            ));
+        }
+        let socket = self.get_inner()?;
+        socket.set_only_v6(only_v6)
+    }
+
+    /// Set IP_TTL for inet sockets, or IPV6_UNICAST_HOPS for inet6 sockets.
+    ///
+    /// IP_TTL will also be set for dual stack inet6 sockets, as it applies to the IPv4 traffic.
+    fn set_hop_limit(&self, socket: &Socket, ttl: u32) -> io::Result<()> {
+        if !self.is_ipv6()? {
+            return socket.set_ttl(ttl);
+        }
+        socket.set_unicast_hops_v6(ttl)?;
+        if !socket.only_v6()? {
+            socket.set_ttl(ttl)?;
+        }
+        Ok(())
+    }
+
+    /// Get IP_TTL for inet sockets, or IPV6_UNICAST_HOPS for inet6 sockets
+    fn hop_limit(&self, socket: &Socket) -> io::Result<u32> {
+        if self.is_ipv6()? {
+            socket.unicast_hops_v6()
+        } else {
+            socket.ttl()
+        }
+    }
+
+    /// Set IP_TOS for inet sockets, or IPV6_TCLASS for inet6 sockets.
+    ///
+    /// IP_TOS will also be set for dual stack inet6 sockets, as it applies to the IPv4 traffic.
+    fn set_traffic_class(&self, socket: &Socket, tos: u32) -> io::Result<()> {
+        if !self.is_ipv6()? {
+            return socket.set_tos(tos);
+        }
+        set_tclass_v6(socket, tos)?;
+        if !socket.only_v6()? {
+            socket.set_tos(tos)?;
+        }
+        Ok(())
+    }
+
+    /// Get IP_TOS for inet sockets, or IPV6_TCLASS for inet6 sockets
+    fn traffic_class(&self, socket: &Socket) -> io::Result<u32> {
+        if self.is_ipv6()? {
+            tclass_v6(socket)
+        } else {
+            socket.tos()
+        }
+    }
+
+    pub fn set_multicast_loop(&self, enable: bool) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        if self.is_ipv6()? {
+            socket.set_multicast_loop_v6(enable)
+        } else {
+            socket.set_multicast_loop_v4(enable)
+        }
+    }
+
+    /// Set IP_MULTICAST_TTL for inet sockets, or IPV6_MULTICAST_HOPS for inet6 sockets
+    pub fn set_multicast_ttl(&self, ttl: u32) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        if self.is_ipv6()? {
+            socket.set_multicast_hops_v6(ttl)
+        } else {
+            socket.set_multicast_ttl_v4(ttl)
+        }
+    }
+
+    /// Set SO_RCVTIMEO on the socket, `None` means no timeout.
+    ///
+    /// This only affects blocking operations, so it's generally useless for async sockets,
+    /// but it's handy for sockets used in blocking probes, such as health checks.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.set_read_timeout(timeout)
+    }
+
+    /// Set SO_SNDTIMEO on the socket, `None` means no timeout.
+    ///
+    /// The same as `set_read_timeout`, only blocking operations are affected.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.set_write_timeout(timeout)
+    }
+
+    /// Set SO_REUSEPORT on the socket, which should be called before bind, to allow multiple
+    /// sockets to bind to the same address, and the kernel will balance the incoming connections
+    /// or datagrams among them.
+    ///
+    /// This is only supported on Linux and BSD, `Unsupported` error will be returned on other
+    /// platforms.
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    pub fn set_reuseport(&self, enable: bool) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        socket.set_reuse_port(enable)
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    pub fn set_reuseport(&self, _enable: bool) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SO_REUSEPORT is not supported on this platform",
+        ))
+    }
+
+    /// Get SO_REUSEPORT of the socket.
+    ///
+    /// This is only supported on Linux and BSD, `Unsupported` error will be returned on other
+    /// platforms.
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    pub fn reuseport(&self) -> io::Result<bool> {
+        let socket = self.get_inner()?;
+        socket.reuse_port()
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    pub fn reuseport(&self) -> io::Result<bool> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SO_REUSEPORT is not supported on this platform",
+        ))
+    }
+
+    /// Set TCP_QUICKACK on the socket, to send ACKs immediately rather than delaying them.
+    ///
+    /// The kernel may clear the flag again as it switches back to delayed ACK mode, so callers
+    /// that rely on it should re-apply it after each recv.
+    ///
+    /// This is only supported on Linux, and it's a no-op on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn set_quick_ack(&self, enable: bool) -> io::Result<()> {
+        let value = libc::c_int::from(enable);
+        unsafe { self.set_sockopt_raw(libc::IPPROTO_TCP, libc::TCP_QUICKACK, &value.to_ne_bytes()) }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_quick_ack(&self, _enable: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Get TCP_QUICKACK of the socket.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn quick_ack(&self) -> io::Result<bool> {
+        self.get_sockopt_c_int(libc::IPPROTO_TCP, libc::TCP_QUICKACK)
+            .map(|v| v != 0)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn quick_ack(&self) -> io::Result<bool> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Set SO_INCOMING_CPU on the socket, to hint the kernel which CPU should be used to
+    /// process the incoming packets of this socket.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn set_incoming_cpu(&self, cpu: i32) -> io::Result<()> {
+        let value: libc::c_int = cpu;
+        unsafe {
+            self.set_sockopt_raw(
+                libc::SOL_SOCKET,
+                libc::SO_INCOMING_CPU,
+                &value.to_ne_bytes(),
+            )
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_incoming_cpu(&self, _cpu: i32) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Get SO_INCOMING_CPU of the socket.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn incoming_cpu(&self) -> io::Result<i32> {
+        self.get_sockopt_c_int(libc::SOL_SOCKET, libc::SO_INCOMING_CPU)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn incoming_cpu(&self) -> io::Result<i32> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Get SO_INCOMING_NAPI_ID of the socket, which is the id of the NAPI context that
+    /// received the last packet. The value will be 0 if no packet has been received yet,
+    /// or if the packets are not received through a NAPI enabled device, such as loopback.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn incoming_napi_id(&self) -> io::Result<u32> {
+        self.get_sockopt_c_int(libc::SOL_SOCKET, libc::SO_INCOMING_NAPI_ID)
+            .map(|v| v as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn incoming_napi_id(&self) -> io::Result<u32> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Set SO_BUSY_POLL on the socket, which is the approximate time in microseconds to
+    /// busy poll the device queue on blocking receive when there is no data, 0 disables it.
+    ///
+    /// Busy polling trades CPU time for lower receive latency, as the polling CPU will not
+    /// be yielded while waiting. The system wide defaults are set by the sysctl
+    /// `net.core.busy_read` and `net.core.busy_poll`, and the latter one also enables busy
+    /// polling for poll/epoll, which is what the async runtime uses. CAP_NET_ADMIN is
+    /// required to set a value larger than the current one.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn set_busy_poll(&self, micros: u32) -> io::Result<()> {
+        let value = libc::c_int::try_from(micros).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "too large busy poll value")
+        })?;
+        unsafe { self.set_sockopt_raw(libc::SOL_SOCKET, libc::SO_BUSY_POLL, &value.to_ne_bytes()) }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn set_busy_poll(&self, _micros: u32) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Get SO_BUSY_POLL of the socket.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn busy_poll(&self) -> io::Result<u32> {
+        self.get_sockopt_c_int(libc::SOL_SOCKET, libc::SO_BUSY_POLL)
+            .map(|v| v as u32)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn busy_poll(&self) -> io::Result<u32> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Get TCP_INFO of the socket.
+    ///
+    /// This is only supported on Linux, `Unsupported` error will be returned on other platforms.
+    #[cfg(target_os = "linux")]
+    pub fn tcp_info(&self) -> io::Result<TcpInfo> {
+        let mut buf = [0u8; std::mem::size_of::<LinuxTcpInfo>()];
+        let len = unsafe { self.get_sockopt_raw(libc::IPPROTO_TCP, libc::TCP_INFO, &mut buf)? };
+        if len < buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("too short tcp info length {len}"),
+            ));
+        }
+
+        // the buffer is filled by the kernel in the exact layout of the struct
+        let info = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const LinuxTcpInfo) };
+        Ok(TcpInfo {
+            rtt: Duration::from_micros(info.tcpi_rtt as u64),
+            rtt_var: Duration::from_micros(info.tcpi_rttvar as u64),
+            retrans: info.tcpi_retrans,
+            total_retrans: info.tcpi_total_retrans,
+            lost: info.tcpi_lost,
+            snd_cwnd: info.tcpi_snd_cwnd,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn tcp_info(&self) -> io::Result<TcpInfo> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_sockopt_c_int(&self, level: libc::c_int, name: libc::c_int) -> io::Result<libc::c_int> {
+        let mut buf = [0u8; std::mem::size_of::<libc::c_int>()];
+        let len = unsafe { self.get_sockopt_raw(level, name, &mut buf)? };
+        if len != buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected option length {len}"),
+            ));
+        }
+        Ok(libc::c_int::from_ne_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn no_backing_fd() {
+        let raw_socket = RawSocket {
+            inner: None,
+            domain: None,
+            owned: None,
+        };
+        let e = raw_socket
+            .set_buf_opts(SocketBufferConfig::default())
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+        let e = raw_socket
+            .set_tcp_misc_opts(&TcpMiscSockOpts::default(), false)
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+        let e = raw_socket
+            .set_udp_misc_opts(UdpMiscSockOpts::default())
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+        let e = raw_socket.ttl().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn multicast_v4() {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let raw_socket = RawSocket::from(&socket);
+        raw_socket.set_multicast_loop(true).unwrap();
+        raw_socket.set_multicast_ttl(2).unwrap();
+        assert!(socket.multicast_loop_v4().unwrap());
+        assert_eq!(socket.multicast_ttl_v4().unwrap(), 2);
+
+        let group = Ipv4Addr::new(239, 255, 0, 1);
+        // the join may fail if there is no multicast capable interface
+        if raw_socket
+            .join_multicast_v4(group, Ipv4Addr::LOCALHOST)
+            .is_ok()
+        {
+            // join again on the same interface should fail if the membership exists
+            assert!(raw_socket
+                .join_multicast_v4(group, Ipv4Addr::LOCALHOST)
+                .is_err());
+            raw_socket
+                .leave_multicast_v4(group, Ipv4Addr::LOCALHOST)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn multicast_v6() {
+        let Ok(socket) = UdpSocket::bind("[::1]:0") else {
+            return;
+        };
+        let raw_socket = RawSocket::from(&socket);
+        raw_socket.set_multicast_loop(false).unwrap();
+        raw_socket.set_multicast_ttl(3).unwrap();
+        assert!(!socket.multicast_loop_v6().unwrap());
+    }
+
+    #[test]
+    fn rw_timeout() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw_socket = RawSocket::from(&socket);
+
+        let timeout = Duration::from_secs(2);
+        raw_socket.set_read_timeout(Some(timeout)).unwrap();
+        raw_socket.set_write_timeout(Some(timeout)).unwrap();
+        assert_eq!(socket.read_timeout().unwrap(), Some(timeout));
+        assert_eq!(socket.write_timeout().unwrap(), Some(timeout));
+
+        raw_socket.set_read_timeout(None).unwrap();
+        raw_socket.set_write_timeout(None).unwrap();
+        assert_eq!(socket.read_timeout().unwrap(), None);
+        assert_eq!(socket.write_timeout().unwrap(), None);
+    }
+
+    #[test]
+    fn domain_v4() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw_socket = RawSocket::from(&socket);
+        assert_eq!(raw_socket.domain().unwrap(), Domain::IPV4);
+        assert!(!raw_socket.is_dual_stack().unwrap());
+        assert!(raw_socket.set_only_v6(true).is_err());
+    }
+
+    #[test]
+    fn domain_v6() {
+        let Ok(socket) = Socket::new(Domain::IPV6, socket2::Type::DGRAM, None) else {
+            return;
+        };
+        let raw_socket = RawSocket::from(&socket);
+        assert_eq!(raw_socket.domain().unwrap(), Domain::IPV6);
+        assert_eq!(raw_socket.clone().domain().unwrap(), Domain::IPV6);
+
+        raw_socket.set_only_v6(true).unwrap();
+        assert!(!raw_socket.is_dual_stack().unwrap());
+        assert!(socket.only_v6().unwrap());
+
+        raw_socket.set_only_v6(false).unwrap();
+        assert!(raw_socket.is_dual_stack().unwrap());
+        assert!(!socket.only_v6().unwrap());
+    }
+
+    #[test]
+    fn misc_opts_v6() {
+        let Ok(socket) = UdpSocket::bind("[::1]:0") else {
+            return;
+        };
+        let raw_socket = RawSocket::from(&socket);
+        let misc_opts = UdpMiscSockOpts {
+            time_to_live: Some(32),
+            type_of_service: Some(0x20),
+            ..Default::default()
+        };
+        raw_socket.set_udp_misc_opts(misc_opts).unwrap();
+
+        let socket = socket2::SockRef::from(&socket);
+        assert_eq!(socket.unicast_hops_v6().unwrap(), 32);
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket.tclass_v6().unwrap(), 0x20);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reuseport() {
+        let first = Socket::new(Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        let raw_socket = RawSocket::from(&first);
+        raw_socket.set_reuseport(true).unwrap();
+        assert!(raw_socket.reuseport().unwrap());
+        let addr = std::net::SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        first.bind(&addr.into()).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        let second = Socket::new(Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        RawSocket::from(&second).set_reuseport(true).unwrap();
+        second.bind(&addr).unwrap();
+
+        let third = Socket::new(Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        assert!(third.bind(&addr).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn incoming_cpu() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw_socket = RawSocket::from(&socket);
+
+        raw_socket.set_incoming_cpu(0).unwrap();
+        assert_eq!(raw_socket.incoming_cpu().unwrap(), 0);
+        assert_eq!(raw_socket.incoming_napi_id().unwrap(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn quick_ack() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let raw_socket = RawSocket::from(&socket);
+
+        raw_socket.set_quick_ack(true).unwrap();
+        let mut buf = [0u8; std::mem::size_of::<libc::c_int>()];
+        let len = unsafe {
+            raw_socket
+                .get_sockopt_raw(libc::IPPROTO_TCP, libc::TCP_QUICKACK, &mut buf)
+                .unwrap()
+        };
+        assert_eq!(len, buf.len());
+        assert_eq!(libc::c_int::from_ne_bytes(buf), 1);
+
+        let misc_opts = TcpMiscSockOpts {
+            quick_ack: Some(false),
+            ..Default::default()
+        };
+        raw_socket.set_tcp_misc_opts(&misc_opts, false).unwrap();
+        assert!(!raw_socket.quick_ack().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tcp_config_diff() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let raw_socket = RawSocket::from(&socket);
+        socket.set_nodelay(false).unwrap();
+        let old_ttl = socket.ttl().unwrap();
+
+        let misc_opts = TcpMiscSockOpts {
+            time_to_live: Some(old_ttl - 1),
+            type_of_service: Some(0),
+            ..Default::default()
+        };
+        let changes = raw_socket.apply_tcp_config_diff(&misc_opts, true).unwrap();
+        assert_eq!(
+            changes,
+            [
+                SockOptChange {
+                    name: "TCP_NODELAY",
+                    old: Some(0),
+                    new: 1
+                },
+                SockOptChange {
+                    name: "IP_TTL",
+                    old: Some(old_ttl),
+                    new: old_ttl - 1
+                },
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "TCP_NODELAY: 0 -> 1");
+        assert!(socket.nodelay().unwrap());
+        assert_eq!(socket.ttl().unwrap(), old_ttl - 1);
+
+        let changes = raw_socket.apply_tcp_config_diff(&misc_opts, true).unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn get_opts() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let raw_socket = RawSocket::from(&socket);
+
+        let buf_conf = SocketBufferConfig::new(65536);
+        raw_socket.set_buf_opts(buf_conf).unwrap();
+        assert!(raw_socket.recv_buffer_size().unwrap() >= 65536);
+        assert!(raw_socket.send_buffer_size().unwrap() >= 65536);
+
+        let misc_opts = TcpMiscSockOpts {
+            no_delay: Some(false),
+            time_to_live: Some(32),
+            type_of_service: Some(0x20),
+            ..Default::default()
+        };
+        raw_socket.set_tcp_misc_opts(&misc_opts, true).unwrap();
+        assert!(!raw_socket.nodelay().unwrap());
+        assert_eq!(raw_socket.ttl().unwrap(), 32);
+        assert_eq!(raw_socket.tos().unwrap(), 0x20);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tcp_opts_atomic() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let raw_socket = RawSocket::from(&socket);
+        socket.set_nodelay(false).unwrap();
+        let old_ttl = socket.ttl().unwrap();
+
+        let misc_opts = TcpMiscSockOpts {
+            no_delay: Some(true),
+            type_of_service: Some(0x20),
+            ..Default::default()
+        };
+        raw_socket.apply_tcp_opts_atomic(&misc_opts, false).unwrap();
+        assert!(socket.nodelay().unwrap());
+        assert_eq!(socket2::SockRef::from(&socket).tos().unwrap(), 0x20);
+
+        socket.set_nodelay(false).unwrap();
+        // the ttl value is out of range, so it will fail after nodelay has been set
+        let misc_opts = TcpMiscSockOpts {
+            no_delay: Some(true),
+            time_to_live: Some(1024),
+            type_of_service: Some(0x40),
+            ..Default::default()
+        };
+        assert!(raw_socket.apply_tcp_opts_atomic(&misc_opts, false).is_err());
+        assert!(!socket.nodelay().unwrap());
+        assert_eq!(socket.ttl().unwrap(), old_ttl);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tcp_info() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut socket = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        socket.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        peer.read_exact(&mut buf).unwrap();
+
+        let info = RawSocket::from(&socket).tcp_info().unwrap();
+        assert!(info.rtt > Duration::ZERO);
+        assert_eq!(info.retrans, 0);
+        assert!(info.snd_cwnd > 0);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(RawSocket::from(&socket).tcp_info().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn busy_poll() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw_socket = RawSocket::from(&socket);
+
+        raw_socket.set_busy_poll(0).unwrap();
+        assert_eq!(raw_socket.busy_poll().unwrap(), 0);
+
+        // CAP_NET_ADMIN is required to increase the value
+        match raw_socket.set_busy_poll(50) {
+            Ok(_) => assert_eq!(raw_socket.busy_poll().unwrap(), 50),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
+        }
+
+        assert!(raw_socket.set_busy_poll(u32::MAX).is_err());
+    }
 }
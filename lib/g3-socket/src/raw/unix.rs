@@ -14,12 +14,80 @@
  * limitations under the License.
  */
 
+use std::io;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
 
+use libc::{c_int, c_void, socklen_t};
 use socket2::Socket;
 
 use super::RawSocket;
 
+impl RawSocket {
+    /// Set a socket option that has no dedicated method, by calling `setsockopt` directly.
+    ///
+    /// The `level` and `name` values are platform specific, use the constants from `libc`.
+    ///
+    /// # Safety
+    ///
+    /// The value should be in the exact format the kernel expects for this option.
+    /// Options that change the socket behaviour the caller is relying on, e.g. the
+    /// blocking mode or the address family related ones, may break the code that uses the
+    /// socket after this call.
+    pub unsafe fn set_sockopt_raw(
+        &self,
+        level: c_int,
+        name: c_int,
+        value: &[u8],
+    ) -> io::Result<()> {
+        let socket = self.get_inner()?;
+        let len = socklen_t::try_from(value.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too large option value"))?;
+        let ret = libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            value.as_ptr() as *const c_void,
+            len,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Get a socket option that has no dedicated method, by calling `getsockopt` directly.
+    ///
+    /// The value will be written to `buf`, and the real length of it will be returned,
+    /// which may be less than the length of `buf`. Whether the value will be truncated
+    /// silently if the buffer is too small depends on the platform.
+    ///
+    /// # Safety
+    ///
+    /// The `level` and `name` values are platform specific, and the caller should parse the
+    /// returned bytes in the exact format the kernel uses for this option.
+    pub unsafe fn get_sockopt_raw(
+        &self,
+        level: c_int,
+        name: c_int,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        let socket = self.get_inner()?;
+        let mut len = socklen_t::try_from(buf.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too large option buffer"))?;
+        let ret = libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            buf.as_mut_ptr() as *mut c_void,
+            &mut len,
+        );
+        if ret == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(len as usize)
+    }
+}
+
 #[cfg(unix)]
 impl Drop for RawSocket {
     fn drop(&mut self) {
@@ -31,10 +99,14 @@ impl Drop for RawSocket {
 
 impl Clone for RawSocket {
     fn clone(&self) -> Self {
-        if let Some(s) = &self.inner {
-            Self::from(s)
-        } else {
-            RawSocket { inner: None }
+        let inner = self
+            .inner
+            .as_ref()
+            .map(|s| unsafe { Socket::from_raw_fd(s.as_raw_fd()) });
+        RawSocket {
+            inner,
+            domain: self.domain,
+            owned: self.owned.clone(),
         }
     }
 }
@@ -42,8 +114,82 @@ impl Clone for RawSocket {
 impl<T: AsRawFd> From<&T> for RawSocket {
     fn from(value: &T) -> Self {
         let socket = unsafe { Socket::from_raw_fd(value.as_raw_fd()) };
-        RawSocket {
-            inner: Some(socket),
+        RawSocket::with_borrowed(socket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn raw_sockopt() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw = RawSocket::from(&socket);
+
+        let value: c_int = 64;
+        unsafe {
+            raw.set_sockopt_raw(libc::IPPROTO_IP, libc::IP_TTL, &value.to_ne_bytes())
+                .unwrap();
         }
+        assert_eq!(socket.ttl().unwrap(), 64);
+
+        let mut buf = [0u8; std::mem::size_of::<c_int>()];
+        let len = unsafe {
+            raw.get_sockopt_raw(libc::IPPROTO_IP, libc::IP_TTL, &mut buf)
+                .unwrap()
+        };
+        assert_eq!(len, buf.len());
+        assert_eq!(c_int::from_ne_bytes(buf), 64);
+    }
+
+    #[test]
+    fn try_dup() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw = RawSocket::from(&socket);
+
+        let dup = raw.try_dup().unwrap();
+        let dup_fd = dup.get_inner().unwrap().as_raw_fd();
+        assert_ne!(dup_fd, socket.as_raw_fd());
+        assert_eq!(
+            dup.get_inner().unwrap().local_addr().unwrap().as_socket(),
+            Some(socket.local_addr().unwrap())
+        );
+
+        // the socket options are shared
+        dup.set_udp_misc_opts(g3_types::net::UdpMiscSockOpts {
+            time_to_live: Some(32),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(socket.ttl().unwrap(), 32);
+
+        // the original fd is still usable after the dup is closed
+        drop(dup);
+        socket.set_ttl(16).unwrap();
+        assert_eq!(socket.ttl().unwrap(), 16);
+    }
+
+    #[test]
+    fn clone_dup() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw = RawSocket::from(&socket);
+
+        let dup = raw.try_dup().unwrap();
+        let dup_fd = dup.get_inner().unwrap().as_raw_fd();
+        let cloned = dup.clone();
+        drop(dup);
+
+        // the fd is still open as the clone keeps the ownership
+        assert_eq!(cloned.get_inner().unwrap().as_raw_fd(), dup_fd);
+        cloned
+            .set_udp_misc_opts(g3_types::net::UdpMiscSockOpts {
+                time_to_live: Some(48),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(socket.ttl().unwrap(), 48);
+        assert_eq!(cloned.ttl().unwrap(), 48);
     }
 }
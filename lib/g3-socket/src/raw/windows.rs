@@ -16,7 +16,7 @@
 
 use std::io::{self, IoSlice};
 use std::net::SocketAddr;
-use std::os::windows::io::{AsRawSocket, FromRawSocket};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket};
 
 use socket2::{MsgHdr, SockAddr, Socket};
 
@@ -37,7 +37,6 @@ impl RawSocket {
     }
 }
 
-#[cfg(unix)]
 impl Drop for RawSocket {
     fn drop(&mut self) {
         if let Some(s) = self.inner.take() {
@@ -48,10 +47,14 @@ impl Drop for RawSocket {
 
 impl Clone for RawSocket {
     fn clone(&self) -> Self {
-        if let Some(s) = &self.inner {
-            Self::from(s)
-        } else {
-            RawSocket { inner: None }
+        let inner = self
+            .inner
+            .as_ref()
+            .map(|s| unsafe { Socket::from_raw_socket(s.as_raw_socket()) });
+        RawSocket {
+            inner,
+            domain: self.domain,
+            owned: self.owned.clone(),
         }
     }
 }
@@ -59,8 +62,6 @@ impl Clone for RawSocket {
 impl<T: AsRawSocket> From<&T> for RawSocket {
     fn from(value: &T) -> Self {
         let socket = unsafe { Socket::from_raw_socket(value.as_raw_socket()) };
-        RawSocket {
-            inner: Some(socket),
-        }
+        RawSocket::with_borrowed(socket)
     }
 }
@@ -15,14 +15,14 @@
  */
 
 use std::io;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 #[cfg(target_os = "linux")]
 use std::os::unix::io::AsRawFd;
 
 use socket2::{Domain, SockAddr, Socket, TcpKeepalive, Type};
 use tokio::net::{TcpListener, TcpSocket};
 
-use g3_types::net::{TcpKeepAliveConfig, TcpListenConfig, TcpMiscSockOpts};
+use g3_types::net::{PortRange, TcpKeepAliveConfig, TcpListenConfig, TcpMiscSockOpts};
 
 #[cfg(target_os = "linux")]
 use super::sockopt::set_bind_address_no_port;
@@ -76,6 +76,79 @@ pub fn new_std_socket_to(
         let addr: SockAddr = SocketAddr::new(ip, 0).into();
         socket.bind(&addr)?;
     }
+    set_connect_sock_opts(&socket, keepalive, misc_opts, default_set_nodelay)?;
+    Ok(std::net::TcpStream::from(socket))
+}
+
+/// Create a tcp socket bound to a local port within the specified range.
+///
+/// Random ports will be tried first, and then all ports in sequence.
+/// An error of kind `AddrNotAvailable` will be returned if all ports in the range are in use.
+pub fn new_std_socket_in_range_to(
+    peer_ip: IpAddr,
+    bind_ip: Option<IpAddr>,
+    port: PortRange,
+    keepalive: &TcpKeepAliveConfig,
+    misc_opts: &TcpMiscSockOpts,
+    default_set_nodelay: bool,
+) -> io::Result<std::net::TcpStream> {
+    let peer_family = AddressFamily::from(&peer_ip);
+    let bind_ip = match bind_ip {
+        Some(ip) => {
+            if AddressFamily::from(&ip) != peer_family {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("peer_ip {peer_ip} and bind_ip {ip} should be of the same family",),
+                ));
+            }
+            ip
+        }
+        None => match peer_family {
+            AddressFamily::Ipv4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            AddressFamily::Ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        },
+    };
+    let socket = new_tcp_socket(peer_family)?;
+
+    let port_start = port.start();
+    let port_end = port.end();
+    let mut bound = false;
+    // like what's has been done in new_std_in_range_bind_lazy_connect for udp
+    let tries = port.count().min(10);
+    for _i in 0..tries {
+        let port = fastrand::u16(port_start..=port_end);
+        let bind_addr: SockAddr = SocketAddr::new(bind_ip, port).into();
+        if socket.bind(&bind_addr).is_ok() {
+            bound = true;
+            break;
+        }
+    }
+    if !bound {
+        for port in port_start..=port_end {
+            let bind_addr: SockAddr = SocketAddr::new(bind_ip, port).into();
+            if socket.bind(&bind_addr).is_ok() {
+                bound = true;
+                break;
+            }
+        }
+    }
+    if !bound {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "no port can be selected within specified range",
+        ));
+    }
+
+    set_connect_sock_opts(&socket, keepalive, misc_opts, default_set_nodelay)?;
+    Ok(std::net::TcpStream::from(socket))
+}
+
+fn set_connect_sock_opts(
+    socket: &Socket,
+    keepalive: &TcpKeepAliveConfig,
+    misc_opts: &TcpMiscSockOpts,
+    default_set_nodelay: bool,
+) -> io::Result<()> {
     if keepalive.is_enabled() {
         // set keepalive_idle
         let mut setting = TcpKeepalive::new().with_time(keepalive.idle_time());
@@ -88,8 +161,8 @@ pub fn new_std_socket_to(
         }
         socket.set_tcp_keepalive(&setting)?;
     }
-    RawSocket::from(&socket).set_tcp_misc_opts(misc_opts, default_set_nodelay)?;
-    Ok(std::net::TcpStream::from(socket))
+    RawSocket::from(socket).set_tcp_misc_opts(misc_opts, default_set_nodelay)?;
+    Ok(())
 }
 
 #[cfg(any(windows, target_os = "macos"))]
@@ -126,3 +199,22 @@ pub fn new_socket_to(
     let socket = new_std_socket_to(peer_ip, bind_ip, keepalive, misc_opts, default_set_nodelay)?;
     Ok(TcpSocket::from_std_stream(socket))
 }
+
+pub fn new_socket_in_range_to(
+    peer_ip: IpAddr,
+    bind_ip: Option<IpAddr>,
+    port: PortRange,
+    keepalive: &TcpKeepAliveConfig,
+    misc_opts: &TcpMiscSockOpts,
+    default_set_nodelay: bool,
+) -> io::Result<TcpSocket> {
+    let socket = new_std_socket_in_range_to(
+        peer_ip,
+        bind_ip,
+        port,
+        keepalive,
+        misc_opts,
+        default_set_nodelay,
+    )?;
+    Ok(TcpSocket::from_std_stream(socket))
+}
@@ -18,6 +18,7 @@ use std::fmt;
 
 use super::SocksNegotiationError;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SocksCommand {
     TcpConnect = 0x01,
     TcpBind = 0x02,
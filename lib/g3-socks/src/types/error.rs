@@ -98,6 +98,8 @@ pub enum SocksConnectError {
     InvalidProtocol(#[from] SocksNegotiationError),
     #[error("peer timeout")]
     PeerTimeout,
+    #[error("command not supported")]
+    CommandNotSupported,
     #[error("request failed: {0}")]
     RequestFailed(String),
 }
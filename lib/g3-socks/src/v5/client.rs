@@ -72,6 +72,7 @@ where
     match rsp {
         Socks5Reply::Succeeded(addr) => Ok(addr),
         Socks5Reply::ConnectionTimedOut => Err(SocksConnectError::PeerTimeout),
+        Socks5Reply::CommandNotSupported => Err(SocksConnectError::CommandNotSupported),
         _ => Err(SocksConnectError::RequestFailed(format!(
             "request failed: {}",
             rsp.error_message()
@@ -103,6 +104,7 @@ where
     match rsp {
         Socks5Reply::Succeeded(addr) => Ok(addr),
         Socks5Reply::ConnectionTimedOut => Err(SocksConnectError::PeerTimeout),
+        Socks5Reply::CommandNotSupported => Err(SocksConnectError::CommandNotSupported),
         _ => Err(SocksConnectError::RequestFailed(format!(
             "request failed: {}",
             rsp.error_message()
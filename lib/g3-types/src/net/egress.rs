@@ -18,11 +18,18 @@ use std::fmt;
 use std::net::IpAddr;
 use std::str::FromStr;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct EgressArea {
     inner: Vec<String>,
 }
 
+impl EgressArea {
+    /// check if `other` is the same area or a sub area of this one
+    pub fn contains(&self, other: &EgressArea) -> bool {
+        other.inner.starts_with(&self.inner)
+    }
+}
+
 impl fmt::Display for EgressArea {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.inner.join("/"))
@@ -50,6 +57,7 @@ impl FromStr for EgressArea {
 
 #[derive(Clone, Debug, Default)]
 pub struct EgressInfo {
+    pub id: Option<String>,
     pub ip: Option<IpAddr>,
     pub isp: Option<String>,
     pub area: Option<EgressArea>,
@@ -57,8 +65,25 @@ pub struct EgressInfo {
 
 impl EgressInfo {
     pub fn reset(&mut self) {
+        self.id = None;
         self.ip = None;
         self.isp = None;
         self.area = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn area_contains() {
+        let us = EgressArea::from_str("us").unwrap();
+        let us_ca = EgressArea::from_str("us/ca").unwrap();
+        let usa = EgressArea::from_str("usa").unwrap();
+        assert!(us.contains(&us));
+        assert!(us.contains(&us_ca));
+        assert!(!us_ca.contains(&us));
+        assert!(!us.contains(&usa));
+    }
+}
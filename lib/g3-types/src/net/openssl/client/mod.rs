@@ -108,8 +108,11 @@ impl OpensslClientConfig {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct OpensslClientConfigBuilder {
     protocol: Option<OpensslProtocol>,
+    min_protocol: Option<OpensslProtocol>,
+    max_protocol: Option<OpensslProtocol>,
     ciphers: Vec<String>,
     disable_sni: bool,
+    insecure: bool,
     ca_certs: Vec<Vec<u8>>,
     no_default_ca_certs: bool,
     client_cert_pair: Option<OpensslCertificatePair>,
@@ -130,8 +133,11 @@ impl Default for OpensslClientConfigBuilder {
     fn default() -> Self {
         OpensslClientConfigBuilder {
             protocol: None,
+            min_protocol: None,
+            max_protocol: None,
             ciphers: Vec::new(),
             disable_sni: false,
+            insecure: false,
             ca_certs: Vec::new(),
             no_default_ca_certs: false,
             client_cert_pair: None,
@@ -181,6 +187,27 @@ impl OpensslClientConfigBuilder {
             ));
         }
 
+        if self.min_protocol.is_some() || self.max_protocol.is_some() {
+            if self.protocol.is_some() {
+                return Err(anyhow!(
+                    "min / max protocol should not be set if protocol is set to a fixed version"
+                ));
+            }
+            #[cfg(feature = "tongsuo")]
+            if matches!(self.min_protocol, Some(OpensslProtocol::Tlcp11))
+                || matches!(self.max_protocol, Some(OpensslProtocol::Tlcp11))
+            {
+                return Err(anyhow!("tlcp can not be used as min / max protocol"));
+            }
+            if let (Some(min), Some(max)) = (self.min_protocol, self.max_protocol) {
+                if min > max {
+                    return Err(anyhow!(
+                        "min protocol {min:?} should not be greater than max protocol {max:?}"
+                    ));
+                }
+            }
+        }
+
         if self.handshake_timeout < MINIMAL_HANDSHAKE_TIMEOUT {
             self.handshake_timeout = MINIMAL_HANDSHAKE_TIMEOUT;
         }
@@ -192,6 +219,16 @@ impl OpensslClientConfigBuilder {
         self.protocol = Some(protocol);
     }
 
+    /// Set the min protocol version, only valid if no fixed protocol is set
+    pub fn set_min_protocol(&mut self, protocol: OpensslProtocol) {
+        self.min_protocol = Some(protocol);
+    }
+
+    /// Set the max protocol version, only valid if no fixed protocol is set
+    pub fn set_max_protocol(&mut self, protocol: OpensslProtocol) {
+        self.max_protocol = Some(protocol);
+    }
+
     pub fn set_ciphers(&mut self, ciphers: Vec<String>) {
         self.ciphers = ciphers;
     }
@@ -200,6 +237,24 @@ impl OpensslClientConfigBuilder {
         self.disable_sni = true;
     }
 
+    /// Skip the verification of the server certificate. Only use it if you know the risk
+    pub fn set_insecure(&mut self, insecure: bool) {
+        self.insecure = insecure;
+    }
+
+    #[inline]
+    pub fn is_insecure(&self) -> bool {
+        self.insecure
+    }
+
+    fn verify_mode(&self) -> SslVerifyMode {
+        if self.insecure {
+            SslVerifyMode::NONE
+        } else {
+            SslVerifyMode::PEER
+        }
+    }
+
     pub fn set_ca_certificates(&mut self, certs: Vec<X509>) -> anyhow::Result<()> {
         let mut all_der = Vec::with_capacity(certs.len());
         for (i, cert) in certs.into_iter().enumerate() {
@@ -295,7 +350,7 @@ impl OpensslClientConfigBuilder {
     fn new_tlcp_builder(&self) -> anyhow::Result<SslConnectorBuilder> {
         let mut ctx_builder = SslConnector::builder(SslMethod::ntls_client())
             .map_err(|e| anyhow!("failed to create ssl context builder: {e}"))?;
-        ctx_builder.set_verify(SslVerifyMode::PEER);
+        ctx_builder.set_verify(self.verify_mode());
         ctx_builder.enable_ntls();
 
         let mut use_dhe = false;
@@ -332,7 +387,7 @@ impl OpensslClientConfigBuilder {
     fn new_tls13_builder(&self) -> anyhow::Result<SslConnectorBuilder> {
         let mut ctx_builder = SslConnector::builder(SslMethod::tls_client())
             .map_err(|e| anyhow!("failed to create ssl context builder: {e}"))?;
-        ctx_builder.set_verify(SslVerifyMode::PEER);
+        ctx_builder.set_verify(self.verify_mode());
 
         ctx_builder
             .set_min_proto_version(Some(SslVersion::TLS1_3))
@@ -359,7 +414,7 @@ impl OpensslClientConfigBuilder {
     fn new_tls13_builder(&self) -> anyhow::Result<SslConnectorBuilder> {
         let mut ctx_builder = SslConnector::builder(SslMethod::tls_client())
             .map_err(|e| anyhow!("failed to create ssl context builder: {e}"))?;
-        ctx_builder.set_verify(SslVerifyMode::PEER);
+        ctx_builder.set_verify(self.verify_mode());
 
         ctx_builder
             .set_min_proto_version(Some(SslVersion::TLS1_3))
@@ -385,7 +440,7 @@ impl OpensslClientConfigBuilder {
     fn new_versioned_builder(&self, version: SslVersion) -> anyhow::Result<SslConnectorBuilder> {
         let mut ctx_builder = SslConnector::builder(SslMethod::tls_client())
             .map_err(|e| anyhow!("failed to create ssl context builder: {e}"))?;
-        ctx_builder.set_verify(SslVerifyMode::PEER);
+        ctx_builder.set_verify(self.verify_mode());
 
         ctx_builder
             .set_min_proto_version(Some(version))
@@ -411,7 +466,18 @@ impl OpensslClientConfigBuilder {
     fn new_default_builder(&self) -> anyhow::Result<SslConnectorBuilder> {
         let mut ctx_builder = SslConnector::builder(SslMethod::tls_client())
             .map_err(|e| anyhow!("failed to create ssl context builder: {e}"))?;
-        ctx_builder.set_verify(SslVerifyMode::PEER);
+        ctx_builder.set_verify(self.verify_mode());
+
+        if let Some(protocol) = self.min_protocol {
+            ctx_builder
+                .set_min_proto_version(Some(protocol_ssl_version(protocol)?))
+                .map_err(|e| anyhow!("failed to set min protocol version {protocol:?}: {e}"))?;
+        }
+        if let Some(protocol) = self.max_protocol {
+            ctx_builder
+                .set_max_proto_version(Some(protocol_ssl_version(protocol)?))
+                .map_err(|e| anyhow!("failed to set max protocol version {protocol:?}: {e}"))?;
+        }
 
         if let Some(cert_pair) = &self.client_cert_pair {
             cert_pair.add_to_client_ssl_context(&mut ctx_builder)?;
@@ -529,3 +595,49 @@ impl OpensslClientConfigBuilder {
         self.build_with_alpn_protocols(None)
     }
 }
+
+fn protocol_ssl_version(protocol: OpensslProtocol) -> anyhow::Result<SslVersion> {
+    match protocol {
+        OpensslProtocol::Ssl3 => Ok(SslVersion::SSL3),
+        OpensslProtocol::Tls1 => Ok(SslVersion::TLS1),
+        OpensslProtocol::Tls11 => Ok(SslVersion::TLS1_1),
+        OpensslProtocol::Tls12 => Ok(SslVersion::TLS1_2),
+        OpensslProtocol::Tls13 => Ok(SslVersion::TLS1_3),
+        #[cfg(feature = "tongsuo")]
+        OpensslProtocol::Tlcp11 => Err(anyhow!("tlcp has no ssl version in tls method")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_range() {
+        let mut builder = OpensslClientConfigBuilder::default();
+        builder.set_min_protocol(OpensslProtocol::Tls12);
+        builder.set_max_protocol(OpensslProtocol::Tls13);
+        builder.check().unwrap();
+        builder.build().unwrap();
+
+        builder.set_min_protocol(OpensslProtocol::Tls13);
+        builder.set_max_protocol(OpensslProtocol::Tls12);
+        assert!(builder.check().is_err());
+
+        let mut builder = OpensslClientConfigBuilder::default();
+        builder.set_protocol(OpensslProtocol::Tls12);
+        builder.set_min_protocol(OpensslProtocol::Tls12);
+        assert!(builder.check().is_err());
+    }
+
+    #[test]
+    fn insecure() {
+        let mut builder = OpensslClientConfigBuilder::default();
+        assert_eq!(builder.verify_mode(), SslVerifyMode::PEER);
+        builder.set_insecure(true);
+        assert!(builder.is_insecure());
+        assert_eq!(builder.verify_mode(), SslVerifyMode::NONE);
+        builder.check().unwrap();
+        builder.build().unwrap();
+    }
+}
@@ -23,6 +23,7 @@ pub struct TcpMiscSockOpts {
     pub time_to_live: Option<u32>,
     pub type_of_service: Option<u8>,
     pub netfilter_mark: Option<u32>,
+    pub quick_ack: Option<bool>,
 }
 
 impl TcpMiscSockOpts {
@@ -34,6 +35,12 @@ impl TcpMiscSockOpts {
             _ => Some(false),
         };
 
+        let quick_ack = match (self.quick_ack, other.quick_ack) {
+            (None, None) => None,
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            _ => Some(false),
+        };
+
         let max_segment_size = self.max_segment_size.existed_min(other.max_segment_size);
         let time_to_live = self.time_to_live.existed_min(other.time_to_live);
 
@@ -46,6 +53,7 @@ impl TcpMiscSockOpts {
             time_to_live,
             type_of_service,
             netfilter_mark,
+            quick_ack,
         }
     }
 }
@@ -21,6 +21,7 @@ pub struct UdpMiscSockOpts {
     pub time_to_live: Option<u32>,
     pub type_of_service: Option<u8>,
     pub netfilter_mark: Option<u32>,
+    pub busy_poll: Option<u32>,
 }
 
 impl UdpMiscSockOpts {
@@ -30,11 +31,13 @@ impl UdpMiscSockOpts {
 
         let type_of_service = other.type_of_service.or(self.type_of_service);
         let netfilter_mark = other.netfilter_mark.or(self.netfilter_mark);
+        let busy_poll = other.busy_poll.or(self.busy_poll);
 
         UdpMiscSockOpts {
             time_to_live,
             type_of_service,
             netfilter_mark,
+            busy_poll,
         }
     }
 }
@@ -25,7 +25,7 @@ use yaml_rust::Yaml;
 use ip_network::IpNetwork;
 
 use g3_types::collection::WeightedValue;
-use g3_types::net::{Host, UpstreamAddr, WeightedUpstreamAddr};
+use g3_types::net::{EgressArea, Host, UpstreamAddr, WeightedUpstreamAddr};
 
 pub fn as_env_sockaddr(value: &Yaml) -> anyhow::Result<SocketAddr> {
     if let Yaml::String(s) = value {
@@ -193,6 +193,16 @@ pub fn as_upstream_addr(value: &Yaml, default_port: u16) -> anyhow::Result<Upstr
     }
 }
 
+pub fn as_egress_area(value: &Yaml) -> anyhow::Result<EgressArea> {
+    if let Yaml::String(s) = value {
+        EgressArea::from_str(s).map_err(|_| anyhow!("invalid egress area string"))
+    } else {
+        Err(anyhow!(
+            "yaml value type for 'EgressArea' should be 'string'"
+        ))
+    }
+}
+
 pub fn as_weighted_upstream_addr(
     value: &Yaml,
     default_port: u16,
@@ -32,8 +32,8 @@ mod ftp;
 mod dns;
 
 pub use base::{
-    as_domain, as_env_sockaddr, as_host, as_ipaddr, as_ipv4addr, as_ipv6addr, as_sockaddr,
-    as_upstream_addr, as_url, as_weighted_sockaddr, as_weighted_upstream_addr,
+    as_domain, as_egress_area, as_env_sockaddr, as_host, as_ipaddr, as_ipv4addr, as_ipv6addr,
+    as_sockaddr, as_upstream_addr, as_url, as_weighted_sockaddr, as_weighted_upstream_addr,
 };
 pub use buf::as_socket_buffer_config;
 pub use haproxy::as_proxy_protocol_version;
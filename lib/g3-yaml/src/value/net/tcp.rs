@@ -267,6 +267,12 @@ pub fn as_tcp_misc_sock_opts(v: &Yaml) -> anyhow::Result<TcpMiscSockOpts> {
                 config.netfilter_mark = Some(mark);
                 Ok(())
             }
+            "quick_ack" => {
+                let quick_ack =
+                    crate::value::as_bool(v).context(format!("invalid bool value for key {k}"))?;
+                config.quick_ack = Some(quick_ack);
+                Ok(())
+            }
             _ => Err(anyhow!("invalid key {k}")),
         })?;
 
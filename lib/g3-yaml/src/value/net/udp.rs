@@ -44,6 +44,12 @@ pub fn as_udp_misc_sock_opts(v: &Yaml) -> anyhow::Result<UdpMiscSockOpts> {
                 config.netfilter_mark = Some(mark);
                 Ok(())
             }
+            "busy_poll" => {
+                let usecs =
+                    crate::value::as_u32(v).context(format!("invalid u32 value for key {k}"))?;
+                config.busy_poll = Some(usecs);
+                Ok(())
+            }
             _ => Err(anyhow!("invalid key {k}")),
         })?;
 